@@ -4,25 +4,68 @@
 //! including adding, listing, prioritizing, archiving, and editing.
 //! Now supports subtasks and multiple lists in Markdown format.
 
+use crate::dates::parse_due_date;
+use crate::identity::Identity;
+use crate::input::{confirm, is_interactive};
 use crate::markdown::{
-    generate_archive_file, generate_todo_file, parse_archive_file, parse_todo_file, ArchiveFile,
-    Task, TaskRef, TodoFile,
+    checkbox, generate_archive_file, generate_todo_file, parse_archive_file,
+    parse_archive_file_with_warnings, parse_todo_file, parse_todo_file_with_warnings,
+    parse_todo_head, ArchiveEntry, ArchiveFile, Recurrence, Subtask, SubtaskNesting, Task, TaskRef,
+    TodoFile, DEFAULT_LIST,
 };
+use crate::migration::{self, MigrationStatus};
+use crate::notify;
+use crate::output::{self, color, truecolor_enabled, Verbosity};
+use crate::store::TodoStore;
 use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime};
+use regex::{Regex, RegexBuilder};
+use serde_json::{json, Value};
 use std::collections::HashSet;
 use std::env;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use termion::color;
 
-// Custom 256-color support
-struct Color256(u8);
+/// Error for failures whose friendly message has already been printed.
+/// `main` exits with a nonzero status for it without printing anything else.
+#[derive(Debug)]
+pub struct AlreadyReported;
 
-impl fmt::Display for Color256 {
+impl fmt::Display for AlreadyReported {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\x1b[38;5;{}m", self.0)
+        write!(f, "error already reported")
+    }
+}
+
+impl std::error::Error for AlreadyReported {}
+
+// Custom 256-color and 24-bit color support
+enum TermColor {
+    Palette(u8),
+    TrueColor(u8, u8, u8),
+}
+
+impl fmt::Display for TermColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !output::colors_enabled() {
+            return Ok(());
+        }
+        match self {
+            TermColor::Palette(index) => write!(f, "\x1b[38;5;{}m", index),
+            TermColor::TrueColor(r, g, b) => write!(f, "\x1b[38;2;{};{};{}m", r, g, b),
+        }
+    }
+}
+
+impl TermColor {
+    /// Plain-text description for when escape codes can't be shown, e.g. "palette 117"
+    fn describe(&self) -> String {
+        match self {
+            TermColor::Palette(index) => format!("palette {}", index),
+            TermColor::TrueColor(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        }
     }
 }
 
@@ -83,10 +126,14 @@ fn rgb_to_256_color(r: u8, g: u8, b: u8) -> u8 {
     16 + 36 * r_index + 6 * g_index + b_index
 }
 
-// Generate color from HSV values
-fn hsv_color(h: f32, s: f32, v: f32) -> Color256 {
+// Generate color from HSV values, quantized to the 256-color palette unless truecolor is on
+fn hsv_color(h: f32, s: f32, v: f32, truecolor: bool) -> TermColor {
     let (r, g, b) = hsv_to_rgb(h, s, v);
-    Color256(rgb_to_256_color(r, g, b))
+    if truecolor {
+        TermColor::TrueColor(r, g, b)
+    } else {
+        TermColor::Palette(rgb_to_256_color(r, g, b))
+    }
 }
 
 // Color scheme configuration
@@ -99,33 +146,65 @@ struct ColorScheme {
 
     // Subtask adjustments
     value_reduction: f32, // Amount to reduce brightness for subtasks
+
+    truecolor: bool, // Emit 24-bit colors instead of the 256-color palette
 }
 
 impl ColorScheme {
+    /// The scheme for the theme chosen with `$LDR_THEME` or the config file
     fn new() -> Self {
-        if Self::is_dark_terminal() {
-            // Dark terminal scheme - bright colors
-            ColorScheme {
+        Self::from_name(output::theme())
+    }
+
+    /// The scheme for a named theme (see `output::THEMES`). "auto" and unknown names
+    /// pick dark or light from the terminal; "mono" gives every task the same gray.
+    fn from_name(name: &str) -> Self {
+        let truecolor = truecolor_enabled();
+        match name.to_ascii_lowercase().as_str() {
+            "dark" => ColorScheme {
                 task1_hue: 200.0, // Light cyan-blue
                 task2_hue: 40.0,  // Light desert tan/gold
                 main_saturation: 0.7,
                 main_value: 0.95, // Very bright
                 value_reduction: 0.2,
-            }
-        } else {
-            // Light terminal scheme - darker colors
-            ColorScheme {
+                truecolor,
+            },
+            "light" => ColorScheme {
                 task1_hue: 210.0, // Darker blue
                 task2_hue: 30.0,  // Darker orange
                 main_saturation: 0.8,
                 main_value: 0.6, // Much darker for light backgrounds
                 value_reduction: 0.15,
-            }
+                truecolor,
+            },
+            "mono" => ColorScheme {
+                task1_hue: 0.0,
+                task2_hue: 0.0,
+                main_saturation: 0.0, // Gray, readable on either background
+                main_value: 0.75,
+                value_reduction: 0.15,
+                truecolor,
+            },
+            "solarized" => ColorScheme {
+                task1_hue: 205.0, // Solarized blue
+                task2_hue: 45.0,  // Solarized yellow
+                main_saturation: 0.85,
+                main_value: 0.8, // Mid tones that work on base03 and base3
+                value_reduction: 0.15,
+                truecolor,
+            },
+            _ if Self::is_dark_terminal() => Self::from_name("dark"),
+            _ => Self::from_name("light"),
         }
     }
 
     fn is_dark_terminal() -> bool {
-        // Check various indicators for dark terminal
+        // Terminals that answer an OSC 11 query know best
+        if let Some(dark) = output::terminal_background_is_dark() {
+            return dark;
+        }
+
+        // Otherwise check various indicators for dark terminal
 
         // Check COLORFGBG environment variable (format: "15;0" means white fg, black bg)
         if let Ok(colorfgbg) = std::env::var("COLORFGBG") {
@@ -156,16 +235,31 @@ impl ColorScheme {
         true
     }
 
-    fn get_main_task_color(&self, task_num: usize) -> Color256 {
+    fn get_main_task_color(&self, task_num: usize) -> TermColor {
         let hue = if task_num % 2 == 1 {
             self.task1_hue
         } else {
             self.task2_hue
         };
-        hsv_color(hue, self.main_saturation, self.main_value)
+        hsv_color(hue, self.main_saturation, self.main_value, self.truecolor)
+    }
+
+    /// Color for a task with an explicit hue (e.g. from its age), dimmed like the
+    /// alternating scheme for subtasks
+    fn get_hue_color(&self, hue: f32, subtask: bool) -> TermColor {
+        if subtask {
+            hsv_color(
+                hue,
+                (self.main_saturation - 0.15).max(0.0),
+                self.main_value - self.value_reduction,
+                self.truecolor,
+            )
+        } else {
+            hsv_color(hue, self.main_saturation, self.main_value, self.truecolor)
+        }
     }
 
-    fn get_subtask_color(&self, task_num: usize, _subtask_idx: usize) -> Color256 {
+    fn get_subtask_color(&self, task_num: usize, _subtask_idx: usize) -> TermColor {
         let base_hue = if task_num % 2 == 1 {
             self.task1_hue
         } else {
@@ -173,22 +267,168 @@ impl ColorScheme {
         };
 
         // Simply inherit parent color but reduce saturation and value
-        let reduced_saturation = self.main_saturation - 0.15; // Reduce saturation by 15%
+        let reduced_saturation = (self.main_saturation - 0.15).max(0.0); // Reduce saturation by 15%
         let reduced_value = self.main_value - self.value_reduction;
 
-        hsv_color(base_hue, reduced_saturation, reduced_value)
+        hsv_color(base_hue, reduced_saturation, reduced_value, self.truecolor)
+    }
+}
+
+/// Formats a due date suffix for display, e.g. " (due 2025-05-01)"
+fn format_due(due: Option<NaiveDate>) -> String {
+    due.map(|date| format!(" (due {})", date.format("%Y-%m-%d")))
+        .unwrap_or_default()
+}
+
+/// Prints a `key=value` summary of a data file and its item counts in verbose mode
+fn report_file<'a>(verbosity: Verbosity, path: &Path, tasks: impl IntoIterator<Item = &'a Task>) {
+    if !verbosity.is_verbose() {
+        return;
+    }
+    let (mut task_count, mut subtask_count) = (0, 0);
+    for task in tasks {
+        task_count += 1;
+        subtask_count += task.subtasks.len();
+    }
+    println!(
+        "file={} tasks={} subtasks={}",
+        path.display(),
+        task_count,
+        subtask_count
+    );
+}
+
+/// Reports every list of a todo file in verbose mode
+fn report_todo_file(verbosity: Verbosity, path: &Path, todo_file: &TodoFile) {
+    let named = todo_file.lists.iter().flat_map(|list| &list.tasks);
+    report_file(verbosity, path, todo_file.tasks.iter().chain(named));
+}
+
+/// Reports every archived list in verbose mode
+fn report_archive_file(verbosity: Verbosity, path: &Path, archive_file: &ArchiveFile) {
+    let tasks = archive_file
+        .entries
+        .iter()
+        .flat_map(|entry| entry.lists.values())
+        .flatten();
+    report_file(verbosity, path, tasks);
+}
+
+/// Limit on the number of tasks in one list, to prevent abuse
+const MAX_TASKS: usize = 1000;
+
+/// Options for `add_entry`
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Add as a subtask under this task ("2"), or right after this subtask ("2a")
+    pub under: Option<String>,
+    /// Due date for a top-level task, as typed on the command line
+    pub due: Option<String>,
+    /// Add to this named list instead of the Default one, creating it if needed
+    pub list: Option<String>,
+    /// Add a top-level task at the bottom of its list instead of the top
+    pub append: bool,
+    /// Add a top-level task right below this task instead of at the top
+    pub after: Option<String>,
+    /// Add a top-level task right above this task instead of at the top
+    pub before: Option<String>,
+    /// Person the task is meant for, stored as `who:` metadata
+    pub who: Option<String>,
+    /// Machine the task is meant for, stored as `host:` metadata
+    pub host: Option<String>,
+    /// Bring the task back with its next due date each time it is archived
+    pub recur: Option<Recurrence>,
+    /// Add even if an identical item already exists
+    pub force: bool,
+}
+
+/// Finds an item whose text matches `text` ignoring surrounding whitespace and case.
+/// Returns its reference, e.g. "3", "2a", or "2a1" for a nested child.
+pub fn find_duplicate(tasks: &[Task], text: &str) -> Option<String> {
+    let needle = text.trim().to_lowercase();
+    let same = |candidate: &str| candidate.trim().to_lowercase() == needle;
+
+    for (task_idx, task) in tasks.iter().enumerate() {
+        if same(&task.text) {
+            return Some((task_idx + 1).to_string());
+        }
+        for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+            let subtask_ref = format!("{}{}", task_idx + 1, (b'a' + subtask_idx as u8) as char);
+            if same(&subtask.text) {
+                return Some(subtask_ref);
+            }
+            if let Some(child_idx) = subtask.children.iter().position(|child| same(child)) {
+                return Some(format!("{}{}", subtask_ref, child_idx + 1));
+            }
+        }
+    }
+
+    None
+}
+
+/// Validates a `who:`/`host:` value, which must be a single word to fit in the metadata comment
+fn parse_identity_value(flag: &str, value: Option<&str>) -> Result<Option<String>> {
+    match value.map(str::trim) {
+        Some(value) if value.is_empty() || value.contains(char::is_whitespace) => Err(anyhow!(
+            "Invalid {} '{}'. Expected a single word",
+            flag,
+            value
+        )),
+        value => Ok(value.map(str::to_string)),
     }
 }
 
 /// Adds a new entry to the todo file.
-/// Creates the file if it doesn't exist, otherwise prepends to the main list.
-/// Can add as subtask if `under` is specified.
-pub fn add_entry(path: &Path, text: &str, under: Option<usize>) -> Result<()> {
+/// Creates the file if it doesn't exist, otherwise prepends to the main list (or appends
+/// with `append`). Can add as subtask if `under` is specified, and attach a due date or
+/// who/host metadata to top-level tasks.
+/// With `list`, adds to that named list instead, creating the list if it doesn't exist.
+pub fn add_entry(
+    path: &Path,
+    text: &str,
+    options: &AddOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
     // Validate input
     if text.trim().is_empty() {
         return Err(anyhow!("Cannot add empty task"));
     }
 
+    let under = options
+        .under
+        .as_deref()
+        .map(TaskRef::parse)
+        .transpose()
+        .map_err(|e| anyhow!("Invalid --under reference: {}", e))?;
+    if under
+        .as_ref()
+        .is_some_and(|task_ref| task_ref.child_index.is_some())
+    {
+        return Err(anyhow!(
+            "--under takes a task or subtask reference (e.g., 2 or 2a)"
+        ));
+    }
+    let due = options
+        .due
+        .as_deref()
+        .map(parse_due_date)
+        .transpose()
+        .map_err(|e| anyhow!(e))?;
+    if due.is_some() && under.is_some() {
+        return Err(anyhow!("Due dates can only be set on top-level tasks"));
+    }
+
+    let who = parse_identity_value("who", options.who.as_deref())?;
+    let host = parse_identity_value("host", options.host.as_deref())?;
+    if (who.is_some() || host.is_some()) && under.is_some() {
+        return Err(anyhow!(
+            "--who and --host can only be set on top-level tasks"
+        ));
+    }
+    if options.recur.is_some() && under.is_some() {
+        return Err(anyhow!("Only top-level tasks can recur"));
+    }
+
     // Limit task text length to prevent abuse
     const MAX_TASK_LENGTH: usize = 500;
     if text.len() > MAX_TASK_LENGTH {
@@ -198,232 +438,2169 @@ pub fn add_entry(path: &Path, text: &str, under: Option<usize>) -> Result<()> {
             MAX_TASK_LENGTH
         ));
     }
-    let mut todo_file = if path.exists() {
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read file: {}", path.display()))?;
-        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
-    } else {
-        TodoFile::new("TODOs".to_string())
-    };
-
-    if let Some(task_num) = under {
-        // Add as subtask
-        if task_num == 0 || task_num > todo_file.tasks.len() {
-            return Err(anyhow!(
-                "Invalid task number: {}. Valid range: 1-{}",
-                task_num,
-                todo_file.tasks.len()
-            ));
-        }
-
-        // Limit number of subtasks per task
-        const MAX_SUBTASKS: usize = 26; // a-z
-        let task = &todo_file.tasks[task_num - 1];
-        if task.subtasks.len() >= MAX_SUBTASKS {
-            return Err(anyhow!(
-                "Task {} already has maximum number of subtasks ({})",
-                task_num,
-                MAX_SUBTASKS
-            ));
+    let mut store = TodoStore::new(path);
+    // A skipped duplicate leaves the file alone
+    let added = store.mutate(|todo_file| {
+        let list = options.list.as_deref();
+        let list_name = list.map(str::trim).unwrap_or(DEFAULT_LIST);
+        if list_name.is_empty() {
+            return Err(anyhow!("List name cannot be empty"));
         }
+        let new_list = !todo_file.has_list(list_name);
+        let id = todo_file.new_task_id();
+        // Only mention the list in messages when one was asked for explicitly
+        let target = match list {
+            Some(_) if new_list => format!(" to {} (new list)", list_name),
+            Some(_) if !TodoFile::is_default_list(list_name) => format!(" to {}", list_name),
+            _ => String::new(),
+        };
+        let porcelain_added = |reference: String, id: Option<&str>| {
+            let mut fields = vec![("ref", reference)];
+            if let Some(id) = id {
+                fields.push(("id", id.to_string()));
+            }
+            if list.is_some() && !TodoFile::is_default_list(list_name) {
+                fields.push(("list", output::quote(list_name)));
+            }
+            fields.push(("text", output::quote(text)));
+            output::porcelain("added", &fields);
+        };
+        let tasks = todo_file.list_or_create(list_name);
 
-        todo_file.tasks[task_num - 1].add_subtask(text.to_string());
-        println!(
-            "{}✓ Added subtask to task {}: {}{}",
-            color::Fg(color::Green),
-            task_num,
-            text,
-            color::Fg(color::Reset)
-        );
-    } else {
-        // Add as new main task at top
-        // Limit total number of tasks to prevent abuse
-        const MAX_TASKS: usize = 1000;
-        if todo_file.tasks.len() >= MAX_TASKS {
-            return Err(anyhow!(
-                "Maximum number of tasks ({}) reached. Please archive or remove some tasks first",
-                MAX_TASKS
-            ));
+        // Skip duplicates unless forced; this isn't an error so scripts keep working
+        if !options.force {
+            if let Some(existing) = find_duplicate(tasks, text) {
+                if verbosity.is_porcelain() {
+                    output::porcelain("duplicate", &[("ref", existing.clone())]);
+                }
+                if !verbosity.is_quiet() {
+                    println!(
+                        "{}Similar item already exists at {}; not adding (use --force to add anyway){}",
+                        color::Fg(color::Yellow),
+                        existing,
+                        color::Fg(color::Reset)
+                    );
+                }
+                return Ok(false);
+            }
         }
 
-        let task = Task::new(text.to_string());
-        todo_file.prepend_task(task);
-        println!(
-            "{}✓ Added: {}{}",
-            color::Fg(color::Green),
-            text,
-            color::Fg(color::Reset)
-        );
-    }
-
-    let content = generate_todo_file(&todo_file);
-    fs::write(path, content).with_context(|| format!("Failed to write file: {}", path.display()))
-}
-
-/// Lists tasks with numbered display including subtasks.
-/// Displays task numbers and subtask letters, supports filtering.
-pub fn list_note(path: &Path, num: usize, all: bool, filter: Option<&str>) -> Result<()> {
-    if !path.exists() {
-        println!(
-            "{}No notes yet.{}",
-            color::Fg(color::Yellow),
-            color::Fg(color::Reset)
-        );
-        return Ok(());
-    }
-
-    let content = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read file: {}", path.display()))?;
-    let todo_file =
-        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
-
-    if todo_file.is_empty() {
-        println!(
-            "{}No notes yet.{}",
-            color::Fg(color::Yellow),
-            color::Fg(color::Reset)
-        );
-        return Ok(());
-    }
-
-    // Build list of items for filtering and display
-    let display_items: Vec<_> = if let Some(filter_text) = filter {
-        let mut filtered = Vec::new();
-        let filter_lower = filter_text.to_lowercase();
+        if let Some(under) = under {
+            // Add as subtask
+            let task_num = under.task_index + 1;
+            if task_num > tasks.len() {
+                return Err(anyhow!(
+                    "Invalid task number: {}. Valid range: 1-{}",
+                    task_num,
+                    tasks.len()
+                ));
+            }
 
-        for (task_idx, task) in todo_file.tasks.iter().enumerate() {
-            let task_num = task_idx + 1;
-            let task_matches = task.text.to_lowercase().contains(&filter_lower);
+            // Limit number of subtasks per task
+            const MAX_SUBTASKS: usize = 26; // a-z
+            let task = &tasks[task_num - 1];
+            if task.subtasks.len() >= MAX_SUBTASKS {
+                return Err(anyhow!(
+                    "Task {} already has maximum number of subtasks ({})",
+                    task_num,
+                    MAX_SUBTASKS
+                ));
+            }
 
-            // Check which subtasks match
-            let mut matching_subtasks = Vec::new();
-            for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
-                if subtask.to_lowercase().contains(&filter_lower) {
-                    matching_subtasks.push(subtask_idx);
+            // A subtask reference inserts right after that subtask; a task number appends
+            let position = match under.subtask_index {
+                Some(subtask_idx) if subtask_idx >= task.subtasks.len() => {
+                    return Err(anyhow!(
+                        "Invalid subtask: {}. Task {} has {} subtasks",
+                        options.under.as_deref().unwrap_or_default(),
+                        task_num,
+                        task.subtasks.len()
+                    ));
                 }
-            }
+                Some(subtask_idx) => subtask_idx + 1,
+                None => task.subtasks.len(),
+            };
 
-            if task_matches {
-                // If task matches, include task and ALL its subtasks
-                let task_line = format!("{:3}. {}", task_num, task.text);
-                filtered.push((task_num, None, task_line));
+            tasks[task_num - 1]
+                .subtasks
+                .insert(position, Subtask::new(text.to_string()));
+            if verbosity.is_porcelain() {
+                porcelain_added(
+                    format!("{}{}", task_num, (b'a' + position as u8) as char),
+                    None,
+                );
+            }
+            if !verbosity.is_quiet() {
+                let placement = if under.is_subtask() {
+                    format!(" as {}{}", task_num, (b'a' + position as u8) as char)
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{}✓ Added subtask{} to task {}{}: {}{}",
+                    color::Fg(color::Green),
+                    target,
+                    task_num,
+                    placement,
+                    text,
+                    color::Fg(color::Reset)
+                );
+            }
+        } else {
+            // Add as new main task at top
+            if tasks.len() >= MAX_TASKS {
+                return Err(anyhow!(
+                    "Maximum number of tasks ({}) reached. Please archive or remove some tasks first",
+                    MAX_TASKS
+                ));
+            }
 
-                for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
-                    let letter = (b'a' + subtask_idx as u8) as char;
-                    let subtask_line = format!("     {}. {}", letter, subtask);
-                    filtered.push((task_num, Some(subtask_idx), subtask_line));
+            // --after/--before place the task next to another one instead of at either end
+            let anchor = match (options.after.as_deref(), options.before.as_deref()) {
+                (Some(reference), _) => Some(("--after", reference, 1)),
+                (None, Some(reference)) => Some(("--before", reference, 0)),
+                (None, None) => None,
+            };
+            let position = match anchor {
+                Some((flag, reference, offset)) => {
+                    let task_ref = TaskRef::resolve(reference, tasks)
+                        .map_err(|e| anyhow!("Invalid {} reference: {}", flag, e))?;
+                    if task_ref.is_subtask() {
+                        return Err(anyhow!(
+                            "{} takes a task number (e.g., 3); use --under to add a subtask",
+                            flag
+                        ));
+                    }
+                    if task_ref.task_index >= tasks.len() {
+                        return Err(anyhow!(
+                            "Invalid task number: {}. Valid range: 1-{}",
+                            task_ref.task_index + 1,
+                            tasks.len()
+                        ));
+                    }
+                    Some(task_ref.task_index + offset)
                 }
-            } else if !matching_subtasks.is_empty() {
-                // If only subtasks match, include task and only matching subtasks
-                let task_line = format!("{:3}. {}", task_num, task.text);
-                filtered.push((task_num, None, task_line));
+                None => None,
+            };
 
-                for &subtask_idx in &matching_subtasks {
-                    let letter = (b'a' + subtask_idx as u8) as char;
-                    let subtask_line = format!("     {}. {}", letter, &task.subtasks[subtask_idx]);
-                    filtered.push((task_num, Some(subtask_idx), subtask_line));
-                }
+            let mut task = Task::new(text.to_string());
+            task.due = due;
+            task.created = Some(Local::now().naive_local());
+            task.who = who;
+            task.host = host;
+            task.recur = options.recur;
+            task.id = Some(id.clone());
+            let (task_num, target) = if let Some(position) = position {
+                tasks.insert(position, task);
+                (position + 1, format!("{} as task {}", target, position + 1))
+            } else if options.append {
+                tasks.push(task);
+                (tasks.len(), format!("{} at the bottom", target))
+            } else {
+                tasks.insert(0, task);
+                (1, target)
+            };
+            if verbosity.is_porcelain() {
+                porcelain_added(task_num.to_string(), Some(&id));
+            }
+            if !verbosity.is_quiet() {
+                println!(
+                    "{}✓ Added{}: {}{}{}",
+                    color::Fg(color::Green),
+                    target,
+                    text,
+                    format_due(due),
+                    color::Fg(color::Reset)
+                );
             }
+            notify::send(&format!("Added{}: {}", target, text));
         }
+        Ok(true)
+    })?;
+    if added {
+        report_todo_file(verbosity, path, store.get()?);
+    }
 
-        filtered
-    } else {
-        // No filter - include everything
-        let mut all_items = Vec::new();
-        for (task_idx, task) in todo_file.tasks.iter().enumerate() {
-            let task_num = task_idx + 1;
-            let task_line = format!("{:3}. {}", task_num, task.text);
-            all_items.push((task_num, None, task_line));
+    Ok(())
+}
 
-            // Add subtasks if any
-            for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
-                let letter = (b'a' + subtask_idx as u8) as char;
-                let subtask_line = format!("     {}. {}", letter, subtask);
-                all_items.push((task_num, Some(subtask_idx), subtask_line));
-            }
-        }
-        all_items
-    };
+/// Options for `import_items`
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Read one task per line instead of parsing Markdown
+    pub plain: bool,
+    /// Import into this named list instead of the Default one, creating it if needed
+    pub list: Option<String>,
+}
 
-    if display_items.is_empty() {
-        if filter.is_some() {
-            println!(
-                "{}No items found matching filter: \"{}\"{}",
-                color::Fg(color::Yellow),
-                filter.unwrap_or(""),
-                color::Fg(color::Reset)
-            );
-        } else {
+/// Tasks read from an import file. Markdown keeps subtasks and metadata, with the tasks
+/// of every list in the file taken in file order; plain text is one task per line.
+/// Blank lines and empty items are skipped.
+fn read_import_tasks(content: &str, plain: bool) -> Result<Vec<Task>> {
+    if plain {
+        return Ok(content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| Task::new(line.to_string()))
+            .collect());
+    }
+
+    let imported =
+        parse_todo_file(content).map_err(|e| anyhow!("Failed to parse import file: {}", e))?;
+    let named = imported.lists.into_iter().flat_map(|list| list.tasks);
+    Ok(imported
+        .tasks
+        .into_iter()
+        .chain(named)
+        .filter(|task| !is_blank_item(&task.text))
+        .map(|mut task| {
+            task.subtasks
+                .retain(|subtask| !is_blank_item(&subtask.text));
+            task
+        })
+        .collect())
+}
+
+/// Prepends the tasks of a Markdown or plain-text file to a list, keeping their order
+pub fn import_items(
+    todo_path: &Path,
+    import_path: &Path,
+    options: &ImportOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let content = fs::read_to_string(import_path)
+        .with_context(|| format!("Failed to read file: {}", import_path.display()))?;
+    let mut imported = read_import_tasks(&content, options.plain)?;
+    if imported.is_empty() {
+        if verbosity.is_porcelain() {
+            output::porcelain("imported", &[("count", "0".to_string())]);
+        }
+        if !verbosity.is_quiet() {
             println!(
-                "{}No notes yet.{}",
+                "{}Nothing to import from {}.{}",
                 color::Fg(color::Yellow),
+                import_path.display(),
                 color::Fg(color::Reset)
             );
         }
         return Ok(());
     }
 
-    let display_count = if all {
-        display_items.len()
+    let mut todo_file = if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
     } else {
-        num.min(display_items.len())
+        TodoFile::new("TODOs".to_string())
     };
 
-    let color_scheme = ColorScheme::new();
+    let list = options.list.as_deref();
+    let list_name = list.map(str::trim).unwrap_or(DEFAULT_LIST);
+    if list_name.is_empty() {
+        return Err(anyhow!("List name cannot be empty"));
+    }
+    let new_list = !todo_file.has_list(list_name);
+    let target = match list {
+        Some(_) if new_list => format!(" to {} (new list)", list_name),
+        Some(_) if !TodoFile::is_default_list(list_name) => format!(" to {}", list_name),
+        _ => String::new(),
+    };
+    let tasks = todo_file.list_or_create(list_name);
 
-    for (task_num, subtask_idx, line) in display_items.iter().take(display_count) {
-        if subtask_idx.is_none() {
-            // Main task - use HSV-based bright colors
-            let color = color_scheme.get_main_task_color(*task_num);
-            println!("{}{}{}", color, line, color::Fg(color::Reset));
-        } else {
-            // Subtask - use same color family as parent but dimmer
-            let color = color_scheme.get_subtask_color(*task_num, subtask_idx.unwrap());
-            println!("{}{}{}", color, line, color::Fg(color::Reset));
-        }
+    if tasks.len() + imported.len() > MAX_TASKS {
+        return Err(anyhow!(
+            "Importing {} tasks would exceed the maximum number of tasks ({}); {} can still be added",
+            imported.len(),
+            MAX_TASKS,
+            MAX_TASKS.saturating_sub(tasks.len())
+        ));
+    }
+
+    let now = Local::now().naive_local();
+    for task in &mut imported {
+        task.created.get_or_insert(now);
     }
+    let count = imported.len();
+    tasks.splice(0..0, imported);
 
-    if !all && display_items.len() > display_count {
+    let content = generate_todo_file(&todo_file);
+    fs::write(todo_path, content)
+        .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+    if verbosity.is_porcelain() {
+        output::porcelain("imported", &[("count", count.to_string())]);
+    }
+    if !verbosity.is_quiet() {
         println!(
-            "{}... and {} more items{}",
-            color::Fg(color::Yellow),
-            display_items.len() - display_count,
+            "{}✓ Imported {} task(s){} from {}{}",
+            color::Fg(color::Green),
+            count,
+            target,
+            import_path.display(),
             color::Fg(color::Reset)
         );
     }
+    report_todo_file(verbosity, todo_path, &todo_file);
 
     Ok(())
 }
 
-/// Parse task references and perform operations on tasks/subtasks
-pub fn prioritize_items(todo_path: &Path, refs: &[String]) -> Result<()> {
-    if !todo_path.exists() {
-        println!(
-            "{}No notes found.{}",
-            color::Fg(color::Yellow),
-            color::Fg(color::Reset)
-        );
-        return Ok(());
-    }
+/// Display options for `list_note`
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// Number of items to show unless `all` is set
+    pub num: usize,
+    /// Show all items (overrides `num`)
+    pub all: bool,
+    /// Only show items containing this text (case-insensitive)
+    pub filter: Option<String>,
+    /// A matching task shows only its matching subtasks instead of all of them
+    pub match_subtasks_only: bool,
+    /// Append each task's relative age, e.g. "(3d)"
+    pub age: bool,
+    /// Show oldest tasks first (numbers still match stored positions)
+    pub reverse: bool,
+    /// Color overdue tasks red and tasks due today yellow, without reordering
+    pub highlight_due: bool,
+    /// Only show tasks whose `who:`/`host:` metadata matches this identity
+    pub mine: Option<Identity>,
+    /// With `mine`, also hide tasks that have no `who:`/`host:` metadata
+    pub strict_mine: bool,
+    /// How each item is printed
+    pub format: ListFormat,
+    /// Delimiter between items in the plain formats (newline by default)
+    pub separator: Option<String>,
+    /// Wrap long lines to this many columns instead of the terminal width
+    pub width: Option<usize>,
+    /// Truncate items to fit `width` and show due dates and ages as right-aligned columns
+    pub columns: bool,
+    /// Mark tasks that are new or moved since the order saved in this file, then update it
+    pub snapshot: Option<PathBuf>,
+    /// How task colors are chosen
+    pub color_by: ColorBy,
+    /// Display order of tasks; numbers still refer to stored positions
+    pub sort: SortOrder,
+    /// Show only tasks or only subtasks
+    pub level: ItemLevel,
+    /// List tasks under a header per group instead of as one list
+    pub group_by: Option<GroupBy>,
+    /// Number every item in one sequence instead of `2`/`2a` references (see `flat_refs`)
+    pub flat_numbers: bool,
+    /// After the list, show this many of the most recently archived items from this archive
+    pub recently_done: Option<(PathBuf, usize)>,
+    /// Draw subtasks with box-drawing connectors and their full references (see `tree_items`)
+    pub tree: bool,
+    /// Only show the task recorded by `focus` (its `id:` and `text:` lines)
+    pub focus: Option<String>,
+    /// Only show tasks created since this time, the start of the last review; tasks
+    /// without a creation time are never shown
+    pub new_since: Option<NaiveDateTime>,
+    /// Show this many tasks picked at random instead of the top ones
+    pub random: Option<usize>,
+    /// Seed for `random`, so the same tasks are picked every time
+    pub seed: Option<u64>,
+    /// Hide items nested deeper than this (0 = tasks only), marking what each shown item
+    /// holds with a dim count like " (3)"
+    pub max_depth: Option<usize>,
+}
 
-    let content = fs::read_to_string(todo_path)
-        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
-    let mut todo_file =
-        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+/// How `ls` picks each task's color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorBy {
+    /// Alternate two hues by task number
+    #[default]
+    Position,
+    /// Shade from green (newest) to red (oldest) by creation time; tasks without a
+    /// creation time keep the alternating colors
+    Age,
+}
 
-    if todo_file.is_empty() {
-        println!(
-            "{}No notes found.{}",
-            color::Fg(color::Yellow),
-            color::Fg(color::Reset)
-        );
-        return Ok(());
+/// Hue per task number for `--color-by age`: the newest displayed task is green (120°)
+/// and the oldest red (0°), normalized across the tasks that have a creation time
+pub fn age_hues(tasks: &[(usize, NaiveDateTime)]) -> Vec<(usize, f32)> {
+    let Some(newest) = tasks.iter().map(|(_, created)| *created).max() else {
+        return Vec::new();
+    };
+    let oldest = tasks
+        .iter()
+        .map(|(_, created)| *created)
+        .min()
+        .unwrap_or(newest);
+    let span = newest.signed_duration_since(oldest).num_seconds() as f32;
+
+    tasks
+        .iter()
+        .map(|(task_num, created)| {
+            let age = newest.signed_duration_since(*created).num_seconds() as f32;
+            let staleness = if span > 0.0 { age / span } else { 0.0 };
+            (*task_num, 120.0 * (1.0 - staleness))
+        })
+        .collect()
+}
+
+/// Which levels of items `ls` shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ItemLevel {
+    /// Tasks with their subtasks
+    #[default]
+    All,
+    /// Only top-level tasks
+    TasksOnly,
+    /// Only subtasks (and their children), as a flat list
+    SubtasksOnly,
+}
+
+/// Display order of tasks in `ls`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SortOrder {
+    /// Stored order, newest first
+    #[default]
+    Position,
+    /// Alphabetically by text, ignoring case
+    Alpha,
+    /// Shortest text first
+    Length,
+    /// By first @tag, with untagged tasks last
+    Tag,
+}
+
+impl SortOrder {
+    /// Stable-sorts groups of display items (a task followed by its subtasks) by their task
+    fn sort_groups<'a>(self, groups: &mut [Vec<DisplayItem>], task: impl Fn(usize) -> &'a Task) {
+        let task_of = |group: &Vec<DisplayItem>| task(group[0].task_num);
+        match self {
+            SortOrder::Position => {}
+            SortOrder::Alpha => {
+                groups.sort_by_cached_key(|group| task_of(group).text.to_lowercase())
+            }
+            SortOrder::Length => groups.sort_by_key(|group| task_of(group).text.chars().count()),
+            SortOrder::Tag => groups.sort_by_cached_key(|group| {
+                let first_tag = extract_tags(&task_of(group).text).into_iter().next();
+                (first_tag.is_none(), first_tag)
+            }),
+        }
     }
+}
 
-    // Parse task references
-    let mut task_refs = Vec::new();
-    for ref_str in refs {
-        match TaskRef::parse(ref_str) {
+/// How `ls --group-by` buckets tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// One group per @tag; a task with several tags is listed under each
+    Tag,
+}
+
+/// Buckets display items under the @tags of their task, alphabetically, with an
+/// "(untagged)" bucket last. Items keep their display order within each bucket.
+fn group_by_tag<'a, 'b>(
+    items: &'a [DisplayItem],
+    task: impl Fn(usize) -> &'b Task,
+) -> Vec<(String, Vec<&'a DisplayItem>)> {
+    let mut tagged: std::collections::BTreeMap<String, Vec<&DisplayItem>> =
+        std::collections::BTreeMap::new();
+    let mut untagged = Vec::new();
+    for item in items {
+        let tags = extract_tags(&task(item.task_num).text);
+        if tags.is_empty() {
+            untagged.push(item);
+        }
+        for tag in tags {
+            tagged.entry(tag).or_default().push(item);
+        }
+    }
+
+    let mut groups: Vec<_> = tagged.into_iter().collect();
+    if !untagged.is_empty() {
+        groups.push(("(untagged)".to_string(), untagged));
+    }
+    groups
+}
+
+/// Output format for `list_note`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListFormat {
+    /// Numbered, indented and colored lines for reading
+    #[default]
+    Colored,
+    /// Uncolored `REF<TAB>TEXT` records, e.g. "2a\tBuy eggs"
+    Plain,
+    /// Only the item references, e.g. "2a"
+    NumbersOnly,
+    /// Only the number of matching items
+    Count,
+    /// A JSON array of tasks, each with its nested subtasks
+    Json,
+    /// A flat JSON array of tasks and subtasks, subtasks naming their `parent`
+    JsonFlat,
+    /// The listed tasks on one line, e.g. "2 tasks: Buy milk | Call Bob (+3 more)"
+    Oneline,
+}
+
+impl ListFormat {
+    /// What to print when there is nothing to list, or None for the usual message
+    fn empty_output(self) -> Option<&'static str> {
+        match self {
+            ListFormat::Count => Some("0"),
+            ListFormat::Json | ListFormat::JsonFlat => Some("[]"),
+            ListFormat::Oneline => Some("0 tasks"),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the `ls --json` output from the listed items, either nested (tasks holding
+/// `subtasks`, subtasks holding `children`) or flat with a `parent` ref on each subtask
+fn list_json(items: &[DisplayItem], flat: bool) -> Value {
+    if flat {
+        return items
+            .iter()
+            .map(|item| {
+                let mut entry = json!({ "ref": item.reference, "text": item.text });
+                if let Some(completed) = item.completed {
+                    entry["completed"] = json!(completed);
+                }
+                if let Some(parent) = &item.parent {
+                    entry["parent"] = json!(parent);
+                }
+                entry
+            })
+            .collect();
+    }
+
+    let mut tasks: Vec<Value> = Vec::new();
+    for item in items {
+        let task_ref = item.task_num.to_string();
+        match &item.parent {
+            None => tasks.push(json!({
+                "ref": item.reference,
+                "text": item.text,
+                "subtasks": [],
+            })),
+            Some(parent) if *parent == task_ref => {
+                let subtasks = tasks
+                    .last_mut()
+                    .and_then(|task| task["subtasks"].as_array_mut());
+                if let Some(subtasks) = subtasks {
+                    let mut subtask = json!({
+                        "ref": item.reference,
+                        "text": item.text,
+                        "children": [],
+                    });
+                    if let Some(completed) = item.completed {
+                        subtask["completed"] = json!(completed);
+                    }
+                    subtasks.push(subtask);
+                }
+            }
+            Some(_) => {
+                let subtask = tasks
+                    .last_mut()
+                    .and_then(|task| task["subtasks"].as_array_mut())
+                    .and_then(|subtasks| subtasks.last_mut());
+                if let Some(subtask) = subtask {
+                    if let Some(children) = subtask["children"].as_array_mut() {
+                        children.push(json!({ "ref": item.reference, "text": item.text }));
+                    }
+                }
+            }
+        }
+    }
+    Value::Array(tasks)
+}
+
+impl ListOptions {
+    /// Whether the listing only needs the first `num` items of the Default list, so the
+    /// rest of the file can be counted instead of kept (see `parse_todo_head`)
+    fn shows_top_only(&self) -> bool {
+        self.num > 0
+            && !self.all
+            && !self.reverse
+            && self.filter.is_none()
+            && self.mine.is_none()
+            && self.snapshot.is_none()
+            && self.new_since.is_none()
+            && self.random.is_none()
+            && self.sort == SortOrder::Position
+            && self.level == ItemLevel::All
+            && !self.flat_numbers
+            && self.focus.is_none()
+            && self.max_depth.is_none()
+            && self.format != ListFormat::Count
+            && self.format != ListFormat::Oneline
+    }
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        ListOptions {
+            num: 5,
+            all: false,
+            filter: None,
+            match_subtasks_only: false,
+            age: false,
+            reverse: false,
+            highlight_due: false,
+            mine: None,
+            strict_mine: false,
+            format: ListFormat::default(),
+            separator: None,
+            width: None,
+            columns: false,
+            snapshot: None,
+            color_by: ColorBy::default(),
+            sort: SortOrder::default(),
+            level: ItemLevel::default(),
+            group_by: None,
+            flat_numbers: false,
+            recently_done: None,
+            tree: false,
+            focus: None,
+            new_since: None,
+            random: None,
+            seed: None,
+            max_depth: None,
+        }
+    }
+}
+
+/// Formats the time elapsed since `created` as a compact age like "5m", "3h" or "12d"
+fn format_age(created: NaiveDateTime, now: NaiveDateTime) -> String {
+    let elapsed = now.signed_duration_since(created);
+    if elapsed.num_hours() < 1 {
+        format!("{}m", elapsed.num_minutes().max(0))
+    } else if elapsed.num_days() < 1 {
+        format!("{}h", elapsed.num_hours())
+    } else {
+        format!("{}d", elapsed.num_days())
+    }
+}
+
+/// Returns the highlight color for a task that is overdue (red) or due today (yellow)
+pub fn due_highlight_color(due: Option<NaiveDate>, today: NaiveDate) -> Option<String> {
+    match due {
+        Some(date) if date < today => Some(color::Fg(color::Red).to_string()),
+        Some(date) if date == today => Some(color::Fg(color::Yellow).to_string()),
+        _ => None,
+    }
+}
+
+/// How a task differs from the previously seen task order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskChange {
+    /// Not in the previous order at all
+    New,
+    /// Present before, but out of order relative to the tasks around it
+    Moved,
+}
+
+impl fmt::Display for TaskChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskChange::New => write!(f, "new"),
+            TaskChange::Moved => write!(f, "moved"),
+        }
+    }
+}
+
+/// Heuristic diff of task texts against a previous snapshot, one entry per current task.
+/// Tasks on the longest common subsequence of both orders are unchanged, so prepending a
+/// task doesn't mark everything below it as moved; the rest are new or moved.
+pub fn diff_task_order(previous: &[String], current: &[&str]) -> Vec<Option<TaskChange>> {
+    let (n, m) = (previous.len(), current.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if previous[i] == current[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut unchanged = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if previous[i] == current[j] {
+            unchanged[j] = true;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    current
+        .iter()
+        .zip(unchanged)
+        .map(|(text, unchanged)| {
+            if unchanged {
+                None
+            } else if previous.iter().any(|old| old == text) {
+                Some(TaskChange::Moved)
+            } else {
+                Some(TaskChange::New)
+            }
+        })
+        .collect()
+}
+
+/// One line of `ls` output, with the reference and bare text it was built from
+struct DisplayItem {
+    task_num: usize,
+    subtask_idx: Option<usize>,
+    reference: String,
+    /// Reference of the task or subtask this item sits under
+    parent: Option<String>,
+    text: String,
+    /// Checkbox state of a subtask written as `- [ ]` or `- [x]`
+    completed: Option<bool>,
+    line: String,
+    /// Width of the number/letter prefix, so wrapped rows align under the text
+    indent: usize,
+    /// Due date and age, kept out of `line` to be printed as right-aligned columns
+    meta: Vec<String>,
+    /// Number of items nested directly under this one that `--max-depth` left out
+    collapsed: usize,
+}
+
+impl DisplayItem {
+    /// The text with its checkbox in front, as lines rebuilt from it show it
+    fn checked_text(&self) -> String {
+        format!("{}{}", checkbox(self.completed), self.text)
+    }
+
+    /// Nesting level: 0 for tasks, 1 for subtasks, 2 for their children
+    fn depth(&self) -> usize {
+        match &self.parent {
+            None => 0,
+            Some(parent) if parent.ends_with(|c: char| c.is_ascii_digit()) => 1,
+            Some(_) => 2,
+        }
+    }
+}
+
+/// The `ls --oneline` summary of `texts` followed by a count of the `more` tasks left
+/// out. To fit `width`, tasks are dropped from the end (and counted as more); a single
+/// task that is still too long has its text cut with an ellipsis, keeping the counts.
+/// Without room for even that, only the number of tasks is given.
+fn oneline_summary(texts: &[&str], more: usize, separator: &str, width: Option<usize>) -> String {
+    if texts.is_empty() {
+        return plural(more, "task");
+    }
+    let more_suffix = |shown: usize| match more + texts.len() - shown {
+        0 => String::new(),
+        left_out => format!(" (+{} more)", left_out),
+    };
+    let summary = |shown: usize| {
+        format!(
+            "{}: {}{}",
+            plural(shown, "task"),
+            texts[..shown].join(separator),
+            more_suffix(shown)
+        )
+    };
+    let Some(width) = width else {
+        return summary(texts.len());
+    };
+    (1..=texts.len())
+        .rev()
+        .map(summary)
+        .find(|line| output::display_width(line) <= width)
+        .unwrap_or_else(|| {
+            let prefix = format!("{}: ", plural(1, "task"));
+            let suffix = more_suffix(1);
+            let room = width
+                .saturating_sub(output::display_width(&prefix) + output::display_width(&suffix));
+            // At least one character besides the ellipsis
+            if room < 2 {
+                return plural(more + texts.len(), "task");
+            }
+            format!("{}{}{}", prefix, output::truncate(texts[0], room), suffix)
+        })
+}
+
+/// Xorshift64* generator for `ls --random`: small and reproducible from a seed, which is
+/// all picking tasks to look at needs
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Spread small seeds over the state; an all-zero state would stay zero
+        XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Keeps `count` of `items` chosen at random (all of them if there are fewer), in their
+/// original order. Without a seed every run picks differently.
+fn pick_random<T>(items: Vec<T>, count: usize, seed: Option<u64>) -> Vec<T> {
+    use std::hash::BuildHasher;
+
+    let seed = seed.unwrap_or_else(|| std::collections::hash_map::RandomState::new().hash_one(0));
+    let mut rng = XorShift::new(seed);
+    // A partial Fisher-Yates shuffle of the positions picks the first `count`
+    let mut positions: Vec<usize> = (0..items.len()).collect();
+    let count = count.min(items.len());
+    for idx in 0..count {
+        let other = idx + (rng.next() % (positions.len() - idx) as u64) as usize;
+        positions.swap(idx, other);
+    }
+    let picked: HashSet<usize> = positions[..count].iter().copied().collect();
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| picked.contains(idx))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// Row width for `ls --columns` when output is piped and no `--width` is given
+const DEFAULT_COLUMNS_WIDTH: usize = 80;
+
+/// Shown before the text of pinned tasks in `ls`
+const PIN_MARKER: &str = "📌 ";
+
+/// Lays out an item on one row: its line, cut with an ellipsis where needed, then its
+/// metadata right-aligned in columns of `column_widths` so the row ends at `width`.
+/// Columns no item uses are left out.
+fn column_line(item: &DisplayItem, column_widths: &[usize], width: usize) -> String {
+    let columns: String = column_widths
+        .iter()
+        .enumerate()
+        .filter(|(_, column_width)| **column_width > 0)
+        .map(|(idx, column_width)| {
+            let value = item.meta.get(idx).map_or("", String::as_str);
+            format!("  {:>width$}", value, width = column_width)
+        })
+        .collect();
+    if columns.is_empty() {
+        return output::truncate(&item.line, width);
+    }
+    let text_width = width.saturating_sub(output::display_width(&columns));
+    let text = output::truncate(&item.line, text_width);
+    let padding = text_width.saturating_sub(output::display_width(&text));
+    format!("{}{}{}", text, " ".repeat(padding), columns)
+}
+
+/// Display lines for a subtask ("a.") and its nested children ("1."), keyed by parent task
+fn subtask_display_items(
+    task_num: usize,
+    subtask_idx: usize,
+    subtask: &Subtask,
+) -> Vec<DisplayItem> {
+    let letter = (b'a' + subtask_idx as u8) as char;
+    let mut items = vec![DisplayItem {
+        task_num,
+        subtask_idx: Some(subtask_idx),
+        reference: format!("{}{}", task_num, letter),
+        parent: Some(task_num.to_string()),
+        text: subtask.text.clone(),
+        completed: subtask.completed,
+        line: format!(
+            "     {}. {}{}",
+            letter,
+            checkbox(subtask.completed),
+            subtask.text
+        ),
+        indent: 8,
+        meta: Vec::new(),
+        collapsed: 0,
+    }];
+    for (child_idx, child) in subtask.children.iter().enumerate() {
+        items.push(DisplayItem {
+            task_num,
+            subtask_idx: Some(subtask_idx),
+            reference: format!("{}{}{}", task_num, letter, child_idx + 1),
+            parent: Some(format!("{}{}", task_num, letter)),
+            text: child.clone(),
+            completed: None,
+            line: format!("        {}. {}", child_idx + 1, child),
+            indent: 11,
+            meta: Vec::new(),
+            collapsed: 0,
+        });
+    }
+    items
+}
+
+/// Redraws subtasks and their children as a tree under their task, e.g. `├─ 2a. Text`,
+/// with `└─` for the last item shown at its level. Full references keep the numbers
+/// usable with other commands; task lines are left as they are.
+fn tree_items(items: Vec<DisplayItem>) -> Vec<DisplayItem> {
+    // Whether another item under `parent` follows position `idx` within the same task
+    let has_later_sibling = |idx: usize, parent: &str| {
+        items[idx + 1..]
+            .iter()
+            .take_while(|other| other.task_num == items[idx].task_num)
+            .any(|other| other.parent.as_deref() == Some(parent))
+    };
+    let prefixes: Vec<Option<String>> = (0..items.len())
+        .map(|idx| {
+            let parent = items[idx].parent.as_deref()?;
+            let connector = if has_later_sibling(idx, parent) {
+                "├─ "
+            } else {
+                "└─ "
+            };
+            // Children sit under a subtask ("2a"), which continues its line while it has
+            // later siblings itself
+            let task_ref = items[idx].task_num.to_string();
+            let rail = if parent == task_ref {
+                ""
+            } else if has_later_sibling(idx, &task_ref) {
+                "│  "
+            } else {
+                "   "
+            };
+            Some(format!(
+                "     {}{}{}. ",
+                rail, connector, items[idx].reference
+            ))
+        })
+        .collect();
+    items
+        .into_iter()
+        .zip(prefixes)
+        .map(|(item, prefix)| match prefix {
+            Some(prefix) => DisplayItem {
+                line: format!("{}{}", prefix, item.checked_text()),
+                indent: prefix.chars().count(),
+                ..item
+            },
+            None => item,
+        })
+        .collect()
+}
+
+/// Lists tasks with numbered display including subtasks.
+/// Displays task numbers and subtask letters, supports filtering.
+/// With `match_subtasks_only`, a matching task no longer pulls in all of its subtasks;
+/// only subtasks that match the filter themselves are shown.
+pub fn list_note(path: &Path, options: &ListOptions, verbosity: Verbosity) -> Result<()> {
+    if !path.exists() {
+        if let Some(output) = options.format.empty_output() {
+            println!("{}", output);
+            return Ok(());
+        }
+        println!(
+            "{}No notes yet.{}",
+            color::Fg(color::Yellow),
+            color::Fg(color::Reset)
+        );
+        return print_recently_done(options);
+    }
+
+    // Huge files are only read as far as the items shown; verbose mode reports every list
+    let head = if options.shows_top_only() && !verbosity.is_verbose() {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let head = parse_todo_head(
+            std::io::BufReader::new(file),
+            options.num,
+            SubtaskNesting::from_env(),
+        )
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Some(head)
+    } else {
+        None
+    };
+    // A pinned task further down is listed at the top, so then the whole file is needed
+    let (todo_file, unread) = if let Some(head) = head.filter(|head| !head.more_pinned) {
+        for warning in head.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        (head.todo_file, (head.more_tasks, head.more_subtasks))
+    } else {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let todo_file =
+            parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+        (todo_file, (0, 0))
+    };
+    report_todo_file(verbosity, path, &todo_file);
+
+    if todo_file.is_empty() {
+        if let Some(output) = options.format.empty_output() {
+            println!("{}", output);
+            return Ok(());
+        }
+        println!(
+            "{}No notes yet.{}",
+            color::Fg(color::Yellow),
+            color::Fg(color::Reset)
+        );
+        return print_recently_done(options);
+    }
+
+    let filter = options.filter.as_deref();
+    let now = Local::now().naive_local();
+    // Compare against the task order seen by the previous run, then remember this one
+    let changes = match &options.snapshot {
+        Some(snapshot_path) => {
+            // The first run has nothing to compare against, so nothing is flagged
+            let previous: Option<Vec<String>> = fs::read_to_string(snapshot_path)
+                .ok()
+                .map(|content| content.lines().map(str::to_string).collect());
+            let current: Vec<&str> = todo_file.tasks.iter().map(|t| t.text.as_str()).collect();
+            fs::write(snapshot_path, current.join("\n")).with_context(|| {
+                format!("Failed to write snapshot: {}", snapshot_path.display())
+            })?;
+            previous
+                .map(|previous| diff_task_order(&previous, &current))
+                .unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+
+    let task_line = |task_num: usize, task: &Task| {
+        let age = match task.created {
+            Some(created) if options.age => format_age(created, now),
+            _ => String::new(),
+        };
+        let change = match changes.get(task_num - 1).copied().flatten() {
+            Some(change) => format!(" ({})", change),
+            None => String::new(),
+        };
+        let prefix = format!("{:3}. ", task_num);
+        let pin = if task.pinned { PIN_MARKER } else { "" };
+        let (line, meta) = if options.columns {
+            let due = task
+                .due
+                .map(|date| format!("due {}", date.format("%Y-%m-%d")))
+                .unwrap_or_default();
+            (
+                format!("{}{}{}{}", prefix, pin, task.text, change),
+                vec![due, age],
+            )
+        } else {
+            let age = if age.is_empty() {
+                age
+            } else {
+                format!(" ({})", age)
+            };
+            let line = format!(
+                "{}{}{}{}{}{}",
+                prefix,
+                pin,
+                task.text,
+                format_due(task.due),
+                age,
+                change
+            );
+            (line, Vec::new())
+        };
+        DisplayItem {
+            task_num,
+            subtask_idx: None,
+            reference: task_num.to_string(),
+            parent: None,
+            text: task.text.clone(),
+            completed: None,
+            line,
+            indent: prefix.len(),
+            meta,
+            collapsed: 0,
+        }
+    };
+
+    // A focused task is found by its ID or text, wherever it has moved since
+    if let Some(focus) = &options.focus {
+        if !todo_file.tasks.iter().any(|task| is_focused(task, focus)) {
+            println!(
+                "{}The focused task is no longer open: {} (`ldr focus --clear` shows everything){}",
+                color::Fg(color::Yellow),
+                focus_text(focus),
+                color::Fg(color::Reset)
+            );
+            return Ok(());
+        }
+    }
+
+    // Tasks meant for someone else are skipped, keeping stored numbering
+    let visible_tasks = todo_file
+        .tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, task)| match &options.mine {
+            Some(identity) => identity.matches(task, options.strict_mine),
+            None => true,
+        })
+        .filter(|(_, task)| {
+            options
+                .focus
+                .as_ref()
+                .is_none_or(|focus| is_focused(task, focus))
+        })
+        .filter(|(_, task)| {
+            options
+                .new_since
+                .is_none_or(|since| task.created.is_some_and(|created| created >= since))
+        });
+
+    // Build list of items for filtering and display
+    let display_items: Vec<_> = if let Some(filter_text) = filter {
+        let mut filtered = Vec::new();
+        let filter_lower = filter_text.to_lowercase();
+
+        for (task_idx, task) in visible_tasks {
+            let task_num = task_idx + 1;
+            let task_matches = task.text.to_lowercase().contains(&filter_lower);
+
+            // Check which subtasks match
+            let mut matching_subtasks = Vec::new();
+            for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+                let child_matches = subtask
+                    .children
+                    .iter()
+                    .any(|child| child.to_lowercase().contains(&filter_lower));
+                if child_matches || subtask.text.to_lowercase().contains(&filter_lower) {
+                    matching_subtasks.push(subtask_idx);
+                }
+            }
+
+            if task_matches && !options.match_subtasks_only {
+                // If task matches, include task and ALL its subtasks
+                filtered.push(task_line(task_num, task));
+
+                for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+                    filtered.extend(subtask_display_items(task_num, subtask_idx, subtask));
+                }
+            } else if task_matches || !matching_subtasks.is_empty() {
+                // Otherwise include task and only matching subtasks
+                filtered.push(task_line(task_num, task));
+
+                for &subtask_idx in &matching_subtasks {
+                    let subtask = &task.subtasks[subtask_idx];
+                    filtered.extend(subtask_display_items(task_num, subtask_idx, subtask));
+                }
+            }
+        }
+
+        filtered
+    } else {
+        // No filter - include everything
+        let mut all_items = Vec::new();
+        for (task_idx, task) in visible_tasks {
+            let task_num = task_idx + 1;
+            all_items.push(task_line(task_num, task));
+
+            // Add subtasks if any
+            for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+                all_items.extend(subtask_display_items(task_num, subtask_idx, subtask));
+            }
+        }
+        all_items
+    };
+
+    // Keep only one level; lone subtasks are prefixed with their full ref, e.g. "2a."
+    let display_items: Vec<DisplayItem> = match options.level {
+        ItemLevel::All => display_items,
+        ItemLevel::TasksOnly => display_items
+            .into_iter()
+            .filter(|item| item.subtask_idx.is_none())
+            .collect(),
+        ItemLevel::SubtasksOnly => display_items
+            .into_iter()
+            .filter(|item| item.subtask_idx.is_some())
+            .map(|item| {
+                let prefix = format!("{:>5}. ", item.reference);
+                DisplayItem {
+                    line: format!("{}{}", prefix, item.checked_text()),
+                    indent: prefix.len(),
+                    ..item
+                }
+            })
+            .collect(),
+    };
+
+    // Items below the depth limit are dropped; the ones at the limit count what they hold
+    let display_items: Vec<DisplayItem> = match options.max_depth {
+        Some(max_depth) => display_items
+            .into_iter()
+            .filter(|item| item.depth() <= max_depth)
+            .map(|item| {
+                let task = &todo_file.tasks[item.task_num - 1];
+                let collapsed = match (item.depth(), item.subtask_idx) {
+                    (depth, _) if depth < max_depth => 0,
+                    (0, _) => task.subtasks.len(),
+                    (1, Some(subtask_idx)) => task.subtasks[subtask_idx].children.len(),
+                    _ => 0,
+                };
+                DisplayItem { collapsed, ..item }
+            })
+            .collect(),
+        None => display_items,
+    };
+
+    // Continuous numbers replace the references; nesting is kept as indentation
+    let display_items: Vec<DisplayItem> = if options.flat_numbers {
+        let flat_numbers: std::collections::HashMap<String, usize> = flat_refs(&todo_file.tasks)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, reference)| (reference, idx + 1))
+            .collect();
+        display_items
+            .into_iter()
+            .map(|item| {
+                let number = flat_numbers[&item.reference];
+                let depth = item.depth();
+                // Task lines carry due dates and ages after the text; nested items don't
+                let rest = if depth == 0 {
+                    item.line[item.indent..].to_string()
+                } else {
+                    item.checked_text()
+                };
+                let prefix = format!("{}{:3}. ", "  ".repeat(depth), number);
+                DisplayItem {
+                    reference: number.to_string(),
+                    line: format!("{}{}", prefix, rest),
+                    indent: prefix.len(),
+                    ..item
+                }
+            })
+            .collect()
+    } else {
+        display_items
+    };
+
+    // Counts every item matching (subtasks and their children included, as each is its
+    // own line), regardless of -n/--all
+    if options.format == ListFormat::Count {
+        println!("{}", display_items.len());
+        return Ok(());
+    }
+
+    if display_items.is_empty() && options.format.empty_output().is_none() {
+        if filter.is_some() {
+            println!(
+                "{}No items found matching filter: \"{}\"{}",
+                color::Fg(color::Yellow),
+                filter.unwrap_or(""),
+                color::Fg(color::Reset)
+            );
+        } else if options.mine.is_some() {
+            println!(
+                "{}No items for you here.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        } else if options.new_since.is_some() {
+            println!(
+                "{}Nothing new since the last review.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        } else {
+            println!(
+                "{}No notes yet.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    // Sort and reverse whole tasks, keeping subtasks under their parent, with pinned tasks
+    // always first. Only the display order changes: items keep their stored numbers, so
+    // refs still work in other commands.
+    let any_pinned = display_items
+        .iter()
+        .any(|item| todo_file.tasks[item.task_num - 1].pinned);
+    let display_items = if options.reverse
+        || options.sort != SortOrder::Position
+        || any_pinned
+        || options.random.is_some()
+    {
+        let mut groups: Vec<Vec<DisplayItem>> = Vec::new();
+        for item in display_items {
+            match groups.last_mut() {
+                Some(group) if group[0].task_num == item.task_num => group.push(item),
+                _ => groups.push(vec![item]),
+            }
+        }
+        if let Some(count) = options.random {
+            groups = pick_random(groups, count, options.seed);
+        }
+        options
+            .sort
+            .sort_groups(&mut groups, |task_num| &todo_file.tasks[task_num - 1]);
+        if options.reverse {
+            groups.reverse();
+        }
+        groups.sort_by_key(|group| !todo_file.tasks[group[0].task_num - 1].pinned);
+        groups.into_iter().flatten().collect()
+    } else {
+        display_items
+    };
+
+    // Randomly picked tasks are shown in full
+    let show_all = options.all || options.random.is_some();
+    let display_count = if show_all {
+        display_items.len()
+    } else {
+        options.num.min(display_items.len())
+    };
+
+    // The tree is drawn over the items shown, so the last one at each level gets `└─`
+    // even when -n cuts off its later siblings
+    let display_items = if options.tree {
+        let mut shown = display_items;
+        let hidden = shown.split_off(display_count);
+        let mut display_items = tree_items(shown);
+        display_items.extend(hidden);
+        display_items
+    } else {
+        display_items
+    };
+
+    if matches!(options.format, ListFormat::Json | ListFormat::JsonFlat) {
+        // Subtasks without their tasks can't be nested, so they are always listed flat
+        let json = list_json(
+            &display_items[..display_count],
+            options.format == ListFormat::JsonFlat || options.level == ItemLevel::SubtasksOnly,
+        );
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    if options.format == ListFormat::Oneline {
+        let texts: Vec<&str> = display_items
+            .iter()
+            .filter(|item| item.parent.is_none())
+            .map(|item| item.text.as_str())
+            .collect();
+        let shown = if show_all {
+            texts.len()
+        } else {
+            options.num.min(texts.len())
+        };
+        let separator = options.separator.as_deref().unwrap_or(" | ");
+        println!(
+            "{}",
+            oneline_summary(
+                &texts[..shown],
+                texts.len() - shown,
+                separator,
+                options.width
+            )
+        );
+        return Ok(());
+    }
+
+    // Uncolored records for scripts, delimited by the chosen separator
+    if options.format != ListFormat::Colored {
+        let records: Vec<String> = display_items
+            .iter()
+            .take(display_count)
+            .map(|item| match options.format {
+                ListFormat::NumbersOnly => item.reference.clone(),
+                _ => format!("{}\t{}", item.reference, item.checked_text()),
+            })
+            .collect();
+        let separator = options.separator.as_deref().unwrap_or("\n");
+        print!("{}", records.join(separator));
+        if separator == "\n" {
+            println!();
+        }
+        return Ok(());
+    }
+
+    let color_scheme = ColorScheme::new();
+    let width = options.width.or_else(output::terminal_width);
+    let shown = &display_items[..display_count];
+    // Each metadata column is as wide as its widest entry among the items shown
+    let column_count = shown.iter().map(|item| item.meta.len()).max().unwrap_or(0);
+    let column_widths: Vec<usize> = (0..column_count)
+        .map(|idx| {
+            shown
+                .iter()
+                .filter_map(|item| item.meta.get(idx))
+                .map(|value| output::display_width(value))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let hues = if options.color_by == ColorBy::Age {
+        let created: Vec<_> = shown
+            .iter()
+            .filter(|item| item.subtask_idx.is_none())
+            .filter_map(|item| Some((item.task_num, todo_file.tasks[item.task_num - 1].created?)))
+            .collect();
+        age_hues(&created)
+    } else {
+        Vec::new()
+    };
+    let hue_for = |task_num: usize| {
+        hues.iter()
+            .find(|(num, _)| *num == task_num)
+            .map(|(_, hue)| *hue)
+    };
+
+    let hyperlinks = output::hyperlinks_enabled();
+    let print_item = |item: &DisplayItem| {
+        let task_num = item.task_num;
+        let line = match width {
+            _ if options.columns => {
+                column_line(item, &column_widths, width.unwrap_or(DEFAULT_COLUMNS_WIDTH))
+            }
+            Some(width) => output::wrap_line(&item.line, width, item.indent),
+            None => item.line.clone(),
+        };
+        let line = if hyperlinks {
+            output::hyperlink_urls(&line)
+        } else {
+            line
+        };
+        let line = if item.collapsed > 0 {
+            format!(
+                "{}{} ({}){}",
+                line,
+                output::style(termion::style::Faint),
+                item.collapsed,
+                output::style(termion::style::Reset)
+            )
+        } else {
+            line
+        };
+        // Checked subtasks out of all, green once every one is checked
+        let progress = match item.subtask_idx {
+            None => todo_file.tasks[task_num - 1].progress(),
+            Some(_) => None,
+        };
+        let line = match progress {
+            Some((done, total)) if done == total => format!(
+                "{}{} [{}/{}]{}",
+                line,
+                color::Fg(color::Green),
+                done,
+                total,
+                color::Fg(color::Reset)
+            ),
+            Some((done, total)) => format!(
+                "{}{} [{}/{}]{}",
+                line,
+                output::style(termion::style::Faint),
+                done,
+                total,
+                output::style(termion::style::Reset)
+            ),
+            None => line,
+        };
+        if let Some(subtask_idx) = item.subtask_idx {
+            // Subtask - use same color family as parent but dimmer
+            let color = match hue_for(task_num) {
+                Some(hue) => color_scheme.get_hue_color(hue, true),
+                None => color_scheme.get_subtask_color(task_num, subtask_idx),
+            };
+            println!("{}{}{}", color, line, color::Fg(color::Reset));
+        } else {
+            // Main task - use HSV-based bright colors, unless flagged as due
+            let highlight = if options.highlight_due {
+                due_highlight_color(todo_file.tasks[task_num - 1].due, now.date())
+            } else {
+                None
+            };
+            let color = highlight.unwrap_or_else(|| match hue_for(task_num) {
+                Some(hue) => color_scheme.get_hue_color(hue, false).to_string(),
+                None => color_scheme.get_main_task_color(task_num).to_string(),
+            });
+            println!("{}{}{}", color, line, color::Fg(color::Reset));
+        }
+    };
+
+    match options.group_by {
+        None => shown.iter().for_each(print_item),
+        Some(GroupBy::Tag) => {
+            let groups = group_by_tag(shown, |task_num| &todo_file.tasks[task_num - 1]);
+            for (idx, (tag, items)) in groups.iter().enumerate() {
+                if idx > 0 {
+                    println!();
+                }
+                let task_count = items
+                    .iter()
+                    .filter(|item| item.subtask_idx.is_none())
+                    .count();
+                println!(
+                    "{}{}{} ({}){}",
+                    color_scheme.get_main_task_color(idx + 1),
+                    output::style(termion::style::Bold),
+                    tag,
+                    task_count,
+                    output::style(termion::style::Reset)
+                );
+                items.iter().copied().for_each(print_item);
+            }
+        }
+    }
+
+    if !show_all && (display_items.len() > display_count || unread.0 > 0) {
+        println!(
+            "{}... and {}{}",
+            color::Fg(color::Yellow),
+            hidden_summary(&display_items[display_count..], unread),
+            color::Fg(color::Reset)
+        );
+    }
+    if options.focus.is_some() {
+        println!(
+            "{}Focused; `ldr focus --clear` or `ls --no-focus` shows every task{}",
+            output::style(termion::style::Faint),
+            output::style(termion::style::Reset)
+        );
+    }
+
+    print_recently_done(options)
+}
+
+/// Prints the `ls --done` section: the latest archived items, newest first, under a dim
+/// divider. Days are stored newest first and items are appended within a day.
+fn print_recently_done(options: &ListOptions) -> Result<()> {
+    let Some((archive_path, count)) = &options.recently_done else {
+        return Ok(());
+    };
+    if *count == 0 || !archive_path.exists() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(archive_path)
+        .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+    let archive_file =
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+
+    let mut recent = Vec::new();
+    for entry in &archive_file.entries {
+        let mut list_names: Vec<&String> = entry.lists.keys().collect();
+        list_names.sort_by_key(|name| (name.as_str() != DEFAULT_LIST, name.as_str()));
+        let mut day: Vec<(&String, &Task)> = list_names
+            .into_iter()
+            .flat_map(|name| entry.lists[name].iter().map(move |task| (name, task)))
+            .collect();
+        day.reverse();
+        recent.extend(
+            day.into_iter()
+                .map(|(name, task)| (&entry.date, name, task)),
+        );
+        if recent.len() >= *count {
+            break;
+        }
+    }
+    if recent.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "{}── Recently done ──{}",
+        output::style(termion::style::Faint),
+        output::style(termion::style::Reset)
+    );
+    for (date, list_name, task) in recent.into_iter().take(*count) {
+        let list_suffix = if list_name == DEFAULT_LIST {
+            String::new()
+        } else {
+            format!(" [{}]", list_name)
+        };
+        println!(
+            "{}  ✓ {}{} ({}){}",
+            output::style(termion::style::Faint),
+            task.text,
+            list_suffix,
+            date,
+            output::style(termion::style::Reset)
+        );
+    }
+    Ok(())
+}
+
+/// Counts items cut off by `ls -n` as tasks and subtasks, e.g. "2 more tasks and 3 subtasks",
+/// adding the `unread` tasks and subtasks that were never parsed. Subtasks are counted
+/// whether their task is shown or not.
+fn hidden_summary(hidden: &[DisplayItem], unread: (usize, usize)) -> String {
+    let tasks = hidden
+        .iter()
+        .filter(|item| item.subtask_idx.is_none())
+        .count();
+    let subtasks = hidden.len() - tasks + unread.1;
+    let tasks = tasks + unread.0;
+    let more = |count: usize, noun: &str| plural(count, noun).replacen(' ', " more ", 1);
+    match (tasks, subtasks) {
+        (_, 0) => more(tasks, "task"),
+        (0, _) => more(subtasks, "subtask"),
+        _ => format!(
+            "{} and {}",
+            more(tasks, "task"),
+            plural(subtasks, "subtask")
+        ),
+    }
+}
+
+/// Text matcher used by `find`: case-insensitive substring or regex
+enum Matcher {
+    Substring(String),
+    Pattern(Regex),
+}
+
+impl Matcher {
+    fn new(query: &str, use_regex: bool) -> Result<Self> {
+        if use_regex {
+            let pattern = RegexBuilder::new(query)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| anyhow!("Invalid regex '{}': {}", query, e))?;
+            Ok(Matcher::Pattern(pattern))
+        } else {
+            Ok(Matcher::Substring(query.to_lowercase()))
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Substring(needle) => text.to_lowercase().contains(needle),
+            Matcher::Pattern(pattern) => pattern.is_match(text),
+        }
+    }
+}
+
+/// Collect the subtask indices to show for a task, using the same rules as the `ls` filter:
+/// a matching task shows all its subtasks, otherwise only the matching subtasks are shown.
+/// Returns None when neither the task nor any of its subtasks match.
+fn matching_subtasks(task: &Task, matcher: &Matcher) -> Option<Vec<usize>> {
+    if matcher.is_match(&task.text) {
+        return Some((0..task.subtasks.len()).collect());
+    }
+
+    let matching: Vec<usize> = task
+        .subtasks
+        .iter()
+        .enumerate()
+        .filter(|(_, subtask)| {
+            matcher.is_match(&subtask.text)
+                || subtask.children.iter().any(|child| matcher.is_match(child))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+
+    if matching.is_empty() {
+        None
+    } else {
+        Some(matching)
+    }
+}
+
+/// Searches both open tasks and the archive for the query.
+/// Open matches are grouped under "Open", archived ones under "Archived (date)".
+pub fn find_items(
+    todo_path: &Path,
+    archive_path: &Path,
+    query: &str,
+    use_regex: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let matcher = Matcher::new(query, use_regex)?;
+    let color_scheme = ColorScheme::new();
+    let mut found_any = false;
+
+    if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        let todo_file =
+            parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+        report_todo_file(verbosity, todo_path, &todo_file);
+
+        let mut printed_header = false;
+        for (task_idx, task) in todo_file.tasks.iter().enumerate() {
+            let task_num = task_idx + 1;
+            let Some(subtask_indices) = matching_subtasks(task, &matcher) else {
+                continue;
+            };
+
+            if !printed_header {
+                println!("{}Open{}", color::Fg(color::Green), color::Fg(color::Reset));
+                printed_header = true;
+            }
+
+            let color = color_scheme.get_main_task_color(task_num);
+            println!(
+                "{}{:3}. {}{}",
+                color,
+                task_num,
+                task.text,
+                color::Fg(color::Reset)
+            );
+            for subtask_idx in subtask_indices {
+                let letter = (b'a' + subtask_idx as u8) as char;
+                let color = color_scheme.get_subtask_color(task_num, subtask_idx);
+                let subtask = &task.subtasks[subtask_idx];
+                println!(
+                    "{}     {}. {}{}",
+                    color,
+                    letter,
+                    subtask.text,
+                    color::Fg(color::Reset)
+                );
+                for (child_idx, child) in subtask.children.iter().enumerate() {
+                    println!(
+                        "{}        {}. {}{}",
+                        color,
+                        child_idx + 1,
+                        child,
+                        color::Fg(color::Reset)
+                    );
+                }
+            }
+        }
+        found_any |= printed_header;
+    }
+
+    if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        let archive_file =
+            parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+        report_archive_file(verbosity, archive_path, &archive_file);
+
+        for entry in &archive_file.entries {
+            // Default list first, then named lists in a stable order
+            let mut list_names: Vec<&String> = entry.lists.keys().collect();
+            list_names.sort_by_key(|name| (name.as_str() != "Default", name.as_str()));
+
+            let mut printed_header = false;
+            for list_name in list_names {
+                for (task_idx, task) in entry.lists[list_name].iter().enumerate() {
+                    let Some(subtask_indices) = matching_subtasks(task, &matcher) else {
+                        continue;
+                    };
+
+                    if !printed_header {
+                        if found_any {
+                            println!();
+                        }
+                        println!(
+                            "{}Archived ({}){}",
+                            color::Fg(color::Yellow),
+                            entry.date,
+                            color::Fg(color::Reset)
+                        );
+                        printed_header = true;
+                        found_any = true;
+                    }
+
+                    let color = color_scheme.get_subtask_color(task_idx + 1, 0);
+                    let list_suffix = if list_name == "Default" {
+                        String::new()
+                    } else {
+                        format!(" [{}]", list_name)
+                    };
+                    println!(
+                        "{}  - {}{}{}",
+                        color,
+                        task.text,
+                        list_suffix,
+                        color::Fg(color::Reset)
+                    );
+                    for subtask_idx in subtask_indices {
+                        println!(
+                            "{}    - {}{}",
+                            color,
+                            task.subtasks[subtask_idx],
+                            color::Fg(color::Reset)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if !found_any {
+        println!(
+            "{}No items found matching: \"{}\"{}",
+            color::Fg(color::Yellow),
+            query,
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// Shows the items archived on one day (today unless `date` is given), across all lists
+pub fn show_completed(archive_path: &Path, date: Option<&str>, verbosity: Verbosity) -> Result<()> {
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let day = match date {
+        Some(date) => parse_date_arg(date)?.format("%Y-%m-%d").to_string(),
+        None => today.clone(),
+    };
+
+    let archive_file = if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+
+    report_archive_file(verbosity, archive_path, &archive_file);
+
+    let entries: Vec<_> = archive_file
+        .entries
+        .iter()
+        .filter(|entry| entry.date == day)
+        .collect();
+    if entries
+        .iter()
+        .all(|entry| entry.lists.values().all(Vec::is_empty))
+    {
+        let message = if day == today {
+            "Nothing completed today yet.".to_string()
+        } else {
+            format!("Nothing completed on {}.", day)
+        };
+        println!(
+            "{}{}{}",
+            color::Fg(color::Yellow),
+            message,
+            color::Fg(color::Reset)
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}Completed {}{}",
+        color::Fg(color::Green),
+        day,
+        color::Fg(color::Reset)
+    );
+    let color_scheme = ColorScheme::new();
+    for entry in entries {
+        print_archive_entry(entry, &color_scheme);
+    }
+
+    Ok(())
+}
+
+/// Prints the items of one archive date section, tagging items from named lists
+fn print_archive_entry(entry: &ArchiveEntry, color_scheme: &ColorScheme) {
+    // Default list first, then named lists in a stable order
+    let mut list_names: Vec<&String> = entry.lists.keys().collect();
+    list_names.sort_by_key(|name| (name.as_str() != DEFAULT_LIST, name.as_str()));
+
+    for list_name in list_names {
+        let list_suffix = if list_name == DEFAULT_LIST {
+            String::new()
+        } else {
+            format!(" [{}]", list_name)
+        };
+        for (task_idx, task) in entry.lists[list_name].iter().enumerate() {
+            let color = color_scheme.get_main_task_color(task_idx + 1);
+            println!(
+                "{}  - {}{}{}",
+                color,
+                task.text,
+                list_suffix,
+                color::Fg(color::Reset)
+            );
+            for subtask in &task.subtasks {
+                println!("{}    - {}{}", color, subtask, color::Fg(color::Reset));
+            }
+        }
+    }
+}
+
+/// Shows the last `weeks` ISO weeks (the current one included), newest first, each with
+/// its number of archived items followed by the items themselves
+pub fn show_weekly(archive_path: &Path, weeks: u32, verbosity: Verbosity) -> Result<()> {
+    if weeks == 0 {
+        return Err(anyhow!("--weeks must be at least 1"));
+    }
+
+    let archive_file = if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+
+    report_archive_file(verbosity, archive_path, &archive_file);
+
+    let today = Local::now().date_naive();
+    let this_monday = today - chrono::Duration::days(today.weekday().num_days_from_monday().into());
+    let color_scheme = ColorScheme::new();
+    for week in 0..weeks {
+        let monday = this_monday - chrono::Duration::weeks(week.into());
+        let sunday = monday + chrono::Duration::days(6);
+        let entries: Vec<&ArchiveEntry> = archive_file
+            .entries
+            .iter()
+            .filter(|entry| {
+                NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d")
+                    .is_ok_and(|date| date >= monday && date <= sunday)
+            })
+            .collect();
+        let item_count: usize = entries
+            .iter()
+            .flat_map(|entry| entry.lists.values())
+            .map(Vec::len)
+            .sum();
+
+        if week > 0 {
+            println!();
+        }
+        let iso_week = monday.iso_week();
+        println!(
+            "{}Week {}-W{:02} ({} to {}): {}{}",
+            color::Fg(color::Green),
+            iso_week.year(),
+            iso_week.week(),
+            monday.format("%Y-%m-%d"),
+            sunday.format("%Y-%m-%d"),
+            plural(item_count, "item"),
+            color::Fg(color::Reset)
+        );
+        for entry in entries {
+            print_archive_entry(entry, &color_scheme);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a YYYY-MM-DD command-line date
+fn parse_date_arg(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date '{}': expected YYYY-MM-DD", date))
+}
+
+/// Shows archived items grouped by the day they were completed, optionally limited to
+/// the days from `since` through `until` (both inclusive)
+pub fn show_archive(
+    archive_path: &Path,
+    since: Option<&str>,
+    until: Option<&str>,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let since = since.map(parse_date_arg).transpose()?;
+    let until = until.map(parse_date_arg).transpose()?;
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err(anyhow!(
+                "--since {} is after --until {}",
+                since.format("%Y-%m-%d"),
+                until.format("%Y-%m-%d")
+            ));
+        }
+    }
+
+    let archive_file = if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+
+    report_archive_file(verbosity, archive_path, &archive_file);
+
+    // Entries with unparseable dates are only shown when there's no range
+    let entries: Vec<&ArchiveEntry> = archive_file
+        .entries
+        .iter()
+        .filter(|entry| {
+            if since.is_none() && until.is_none() {
+                return true;
+            }
+            NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d").is_ok_and(|date| {
+                since.is_none_or(|since| date >= since) && until.is_none_or(|until| date <= until)
+            })
+        })
+        .filter(|entry| entry.lists.values().any(|tasks| !tasks.is_empty()))
+        .collect();
+
+    if entries.is_empty() {
+        let period = match (since, until) {
+            (Some(since), Some(until)) => format!(
+                " between {} and {}",
+                since.format("%Y-%m-%d"),
+                until.format("%Y-%m-%d")
+            ),
+            (Some(since), None) => format!(" since {}", since.format("%Y-%m-%d")),
+            (None, Some(until)) => format!(" until {}", until.format("%Y-%m-%d")),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{}No archived items{}.{}",
+            color::Fg(color::Yellow),
+            period,
+            color::Fg(color::Reset)
+        );
+        return Ok(());
+    }
+
+    let color_scheme = ColorScheme::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        println!(
+            "{}Completed {}{}",
+            color::Fg(color::Green),
+            entry.date,
+            color::Fg(color::Reset)
+        );
+        print_archive_entry(entry, &color_scheme);
+    }
+
+    Ok(())
+}
+
+/// The reference of every item in stored order: each task, then its subtasks, each
+/// followed by its children. `ls --flat` shows item N as number N.
+fn flat_refs(tasks: &[Task]) -> Vec<String> {
+    let mut refs = Vec::new();
+    for (task_idx, task) in tasks.iter().enumerate() {
+        refs.push((task_idx + 1).to_string());
+        for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+            let subtask_ref = format!("{}{}", task_idx + 1, (b'a' + subtask_idx as u8) as char);
+            let children =
+                (1..=subtask.children.len()).map(|num| format!("{}{}", subtask_ref, num));
+            refs.push(subtask_ref.clone());
+            refs.extend(children);
+        }
+    }
+    refs
+}
+
+/// Translates `ls --flat` numbers back into regular references
+fn resolve_flat_refs(tasks: &[Task], refs: &[String]) -> Result<Vec<String>, String> {
+    let all_refs = flat_refs(tasks);
+    refs.iter()
+        .map(|number| {
+            number
+                .parse::<usize>()
+                .ok()
+                .filter(|&number| number >= 1)
+                .and_then(|number| all_refs.get(number - 1).cloned())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid flat number: {}. Valid range: 1-{}",
+                        number,
+                        all_refs.len()
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Splits arguments like "1,2" or "1 2" into separate references, so `do 1,2,3`
+/// works like `do 1 2 3`; empty fragments from stray commas are dropped. Arguments
+/// starting with `/` or `#` are kept whole, so `do "/buy milk"` looks for that text.
+pub fn split_refs(refs: &[String]) -> Vec<String> {
+    refs.iter()
+        .flat_map(|arg| {
+            let trimmed = arg.trim();
+            if trimmed.starts_with('/') || trimmed.starts_with('#') {
+                vec![trimmed]
+            } else {
+                arg.split(|c: char| c == ',' || c.is_whitespace()).collect()
+            }
+        })
+        .filter(|fragment| !fragment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Options for `prioritize_items`
+#[derive(Debug, Clone, Default)]
+pub struct PrioritizeOptions {
+    /// Only print which tasks would move, without writing anything
+    pub dry_run: bool,
+    /// Also reset the moved tasks' creation time to now (`ldr bump`)
+    pub bump: bool,
+    /// The references are `ls --flat` numbers
+    pub flat: bool,
+    /// Move the referenced subtasks to the front of their task instead of moving the task
+    pub within: bool,
+}
+
+/// Parse task references and move the referenced tasks to the top, in the order given
+/// (a subtask reference moves its parent task, unless `within` is set)
+pub fn prioritize_items(
+    todo_path: &Path,
+    refs: &[String],
+    options: &PrioritizeOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let dry_run = options.dry_run;
+    let event = if options.bump {
+        "bumped"
+    } else {
+        "prioritized"
+    };
+    let porcelain_moved = |refs: String| {
+        let mut fields = vec![("refs", refs)];
+        if dry_run {
+            fields.push(("dry_run", "true".to_string()));
+        }
+        output::porcelain(event, &fields);
+    };
+    let mut store = TodoStore::new(todo_path);
+    if !store.exists() {
+        if verbosity.is_porcelain() {
+            porcelain_moved(String::new());
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No notes found.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    let todo_file = store.get()?;
+    if todo_file.is_empty() {
+        if verbosity.is_porcelain() {
+            porcelain_moved(String::new());
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No notes found.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    // Parse task references
+    let refs = split_refs(refs);
+    let refs = if options.flat {
+        match resolve_flat_refs(&todo_file.tasks, &refs) {
+            Ok(refs) => refs,
+            Err(message) => {
+                println!(
+                    "{}{}{}",
+                    color::Fg(color::Red),
+                    message,
+                    color::Fg(color::Reset)
+                );
+                return Err(AlreadyReported.into());
+            }
+        }
+    } else {
+        refs
+    };
+    let mut task_refs = Vec::new();
+    for ref_str in &refs {
+        match TaskRef::resolve(ref_str, &todo_file.tasks) {
             Ok(task_ref) => {
                 if task_ref.task_index >= todo_file.tasks.len() {
                     println!(
@@ -433,348 +2610,3071 @@ pub fn prioritize_items(todo_path: &Path, refs: &[String]) -> Result<()> {
                         todo_file.tasks.len(),
                         color::Fg(color::Reset)
                     );
-                    return Ok(());
+                    return Err(AlreadyReported.into());
+                }
+
+                if let Some(subtask_idx) = task_ref.subtask_index {
+                    let task = &todo_file.tasks[task_ref.task_index];
+                    if subtask_idx >= task.subtasks.len() {
+                        println!(
+                            "{}Invalid subtask: {}{}. Task {} has {} subtasks{}",
+                            color::Fg(color::Red),
+                            ref_str,
+                            color::Fg(color::Reset),
+                            task_ref.task_index + 1,
+                            task.subtasks.len(),
+                            color::Fg(color::Reset)
+                        );
+                        return Err(AlreadyReported.into());
+                    }
+                }
+
+                task_refs.push(task_ref);
+            }
+            Err(e) => {
+                println!(
+                    "{}Invalid task reference '{}': {}{}",
+                    color::Fg(color::Red),
+                    ref_str,
+                    e,
+                    color::Fg(color::Reset)
+                );
+                return Err(AlreadyReported.into());
+            }
+        }
+    }
+
+    if options.within {
+        return prioritize_within(&mut store, todo_path, &refs, &task_refs, dry_run, verbosity);
+    }
+
+    // For prioritizing, we move entire tasks to the top (subtask refs move their parent task)
+    let mut tasks_to_move = Vec::new();
+    let mut moved_task_indices = HashSet::new();
+
+    for task_ref in &task_refs {
+        if !moved_task_indices.contains(&task_ref.task_index) {
+            tasks_to_move.push(task_ref.task_index);
+            moved_task_indices.insert(task_ref.task_index);
+        }
+    }
+
+    // Create new task order by swapping moved tasks to front
+    let old_tasks = std::mem::take(&mut todo_file.tasks);
+    let mut new_tasks = Vec::with_capacity(old_tasks.len());
+    let mut moved_task_names = Vec::new();
+
+    // First add the moved tasks in the order specified
+    let now = Local::now().naive_local();
+    for &task_idx in &tasks_to_move {
+        if task_idx < old_tasks.len() {
+            let mut task = old_tasks[task_idx].clone();
+            if options.bump {
+                task.created = Some(now);
+            }
+            moved_task_names.push(task.text.clone());
+            new_tasks.push(task);
+        }
+    }
+
+    // Then add all non-moved tasks
+    for (idx, task) in old_tasks.into_iter().enumerate() {
+        if !moved_task_indices.contains(&idx) {
+            new_tasks.push(task);
+        }
+    }
+
+    todo_file.tasks = new_tasks;
+
+    if !dry_run {
+        store.save()?;
+        report_todo_file(verbosity, todo_path, store.get()?);
+    }
+
+    if verbosity.is_porcelain() {
+        let refs: Vec<String> = tasks_to_move
+            .iter()
+            .map(|idx| (idx + 1).to_string())
+            .collect();
+        porcelain_moved(refs.join(","));
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
+
+    let (verb, done) = if options.bump {
+        ("bump", "Bumped")
+    } else {
+        ("prioritize", "Prioritized")
+    };
+    if dry_run {
+        println!(
+            "{}Would {} {} task(s):{}",
+            color::Fg(color::Yellow),
+            verb,
+            moved_task_names.len(),
+            color::Fg(color::Reset)
+        );
+    } else {
+        println!(
+            "{}✓ {} {} task(s){}",
+            color::Fg(color::Green),
+            done,
+            moved_task_names.len(),
+            color::Fg(color::Reset)
+        );
+    }
+
+    for task_name in moved_task_names {
+        println!(
+            "  {}{}{}",
+            color::Fg(color::Magenta),
+            task_name,
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// `up --within`: moves the referenced subtasks to the front of their task's subtasks,
+/// in the order given, leaving the tasks themselves where they are
+fn prioritize_within(
+    store: &mut TodoStore,
+    todo_path: &Path,
+    refs: &[String],
+    task_refs: &[TaskRef],
+    dry_run: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    if let Some(idx) = task_refs.iter().position(|r| r.subtask_index.is_none()) {
+        println!(
+            "{}--within reorders subtasks, but '{}' is a task{}",
+            color::Fg(color::Red),
+            refs[idx],
+            color::Fg(color::Reset)
+        );
+        return Err(AlreadyReported.into());
+    }
+
+    // (task, subtask) pairs in the order given; a child reference moves its subtask
+    let mut moves: Vec<(usize, usize)> = Vec::new();
+    for task_ref in task_refs {
+        let subtask_idx = task_ref.subtask_index.expect("checked above");
+        if !moves.contains(&(task_ref.task_index, subtask_idx)) {
+            moves.push((task_ref.task_index, subtask_idx));
+        }
+    }
+
+    let todo_file = store.get()?;
+    let subtask_ref = |task_idx: usize, subtask_idx: usize| {
+        format!("{}{}", task_idx + 1, (b'a' + subtask_idx as u8) as char)
+    };
+    // The reference each subtask had, the one it moves to (its rank among the subtasks
+    // chosen in its task) and its text
+    let moved: Vec<(String, String, String)> = moves
+        .iter()
+        .enumerate()
+        .map(|(move_idx, &(task_idx, subtask_idx))| {
+            let position = moves[..move_idx]
+                .iter()
+                .filter(|(idx, _)| *idx == task_idx)
+                .count();
+            let text = todo_file.tasks[task_idx].subtasks[subtask_idx].text.clone();
+            (
+                subtask_ref(task_idx, subtask_idx),
+                subtask_ref(task_idx, position),
+                text,
+            )
+        })
+        .collect();
+    for task_idx in 0..todo_file.tasks.len() {
+        let chosen: Vec<usize> = moves
+            .iter()
+            .filter(|(idx, _)| *idx == task_idx)
+            .map(|(_, subtask_idx)| *subtask_idx)
+            .collect();
+        if chosen.is_empty() {
+            continue;
+        }
+        let task = &mut todo_file.tasks[task_idx];
+        let old_subtasks = std::mem::take(&mut task.subtasks);
+        task.subtasks = chosen
+            .iter()
+            .map(|&idx| old_subtasks[idx].clone())
+            .collect();
+        task.subtasks.extend(
+            old_subtasks
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| !chosen.contains(idx))
+                .map(|(_, subtask)| subtask),
+        );
+    }
+
+    if !dry_run {
+        store.save()?;
+        report_todo_file(verbosity, todo_path, store.get()?);
+    }
+
+    if verbosity.is_porcelain() {
+        let refs: Vec<&str> = moved
+            .iter()
+            .map(|(reference, _, _)| reference.as_str())
+            .collect();
+        let mut fields = vec![("refs", refs.join(","))];
+        if dry_run {
+            fields.push(("dry_run", "true".to_string()));
+        }
+        output::porcelain("prioritized", &fields);
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
+
+    if dry_run {
+        println!(
+            "{}Would prioritize {} subtask(s):{}",
+            color::Fg(color::Yellow),
+            moved.len(),
+            color::Fg(color::Reset)
+        );
+    } else {
+        println!(
+            "{}✓ Prioritized {} subtask(s) within their task{}",
+            color::Fg(color::Green),
+            moved.len(),
+            color::Fg(color::Reset)
+        );
+    }
+    for (_, new_reference, text) in moved {
+        println!(
+            "  {}{}. {}{}",
+            color::Fg(color::Magenta),
+            new_reference,
+            text,
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that a parsed reference points at an existing task, subtask or child.
+/// The error is the message shown to the user, quoting the reference as typed.
+fn check_ref_in_range(tasks: &[Task], ref_str: &str, task_ref: &TaskRef) -> Result<(), String> {
+    let Some(task) = tasks.get(task_ref.task_index) else {
+        return Err(format!(
+            "Invalid task number in '{}': {}. Valid range: 1-{}",
+            ref_str,
+            task_ref.task_index + 1,
+            tasks.len()
+        ));
+    };
+
+    if let Some(subtask_idx) = task_ref.subtask_index {
+        let Some(subtask) = task.subtasks.get(subtask_idx) else {
+            return Err(format!(
+                "Invalid subtask '{}': Task {} has {} subtasks",
+                ref_str,
+                task_ref.task_index + 1,
+                task.subtasks.len()
+            ));
+        };
+        if let Some(child_idx) = task_ref.child_index {
+            if child_idx >= subtask.children.len() {
+                return Err(format!(
+                    "Invalid child '{}': Subtask has {} children",
+                    ref_str,
+                    subtask.children.len()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Options for `archive_items` and `remove_items`
+#[derive(Debug, Clone, Default)]
+pub struct RemoveOptions {
+    /// Don't ask before removing (required in non-interactive mode; archiving never asks)
+    pub assume_yes: bool,
+    /// Keep a task as a plain item when its last subtask goes, instead of completing it
+    /// (archiving) or, without an archive, removing it
+    pub keep_empty_parents: bool,
+    /// Only print what would change, without writing anything
+    pub dry_run: bool,
+    /// Take the items from this named list instead of the Default one
+    pub list: Option<String>,
+    /// The references are `ls --flat` numbers
+    pub flat: bool,
+    /// Completion note archived as a `note:` subtask under each whole task
+    pub note: Option<String>,
+    /// Put a fresh copy of each archived recurring task back at the top (set by `do`)
+    pub renew_recurring: bool,
+    /// The references are tasks whose subtasks are all taken, leaving the tasks in place
+    /// (combine with `keep_empty_parents`)
+    pub all_subtasks: bool,
+    /// Archive under this day instead of today (set by `do --on`)
+    pub archive_date: Option<NaiveDate>,
+    /// Also append each archived item as a JSON line to this file (set by `do` when the
+    /// `completion_log` setting is on)
+    pub completion_log: Option<PathBuf>,
+}
+
+/// Appends one JSON object per archived task to the completion log, never touching the
+/// lines already there, so other tools can tail it
+fn append_completion_log(
+    log_path: &Path,
+    archive_date: NaiveDate,
+    list_name: &str,
+    tasks: &[Task],
+) -> Result<()> {
+    use std::io::Write;
+
+    let timestamp = Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string();
+    let mut lines = String::new();
+    for task in tasks {
+        let entry = json!({
+            "timestamp": timestamp,
+            "date": archive_date.format("%Y-%m-%d").to_string(),
+            "list": list_name,
+            "text": task.text,
+            "subtasks": task.subtasks.iter().map(|subtask| &subtask.text).collect::<Vec<_>>(),
+        });
+        lines.push_str(&entry.to_string());
+        lines.push('\n');
+    }
+    // A single write keeps the lines of one `do` together
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .with_context(|| format!("Failed to append to completion log: {}", log_path.display()))
+}
+
+/// Internal helper to process items for removal or archiving.
+/// Archived items are filed under the name of the list they came from.
+/// A task whose last subtask goes is completed along with it, unless `keep_empty_parents`
+/// is set, in which case it stays as a plain task. Without an archive such a parent is
+/// simply removed too, never archived.
+fn process_items_for_removal(
+    todo_path: &Path,
+    refs: &[String],
+    archive_path: Option<&Path>,
+    options: &RemoveOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let should_archive = archive_path.is_some();
+    let archive_date = options
+        .archive_date
+        .unwrap_or_else(|| Local::now().date_naive());
+    let mut store = TodoStore::new(todo_path);
+    if !store.exists() {
+        if verbosity.is_porcelain() {
+            porcelain_removal(should_archive, 0, options.dry_run);
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No notes found.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+    let todo_file = store.get()?;
+
+    // Work on the requested list, archiving under its name as written in the file
+    let list_name = match options.list.as_deref().map(str::trim) {
+        None => DEFAULT_LIST.to_string(),
+        Some(name) if TodoFile::is_default_list(name) => DEFAULT_LIST.to_string(),
+        Some(name) => todo_file
+            .lists
+            .iter()
+            .find(|list| list.name.eq_ignore_ascii_case(name))
+            .map(|list| list.name.clone())
+            .ok_or_else(|| anyhow!("No such list '{}'", name))?,
+    };
+    let tasks = todo_file
+        .list_mut(&list_name)
+        .expect("list name was resolved above");
+
+    if tasks.is_empty() {
+        if verbosity.is_porcelain() {
+            porcelain_removal(should_archive, 0, options.dry_run);
+        }
+        if !verbosity.is_quiet() {
+            let action = if should_archive { "archive" } else { "remove" };
+            println!(
+                "{}No notes to {}.{}",
+                color::Fg(color::Yellow),
+                action,
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    // Parse task references
+    let refs = split_refs(refs);
+    let refs = if options.flat {
+        match resolve_flat_refs(tasks, &refs) {
+            Ok(refs) => refs,
+            Err(message) => {
+                println!(
+                    "{}{}{}",
+                    color::Fg(color::Red),
+                    message,
+                    color::Fg(color::Reset)
+                );
+                return Err(AlreadyReported.into());
+            }
+        }
+    } else {
+        refs
+    };
+    let mut task_refs = Vec::new();
+    for ref_str in &refs {
+        match TaskRef::resolve(ref_str, tasks) {
+            Ok(task_ref) => task_refs.push((ref_str.clone(), task_ref)),
+            Err(e) => {
+                println!(
+                    "{}Invalid task reference '{}': {}{}",
+                    color::Fg(color::Red),
+                    ref_str,
+                    e,
+                    color::Fg(color::Reset)
+                );
+                return Err(AlreadyReported.into());
+            }
+        }
+    }
+
+    // Separate tasks and subtasks to archive
+    let mut tasks_to_archive = Vec::new();
+    let mut subtasks_to_remove = Vec::new(); // (task_idx, subtask_idx)
+    let mut children_to_remove = Vec::new(); // (task_idx, subtask_idx, child_idx)
+    let mut whole_tasks_to_remove = HashSet::new();
+
+    for (ref_str, task_ref) in &task_refs {
+        if let Err(message) = check_ref_in_range(tasks, ref_str, task_ref) {
+            println!(
+                "{}{}{}",
+                color::Fg(color::Red),
+                message,
+                color::Fg(color::Reset)
+            );
+            return Err(AlreadyReported.into());
+        }
+
+        if options.all_subtasks {
+            if task_ref.subtask_index.is_some() || tasks[task_ref.task_index].subtasks.is_empty() {
+                println!(
+                    "{}{} takes tasks that have subtasks, but '{}' {}{}",
+                    color::Fg(color::Red),
+                    if archive_path.is_some() {
+                        "--all-subtasks"
+                    } else {
+                        "--subtasks"
+                    },
+                    ref_str,
+                    if task_ref.subtask_index.is_some() {
+                        "is a subtask"
+                    } else {
+                        "has none"
+                    },
+                    color::Fg(color::Reset)
+                );
+                return Err(AlreadyReported.into());
+            }
+            for subtask_idx in 0..tasks[task_ref.task_index].subtasks.len() {
+                if !subtasks_to_remove.contains(&(task_ref.task_index, subtask_idx)) {
+                    subtasks_to_remove.push((task_ref.task_index, subtask_idx));
+                }
+            }
+            continue;
+        }
+
+        match (task_ref.subtask_index, task_ref.child_index) {
+            // Archiving a nested child of the subtask
+            (Some(subtask_idx), Some(child_idx)) => {
+                children_to_remove.push((task_ref.task_index, subtask_idx, child_idx))
+            }
+            // Archiving a subtask
+            (Some(subtask_idx), None) => {
+                subtasks_to_remove.push((task_ref.task_index, subtask_idx))
+            }
+            // Archiving whole task
+            _ => {
+                whole_tasks_to_remove.insert(task_ref.task_index);
+            }
+        }
+    }
+
+    // A note describes how a task was finished, so it only goes with whole tasks
+    let note = options.note.as_deref().map(str::trim);
+    if note.is_some() && whole_tasks_to_remove.is_empty() {
+        println!(
+            "{}A note can only be added when archiving whole tasks, not subtasks{}",
+            color::Fg(color::Red),
+            color::Fg(color::Reset)
+        );
+        return Err(AlreadyReported.into());
+    }
+
+    // Collect items to archive
+    for &task_idx in &whole_tasks_to_remove {
+        let mut task = tasks[task_idx].clone();
+        if let Some(note) = note {
+            task.add_subtask(format!("note: {}", note));
+        }
+        tasks_to_archive.push(task);
+    }
+
+    for &(task_idx, subtask_idx) in &subtasks_to_remove {
+        if !whole_tasks_to_remove.contains(&task_idx) {
+            let subtask = &tasks[task_idx].subtasks[subtask_idx];
+            tasks_to_archive.push(Task::with_subtasks(
+                subtask.text.clone(),
+                subtask.children.clone(),
+            ));
+        }
+    }
+
+    // Children are only archived on their own when their subtask isn't going too
+    children_to_remove.sort();
+    children_to_remove.dedup();
+    children_to_remove.retain(|&(task_idx, subtask_idx, _)| {
+        !whole_tasks_to_remove.contains(&task_idx)
+            && !subtasks_to_remove.contains(&(task_idx, subtask_idx))
+    });
+    for &(task_idx, subtask_idx, child_idx) in &children_to_remove {
+        let child = &tasks[task_idx].subtasks[subtask_idx].children[child_idx];
+        tasks_to_archive.push(Task::new(child.clone()));
+    }
+
+    // Removal without archiving can't be undone, so show what would go and ask first
+    if !should_archive && !options.dry_run {
+        let assume_yes = options.assume_yes;
+        if !assume_yes && is_interactive() {
+            println!(
+                "{}About to remove:{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+            for task in &tasks_to_archive {
+                println!(
+                    "  {}{}{}",
+                    color::Fg(color::Red),
+                    task.text,
+                    color::Fg(color::Reset)
+                );
+            }
+        }
+
+        let prompt = format!("Remove {} item(s)?", tasks_to_archive.len());
+        if !confirm(&prompt, assume_yes)? {
+            return Err(anyhow!("Aborted; nothing was removed"));
+        }
+    }
+
+    // Load archive file if we're archiving. Lines that can't be read don't block
+    // archiving, but the file is copied aside before it's rewritten without them.
+    let mut damaged_archive = None;
+    let mut archive_file = match archive_path {
+        Some(archive_path) if archive_path.exists() => {
+            let archive_content = fs::read_to_string(archive_path)
+                .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+            let (archive_file, warnings) = parse_archive_file_with_warnings(&archive_content);
+            for warning in &warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            if !warnings.is_empty() {
+                damaged_archive = Some(archive_content);
+            }
+            archive_file
+        }
+        _ => ArchiveFile::new(),
+    };
+
+    // Add items to archive if we're archiving
+    if should_archive && !tasks_to_archive.is_empty() {
+        archive_file.add_items_for_date(archive_date, &list_name, tasks_to_archive.clone());
+    }
+
+    // Remove items from todo file
+    // Remove nested children first (in reverse order to maintain indices)
+    for &(task_idx, subtask_idx, child_idx) in children_to_remove.iter().rev() {
+        tasks[task_idx].subtasks[subtask_idx]
+            .children
+            .remove(child_idx);
+    }
+
+    // Then remove subtasks (in reverse order to maintain indices)
+    let mut subtasks_by_task: std::collections::HashMap<usize, Vec<usize>> =
+        std::collections::HashMap::new();
+    for &(task_idx, subtask_idx) in &subtasks_to_remove {
+        if !whole_tasks_to_remove.contains(&task_idx) {
+            subtasks_by_task
+                .entry(task_idx)
+                .or_default()
+                .push(subtask_idx);
+        }
+    }
+
+    // Track tasks that might need auto-completion
+    let mut tasks_to_auto_complete = Vec::new();
+
+    for (task_idx, mut subtask_indices) in subtasks_by_task {
+        subtask_indices.sort_by(|a, b| b.cmp(a)); // Sort in reverse order
+        for subtask_idx in subtask_indices {
+            tasks[task_idx].subtasks.remove(subtask_idx);
+        }
+
+        // Check if this task now has no subtasks left and should be auto-completed,
+        // unless it should stay behind as a plain task
+        if !options.keep_empty_parents && tasks[task_idx].subtasks.is_empty() {
+            tasks_to_auto_complete.push(task_idx);
+        }
+    }
+
+    // Auto-complete parent tasks that have no subtasks left
+    let mut auto_completed_tasks = Vec::new();
+    if !tasks_to_auto_complete.is_empty() {
+        for &task_idx in &tasks_to_auto_complete {
+            auto_completed_tasks.push(tasks[task_idx].clone());
+        }
+
+        // Add auto-completed tasks to archive if we're archiving
+        if should_archive && !auto_completed_tasks.is_empty() {
+            archive_file.add_items_for_date(archive_date, &list_name, auto_completed_tasks.clone());
+        }
+    }
+
+    // Remove whole tasks (in reverse order) - include auto-completed tasks
+    let mut whole_task_indices: Vec<_> = whole_tasks_to_remove.into_iter().collect();
+    whole_task_indices.extend(tasks_to_auto_complete);
+    whole_task_indices.sort_by(|a, b| b.cmp(a));
+    whole_task_indices.dedup(); // Remove duplicates in case a task was both manually selected and auto-completed
+
+    // Finished recurring tasks come back with their next due date, in their current order,
+    // as fresh items: checked subtasks are unchecked and an ID is handed out below
+    let now = Local::now().naive_local();
+    let renewed: Vec<Task> = if should_archive && options.renew_recurring {
+        whole_task_indices
+            .iter()
+            .rev()
+            .map(|&task_idx| &tasks[task_idx])
+            .filter_map(|task| {
+                let recur = task.recur?;
+                let subtasks = task
+                    .subtasks
+                    .iter()
+                    .map(|subtask| Subtask {
+                        completed: subtask.completed.map(|_| false),
+                        ..subtask.clone()
+                    })
+                    .collect();
+                Some(Task {
+                    due: Some(recur.next_due(task.due, now.date())),
+                    created: Some(now),
+                    id: None,
+                    subtasks,
+                    ..task.clone()
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let retired_ids: HashSet<String> = whole_task_indices
+        .iter()
+        .filter_map(|&task_idx| tasks[task_idx].id.clone())
+        .collect();
+
+    for task_idx in whole_task_indices {
+        tasks.remove(task_idx);
+    }
+    for task in renewed.iter().rev() {
+        tasks.insert(0, task.clone());
+    }
+    // Never the ID of the archived original, so `#id` can't mix the two up
+    for task_idx in 0..renewed.len() {
+        let id = loop {
+            let id = todo_file.new_task_id();
+            if !retired_ids.contains(&id) {
+                break id;
+            }
+        };
+        todo_file
+            .list_mut(&list_name)
+            .expect("list name was resolved above")[task_idx]
+            .id = Some(id);
+    }
+
+    // Save the archive and the updated todo file
+    let total_processed = tasks_to_archive.len() + auto_completed_tasks.len();
+    if !options.dry_run {
+        if let Some(archive_path) = archive_path {
+            if total_processed > 0 {
+                if let Some(damaged_archive) = &damaged_archive {
+                    let backup_path = corrupt_backup_path(archive_path);
+                    fs::write(&backup_path, damaged_archive).with_context(|| {
+                        format!("Failed to back up archive: {}", backup_path.display())
+                    })?;
+                    eprintln!(
+                        "Warning: skipped lines are dropped from the archive; the original was saved to {}",
+                        backup_path.display()
+                    );
+                }
+                let archive_content = generate_archive_file(&archive_file);
+                fs::write(archive_path, archive_content).with_context(|| {
+                    format!("Failed to write archive: {}", archive_path.display())
+                })?;
+                if let Some(log_path) = &options.completion_log {
+                    let archived: Vec<Task> = tasks_to_archive
+                        .iter()
+                        .chain(&auto_completed_tasks)
+                        .cloned()
+                        .collect();
+                    append_completion_log(log_path, archive_date, &list_name, &archived)?;
+                }
+            }
+        }
+
+        store.save()?;
+        report_todo_file(verbosity, todo_path, store.get()?);
+        if let Some(archive_path) = archive_path {
+            report_archive_file(verbosity, archive_path, &archive_file);
+        }
+        if should_archive && total_processed > 0 {
+            let texts: Vec<&str> = tasks_to_archive
+                .iter()
+                .chain(&auto_completed_tasks)
+                .map(|task| task.text.as_str())
+                .collect();
+            notify::send(&match texts.as_slice() {
+                [text] => format!("Completed: {}", text),
+                _ => format!("Completed {} items: {}", texts.len(), texts.join(", ")),
+            });
+        }
+    }
+
+    if verbosity.is_porcelain() {
+        porcelain_removal(should_archive, total_processed, options.dry_run);
+        for (idx, task) in renewed.iter().enumerate() {
+            let due = task.due.map(|due| due.to_string()).unwrap_or_default();
+            output::porcelain(
+                "renewed",
+                &[
+                    ("ref", (idx + 1).to_string()),
+                    ("due", due),
+                    ("text", output::quote(&task.text)),
+                ],
+            );
+        }
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
+
+    let noun = if options.all_subtasks {
+        "subtask(s)"
+    } else {
+        "item(s)"
+    };
+    if options.dry_run {
+        let action = if should_archive { "archive" } else { "remove" };
+        println!(
+            "{}Would {} {} {}:{}",
+            color::Fg(color::Yellow),
+            action,
+            total_processed,
+            noun,
+            color::Fg(color::Reset)
+        );
+    } else {
+        let action_verb = if should_archive {
+            "Archived"
+        } else {
+            "Removed"
+        };
+        let under = match options.archive_date {
+            Some(date) if should_archive => format!(" under {}", date.format("%Y-%m-%d")),
+            _ => String::new(),
+        };
+        println!(
+            "{}✓ {} {} {}{}{}",
+            color::Fg(color::Green),
+            action_verb,
+            total_processed,
+            noun,
+            under,
+            color::Fg(color::Reset)
+        );
+    }
+
+    for task in tasks_to_archive {
+        println!(
+            "  {}{}{}",
+            color::Fg(color::Red),
+            task.text,
+            color::Fg(color::Reset)
+        );
+    }
+
+    // Show auto-completed tasks; without an archive they are only removed
+    if !auto_completed_tasks.is_empty() {
+        let note = match (should_archive, options.dry_run) {
+            (true, true) => "would be auto-completed - all subtasks done",
+            (true, false) => "auto-completed - all subtasks done",
+            (false, true) => "would be removed too - no subtasks left",
+            (false, false) => "removed too - no subtasks left",
+        };
+        for task in auto_completed_tasks {
+            println!(
+                "  {}{} ({}){}",
+                color::Fg(color::Magenta),
+                task.text,
+                note,
+                color::Fg(color::Reset)
+            );
+        }
+    }
+
+    let renew_note = if options.dry_run {
+        "would come back"
+    } else {
+        "is back"
+    };
+    for (idx, task) in renewed.iter().enumerate() {
+        println!(
+            "  {}↻ {} {} as {}, due {}{}",
+            color::Fg(color::Green),
+            task.text,
+            renew_note,
+            idx + 1,
+            task.due.map(|due| due.to_string()).unwrap_or_default(),
+            color::Fg(color::Reset)
+        );
+    }
+
+    // The archive lives in a hidden XDG directory, so say where finished items went
+    if let Some(archive_path) = archive_path {
+        if total_processed > 0 && !options.dry_run {
+            println!("archived to {}", archive_path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// The `--porcelain` line for `do`, `rm` and `clear`, e.g. `archived count=3`
+fn porcelain_removal(archived: bool, count: usize, dry_run: bool) {
+    let event = if archived { "archived" } else { "removed" };
+    let mut fields = vec![("count", count.to_string())];
+    if dry_run {
+        fields.push(("dry_run", "true".to_string()));
+    }
+    output::porcelain(event, &fields);
+}
+
+/// Where a copy of an archive with unreadable lines is kept, e.g. `archive.md.corrupt`
+fn corrupt_backup_path(archive_path: &Path) -> PathBuf {
+    let mut name = archive_path.as_os_str().to_owned();
+    name.push(".corrupt");
+    PathBuf::from(name)
+}
+
+/// Archive specified tasks or subtasks
+pub fn archive_items(
+    todo_path: &Path,
+    archive_path: &Path,
+    refs: &[String],
+    options: &RemoveOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let options = RemoveOptions {
+        renew_recurring: true,
+        ..options.clone()
+    };
+    process_items_for_removal(todo_path, refs, Some(archive_path), &options, verbosity)
+}
+
+/// Reads the `do --on` date, which has to be YYYY-MM-DD and not in the future (unless
+/// `force`), as finished work can't be logged ahead of time
+pub fn parse_archive_date(date: &str, force: bool) -> Result<NaiveDate> {
+    let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date '{}': expected YYYY-MM-DD", date))?;
+    if parsed > Local::now().date_naive() && !force {
+        return Err(anyhow!(
+            "{} is in the future; pass --force to archive under it anyway",
+            date
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Remove items without archiving.
+/// Lists the items and asks for confirmation unless `assume_yes` is set;
+/// in non-interactive mode `assume_yes` is required.
+pub fn remove_items(
+    todo_path: &Path,
+    refs: &[String],
+    options: &RemoveOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    process_items_for_removal(todo_path, refs, None, options, verbosity)
+}
+
+/// Archives every open task of a list at once (or removes them when `archive_path` is
+/// None), subtasks included. Always asks first unless `assume_yes` is set.
+pub fn clear_items(
+    todo_path: &Path,
+    archive_path: Option<&Path>,
+    options: &RemoveOptions,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let mut store = TodoStore::new(todo_path);
+    let list_name = options.list.as_deref().unwrap_or(DEFAULT_LIST);
+    let task_count = store
+        .get()?
+        .list(list_name)
+        .ok_or_else(|| anyhow!("No such list '{}'", list_name.trim()))?
+        .len();
+
+    if task_count == 0 {
+        if verbosity.is_porcelain() {
+            porcelain_removal(archive_path.is_some(), 0, options.dry_run);
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No notes to clear.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    let action = if archive_path.is_some() {
+        "Archive"
+    } else {
+        "Remove"
+    };
+    let prompt = format!("{} all {} open task(s)?", action, task_count);
+    if !confirm(&prompt, options.assume_yes)? {
+        return Err(anyhow!("Aborted; nothing was cleared"));
+    }
+
+    // Already confirmed above, so removal doesn't ask again
+    let refs: Vec<String> = (1..=task_count).map(|num| num.to_string()).collect();
+    let options = RemoveOptions {
+        assume_yes: true,
+        flat: false,
+        ..options.clone()
+    };
+    process_items_for_removal(todo_path, &refs, archive_path, &options, verbosity)
+}
+
+/// Renders a horizontal bar scaled so that `max` fills `width` cells
+fn render_bar(count: usize, max: usize, width: usize) -> String {
+    if max == 0 || count == 0 {
+        return String::new();
+    }
+    let cells = (count * width).div_ceil(max);
+    "█".repeat(cells)
+}
+
+/// Counts of active tasks bucketed by how long ago they were created
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AgeHistogram {
+    pub today: usize,
+    pub this_week: usize,
+    pub this_month: usize,
+    pub older: usize,
+    /// Tasks without a creation timestamp
+    pub unknown: usize,
+}
+
+impl AgeHistogram {
+    /// Buckets top-level tasks by age relative to `today`
+    pub fn from_tasks(tasks: &[Task], today: NaiveDate) -> Self {
+        let mut histogram = AgeHistogram::default();
+        for task in tasks {
+            let Some(created) = task.created else {
+                histogram.unknown += 1;
+                continue;
+            };
+            match today.signed_duration_since(created.date()).num_days() {
+                ..=0 => histogram.today += 1,
+                1..=6 => histogram.this_week += 1,
+                7..=29 => histogram.this_month += 1,
+                _ => histogram.older += 1,
+            }
+        }
+        histogram
+    }
+
+    fn rows(&self) -> [(&'static str, usize); 5] {
+        [
+            ("Today", self.today),
+            ("This week", self.this_week),
+            ("This month", self.this_month),
+            ("Older", self.older),
+            ("Unknown", self.unknown),
+        ]
+    }
+}
+
+/// Counts shown by `ldr stats`, computed once for both the text and the JSON output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Top-level tasks in the Default list
+    pub open_tasks: usize,
+    pub subtasks: usize,
+    /// Archived tasks and subtasks
+    pub archived_total: usize,
+    pub archived_today: usize,
+    /// Archived in the last seven days, today included
+    pub archived_week: usize,
+    pub age: AgeHistogram,
+    pub streak: Streak,
+}
+
+/// Runs of consecutive days with at least one archived item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Streak {
+    /// Days in the run ending today, or ending yesterday while today has nothing yet
+    pub current: usize,
+    /// Days in the longest run so far
+    pub longest: usize,
+    /// True when the current run ends yesterday, so it's kept alive by finishing
+    /// something today
+    pub pending_today: bool,
+}
+
+impl Streak {
+    /// Walks the archive's dates in order. Days after `today` (archived with `do --on
+    /// --force`) and entries without a parseable date or items don't count.
+    pub fn compute(archive_file: &ArchiveFile, today: NaiveDate) -> Self {
+        let days: std::collections::BTreeSet<NaiveDate> = archive_file
+            .entries
+            .iter()
+            .filter(|entry| entry.lists.values().any(|tasks| !tasks.is_empty()))
+            .filter_map(|entry| NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d").ok())
+            .filter(|date| *date <= today)
+            .collect();
+
+        let mut streak = Streak::default();
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for &day in &days {
+            run = match previous {
+                Some(previous) if previous.succ_opt() == Some(day) => run + 1,
+                _ => 1,
+            };
+            streak.longest = streak.longest.max(run);
+            previous = Some(day);
+        }
+
+        // The last run is the current one if it reaches today or yesterday
+        if let Some(last) = previous {
+            let days_ago = today.signed_duration_since(last).num_days();
+            if days_ago <= 1 {
+                streak.current = run;
+                streak.pending_today = days_ago == 1;
+            }
+        }
+        streak
+    }
+}
+
+impl Stats {
+    pub fn compute(todo_file: &TodoFile, archive_file: &ArchiveFile, today: NaiveDate) -> Self {
+        let count_items =
+            |tasks: &Vec<Task>| tasks.iter().map(|t| 1 + t.subtasks.len()).sum::<usize>();
+        // Days since each entry's date; entries with unparseable dates only count in the total
+        let archived_within = |max_days: Option<i64>| -> usize {
+            archive_file
+                .entries
+                .iter()
+                .filter(|entry| {
+                    let Some(max_days) = max_days else {
+                        return true;
+                    };
+                    NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d").is_ok_and(|date| {
+                        (0..=max_days).contains(&today.signed_duration_since(date).num_days())
+                    })
+                })
+                .flat_map(|entry| entry.lists.values())
+                .map(count_items)
+                .sum()
+        };
+
+        Stats {
+            open_tasks: todo_file.task_count(),
+            subtasks: todo_file.tasks.iter().map(|t| t.subtasks.len()).sum(),
+            archived_total: archived_within(None),
+            archived_today: archived_within(Some(0)),
+            archived_week: archived_within(Some(6)),
+            age: AgeHistogram::from_tasks(&todo_file.tasks, today),
+            streak: Streak::compute(archive_file, today),
+        }
+    }
+
+    /// The counts as JSON; the streak is only included when asked for
+    fn to_json(&self, streak: bool) -> Value {
+        let mut value = json!({
+            "open_tasks": self.open_tasks,
+            "subtasks": self.subtasks,
+            "archived_total": self.archived_total,
+            "archived_today": self.archived_today,
+            "archived_week": self.archived_week,
+            "active_age": {
+                "today": self.age.today,
+                "this_week": self.age.this_week,
+                "this_month": self.age.this_month,
+                "older": self.age.older,
+                "unknown": self.age.unknown,
+            },
+        });
+        if streak {
+            value["streak"] = json!({
+                "current": self.streak.current,
+                "longest": self.streak.longest,
+            });
+        }
+        value
+    }
+}
+
+/// Shows summary statistics for open and archived items, as text or as a JSON object.
+/// With `streak`, also shows the current and longest runs of days with completions.
+pub fn show_stats(
+    todo_path: &Path,
+    archive_path: &Path,
+    age_histogram: bool,
+    streak: bool,
+    json: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let todo_file = if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        TodoFile::new("TODOs".to_string())
+    };
+
+    let archive_file = if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+
+    let stats = Stats::compute(&todo_file, &archive_file, Local::now().date_naive());
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats.to_json(streak))?);
+        return Ok(());
+    }
+    report_todo_file(verbosity, todo_path, &todo_file);
+    report_archive_file(verbosity, archive_path, &archive_file);
+
+    println!(
+        "{}Open tasks: {} ({} subtasks){}",
+        color::Fg(color::Green),
+        stats.open_tasks,
+        stats.subtasks,
+        color::Fg(color::Reset)
+    );
+    println!(
+        "{}Archived:   {} ({} today){}",
+        color::Fg(color::Magenta),
+        stats.archived_total,
+        stats.archived_today,
+        color::Fg(color::Reset)
+    );
+    if streak {
+        let hint = if stats.streak.pending_today {
+            " Finish something today to keep it going!"
+        } else {
+            ""
+        };
+        println!(
+            "{}Current streak: {}, longest: {}.{}{}",
+            color::Fg(color::Yellow),
+            plural(stats.streak.current, "day"),
+            plural(stats.streak.longest, "day"),
+            hint,
+            color::Fg(color::Reset)
+        );
+    }
+
+    if age_histogram {
+        let rows = stats.age.rows();
+        let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let color_scheme = ColorScheme::new();
+
+        println!();
+        println!("Active task age:");
+        for (idx, (label, count)) in rows.iter().enumerate() {
+            println!(
+                "  {:<10} {:>4} {}{}{}",
+                label,
+                count,
+                color_scheme.get_main_task_color(idx + 1),
+                render_bar(*count, max, 30),
+                color::Fg(color::Reset)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// A tag with the references of every item that carries it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagSummary {
+    /// Lowercased tag including its `@`, e.g. "@work"
+    pub tag: String,
+    /// Items tagged with it, e.g. ["1", "3b"]
+    pub refs: Vec<String>,
+}
+
+/// Extracts the `@tags` from item text: words starting with `@` followed by a letter or
+/// digit, lowercased, with trailing punctuation like "," or ")" dropped
+fn extract_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = text
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_end_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|name| name.starts_with(|c: char| c.is_alphanumeric()))
+        .map(|name| format!("@{}", name.to_lowercase()))
+        .collect();
+    tags.dedup();
+    tags
+}
+
+/// Collects tags from tasks, subtasks and nested children, keyed by the most specific
+/// reference so a tag on a subtask points at `3b` rather than its parent.
+/// Sorted by how many items use the tag, then alphabetically.
+pub fn collect_tags(todo_file: &TodoFile) -> Vec<TagSummary> {
+    let mut summaries: Vec<TagSummary> = Vec::new();
+    let mut record = |text: &str, reference: String| {
+        for tag in extract_tags(text) {
+            match summaries.iter_mut().find(|summary| summary.tag == tag) {
+                Some(summary) if summary.refs.last() == Some(&reference) => {}
+                Some(summary) => summary.refs.push(reference.clone()),
+                None => summaries.push(TagSummary {
+                    tag,
+                    refs: vec![reference.clone()],
+                }),
+            }
+        }
+    };
+
+    for (task_idx, task) in todo_file.tasks.iter().enumerate() {
+        let task_num = task_idx + 1;
+        record(&task.text, task_num.to_string());
+        for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+            let letter = (b'a' + subtask_idx as u8) as char;
+            record(&subtask.text, format!("{}{}", task_num, letter));
+            for (child_idx, child) in subtask.children.iter().enumerate() {
+                record(child, format!("{}{}{}", task_num, letter, child_idx + 1));
+            }
+        }
+    }
+
+    summaries.sort_by(|a, b| b.refs.len().cmp(&a.refs.len()).then(a.tag.cmp(&b.tag)));
+    summaries
+}
+
+/// Lists the `@tags` used in open items with how often each appears and where
+pub fn show_tags(todo_path: &Path, verbosity: Verbosity) -> Result<()> {
+    let todo_file = if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        TodoFile::new("TODOs".to_string())
+    };
+
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    let tags = collect_tags(&todo_file);
+    if tags.is_empty() {
+        println!(
+            "{}No tags yet.{}",
+            color::Fg(color::Yellow),
+            color::Fg(color::Reset)
+        );
+        return Ok(());
+    }
+
+    let tag_width = tags
+        .iter()
+        .map(|summary| summary.tag.len())
+        .max()
+        .unwrap_or(0);
+    let color_scheme = ColorScheme::new();
+    for (idx, summary) in tags.iter().enumerate() {
+        println!(
+            "{}{:<width$} {:>3}  {}{}",
+            color_scheme.get_main_task_color(idx + 1),
+            summary.tag,
+            summary.refs.len(),
+            summary.refs.join(", "),
+            color::Fg(color::Reset),
+            width = tag_width
+        );
+    }
+
+    Ok(())
+}
+
+/// Previews the active color scheme: the detected background, color depth and a sample
+/// of task and subtask colors. When colors are off (see `output::colors_enabled`) they are
+/// described instead.
+pub fn show_colors() -> Result<()> {
+    let color_scheme = ColorScheme::new();
+    let theme = output::theme();
+    let depth = if color_scheme.truecolor {
+        "truecolor (24-bit)"
+    } else {
+        "256-color palette"
+    };
+    let no_color = !output::colors_enabled();
+
+    println!("Theme:      {}", theme);
+    // Only the auto theme depends on the detected background
+    if theme == "auto" {
+        let background = if ColorScheme::is_dark_terminal() {
+            "dark"
+        } else {
+            "light"
+        };
+        println!("Background: {}", background);
+    }
+    println!("Colors:     {}", depth);
+    println!();
+    for task_num in 1..=4 {
+        let main = color_scheme.get_main_task_color(task_num);
+        let subtask = color_scheme.get_subtask_color(task_num, 0);
+        if no_color {
+            println!(
+                "{:3}. task {}, subtask {}",
+                task_num,
+                main.describe(),
+                subtask.describe()
+            );
+        } else {
+            println!(
+                "{}{:3}. Task {}{}",
+                main,
+                task_num,
+                task_num,
+                color::Fg(color::Reset)
+            );
+            println!(
+                "{}     a. Subtask of task {}{}",
+                subtask,
+                task_num,
+                color::Fg(color::Reset)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Summarizes the archive, optionally only entries on or after `since` (YYYY-MM-DD).
+/// With `top_tags`, also ranks that many `@tags` by how many archived items carry them.
+pub fn archive_stats(
+    archive_path: &Path,
+    since: Option<&str>,
+    top_tags: Option<usize>,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let since = since.map(parse_date_arg).transpose()?;
+
+    let archive_file = if archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+
+    report_archive_file(verbosity, archive_path, &archive_file);
+
+    // Entries with unparseable dates are only counted when there's no cutoff
+    let tasks: Vec<&Task> = archive_file
+        .entries
+        .iter()
+        .filter(|entry| match since {
+            Some(since) => {
+                NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d").is_ok_and(|date| date >= since)
+            }
+            None => true,
+        })
+        .flat_map(|entry| entry.lists.values().flatten())
+        .collect();
+
+    let mut tag_counts: Vec<(String, usize)> = Vec::new();
+    let mut item_count = 0;
+    for task in &tasks {
+        let texts =
+            std::iter::once(task.text.as_str()).chain(task.subtasks.iter().flat_map(|subtask| {
+                std::iter::once(subtask.text.as_str())
+                    .chain(subtask.children.iter().map(String::as_str))
+            }));
+        for text in texts {
+            item_count += 1;
+            for tag in extract_tags(text) {
+                match tag_counts.iter_mut().find(|(name, _)| *name == tag) {
+                    Some((_, count)) => *count += 1,
+                    None => tag_counts.push((tag, 1)),
                 }
+            }
+        }
+    }
+
+    let period = since
+        .map(|date| format!(" since {}", date.format("%Y-%m-%d")))
+        .unwrap_or_default();
+    println!(
+        "{}Archived: {} items{}{}",
+        color::Fg(color::Magenta),
+        item_count,
+        period,
+        color::Fg(color::Reset)
+    );
+
+    if let Some(limit) = top_tags {
+        tag_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        tag_counts.truncate(limit);
+
+        println!();
+        if tag_counts.is_empty() {
+            println!(
+                "{}No tags in archived items.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+            return Ok(());
+        }
+
+        println!("Top tags:");
+        let tag_width = tag_counts
+            .iter()
+            .map(|(tag, _)| tag.len())
+            .max()
+            .unwrap_or(0);
+        let color_scheme = ColorScheme::new();
+        for (idx, (tag, count)) in tag_counts.iter().enumerate() {
+            println!(
+                "  {}{:<width$} {:>4}{}",
+                color_scheme.get_main_task_color(idx + 1),
+                tag,
+                count,
+                color::Fg(color::Reset),
+                width = tag_width
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// File format for `ldr export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// The todo file itself (followed by the archive file with --include-archive)
+    #[default]
+    Markdown,
+    /// Lists of tasks with nested subtasks, shaped like `ls --json`
+    Json,
+    /// One `ref,parent,text,list,archived` row per task, subtask and child
+    Csv,
+    /// An iCalendar event on its archive date for each archived task, for calendar apps
+    Ical,
+}
+
+/// The tasks of one list, either open or from one day of the archive
+struct ExportGroup<'a> {
+    list: &'a str,
+    /// Archive date, or None for open tasks
+    archived: Option<&'a str>,
+    tasks: &'a [Task],
+}
+
+/// Tasks and their subtasks numbered the same way `ls` numbers them
+fn export_items(tasks: &[Task]) -> Vec<DisplayItem> {
+    let mut items = Vec::new();
+    for (task_idx, task) in tasks.iter().enumerate() {
+        let task_num = task_idx + 1;
+        items.push(DisplayItem {
+            task_num,
+            subtask_idx: None,
+            reference: task_num.to_string(),
+            parent: None,
+            text: task.text.clone(),
+            completed: None,
+            line: task.text.clone(),
+            indent: 0,
+            meta: Vec::new(),
+            collapsed: 0,
+        });
+        for (subtask_idx, subtask) in task.subtasks.iter().enumerate() {
+            items.extend(subtask_display_items(task_num, subtask_idx, subtask));
+        }
+    }
+    items
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote or line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Builds the CSV export, with a header row
+fn export_csv(groups: &[ExportGroup]) -> String {
+    let mut content = String::from("ref,parent,text,list,archived\n");
+    for group in groups {
+        for item in export_items(group.tasks) {
+            let fields = [
+                item.reference.as_str(),
+                item.parent.as_deref().unwrap_or(""),
+                item.text.as_str(),
+                group.list,
+                group.archived.unwrap_or(""),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            content.push_str(&row.join(","));
+            content.push('\n');
+        }
+    }
+    content
+}
+
+/// Escapes a value for an iCalendar TEXT property
+fn ical_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Ends an iCalendar content line with CRLF, folding it so no physical line is longer
+/// than 75 bytes; continuation lines start with a space
+fn push_ical_line(content: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            content.push_str("\r\n ");
+            width = 1;
+        }
+        content.push(c);
+        width += c.len_utf8();
+    }
+    content.push_str("\r\n");
+}
+
+/// 64-bit FNV-1a hash of `parts`, each followed by a 0x1f separator. Unlike the std
+/// hashers its output is fixed by the algorithm, so it suits identifiers that are
+/// written out and compared later.
+fn fnv1a_hash(parts: &[&str]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    parts
+        .iter()
+        .flat_map(|part| part.bytes().chain([0x1f]))
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        })
+}
+
+/// The UID of an archived task's event, the same in every export: built from the task's
+/// ID when it has one, else from a hash of its list, text and due date. Its position
+/// isn't part of it, so archiving more tasks leaves the other UIDs alone.
+fn ical_uid(date: NaiveDate, list: &str, task: &Task) -> String {
+    let key = match &task.id {
+        Some(id) => id.clone(),
+        None => {
+            let due = task.due.map(|due| due.to_string()).unwrap_or_default();
+            format!("{:016x}", fnv1a_hash(&[list, &task.text, &due]))
+        }
+    };
+    format!("{}-{}@ldr", date.format("%Y%m%d"), key)
+}
+
+/// Builds the iCalendar export: an all-day event on the archive date for each archived
+/// task, with its due date and subtasks in the description
+fn export_ical(groups: &[ExportGroup]) -> String {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//ldr//ldr {}//EN", env!("CARGO_PKG_VERSION")),
+    ];
+    for group in groups {
+        let Some(date) = group
+            .archived
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        else {
+            continue;
+        };
+        for task in group.tasks {
+            let mut description = Vec::new();
+            if let Some(due) = task.due {
+                description.push(format!("Due: {}", due.format("%Y-%m-%d")));
+            }
+            for subtask in &task.subtasks {
+                description.push(format!("- {}", subtask.text));
+                description.extend(
+                    subtask
+                        .children
+                        .iter()
+                        .map(|child| format!("  - {}", child)),
+                );
+            }
+
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}", ical_uid(date, group.list, task)));
+            lines.push(format!("DTSTAMP:{}", stamp));
+            lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+            lines.push(format!(
+                "DTEND;VALUE=DATE:{}",
+                (date + chrono::Duration::days(1)).format("%Y%m%d")
+            ));
+            lines.push(format!("SUMMARY:{}", ical_text(&task.text)));
+            if !description.is_empty() {
+                lines.push(format!(
+                    "DESCRIPTION:{}",
+                    ical_text(&description.join("\n"))
+                ));
+            }
+            if group.list != DEFAULT_LIST {
+                lines.push(format!("CATEGORIES:{}", ical_text(group.list)));
+            }
+            lines.push("TRANSP:TRANSPARENT".to_string());
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut content = String::new();
+    for line in &lines {
+        push_ical_line(&mut content, line);
+    }
+    content
+}
+
+/// Writes the whole todo file (and optionally the archive) to stdout or `out`
+/// as Markdown, JSON or CSV, for moving to other tools. The iCalendar format holds
+/// only archived tasks, so it always reads the archive.
+pub fn export(
+    todo_path: &Path,
+    archive_path: &Path,
+    format: ExportFormat,
+    include_archive: bool,
+    out: Option<&Path>,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let (todo_file, warnings) = if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        parse_todo_file_with_warnings(&content, SubtaskNesting::from_env())
+            .map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        (TodoFile::new("TODOs".to_string()), Vec::new())
+    };
+    let include_archive = include_archive || format == ExportFormat::Ical;
+    let archive_file = if include_archive && archive_path.exists() {
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        parse_archive_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        ArchiveFile::new()
+    };
+    report_todo_file(verbosity, todo_path, &todo_file);
+    if include_archive {
+        report_archive_file(verbosity, archive_path, &archive_file);
+    }
+
+    let mut todo_groups = vec![ExportGroup {
+        list: DEFAULT_LIST,
+        archived: None,
+        tasks: &todo_file.tasks,
+    }];
+    todo_groups.extend(todo_file.lists.iter().map(|list| ExportGroup {
+        list: &list.name,
+        archived: None,
+        tasks: &list.tasks,
+    }));
+    // Archive lists are unordered in the file model, so keep Default first and sort the rest
+    let mut archive_groups = Vec::new();
+    for entry in &archive_file.entries {
+        let mut names: Vec<&String> = entry.lists.keys().collect();
+        names.sort_by_key(|name| (name.as_str() != DEFAULT_LIST, name.as_str()));
+        archive_groups.extend(names.into_iter().map(|name| ExportGroup {
+            list: name,
+            archived: Some(&entry.date),
+            tasks: &entry.lists[name],
+        }));
+    }
+
+    let content = match format {
+        ExportFormat::Markdown => {
+            let mut content = generate_todo_file(&todo_file);
+            if include_archive {
+                content.push('\n');
+                content.push_str(&generate_archive_file(&archive_file));
+            }
+            content
+        }
+        ExportFormat::Json => {
+            let lists: Vec<Value> = todo_groups
+                .iter()
+                .map(|group| {
+                    json!({
+                        "name": group.list,
+                        "tasks": list_json(&export_items(group.tasks), false),
+                    })
+                })
+                .collect();
+            let warnings: Vec<Value> = warnings
+                .iter()
+                .map(|warning| json!({ "line": warning.line(), "message": warning.to_string() }))
+                .collect();
+            let mut export = json!({ "lists": lists, "warnings": warnings });
+            if include_archive {
+                export["archive"] = archive_groups
+                    .iter()
+                    .map(|group| {
+                        json!({
+                            "date": group.archived,
+                            "list": group.list,
+                            "tasks": list_json(&export_items(group.tasks), false),
+                        })
+                    })
+                    .collect();
+            }
+            serde_json::to_string_pretty(&export).context("Failed to serialize JSON")? + "\n"
+        }
+        ExportFormat::Csv => {
+            todo_groups.extend(archive_groups);
+            export_csv(&todo_groups)
+        }
+        ExportFormat::Ical => export_ical(&archive_groups),
+    };
+    // JSON carries its warnings; the other formats can only mention them on stderr
+    if format != ExportFormat::Json {
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+
+    match out {
+        Some(out) => {
+            fs::write(out, &content)
+                .with_context(|| format!("Failed to write file: {}", out.display()))?;
+            if !verbosity.is_quiet() {
+                println!(
+                    "{}✓ Exported to {}{}",
+                    color::Fg(color::Green),
+                    out.display(),
+                    color::Fg(color::Reset)
+                );
+            }
+        }
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
+
+/// Resolves a reference to the top-level task whose due date is being changed
+fn resolve_due_target(todo_file: &TodoFile, reference: &str) -> Result<usize> {
+    let task_ref = TaskRef::resolve(reference, &todo_file.tasks)
+        .map_err(|e| anyhow!("Invalid task reference '{}': {}", reference, e))?;
+
+    if task_ref.is_subtask() {
+        return Err(anyhow!(
+            "Due dates can only be set on top-level tasks, not subtask '{}'",
+            reference
+        ));
+    }
+
+    if task_ref.task_index >= todo_file.tasks.len() {
+        return Err(anyhow!(
+            "Invalid task number: {}. Valid range: 1-{}",
+            task_ref.task_index + 1,
+            todo_file.tasks.len()
+        ));
+    }
+
+    Ok(task_ref.task_index)
+}
+
+/// Sets or replaces the due date of a task
+pub fn set_due(todo_path: &Path, reference: &str, date: &str, verbosity: Verbosity) -> Result<()> {
+    let due = parse_due_date(date).map_err(|e| anyhow!(e))?;
+
+    if !todo_path.exists() {
+        return Err(anyhow!("No notes found"));
+    }
+
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let mut todo_file =
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+
+    let task_idx = resolve_due_target(&todo_file, reference)?;
+    let previous = todo_file.tasks[task_idx].due.replace(due);
+
+    let new_content = generate_todo_file(&todo_file);
+    fs::write(todo_path, new_content)
+        .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "due_set",
+            &[
+                ("ref", (task_idx + 1).to_string()),
+                ("date", due.format("%Y-%m-%d").to_string()),
+            ],
+        );
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
+
+    let task = &todo_file.tasks[task_idx];
+    match previous {
+        Some(old) if old != due => println!(
+            "{}✓ Changed due date of task {} from {} to {}: {}{}",
+            color::Fg(color::Green),
+            task_idx + 1,
+            old.format("%Y-%m-%d"),
+            due.format("%Y-%m-%d"),
+            task.text,
+            color::Fg(color::Reset)
+        ),
+        _ => println!(
+            "{}✓ Task {} due {}: {}{}",
+            color::Fg(color::Green),
+            task_idx + 1,
+            due.format("%Y-%m-%d"),
+            task.text,
+            color::Fg(color::Reset)
+        ),
+    }
+
+    Ok(())
+}
+
+/// Removes the due date from a task
+pub fn clear_due(todo_path: &Path, reference: &str, verbosity: Verbosity) -> Result<()> {
+    if !todo_path.exists() {
+        return Err(anyhow!("No notes found"));
+    }
+
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let mut todo_file =
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+
+    let task_idx = resolve_due_target(&todo_file, reference)?;
+    // Either way the task ends up without a due date
+    if verbosity.is_porcelain() {
+        output::porcelain("due_cleared", &[("ref", (task_idx + 1).to_string())]);
+    }
+    if todo_file.tasks[task_idx].due.take().is_none() {
+        if !verbosity.is_quiet() {
+            println!(
+                "{}Task {} has no due date; nothing to clear.{}",
+                color::Fg(color::Yellow),
+                task_idx + 1,
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    let new_content = generate_todo_file(&todo_file);
+    fs::write(todo_path, new_content)
+        .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ Cleared due date of task {}: {}{}",
+            color::Fg(color::Green),
+            task_idx + 1,
+            todo_file.tasks[task_idx].text,
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// Pins or unpins a task; pinned tasks are listed above the others by `ls`
+pub fn set_pinned(
+    todo_path: &Path,
+    reference: &str,
+    pinned: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let mut store = TodoStore::new(todo_path);
+    if !store.exists() {
+        return Err(anyhow!("No notes found"));
+    }
 
-                if let Some(subtask_idx) = task_ref.subtask_index {
-                    let task = &todo_file.tasks[task_ref.task_index];
-                    if subtask_idx >= task.subtasks.len() {
-                        println!(
-                            "{}Invalid subtask: {}{}. Task {} has {} subtasks{}",
-                            color::Fg(color::Red),
-                            ref_str,
-                            color::Fg(color::Reset),
-                            task_ref.task_index + 1,
-                            task.subtasks.len(),
-                            color::Fg(color::Reset)
-                        );
-                        return Ok(());
-                    }
-                }
+    let (task_idx, changed, text) = store.mutate(|todo_file| {
+        let task_ref = TaskRef::resolve(reference, &todo_file.tasks)
+            .map_err(|e| anyhow!("Invalid task reference '{}': {}", reference, e))?;
+        if task_ref.is_subtask() {
+            return Err(anyhow!(
+                "Only top-level tasks can be pinned, not subtask '{}'",
+                reference
+            ));
+        }
+        let task_idx = task_ref.task_index;
+        if task_idx >= todo_file.tasks.len() {
+            return Err(anyhow!(
+                "Invalid task number: {}. Valid range: 1-{}",
+                task_idx + 1,
+                todo_file.tasks.len()
+            ));
+        }
+        let task = &mut todo_file.tasks[task_idx];
+        let changed = task.pinned != pinned;
+        task.pinned = pinned;
+        Ok((task_idx, changed, task.text.clone()))
+    })?;
 
-                task_refs.push(task_ref);
-            }
-            Err(e) => {
+    // Either way the task ends up in the requested state
+    if verbosity.is_porcelain() {
+        let event = if pinned { "pinned" } else { "unpinned" };
+        output::porcelain(event, &[("ref", (task_idx + 1).to_string())]);
+    }
+    if !changed {
+        if !verbosity.is_quiet() {
+            println!(
+                "{}Task {} is already {}.{}",
+                color::Fg(color::Yellow),
+                task_idx + 1,
+                if pinned { "pinned" } else { "not pinned" },
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+    report_todo_file(verbosity, todo_path, store.get()?);
+
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ {} task {}: {}{}",
+            color::Fg(color::Green),
+            if pinned { "Pinned" } else { "Unpinned" },
+            task_idx + 1,
+            text,
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the text of the top task of the Default list, uncolored and without its
+/// number or subtasks. With no tasks nothing is printed and the command fails, so
+/// `ldr peek || echo idle` works in a prompt.
+pub fn peek(todo_path: &Path) -> Result<()> {
+    let mut store = TodoStore::new(todo_path);
+    let Some(task) = store.get()?.tasks.first() else {
+        return Err(AlreadyReported.into());
+    };
+    println!("{}", task.text);
+    Ok(())
+}
+
+/// Sets, clears or shows the focused task kept in `focus_path`. The task is remembered
+/// by its ID (or its text, lacking one), so it stays focused when moved; a subtask
+/// reference focuses its task.
+pub fn focus_task(
+    todo_path: &Path,
+    focus_path: &Path,
+    reference: Option<&str>,
+    clear: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    if clear {
+        let was_focused = focus_path.exists();
+        if was_focused {
+            fs::remove_file(focus_path)
+                .with_context(|| format!("Failed to remove file: {}", focus_path.display()))?;
+        }
+        if !verbosity.is_quiet() {
+            if was_focused {
                 println!(
-                    "{}Invalid task reference '{}': {}{}",
-                    color::Fg(color::Red),
-                    ref_str,
-                    e,
+                    "{}✓ Focus cleared{}",
+                    color::Fg(color::Green),
+                    color::Fg(color::Reset)
+                );
+            } else {
+                println!(
+                    "{}No task is focused.{}",
+                    color::Fg(color::Yellow),
                     color::Fg(color::Reset)
                 );
-                return Ok(());
             }
         }
+        return Ok(());
     }
 
-    // For prioritizing, we move entire tasks to the top (subtask refs move their parent task)
-    let mut tasks_to_move = Vec::new();
-    let mut moved_task_indices = HashSet::new();
+    let mut store = TodoStore::new(todo_path);
+    let tasks = &store.get()?.tasks;
+    let Some(reference) = reference else {
+        let focus = read_focus(focus_path)?;
+        let position = |focus: &String| tasks.iter().position(|task| is_focused(task, focus));
+        match focus {
+            Some(focus) => match position(&focus) {
+                Some(task_idx) => {
+                    println!("Focused on task {}: {}", task_idx + 1, tasks[task_idx].text)
+                }
+                None => println!(
+                    "{}The focused task is no longer open: {}{}",
+                    color::Fg(color::Yellow),
+                    focus_text(&focus),
+                    color::Fg(color::Reset)
+                ),
+            },
+            None => println!(
+                "{}No task is focused.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            ),
+        }
+        return Ok(());
+    };
 
-    for task_ref in &task_refs {
-        if !moved_task_indices.contains(&task_ref.task_index) {
-            tasks_to_move.push(task_ref.task_index);
-            moved_task_indices.insert(task_ref.task_index);
+    let task_ref = TaskRef::resolve(reference, tasks)
+        .and_then(|task_ref| {
+            check_ref_in_range(tasks, reference, &task_ref)?;
+            Ok(task_ref)
+        })
+        .map_err(|message| anyhow!("Invalid task reference '{}': {}", reference, message))?;
+    let task = &tasks[task_ref.task_index];
+    let focus = match &task.id {
+        Some(id) => format!("id:{}\ntext:{}", id, task.text),
+        None => format!("text:{}", task.text),
+    };
+    fs::write(focus_path, format!("{}\n", focus))
+        .with_context(|| format!("Failed to write file: {}", focus_path.display()))?;
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ Focused on task {}: {}{}",
+            color::Fg(color::Green),
+            task_ref.task_index + 1,
+            task.text,
+            color::Fg(color::Reset)
+        );
+    }
+    Ok(())
+}
+
+/// The ID and text lines of a focus record, `id:<id>` (for tasks with an ID) and
+/// `text:<text>`. A record without a text line is taken as text alone.
+fn focus_fields(focus: &str) -> (Option<&str>, &str) {
+    let mut id = None;
+    let mut text = None;
+    for line in focus.lines() {
+        if let Some(value) = line.strip_prefix("id:") {
+            id = Some(value);
+        } else if let Some(value) = line.strip_prefix("text:") {
+            text = Some(value);
         }
     }
+    (id, text.unwrap_or(focus))
+}
 
-    // Create new task order by swapping moved tasks to front
-    let old_tasks = std::mem::take(&mut todo_file.tasks);
-    let mut new_tasks = Vec::with_capacity(old_tasks.len());
-    let mut moved_task_names = Vec::new();
+/// Whether `task` is the one `focus` records: by ID when it has one (the text is then
+/// only for messages), else by its text
+fn is_focused(task: &Task, focus: &str) -> bool {
+    match focus_fields(focus) {
+        (Some(id), _) => task.id.as_deref() == Some(id),
+        (None, text) => task.text == text,
+    }
+}
 
-    // First add the moved tasks in the order specified
-    for &task_idx in &tasks_to_move {
-        if task_idx < old_tasks.len() {
-            new_tasks.push(old_tasks[task_idx].clone());
-            moved_task_names.push(old_tasks[task_idx].text.clone());
+/// The task text recorded in `focus`, for messages
+fn focus_text(focus: &str) -> &str {
+    focus_fields(focus).1
+}
+
+/// The focused task as recorded by `focus_task` (`id:` and `text:` lines), if one is set
+pub fn read_focus(focus_path: &Path) -> Result<Option<String>> {
+    if !focus_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(focus_path)
+        .with_context(|| format!("Failed to read file: {}", focus_path.display()))?;
+    let focus = content.trim();
+    Ok((!focus.is_empty()).then(|| focus.to_string()))
+}
+
+/// Checks that a reference resolves to an existing task, subtask or child without
+/// changing anything, so scripts can validate refs before acting on them
+pub fn validate_ref(todo_path: &Path, reference: &str, verbosity: Verbosity) -> Result<()> {
+    if !todo_path.exists() {
+        return Err(anyhow!("No notes found"));
+    }
+
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let todo_file =
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+
+    let task_ref = TaskRef::resolve(reference, &todo_file.tasks)
+        .map_err(|e| anyhow!("Invalid task reference '{}': {}", reference, e))?;
+    check_ref_in_range(&todo_file.tasks, reference, &task_ref)
+        .map_err(|message| anyhow!(message))?;
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
+
+    let task = &todo_file.tasks[task_ref.task_index];
+    let text = match (task_ref.subtask_index, task_ref.child_index) {
+        (Some(subtask_idx), Some(child_idx)) => &task.subtasks[subtask_idx].children[child_idx],
+        (Some(subtask_idx), None) => &task.subtasks[subtask_idx].text,
+        _ => &task.text,
+    };
+    println!(
+        "{}✓ {}: {}{}",
+        color::Fg(color::Green),
+        reference,
+        text,
+        color::Fg(color::Reset)
+    );
+
+    Ok(())
+}
+
+/// What `reindex` changed while tidying the todo file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReindexSummary {
+    pub empty_tasks: usize,
+    pub empty_subtasks: usize,
+    pub duplicate_subtasks: usize,
+}
+
+/// True for items with no text besides stray bullet markers (e.g. a lone "-")
+fn is_blank_item(text: &str) -> bool {
+    text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '*' | '+'))
+        .is_empty()
+}
+
+/// Strips empty tasks and subtasks and collapses consecutive duplicate subtasks in every list
+pub fn canonicalize_todo_file(todo_file: &mut TodoFile) -> ReindexSummary {
+    let mut summary = ReindexSummary::default();
+    let named = todo_file.lists.iter_mut().map(|list| &mut list.tasks);
+
+    for tasks in std::iter::once(&mut todo_file.tasks).chain(named) {
+        for task in tasks.iter_mut() {
+            let before = task.subtasks.len();
+            task.subtasks
+                .retain(|subtask| !is_blank_item(&subtask.text));
+            summary.empty_subtasks += before - task.subtasks.len();
+
+            let before = task.subtasks.len();
+            task.subtasks.dedup();
+            summary.duplicate_subtasks += before - task.subtasks.len();
+        }
+
+        // A blank task's subtasks are promoted so no real items are lost
+        let mut kept = Vec::with_capacity(tasks.len());
+        for task in tasks.drain(..) {
+            if is_blank_item(&task.text) {
+                summary.empty_tasks += 1;
+                kept.extend(
+                    task.subtasks
+                        .into_iter()
+                        .map(|subtask| Task::with_subtasks(subtask.text, subtask.children)),
+                );
+            } else {
+                kept.push(task);
+            }
         }
+        *tasks = kept;
     }
 
-    // Then add all non-moved tasks
-    for (idx, task) in old_tasks.into_iter().enumerate() {
-        if !moved_task_indices.contains(&idx) {
-            new_tasks.push(task);
+    summary
+}
+
+/// File names of `paths`, comma-separated, or "none"
+fn file_names(paths: &[PathBuf]) -> String {
+    if paths.is_empty() {
+        return "none".to_string();
+    }
+    paths
+        .iter()
+        .map(|path| {
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Explains the migration status in one line
+fn describe_migration_status(status: &MigrationStatus) -> String {
+    if status.needed() {
+        format!(
+            "Migration needed: found {} and no Markdown files",
+            file_names(&status.plain_files)
+        )
+    } else if status.plain_files.is_empty() {
+        "No migration needed: no plain text files found".to_string()
+    } else {
+        format!(
+            "No migration needed: {} already exist (use --force to convert {} again)",
+            file_names(&status.markdown_files),
+            file_names(&status.plain_files)
+        )
+    }
+}
+
+/// Runs the plain text to Markdown migration on request. Plain `migrate` reports which
+/// files exist and migrates if needed, `status_only` prints the detection result alone,
+/// and `force` converts the plain text files again over existing Markdown files, which
+/// are backed up first.
+pub fn migrate(
+    note_path: &Path,
+    archive_path: &Path,
+    todo_md_path: &Path,
+    archive_md_path: &Path,
+    force: bool,
+    status_only: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let status =
+        migration::migration_status(note_path, archive_path, todo_md_path, archive_md_path);
+    if status_only {
+        if !verbosity.is_quiet() {
+            println!("{}", describe_migration_status(&status));
         }
+        return Ok(());
+    }
+    if force && status.plain_files.is_empty() {
+        return Err(anyhow!(
+            "Nothing to migrate: no note.txt or archive.txt in {}",
+            note_path.parent().unwrap_or(note_path).display()
+        ));
     }
+    if !verbosity.is_quiet() {
+        println!("Plain text files: {}", file_names(&status.plain_files));
+        println!("Markdown files:   {}", file_names(&status.markdown_files));
+    }
+    if force || status.needed() {
+        return migration::perform_migration(
+            note_path,
+            archive_path,
+            todo_md_path,
+            archive_md_path,
+            verbosity,
+        )
+        .map_err(|e| anyhow!(e));
+    }
+    if !verbosity.is_quiet() {
+        println!("{}", describe_migration_status(&status));
+    }
+    Ok(())
+}
 
-    todo_file.tasks = new_tasks;
+/// Rewrites the todo file in canonical form after heavy manual edits.
+/// Normalizes bullets and indentation, strips empty items, and collapses consecutive
+/// duplicate subtasks, saving the previous version as `todos.md.bak` first.
+pub fn reindex(todo_path: &Path, verbosity: Verbosity) -> Result<()> {
+    if !todo_path.exists() {
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No notes found.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
 
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let mut todo_file =
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+    let reformatted = generate_todo_file(&todo_file) != content;
+    let summary = canonicalize_todo_file(&mut todo_file);
     let new_content = generate_todo_file(&todo_file);
+
+    if new_content == content {
+        if verbosity.is_porcelain() {
+            output::porcelain("reindexed", &[("changed", "false".to_string())]);
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}Already tidy; nothing to reindex.{}",
+                color::Fg(color::Green),
+                color::Fg(color::Reset)
+            );
+        }
+        report_todo_file(verbosity, todo_path, &todo_file);
+        return Ok(());
+    }
+
+    let backup_path = todo_path.with_extension("md.bak");
+    fs::copy(todo_path, &backup_path)
+        .with_context(|| format!("Failed to write backup: {}", backup_path.display()))?;
     fs::write(todo_path, new_content)
         .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "reindexed",
+            &[
+                ("changed", "true".to_string()),
+                ("backup", output::quote(&backup_path.display().to_string())),
+            ],
+        );
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
 
     println!(
-        "{}✓ Prioritized {} task(s){}",
+        "{}✓ Reindexed {} (backup: {}){}",
         color::Fg(color::Green),
-        moved_task_names.len(),
+        todo_path.display(),
+        backup_path.display(),
         color::Fg(color::Reset)
     );
+    let changes = [
+        ("empty task(s) removed", summary.empty_tasks),
+        ("empty subtask(s) removed", summary.empty_subtasks),
+        ("duplicate subtask(s) removed", summary.duplicate_subtasks),
+    ];
+    for (label, count) in changes {
+        if count > 0 {
+            println!("  {} {}", count, label);
+        }
+    }
+    if reformatted {
+        println!("  Bullets and indentation normalized");
+    }
 
-    for task_name in moved_task_names {
+    Ok(())
+}
+
+/// Sets the order of named list sections; lists not mentioned keep their order after them
+pub fn reorder_lists(todo_path: &Path, names: &[String], verbosity: Verbosity) -> Result<()> {
+    if !todo_path.exists() {
+        return Err(anyhow!("No notes found"));
+    }
+
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let mut todo_file =
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+    todo_file.reorder_lists(names).map_err(|e| anyhow!(e))?;
+
+    let new_content = generate_todo_file(&todo_file);
+    fs::write(todo_path, new_content)
+        .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    let order: Vec<&str> = todo_file
+        .lists
+        .iter()
+        .map(|list| list.name.as_str())
+        .collect();
+    if verbosity.is_porcelain() {
+        let names = std::iter::once(DEFAULT_LIST).chain(order.iter().copied());
+        output::porcelain(
+            "lists_reordered",
+            &[(
+                "order",
+                names.map(output::quote).collect::<Vec<_>>().join(","),
+            )],
+        );
+    }
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ List order: {}, {}{}",
+            color::Fg(color::Green),
+            DEFAULT_LIST,
+            order.join(", "),
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
+
+/// Shows the Default list and each named list with its open task and subtask counts
+pub fn show_lists(todo_path: &Path, verbosity: Verbosity) -> Result<()> {
+    let todo_file = if todo_path.exists() {
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+    } else {
+        TodoFile::new("TODOs".to_string())
+    };
+
+    report_todo_file(verbosity, todo_path, &todo_file);
+
+    let lists: Vec<(&str, &Vec<Task>)> = std::iter::once((DEFAULT_LIST, &todo_file.tasks))
+        .chain(
+            todo_file
+                .lists
+                .iter()
+                .map(|list| (list.name.as_str(), &list.tasks)),
+        )
+        .collect();
+    let name_width = lists.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let color_scheme = ColorScheme::new();
+    for (idx, (name, tasks)) in lists.iter().enumerate() {
+        let subtasks: usize = tasks.iter().map(|task| task.subtasks.len()).sum();
+        println!(
+            "{}{:<width$} {:>3}  {}{}",
+            color_scheme.get_main_task_color(idx + 1),
+            name,
+            tasks.len(),
+            if subtasks > 0 {
+                format!("(+{})", plural(subtasks, "subtask"))
+            } else {
+                String::new()
+            },
+            color::Fg(color::Reset),
+            width = name_width
+        );
+    }
+
+    Ok(())
+}
+
+/// Applies a change to the named lists and writes the file back. A missing file is
+/// treated as empty, so `lists create` works before anything has been added.
+fn update_lists<T>(
+    todo_path: &Path,
+    verbosity: Verbosity,
+    change: impl FnOnce(&mut TodoFile) -> Result<T, String>,
+) -> Result<T> {
+    let mut store = TodoStore::new(todo_path);
+    let result = store.mutate(|todo_file| change(todo_file).map_err(|e| anyhow!(e)))?;
+    report_todo_file(verbosity, todo_path, store.get()?);
+    Ok(result)
+}
+
+/// Creates an empty named list, added after the existing lists
+pub fn create_list(todo_path: &Path, name: &str, verbosity: Verbosity) -> Result<()> {
+    let name = name.trim();
+    update_lists(todo_path, verbosity, |todo_file| {
+        if name.is_empty() {
+            return Err("List name cannot be empty".to_string());
+        }
+        if todo_file.has_list(name) {
+            return Err(format!("List '{}' already exists", name));
+        }
+        todo_file.list_or_create(name);
+        Ok(())
+    })?;
+
+    if verbosity.is_porcelain() {
+        output::porcelain("list_created", &[("name", output::quote(name))]);
+    }
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ Created list '{}'{}",
+            color::Fg(color::Green),
+            name,
+            color::Fg(color::Reset)
+        );
+    }
+    Ok(())
+}
+
+/// Renames a named list in the todo file. Archived items keep the list name they were
+/// archived under.
+pub fn rename_list(
+    todo_path: &Path,
+    old_name: &str,
+    new_name: &str,
+    verbosity: Verbosity,
+) -> Result<()> {
+    update_lists(todo_path, verbosity, |todo_file| {
+        todo_file.rename_list(old_name, new_name)
+    })?;
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "list_renamed",
+            &[
+                ("from", output::quote(old_name)),
+                ("to", output::quote(new_name.trim())),
+            ],
+        );
+    }
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ Renamed list '{}' to '{}'{}",
+            color::Fg(color::Green),
+            old_name,
+            new_name.trim(),
+            color::Fg(color::Reset)
+        );
+    }
+    Ok(())
+}
+
+/// Deletes a named list along with its tasks, which are not archived
+pub fn remove_list(todo_path: &Path, name: &str, force: bool, verbosity: Verbosity) -> Result<()> {
+    let removed = update_lists(todo_path, verbosity, |todo_file| {
+        todo_file.remove_list(name, force)
+    })?;
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "list_removed",
+            &[
+                ("name", output::quote(&removed.name)),
+                ("tasks", removed.tasks.len().to_string()),
+            ],
+        );
+    }
+    if !verbosity.is_quiet() {
+        let contents = if removed.tasks.is_empty() {
+            String::new()
+        } else {
+            format!(" and its {}", plural(removed.tasks.len(), "task"))
+        };
         println!(
-            "  {}{}{}",
-            color::Fg(color::Magenta),
-            task_name,
+            "{}✓ Deleted list '{}'{}{}",
+            color::Fg(color::Green),
+            removed.name,
+            contents,
             color::Fg(color::Reset)
         );
     }
-
     Ok(())
 }
 
-/// Internal helper to process items for removal or archiving
-fn process_items_for_removal(
+/// Moves a task, with its subtasks, from one list to the top of another, creating the
+/// target list if needed. `from` defaults to the Default list.
+pub fn move_to_list(
     todo_path: &Path,
-    refs: &[String],
-    archive_path: Option<&Path>,
+    reference: &str,
+    from: Option<&str>,
+    to: &str,
+    verbosity: Verbosity,
 ) -> Result<()> {
-    let should_archive = archive_path.is_some();
-    if !todo_path.exists() {
-        println!(
-            "{}No notes found.{}",
-            color::Fg(color::Yellow),
-            color::Fg(color::Reset)
-        );
-        return Ok(());
+    let to = to.trim();
+    if to.is_empty() {
+        return Err(anyhow!("List name cannot be empty"));
     }
+    let mut store = TodoStore::new(todo_path);
+    let (task_number, source, target, new_list, text) = store.mutate(|todo_file| {
+        // Report both lists under their names as written in the file
+        let source = match from.map(str::trim) {
+            None => DEFAULT_LIST.to_string(),
+            Some(name) if TodoFile::is_default_list(name) => DEFAULT_LIST.to_string(),
+            Some(name) => todo_file
+                .lists
+                .iter()
+                .find(|list| list.name.eq_ignore_ascii_case(name))
+                .map(|list| list.name.clone())
+                .ok_or_else(|| anyhow!("No such list '{}'", name))?,
+        };
+        let target = if TodoFile::is_default_list(to) {
+            DEFAULT_LIST.to_string()
+        } else {
+            todo_file
+                .lists
+                .iter()
+                .find(|list| list.name.eq_ignore_ascii_case(to))
+                .map_or_else(|| to.to_string(), |list| list.name.clone())
+        };
+        if source.eq_ignore_ascii_case(&target) {
+            return Err(anyhow!("Task {} is already in {}", reference, source));
+        }
 
-    let content = fs::read_to_string(todo_path)
-        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
-    let mut todo_file =
-        parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?;
+        let tasks = todo_file
+            .list_mut(&source)
+            .expect("list name was resolved above");
+        let task_ref = TaskRef::resolve(reference, tasks)
+            .map_err(|e| anyhow!("Invalid task reference '{}': {}", reference, e))?;
+        if task_ref.is_subtask() {
+            return Err(anyhow!(
+                "Only top-level tasks can be moved to another list, not subtask '{}'",
+                reference
+            ));
+        }
+        let task_idx = task_ref.task_index;
+        if task_idx >= tasks.len() {
+            return Err(anyhow!(
+                "Invalid task number: {}. Valid range: 1-{}",
+                task_idx + 1,
+                tasks.len()
+            ));
+        }
+        let task = tasks.remove(task_idx);
+        let text = task.text.clone();
 
-    if todo_file.is_empty() {
-        let action = if should_archive { "archive" } else { "remove" };
+        let new_list = !todo_file.has_list(&target);
+        todo_file.list_or_create(&target).insert(0, task);
+        Ok((task_idx + 1, source, target, new_list, text))
+    })?;
+    report_todo_file(verbosity, todo_path, store.get()?);
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "moved",
+            &[
+                ("ref", task_number.to_string()),
+                ("from", output::quote(&source)),
+                ("to", output::quote(&target)),
+            ],
+        );
+    }
+    if !verbosity.is_quiet() {
         println!(
-            "{}No notes to {}.{}",
-            color::Fg(color::Yellow),
-            action,
+            "{}✓ Moved task {} from {} to {}{}: {}{}",
+            color::Fg(color::Green),
+            task_number,
+            source,
+            target,
+            if new_list { " (new list)" } else { "" },
+            text,
             color::Fg(color::Reset)
         );
-        return Ok(());
-    }
-
-    // Parse task references
-    let mut task_refs = Vec::new();
-    for ref_str in refs {
-        match TaskRef::parse(ref_str) {
-            Ok(task_ref) => task_refs.push((ref_str.clone(), task_ref)),
-            Err(e) => {
-                println!(
-                    "{}Invalid task reference '{}': {}{}",
-                    color::Fg(color::Red),
-                    ref_str,
-                    e,
-                    color::Fg(color::Reset)
-                );
-                return Ok(());
-            }
-        }
     }
+    Ok(())
+}
 
-    // Separate tasks and subtasks to archive
-    let mut tasks_to_archive = Vec::new();
-    let mut subtasks_to_remove = Vec::new(); // (task_idx, subtask_idx)
-    let mut whole_tasks_to_remove = HashSet::new();
+/// Number of top-level tasks and subtasks across all lists
+fn count_items(todo_file: &TodoFile) -> (usize, usize) {
+    let named = todo_file.lists.iter().flat_map(|list| &list.tasks);
+    todo_file
+        .tasks
+        .iter()
+        .chain(named)
+        .fold((0, 0), |(tasks, subtasks), task| {
+            (tasks + 1, subtasks + task.subtasks.len())
+        })
+}
 
-    for (ref_str, task_ref) in &task_refs {
-        if task_ref.task_index >= todo_file.tasks.len() {
-            println!(
-                "{}Invalid task number in '{}': {}. Valid range: 1-{}{}",
-                color::Fg(color::Red),
-                ref_str,
-                task_ref.task_index + 1,
-                todo_file.tasks.len(),
-                color::Fg(color::Reset)
-            );
-            return Ok(());
-        }
+/// "1 task" or "3 tasks"
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
 
-        if let Some(subtask_idx) = task_ref.subtask_index {
-            // Archiving a subtask
-            let task = &todo_file.tasks[task_ref.task_index];
-            if subtask_idx >= task.subtasks.len() {
-                println!(
-                    "{}Invalid subtask '{}': Task {} has {} subtasks{}",
-                    color::Fg(color::Red),
-                    ref_str,
-                    task_ref.task_index + 1,
-                    task.subtasks.len(),
-                    color::Fg(color::Reset)
-                );
-                return Ok(());
-            }
-            subtasks_to_remove.push((task_ref.task_index, subtask_idx));
-        } else {
-            // Archiving whole task
-            whole_tasks_to_remove.insert(task_ref.task_index);
+/// Signs that a hand edit went wrong, given the item counts before and after it
+pub fn edit_problems(
+    content: &str,
+    before: Option<(usize, usize)>,
+    after: (usize, usize),
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some((tasks, _)) = before.filter(|(tasks, _)| *tasks > 0) {
+        if after.0 == 0 {
+            problems.push(format!(
+                "no tasks found (there were {} before editing)",
+                tasks
+            ));
         }
     }
-
-    // Collect items to archive
-    for &task_idx in &whole_tasks_to_remove {
-        tasks_to_archive.push(todo_file.tasks[task_idx].clone());
+    // Indented items under no task can't be subtasks, so they are read as tasks
+    let first_item = content
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    if first_item.is_some_and(|line| line.starts_with([' ', '\t'])) {
+        problems.push("the first item is indented, so it was read as a task".to_string());
     }
+    problems
+}
 
-    for &(task_idx, subtask_idx) in &subtasks_to_remove {
-        if !whole_tasks_to_remove.contains(&task_idx) {
-            let subtask_text = todo_file.tasks[task_idx].subtasks[subtask_idx].clone();
-            tasks_to_archive.push(Task::new(subtask_text));
-        }
+/// The editor command from `$EDITOR`, or `nano` when it isn't set
+pub fn default_editor() -> String {
+    env::var("EDITOR").unwrap_or_else(|_| "nano".to_string())
+}
+
+/// Opens the todo file in `editor`, then re-reads it and reports how many tasks and
+/// subtasks it holds. If the edit looks broken, offers to reopen the editor.
+pub fn edit_note(
+    todo_path: &Path,
+    review_path: &Path,
+    editor: &str,
+    verbosity: Verbosity,
+) -> Result<()> {
+    // Tasks added while the editor is open still count as new for the next `ls --new`
+    let started = Local::now().naive_local();
+
+    // Create the file if it doesn't exist
+    if !todo_path.exists() {
+        let empty_file = TodoFile::new("TODOs".to_string());
+        let content = generate_todo_file(&empty_file);
+        fs::write(todo_path, content)
+            .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
     }
+    let content = fs::read_to_string(todo_path)
+        .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+    let before = parse_todo_file(&content)
+        .map(|todo_file| count_items(&todo_file))
+        .ok();
 
-    // Load archive file if we're archiving
-    let mut archive_file = if let Some(archive_path) = archive_path {
-        if archive_path.exists() {
-            let archive_content = fs::read_to_string(archive_path)
-                .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
-            parse_archive_file(&archive_content)
-                .map_err(|e| anyhow!("Failed to parse file: {}", e))?
-        } else {
-            ArchiveFile::new()
+    loop {
+        if !run_editor(editor, todo_path)? {
+            return Ok(());
         }
-    } else {
-        ArchiveFile::new()
-    };
 
-    // Add items to archive if we're archiving
-    if should_archive && !tasks_to_archive.is_empty() {
-        if let Some(archive_path) = archive_path {
-            archive_file.add_items_for_today("Default", tasks_to_archive.clone());
-            let archive_content = generate_archive_file(&archive_file);
-            fs::write(archive_path, archive_content)
-                .with_context(|| format!("Failed to write archive: {}", archive_path.display()))?;
+        let content = fs::read_to_string(todo_path)
+            .with_context(|| format!("Failed to read file: {}", todo_path.display()))?;
+        let problems = match parse_todo_file_with_warnings(&content, SubtaskNesting::from_env()) {
+            Ok((todo_file, warnings)) => {
+                let (tasks, subtasks) = count_items(&todo_file);
+                if verbosity.is_porcelain() {
+                    output::porcelain(
+                        "edited",
+                        &[
+                            ("tasks", tasks.to_string()),
+                            ("subtasks", subtasks.to_string()),
+                        ],
+                    );
+                }
+                if !verbosity.is_quiet() {
+                    let was = before
+                        .map(|(tasks, subtasks)| {
+                            format!(
+                                " (was {} and {})",
+                                plural(tasks, "task"),
+                                plural(subtasks, "subtask")
+                            )
+                        })
+                        .unwrap_or_default();
+                    println!(
+                        "Found {} and {}{}",
+                        plural(tasks, "task"),
+                        plural(subtasks, "subtask"),
+                        was
+                    );
+                }
+                report_todo_file(verbosity, todo_path, &todo_file);
+                let mut problems: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+                problems.extend(edit_problems(&content, before, (tasks, subtasks)));
+                problems
+            }
+            Err(e) => vec![format!("The file no longer parses: {}", e)],
+        };
+        if problems.is_empty() {
+            return write_last_review(review_path, started);
         }
-    }
 
-    // Remove items from todo file
-    // Remove subtasks first (in reverse order to maintain indices)
-    let mut subtasks_by_task: std::collections::HashMap<usize, Vec<usize>> =
-        std::collections::HashMap::new();
-    for &(task_idx, subtask_idx) in &subtasks_to_remove {
-        if !whole_tasks_to_remove.contains(&task_idx) {
-            subtasks_by_task
-                .entry(task_idx)
-                .or_default()
-                .push(subtask_idx);
+        for problem in &problems {
+            println!(
+                "{}Warning: {}{}",
+                color::Fg(color::Yellow),
+                problem,
+                color::Fg(color::Reset)
+            );
+        }
+        if !is_interactive() || !confirm("Reopen the editor?", false)? {
+            return write_last_review(review_path, started);
         }
     }
+}
 
-    // Track tasks that might need auto-completion
-    let mut tasks_to_auto_complete = Vec::new();
-
-    for (task_idx, mut subtask_indices) in subtasks_by_task {
-        subtask_indices.sort_by(|a, b| b.cmp(a)); // Sort in reverse order
-        for subtask_idx in subtask_indices {
-            todo_file.tasks[task_idx].subtasks.remove(subtask_idx);
-        }
+/// Splits the `[N] ` prefix that `edit_filtered` puts before each task off its text
+fn split_edit_ref(text: &str) -> Option<(usize, &str)> {
+    let (number, rest) = text.strip_prefix('[')?.split_once("] ")?;
+    Some((number.parse().ok()?, rest))
+}
 
-        // Check if this task now has no subtasks left and should be auto-completed
-        if todo_file.tasks[task_idx].subtasks.is_empty() {
-            tasks_to_auto_complete.push(task_idx);
+/// Opens only the Default tasks matching `filter` (in their text, subtasks or children) in
+/// the user's editor, each prefixed with its number as `[N]`, then merges the result
+/// back: numbered tasks replace the task they came from, unnumbered ones are added at the
+/// top and tasks whose number is gone are removed. Other tasks are left as they are.
+pub fn edit_filtered(
+    todo_path: &Path,
+    filter: &str,
+    editor: &str,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let mut store = TodoStore::new(todo_path);
+    let filter_lower = filter.to_lowercase();
+    let contains = |text: &str| text.to_lowercase().contains(&filter_lower);
+    let tasks = &store.get()?.tasks;
+    let matching: Vec<usize> = tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, task)| {
+            contains(&task.text)
+                || task.subtasks.iter().any(|subtask| {
+                    contains(&subtask.text) || subtask.children.iter().any(|child| contains(child))
+                })
+        })
+        .map(|(task_idx, _)| task_idx)
+        .collect();
+    if matching.is_empty() {
+        if !verbosity.is_quiet() {
+            println!(
+                "{}No items found matching filter: \"{}\"{}",
+                color::Fg(color::Yellow),
+                filter,
+                color::Fg(color::Reset)
+            );
         }
+        return Ok(());
     }
 
-    // Auto-complete parent tasks that have no subtasks left
-    let mut auto_completed_tasks = Vec::new();
-    if !tasks_to_auto_complete.is_empty() {
-        for &task_idx in &tasks_to_auto_complete {
-            auto_completed_tasks.push(todo_file.tasks[task_idx].clone());
+    let mut subset = TodoFile::new(format!("Tasks matching \"{}\"", filter));
+    subset.tasks = matching
+        .iter()
+        .map(|&task_idx| {
+            let mut task = tasks[task_idx].clone();
+            task.text = format!("[{}] {}", task_idx + 1, task.text);
+            task
+        })
+        .collect();
+    // A new file only we can read, removed when `edit_file` is dropped
+    let mut edit_file = tempfile::Builder::new()
+        .prefix("ldr-edit-")
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create a temporary file to edit")?;
+    let edit_path = edit_file.path().to_path_buf();
+    std::io::Write::write_all(&mut edit_file, generate_todo_file(&subset).as_bytes())
+        .with_context(|| format!("Failed to write file: {}", edit_path.display()))?;
+    let edited = run_editor(editor, &edit_path).and_then(|saved| {
+        if !saved {
+            return Ok(None);
         }
+        let content = fs::read_to_string(&edit_path)
+            .with_context(|| format!("Failed to read file: {}", edit_path.display()))?;
+        parse_todo_file(&content)
+            .map(Some)
+            .map_err(|e| anyhow!("Failed to parse {}: {}", edit_path.display(), e))
+    });
+    let Some(edited) = edited? else {
+        return Ok(());
+    };
 
-        // Add auto-completed tasks to archive if we're archiving
-        if should_archive && !auto_completed_tasks.is_empty() {
-            if let Some(archive_path) = archive_path {
-                archive_file.add_items_for_today("Default", auto_completed_tasks.clone());
-                let archive_content = generate_archive_file(&archive_file);
-                fs::write(archive_path, archive_content).with_context(|| {
-                    format!("Failed to write archive: {}", archive_path.display())
-                })?;
+    // Sort the edited tasks back to where they came from before touching anything
+    let mut replaced: std::collections::HashMap<usize, Task> = std::collections::HashMap::new();
+    let mut added = Vec::new();
+    for mut task in edited.tasks {
+        match split_edit_ref(&task.text) {
+            Some((number, text)) => {
+                if !matching.contains(&(number.wrapping_sub(1))) || replaced.contains_key(&number) {
+                    println!(
+                        "{}[{}] isn't a task being edited, or appears twice; nothing was changed (your edits are in {}){}",
+                        color::Fg(color::Red),
+                        number,
+                        edit_path.display(),
+                        color::Fg(color::Reset)
+                    );
+                    let _ = edit_file.keep();
+                    return Err(AlreadyReported.into());
+                }
+                task.text = text.to_string();
+                replaced.insert(number, task);
             }
+            None => added.push(task),
         }
     }
+    drop(edit_file);
 
-    // Remove whole tasks (in reverse order) - include auto-completed tasks
-    let mut whole_task_indices: Vec<_> = whole_tasks_to_remove.into_iter().collect();
-    whole_task_indices.extend(tasks_to_auto_complete);
-    whole_task_indices.sort_by(|a, b| b.cmp(a));
-    whole_task_indices.dedup(); // Remove duplicates in case a task was both manually selected and auto-completed
+    let (changed, removed) = store.mutate(|todo_file| {
+        let mut changed = 0;
+        let mut removed = 0;
+        let mut tasks = Vec::new();
+        for (task_idx, task) in std::mem::take(&mut todo_file.tasks).into_iter().enumerate() {
+            if !matching.contains(&task_idx) {
+                tasks.push(task);
+                continue;
+            }
+            match replaced.remove(&(task_idx + 1)) {
+                Some(edited) => {
+                    if edited != task {
+                        changed += 1;
+                    }
+                    tasks.push(edited);
+                }
+                None => removed += 1,
+            }
+        }
+        // New tasks go on top in the order written, like `add` with its timestamp and ID
+        let now = Local::now().naive_local();
+        tasks.splice(0..0, added.iter().cloned());
+        todo_file.tasks = tasks;
+        // IDs are picked once every task is back, so they can't clash with any of them
+        for task_idx in 0..added.len() {
+            if todo_file.tasks[task_idx].id.is_none() {
+                todo_file.tasks[task_idx].id = Some(todo_file.new_task_id());
+            }
+            let task = &mut todo_file.tasks[task_idx];
+            task.created = task.created.or(Some(now));
+        }
+        Ok((changed, removed))
+    })?;
 
-    for task_idx in whole_task_indices {
-        todo_file.tasks.remove(task_idx);
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "edited",
+            &[
+                ("changed", changed.to_string()),
+                ("added", added.len().to_string()),
+                ("removed", removed.to_string()),
+            ],
+        );
     }
-
-    // Save updated todo file
-    let new_content = generate_todo_file(&todo_file);
-    fs::write(todo_path, new_content)
-        .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
-
-    let total_processed = tasks_to_archive.len() + auto_completed_tasks.len();
-    let action_verb = if should_archive {
-        "Archived"
-    } else {
-        "Removed"
-    };
-    println!(
-        "{}✓ {} {} item(s){}",
-        color::Fg(color::Green),
-        action_verb,
-        total_processed,
-        color::Fg(color::Reset)
-    );
-
-    for task in tasks_to_archive {
+    if !verbosity.is_quiet() {
         println!(
-            "  {}{}{}",
-            color::Fg(color::Red),
-            task.text,
+            "{}✓ Changed {}, added {} and removed {} of the matching tasks{}",
+            color::Fg(color::Green),
+            changed,
+            added.len(),
+            removed,
             color::Fg(color::Reset)
         );
     }
-
-    // Show auto-completed tasks
-    if !auto_completed_tasks.is_empty() {
-        for task in auto_completed_tasks {
-            println!(
-                "  {}{} (auto-completed - all subtasks done){}",
-                color::Fg(color::Magenta),
-                task.text,
-                color::Fg(color::Reset)
-            );
-        }
-    }
-
     Ok(())
 }
 
-/// Archive specified tasks or subtasks
-pub fn archive_items(todo_path: &Path, archive_path: &Path, refs: &[String]) -> Result<()> {
-    process_items_for_removal(todo_path, refs, Some(archive_path))
-}
+/// Format of the time stored by `write_last_review`, to the minute like creation times
+const LAST_REVIEW_FORMAT: &str = "%Y-%m-%dT%H:%M";
 
-/// Remove items without archiving
-pub fn remove_items(todo_path: &Path, refs: &[String]) -> Result<()> {
-    process_items_for_removal(todo_path, refs, None)
+/// Records when the review that just finished started, for `ls --new`
+fn write_last_review(review_path: &Path, started: NaiveDateTime) -> Result<()> {
+    fs::write(
+        review_path,
+        format!("{}\n", started.format(LAST_REVIEW_FORMAT)),
+    )
+    .with_context(|| format!("Failed to write file: {}", review_path.display()))
 }
 
-/// Opens the todo file in the user's preferred editor
-pub fn edit_note(todo_path: &Path) -> Result<()> {
-    let editor = env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
+/// When the last review (`ldr edit`) started, if one has finished
+pub fn read_last_review(review_path: &Path) -> Result<Option<NaiveDateTime>> {
+    if !review_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(review_path)
+        .with_context(|| format!("Failed to read file: {}", review_path.display()))?;
+    NaiveDateTime::parse_from_str(content.trim(), LAST_REVIEW_FORMAT)
+        .map(Some)
+        .with_context(|| format!("Invalid review time in {}", review_path.display()))
+}
 
-    // Create the file if it doesn't exist
-    if !todo_path.exists() {
-        let empty_file = TodoFile::new("TODOs".to_string());
-        let content = generate_todo_file(&empty_file);
-        fs::write(todo_path, content)
-            .with_context(|| format!("Failed to write file: {}", todo_path.display()))?;
+/// Opens the archive file in `editor`, creating it if needed. Afterwards the file is
+/// read back and any lines that no longer parse are reported, with an offer to reopen
+/// the editor.
+pub fn edit_archive(archive_path: &Path, editor: &str, verbosity: Verbosity) -> Result<()> {
+    if !archive_path.exists() {
+        let content = generate_archive_file(&ArchiveFile::new());
+        fs::write(archive_path, content)
+            .with_context(|| format!("Failed to write archive: {}", archive_path.display()))?;
     }
 
-    let status = Command::new(&editor)
-        .arg(todo_path)
-        .status()
-        .with_context(|| format!("Failed to run editor: {}", editor))?;
+    loop {
+        if !run_editor(editor, archive_path)? {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(archive_path)
+            .with_context(|| format!("Failed to read archive: {}", archive_path.display()))?;
+        let (archive_file, warnings) = parse_archive_file_with_warnings(&content);
+        let items: usize = archive_file
+            .entries
+            .iter()
+            .flat_map(|entry| entry.lists.values())
+            .map(Vec::len)
+            .sum();
+        if verbosity.is_porcelain() {
+            output::porcelain(
+                "edited",
+                &[
+                    ("file", "archive".to_string()),
+                    ("items", items.to_string()),
+                    ("days", archive_file.entries.len().to_string()),
+                ],
+            );
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "Found {} archived over {}",
+                plural(items, "item"),
+                plural(archive_file.entries.len(), "day")
+            );
+        }
+        report_archive_file(verbosity, archive_path, &archive_file);
+        if warnings.is_empty() {
+            return Ok(());
+        }
+
+        for warning in &warnings {
+            println!(
+                "{}Warning: {}{}",
+                color::Fg(color::Yellow),
+                warning,
+                color::Fg(color::Reset)
+            );
+        }
+        if !is_interactive() || !confirm("Reopen the editor?", false)? {
+            return Ok(());
+        }
+    }
+}
 
+/// Runs `editor` on `path`, returning false (after saying so) if it exited with an error.
+/// The command is split like a shell would, so `code --wait` or a quoted path works.
+fn run_editor(editor: &str, path: &Path) -> Result<bool> {
+    let words = shlex::split(editor)
+        .ok_or_else(|| anyhow!("Invalid editor command (unbalanced quotes): {}", editor))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(anyhow!(
+            "No editor given; set $EDITOR (e.g., export EDITOR=\"code --wait\") or pass --editor"
+        ));
+    };
+    let status = match Command::new(program).args(args).arg(path).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "Editor '{}' not found; set $EDITOR to an installed editor (e.g., export EDITOR=\"code --wait\") or pass --editor",
+                program
+            ));
+        }
+        Err(e) => {
+            return Err(anyhow!(e).context(format!("Failed to run editor: {}", editor)));
+        }
+    };
     if !status.success() {
         println!(
             "{}Editor exited with error code: {}{}",
@@ -783,6 +5683,5 @@ pub fn edit_note(todo_path: &Path) -> Result<()> {
             color::Fg(color::Reset)
         );
     }
-
-    Ok(())
+    Ok(status.success())
 }
@@ -0,0 +1,160 @@
+//! User settings from `$XDG_CONFIG_HOME/ldr/config.toml`.
+//!
+//! The file holds flat `key = value` lines in TOML syntax: values are `true`/`false`,
+//! numbers or quoted strings, and `#` starts a comment. Every setting is optional.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Settings read from the config file, with defaults for anything not set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Complete a task when its last subtask is archived with `do`
+    pub auto_complete_empty_parents: bool,
+    /// Remove a task along with its last subtask under `rm` (not archived either way)
+    pub remove_empty_parents: bool,
+    /// Color theme name (see `output::THEMES`); `$LDR_THEME` takes precedence
+    pub theme: Option<String>,
+    /// Desktop notifications on `add` and `do`, in builds with the `notify` feature
+    pub notifications: bool,
+    /// Append each item archived by `do` to `log.jsonl` in the data directory as well
+    pub completion_log: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            auto_complete_empty_parents: true,
+            remove_empty_parents: false,
+            theme: None,
+            notifications: false,
+            completion_log: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, or the defaults when there is none
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let Some(path) = path.filter(|path| path.exists()) else {
+            return Ok(Config::default());
+        };
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?;
+        Config::parse(&content).map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))
+    }
+
+    /// Parses config file content. Unknown keys are rejected so typos don't go unnoticed.
+    pub fn parse(content: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (line_idx, line) in content.lines().enumerate() {
+            let line_num = line_idx + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", line_num))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "auto_complete_empty_parents" => {
+                    config.auto_complete_empty_parents = parse_bool(value, line_num)?
+                }
+                "remove_empty_parents" => {
+                    config.remove_empty_parents = parse_bool(value, line_num)?
+                }
+                "theme" => config.theme = Some(parse_string(value, line_num)?),
+                "notifications" => config.notifications = parse_bool(value, line_num)?,
+                "completion_log" => config.completion_log = parse_bool(value, line_num)?,
+                _ => return Err(format!("line {}: unknown setting '{}'", line_num, key)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Drops a trailing `# comment`, leaving `#` inside quoted strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_bool(value: &str, line_num: usize) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!(
+            "line {}: expected true or false, got '{}'",
+            line_num, value
+        )),
+    }
+}
+
+fn parse_string(value: &str, line_num: usize) -> Result<String, String> {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .map(str::to_string)
+        .ok_or_else(|| {
+            format!(
+                "line {}: expected a quoted string, got '{}'",
+                line_num, value
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings_and_comments() {
+        let config = Config::parse(
+            "# ldr settings\n\nauto_complete_empty_parents = false  # keep parents\n",
+        )
+        .unwrap();
+        assert!(!config.auto_complete_empty_parents);
+        assert!(!config.remove_empty_parents);
+        assert!(
+            Config::parse("remove_empty_parents = true")
+                .unwrap()
+                .remove_empty_parents
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        let config = Config::parse("theme = \"solarized\" # easier on the eyes").unwrap();
+        assert_eq!(config.theme.as_deref(), Some("solarized"));
+        assert!(Config::parse("notifications = true").unwrap().notifications);
+        assert!(
+            Config::parse("completion_log = true")
+                .unwrap()
+                .completion_log
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Config::parse("auto_complete_empty_parents = maybe").unwrap_err(),
+            "line 1: expected true or false, got 'maybe'"
+        );
+        assert_eq!(
+            Config::parse("\nauto_complete = false").unwrap_err(),
+            "line 2: unknown setting 'auto_complete'"
+        );
+        assert!(Config::parse("auto_complete_empty_parents").is_err());
+        assert_eq!(
+            Config::parse("theme = dark").unwrap_err(),
+            "line 1: expected a quoted string, got 'dark'"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Date parsing helpers for due dates.
+//!
+//! Dates given on the command line are resolved to a concrete calendar date
+//! before being stored, so the Markdown file always contains absolute dates.
+
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+
+/// Parses a due date argument such as `2025-05-01`, `today`, `tomorrow`, `3d` or `fri`,
+/// relative to the local date.
+pub fn parse_due_date(input: &str) -> Result<NaiveDate, String> {
+    parse_due_date_from(input, Local::now().date_naive())
+}
+
+/// Parses a due date argument relative to `today`. `Nd` is N days out, and a weekday
+/// name (`fri` or `friday`) is the next such day after today.
+pub fn parse_due_date_from(input: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let input = input.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let lower = input.to_ascii_lowercase();
+    let relative = match lower.as_str() {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        _ => None,
+    };
+    let relative = relative
+        .or_else(|| {
+            let days: u32 = lower.strip_suffix('d')?.parse().ok()?;
+            today.checked_add_signed(Duration::days(days.into()))
+        })
+        .or_else(|| {
+            let weekday = lower.parse::<Weekday>().ok()?;
+            let days_ahead =
+                (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            // The same weekday as today means next week
+            let days_ahead = if days_ahead == 0 { 7 } else { days_ahead };
+            today.checked_add_signed(Duration::days(days_ahead.into()))
+        });
+
+    relative.ok_or_else(|| {
+        format!(
+            "Invalid due date '{}'. Expected YYYY-MM-DD, today, tomorrow, a number of days like 3d, or a weekday like fri",
+            input
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_iso_due_date() {
+        assert_eq!(
+            parse_due_date("2025-05-01").unwrap(),
+            NaiveDate::from_ymd_opt(2025, 5, 1).unwrap()
+        );
+        assert_eq!(
+            parse_due_date(" 2025-12-31 ").unwrap(),
+            NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_relative_due_date() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2025, 4, 30).unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(parse_due_date_from("today", today).unwrap(), today);
+        assert_eq!(
+            parse_due_date_from("Tomorrow", today).unwrap(),
+            date(2025, 5, 1)
+        );
+        assert_eq!(parse_due_date_from("3d", today).unwrap(), date(2025, 5, 3));
+        assert_eq!(parse_due_date_from("0d", today).unwrap(), today);
+        assert_eq!(parse_due_date_from("fri", today).unwrap(), date(2025, 5, 2));
+        assert_eq!(
+            parse_due_date_from("monday", today).unwrap(),
+            date(2025, 5, 5)
+        );
+        assert_eq!(parse_due_date_from("wed", today).unwrap(), date(2025, 5, 7));
+    }
+
+    #[test]
+    fn test_parse_invalid_due_date() {
+        assert!(parse_due_date("").is_err());
+        assert!(parse_due_date("2025-13-01").is_err());
+        assert!(parse_due_date("May 1st").is_err());
+        assert!(parse_due_date("-2d").is_err());
+        assert!(parse_due_date("d").is_err());
+        assert!(parse_due_date("someday")
+            .unwrap_err()
+            .contains("a weekday like fri"));
+    }
+}
@@ -0,0 +1,365 @@
+//! `ldr doctor`: a health check for hand-edited data files.
+//!
+//! The parsers are forgiving, so damage in `todos.md` or `archive.md` usually goes
+//! unnoticed until the next write quietly reshapes it. The doctor runs both files through
+//! the same parsers, adds a few checks of its own on the raw lines, and reports every
+//! problem with its line number. With `--fix`, a file with problems the parsers can
+//! repair is rewritten from its parsed form, after a `.bak` copy is saved, unless the
+//! rewrite would drop lines the parsers can't read.
+
+use crate::commands::AlreadyReported;
+use crate::markdown::{
+    generate_archive_file, generate_todo_file, parse_archive_file_with_warnings,
+    parse_todo_file_with_warnings, ParseWarning, SubtaskNesting,
+};
+use crate::output::{self, color, Verbosity};
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Items longer than this many characters are reported as suspicious
+pub const LONG_TEXT_CHARS: usize = 200;
+
+/// One problem found in a data file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub line: usize,
+    pub message: String,
+    /// Whether rewriting the file through the parser repairs it
+    pub fixable: bool,
+    /// Whether rewriting the file would drop what's on this line, so `--fix` leaves the
+    /// file alone until it's fixed by hand
+    pub lost_on_rewrite: bool,
+}
+
+/// Control characters other than tabs, which can't be seen but break matching and display
+fn is_stray_control(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+/// Checks every line of `content` for control characters and overly long items
+fn check_lines(content: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        if let Some(c) = line.chars().find(|&c| is_stray_control(c)) {
+            problems.push(Problem {
+                line: line_idx + 1,
+                message: format!("control character U+{:04X}", c as u32),
+                fixable: true,
+                lost_on_rewrite: false,
+            });
+        }
+        let rest = line.trim_start();
+        let item = rest
+            .strip_prefix("- ")
+            .or_else(|| rest.strip_prefix("* "))
+            .or_else(|| rest.strip_prefix("+ "));
+        // Metadata comments don't count towards the length
+        let text = item.map(|item| item.split("<!--").next().unwrap_or(item).trim());
+        if let Some(length) = text.map(|text| text.chars().count()) {
+            if length > LONG_TEXT_CHARS {
+                problems.push(Problem {
+                    line: line_idx + 1,
+                    message: format!("item is {} characters long; was a line break lost?", length),
+                    fixable: false,
+                    lost_on_rewrite: false,
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// Turns a parser warning into a problem; everything but unreadable archive lines is
+/// repaired by writing the parsed file back
+fn warning_problem(warning: &ParseWarning) -> Problem {
+    let (message, fixable) = match warning {
+        ParseWarning::DeepNesting { level, .. } => (
+            format!(
+                "items nested deeper than supported (flattened to level {})",
+                level
+            ),
+            true,
+        ),
+        ParseWarning::MixedBullets { bullet, .. } => {
+            (format!("mixed bullets (rewritten with '{}')", bullet), true)
+        }
+        ParseWarning::OrphanedSubtask { .. } => (
+            "subtask without a task above it (becomes a task)".to_string(),
+            true,
+        ),
+        ParseWarning::UnrecognizedLine { text, .. } => (
+            format!("unrecognized line, which a rewrite would drop: {}", text),
+            false,
+        ),
+    };
+    Problem {
+        line: warning.line(),
+        message,
+        fixable,
+        lost_on_rewrite: matches!(warning, ParseWarning::UnrecognizedLine { .. }),
+    }
+}
+
+/// Date sections appearing more than once, reported at each repeat
+fn check_archive_dates(content: &str) -> Vec<Problem> {
+    let mut first_seen: HashMap<&str, usize> = HashMap::new();
+    let mut problems = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        let Some(date) = line.trim().strip_prefix("## ") else {
+            continue;
+        };
+        match first_seen.get(date) {
+            Some(first) => problems.push(Problem {
+                line: line_idx + 1,
+                message: format!("duplicate date section {} (first at line {})", date, first),
+                fixable: true,
+                lost_on_rewrite: false,
+            }),
+            None => {
+                first_seen.insert(date, line_idx + 1);
+            }
+        }
+    }
+    problems
+}
+
+/// Problems in a todo file, in line order, with the file as the parser would write it
+pub fn check_todo_file(content: &str, nesting: SubtaskNesting) -> (Vec<Problem>, String) {
+    let mut problems = check_lines(content);
+    let cleaned: String = content
+        .chars()
+        .filter(|&c| c == '\n' || !is_stray_control(c))
+        .collect();
+    let repaired = match parse_todo_file_with_warnings(content, nesting) {
+        Ok((_, warnings)) => {
+            problems.extend(warnings.iter().map(warning_problem));
+            parse_todo_file_with_warnings(&cleaned, nesting)
+                .map(|(todo_file, _)| generate_todo_file(&todo_file))
+                .unwrap_or_else(|_| content.to_string())
+        }
+        Err(e) => {
+            problems.push(Problem {
+                line: 0,
+                message: format!("can't be read: {}", e),
+                fixable: false,
+                lost_on_rewrite: true,
+            });
+            content.to_string()
+        }
+    };
+    problems.sort_by_key(|problem| problem.line);
+    (problems, repaired)
+}
+
+/// Problems in an archive file, in line order, with the file as the parser would write it
+pub fn check_archive_file(content: &str) -> (Vec<Problem>, String) {
+    let mut problems = check_lines(content);
+    problems.extend(check_archive_dates(content));
+    let (_, warnings) = parse_archive_file_with_warnings(content);
+    problems.extend(warnings.iter().map(warning_problem));
+    problems.sort_by_key(|problem| problem.line);
+
+    let cleaned: String = content
+        .chars()
+        .filter(|&c| c == '\n' || !is_stray_control(c))
+        .collect();
+    let (archive_file, _) = parse_archive_file_with_warnings(&cleaned);
+    (problems, generate_archive_file(&archive_file))
+}
+
+/// Path of the backup written before a file is repaired, e.g. `todos.md.bak`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// Checks `todos.md` and `archive.md` and prints a report. With `fix`, each file with a
+/// repairable problem is backed up and rewritten, unless the rewrite would drop lines
+/// that couldn't be read. Fails (after printing the report) if problems remain.
+pub fn run_doctor(
+    todo_path: &Path,
+    archive_path: &Path,
+    fix: bool,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let mut found = 0;
+    let mut fixed = 0;
+    // Problems `--fix` would repair, and those (including repairs held back by
+    // unreadable lines) that need a hand edit
+    let mut repairable = 0;
+    let mut by_hand = 0;
+    for (path, is_archive) in [(todo_path, false), (archive_path, true)] {
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let (problems, repaired) = if is_archive {
+            check_archive_file(&content)
+        } else {
+            check_todo_file(&content, SubtaskNesting::from_env())
+        };
+        if problems.is_empty() {
+            continue;
+        }
+
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let blocked = problems.iter().any(|problem| problem.lost_on_rewrite);
+        let repairs = fix && !blocked && problems.iter().any(|problem| problem.fixable);
+        if repairs {
+            let backup = backup_path(path);
+            fs::copy(path, &backup)
+                .with_context(|| format!("Failed to write backup: {}", backup.display()))?;
+            fs::write(path, &repaired)
+                .with_context(|| format!("Failed to write file: {}", path.display()))?;
+        }
+
+        found += problems.len();
+        for problem in &problems {
+            match (problem.fixable, repairs, blocked) {
+                (true, true, _) => fixed += 1,
+                (true, false, false) => repairable += 1,
+                _ => by_hand += 1,
+            }
+        }
+        if verbosity.is_quiet() {
+            continue;
+        }
+        println!(
+            "{}{}{}",
+            output::style(termion::style::Bold),
+            name,
+            output::style(termion::style::Reset)
+        );
+        for problem in &problems {
+            let status = match (problem.fixable, repairs, blocked) {
+                (true, true, _) => " (fixed)",
+                (true, false, false) => " (--fix repairs this)",
+                (true, false, true) => {
+                    " (--fix repairs this once the unrecognized lines are fixed)"
+                }
+                (false, _, _) => "",
+            };
+            println!(
+                "  {}line {}: {}{}{}",
+                color::Fg(color::Yellow),
+                problem.line,
+                problem.message,
+                status,
+                color::Fg(color::Reset)
+            );
+        }
+        if repairs {
+            println!(
+                "  {}✓ Rewrote {} (backup: {}){}",
+                color::Fg(color::Green),
+                name,
+                backup_path(path).display(),
+                color::Fg(color::Reset)
+            );
+        } else if fix && blocked {
+            println!(
+                "  {}Not rewritten: that would drop the unrecognized lines{}",
+                color::Fg(color::Red),
+                color::Fg(color::Reset)
+            );
+        }
+    }
+
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "doctor",
+            &[
+                ("problems", found.to_string()),
+                ("fixed", fixed.to_string()),
+            ],
+        );
+    }
+    if repairable + by_hand == 0 {
+        if !verbosity.is_quiet() && found == 0 {
+            println!(
+                "{}✓ No problems found{}",
+                color::Fg(color::Green),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+    if verbosity.is_quiet() {
+        return Err(anyhow!(
+            "{} problem(s) found in the data files",
+            repairable + by_hand
+        ));
+    }
+    if repairable > 0 {
+        println!(
+            "{}{} problem(s) can be repaired with `ldr doctor --fix`{}",
+            color::Fg(color::Yellow),
+            repairable,
+            color::Fg(color::Reset)
+        );
+    }
+    if by_hand > 0 {
+        println!(
+            "{}{} problem(s) left to fix by hand{}",
+            color::Fg(color::Red),
+            by_hand,
+            color::Fg(color::Reset)
+        );
+    }
+    Err(AlreadyReported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_todo_problems_have_line_numbers() {
+        let long = "x".repeat(LONG_TEXT_CHARS + 1);
+        let content = format!(
+            "# TODOs\n\n- Call\u{7} Bob\n* Starred\n- {}\n\n## Work\n  - Stray\n",
+            long
+        );
+
+        let (problems, repaired) = check_todo_file(&content, SubtaskNesting::Flatten);
+        let found: Vec<(usize, bool)> = problems
+            .iter()
+            .map(|problem| (problem.line, problem.fixable))
+            .collect();
+        assert_eq!(found, vec![(3, true), (4, true), (5, false), (8, true)]);
+        assert!(problems[0].message.contains("U+0007"));
+        assert_eq!(
+            repaired,
+            format!(
+                "# TODOs\n\n- Call Bob\n- Starred\n- {}\n\n## Work\n\n- Stray\n",
+                long
+            )
+        );
+    }
+
+    #[test]
+    fn test_archive_duplicate_dates_are_merged() {
+        let content = "# Archive\n\n## 2025-05-01\n- First\n\n## 2025-04-30\n- Old\n\n## 2025-05-01\n- Second\n";
+
+        let (problems, repaired) = check_archive_file(content);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, 9);
+        assert_eq!(
+            problems[0].message,
+            "duplicate date section 2025-05-01 (first at line 3)"
+        );
+        assert_eq!(
+            repaired,
+            "# Archive\n\n## 2025-05-01\n- First\n- Second\n\n## 2025-04-30\n- Old\n"
+        );
+
+        assert!(check_archive_file(&repaired).0.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+//! Identity of the current user and machine, used to filter shared todo files.
+
+use crate::markdown::Task;
+use std::env;
+use std::process::Command;
+
+/// Overrides the user name used by `ls --mine` (defaults to `$USER`)
+pub const WHO_ENV: &str = "LDR_WHO";
+
+/// Overrides the host name used by `ls --mine` (defaults to the machine's hostname)
+pub const HOST_ENV: &str = "LDR_HOST";
+
+/// Who and where `ldr` is running, compared against `who:`/`host:` task metadata
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Identity {
+    pub who: Option<String>,
+    pub host: Option<String>,
+}
+
+impl Identity {
+    /// Resolves the identity from `LDR_WHO`/`LDR_HOST`, falling back to `$USER` and the hostname
+    pub fn current() -> Self {
+        Identity {
+            who: env_value(WHO_ENV).or_else(|| env_value("USER")),
+            host: env_value(HOST_ENV).or_else(system_hostname),
+        }
+    }
+
+    /// True when every `who:`/`host:` field set on the task matches this identity.
+    /// Tasks with neither field match unless `strict` is set.
+    pub fn matches(&self, task: &Task, strict: bool) -> bool {
+        if task.who.is_none() && task.host.is_none() {
+            return !strict;
+        }
+
+        let who_matches = match &task.who {
+            Some(who) => self
+                .who
+                .as_deref()
+                .is_some_and(|mine| mine.eq_ignore_ascii_case(who)),
+            None => true,
+        };
+        let host_matches = match &task.host {
+            Some(host) => self.host.as_deref().is_some_and(|mine| {
+                // A tag for "laptop" also matches "laptop.local"
+                let short = mine.split('.').next().unwrap_or(mine);
+                mine.eq_ignore_ascii_case(host) || short.eq_ignore_ascii_case(host)
+            }),
+            None => true,
+        };
+
+        who_matches && host_matches
+    }
+}
+
+fn env_value(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn system_hostname() -> Option<String> {
+    env_value("HOSTNAME").or_else(|| {
+        let output = Command::new("hostname").output().ok()?;
+        let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !name.is_empty()).then_some(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(who: Option<&str>, host: Option<&str>) -> Task {
+        let mut task = Task::new("Task".to_string());
+        task.who = who.map(str::to_string);
+        task.host = host.map(str::to_string);
+        task
+    }
+
+    #[test]
+    fn test_matches_tagged_tasks() {
+        let me = Identity {
+            who: Some("alice".to_string()),
+            host: Some("laptop.local".to_string()),
+        };
+
+        assert!(me.matches(&tagged(Some("alice"), None), false));
+        assert!(me.matches(&tagged(Some("Alice"), Some("laptop")), false));
+        assert!(me.matches(&tagged(None, Some("laptop.local")), true));
+        assert!(!me.matches(&tagged(Some("bob"), None), false));
+        assert!(!me.matches(&tagged(Some("alice"), Some("desktop")), false));
+    }
+
+    #[test]
+    fn test_untagged_tasks_depend_on_strict() {
+        let me = Identity::default();
+        let untagged = tagged(None, None);
+
+        assert!(me.matches(&untagged, false));
+        assert!(!me.matches(&untagged, true));
+        // Without a known identity, tagged tasks never match
+        assert!(!me.matches(&tagged(Some("alice"), None), false));
+    }
+}
@@ -1,11 +1,38 @@
 //! Input handling utilities for interactive features.
 //!
 //! This module provides functions for reading raw keyboard input,
-//! particularly for handling arrow keys in the interactive review mode.
+//! particularly for handling arrow keys in the interactive review mode,
+//! and the confirmation prompt shared by destructive commands.
 
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use termion::raw::IntoRawMode;
 
+/// Returns true when stdin is attached to a terminal, so prompts can be answered.
+pub fn is_interactive() -> bool {
+    termion::is_tty(&io::stdin())
+}
+
+/// Asks the user to confirm a destructive operation with a `[y/N]` prompt.
+/// Always confirms when `assume_yes` is set (e.g. `--yes`). In non-interactive mode
+/// there is nobody to answer, so this refuses instead of blocking on stdin.
+pub fn confirm(prompt: &str, assume_yes: bool) -> io::Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+
+    if !is_interactive() {
+        eprintln!("refusing destructive operation without --yes in non-interactive mode.");
+        return Ok(false);
+    }
+
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 /// Reads raw keyboard input including arrow keys and special characters.
 /// Handles ANSI escape sequences for arrow keys and returns string representations.
 /// Used for interactive navigation in review mode.
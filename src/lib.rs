@@ -0,0 +1,28 @@
+//! LDR - Log, Do, Review
+//!
+//! The file formats behind the `ldr` command, for tools that want to read or write its
+//! todo and archive files without running the binary. Printing, colors and the commands
+//! themselves stay in the binary.
+//!
+//! ```
+//! use ldr::{generate_todo_file, parse_todo_file, Task};
+//!
+//! let mut todo_file = parse_todo_file("# TODOs\n\n- Write report\n").unwrap();
+//! todo_file.tasks.insert(0, Task::new("Call Bob".to_string()));
+//! assert_eq!(
+//!     generate_todo_file(&todo_file),
+//!     "# TODOs\n\n- Call Bob\n- Write report\n"
+//! );
+//! ```
+
+pub mod content;
+pub mod dates;
+pub mod markdown;
+
+pub use dates::parse_due_date;
+pub use markdown::{
+    generate_archive_file, generate_todo_file, parse_archive_file,
+    parse_archive_file_with_warnings, parse_todo_file, parse_todo_file_with_nesting,
+    parse_todo_file_with_warnings, parse_todo_head, ArchiveEntry, ArchiveFile, ParseWarning,
+    Recurrence, Subtask, SubtaskNesting, Task, TaskList, TaskRef, TodoFile, TodoHead, DEFAULT_LIST,
+};
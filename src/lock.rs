@@ -0,0 +1,167 @@
+//! Cross-process lock for commands that change the data files.
+//!
+//! Two `ldr add` runs at once would both read `todos.md` and the second write would drop
+//! the first one's task. Commands that change files therefore hold `ldr.lock` in the
+//! data directory while they run: the file is created exclusively, holds the owner's
+//! process ID, and is removed again when the `DataLock` is dropped. Reading commands
+//! don't take the lock. A lock whose owner has exited is taken over, by renaming it
+//! aside first so that two waiters can't both remove it.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another `ldr` to finish before giving up
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause between attempts to take the lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The held lock; dropping it releases the lock
+#[derive(Debug)]
+pub struct DataLock {
+    path: PathBuf,
+}
+
+impl DataLock {
+    /// Takes the lock at `path`, waiting up to `LOCK_TIMEOUT` for another `ldr` to let go.
+    /// A lock left behind by a process that no longer runs is taken over.
+    pub fn acquire(path: &Path) -> Result<DataLock> {
+        Self::acquire_within(path, LOCK_TIMEOUT)
+    }
+
+    fn acquire_within(path: &Path, timeout: Duration) -> Result<DataLock> {
+        let started = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(mut file) => {
+                    // The owner's ID only helps to spot stale locks, so failing to write
+                    // it doesn't matter
+                    let _ = write!(file, "{}", std::process::id());
+                    return Ok(DataLock {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if stale_owner(path).is_some_and(|pid| take_over(path, pid)) {
+                        continue;
+                    }
+                    if started.elapsed() >= timeout {
+                        return Err(anyhow!(
+                            "Another ldr is running (lock file: {}); if none is, delete the lock file",
+                            path.display()
+                        ));
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create lock file: {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DataLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The process ID in a lock file, if it has one yet
+fn lock_owner(path: &Path) -> Option<u32> {
+    // An empty file is a lock whose owner hasn't written its ID yet
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// The owner of the lock if it's a process that has exited. Only Linux exposes running
+/// processes under `/proc`; elsewhere a lock is never considered stale.
+fn stale_owner(path: &Path) -> Option<u32> {
+    let pid = lock_owner(path)?;
+    let exited = Path::new("/proc/self").exists() && !Path::new(&format!("/proc/{}", pid)).exists();
+    exited.then_some(pid)
+}
+
+/// Removes the stale lock of `stale_pid`, returning whether it's gone. The lock is
+/// renamed to a name of this process's own first: a rename is atomic, so of several
+/// waiters only one moves any given file. That file is then checked again, since
+/// another waiter may already have replaced the stale lock with its own; a live lock
+/// is put back, unless the lock has been taken anew in the meantime.
+fn take_over(path: &Path, stale_pid: u32) -> bool {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(format!(".stale.{}", std::process::id()));
+    let aside = PathBuf::from(aside);
+    if fs::rename(path, &aside).is_err() {
+        // Someone else moved or released it; just try again
+        return false;
+    }
+    let gone = lock_owner(&aside) == Some(stale_pid);
+    if !gone {
+        // Linking fails rather than replace a lock created since
+        let _ = fs::hard_link(&aside, path);
+    }
+    let _ = fs::remove_file(&aside);
+    gone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("ldr.lock");
+
+        let lock = DataLock::acquire(&path).unwrap();
+        let error = DataLock::acquire_within(&path, Duration::from_millis(50)).unwrap_err();
+        assert!(error.to_string().starts_with("Another ldr is running"));
+
+        drop(lock);
+        assert!(!path.exists());
+        DataLock::acquire_within(&path, Duration::from_millis(50)).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_lock_of_exited_process_is_taken_over() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("ldr.lock");
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+
+        let _lock = DataLock::acquire_within(&path, Duration::from_millis(50)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_take_over_leaves_a_live_lock_in_place() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("ldr.lock");
+
+        // A waiter that judged the lock stale finds this process's fresh lock instead
+        let lock = DataLock::acquire(&path).unwrap();
+        assert!(!take_over(&path, u32::MAX));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+        assert!(take_over(&path, u32::MAX));
+        assert!(!path.exists());
+        drop(lock);
+    }
+}
@@ -5,13 +5,24 @@
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
+use ldr::{dates, markdown};
+use output::Verbosity;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use xdg::BaseDirectories;
 
 mod commands;
-mod content;
+mod config;
+mod doctor;
+mod identity;
 mod input;
-mod markdown;
+mod lock;
 mod migration;
+mod notify;
+mod output;
+mod store;
+mod undo;
 
 #[cfg(test)]
 mod tests;
@@ -22,6 +33,25 @@ mod tests;
 #[command(about = "Log, Do, Review - A simple todo system", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Suppress confirmations and other messages (useful for cron jobs); listings and reports still print
+    #[arg(short = 'q', long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print the data file path and item counts as key=value pairs
+    #[arg(short = 'v', long, global = true)]
+    verbose: bool,
+    /// Confirm changes with stable `event key=value` lines for scripts, e.g.
+    /// `added ref=1 text="Buy milk"` (only affects commands that change files)
+    #[arg(long, global = true, conflicts_with_all = ["quiet", "verbose"])]
+    porcelain: bool,
+    /// When to color output: auto colors only on a terminal without NO_COLOR set
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = output::ColorChoice::Auto)]
+    color: output::ColorChoice,
+    /// Use 24-bit colors (also enabled by COLORTERM=truecolor)
+    #[arg(long, global = true)]
+    truecolor: bool,
+    /// Keep todos.md and archive.md in DIR instead of the XDG data directory (also $LDR_DATA_DIR)
+    #[arg(long, global = true, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,11 +62,42 @@ enum Commands {
     /// Add a new item at the top
     #[command(aliases = ["a", "prepend"])]
     Add {
-        /// The text to add (e.g., "Read: Book XYZ")
+        /// The text to add (e.g., "Read: Book XYZ"); it may start with "-", like "- [ ] draft"
+        #[arg(allow_hyphen_values = true)]
         text: String,
-        /// Add as subtask under this task number (e.g., --under 3)
+        /// Add as subtask under this task (e.g., --under 3), or right after a subtask (e.g., --under 3a)
         #[arg(long)]
-        under: Option<usize>,
+        under: Option<String>,
+        /// Due date for the new task (e.g., --due 2025-05-01, tomorrow, 3d or fri)
+        #[arg(long)]
+        due: Option<String>,
+        /// Add to this named list instead of the default one, creating it if needed (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+        /// Accepted for older scripts; --list now creates missing lists by itself
+        #[arg(long, requires = "list", hide = true)]
+        create: bool,
+        /// Add at the bottom of the list instead of the top
+        #[arg(long, visible_alias = "bottom", conflicts_with = "under")]
+        append: bool,
+        /// Add right below this task instead of at the top (e.g., --after 3)
+        #[arg(long, value_name = "REF", conflicts_with_all = ["under", "append", "before"])]
+        after: Option<String>,
+        /// Add right above this task instead of at the top (e.g., --before 3)
+        #[arg(long, value_name = "REF", conflicts_with_all = ["under", "append"])]
+        before: Option<String>,
+        /// Tag the task for a person on a shared file (see `ls --mine`)
+        #[arg(long)]
+        who: Option<String>,
+        /// Tag the task for a machine on a shared file (see `ls --mine`)
+        #[arg(long)]
+        host: Option<String>,
+        /// Make the task recur daily or weekly: archiving it with `do` adds it back with the next due date
+        #[arg(long, value_name = "daily|weekly")]
+        recur: Option<markdown::Recurrence>,
+        /// Add even if an identical item already exists
+        #[arg(long)]
+        force: bool,
     },
     /// List the top N items (default 5)
     #[command(aliases = ["l", "list"])]
@@ -48,74 +109,1027 @@ enum Commands {
         all: bool,
         /// Filter items containing this text (e.g., "read:", "@work")
         filter: Option<String>,
+        /// When filtering, don't pull in all subtasks of a matching task; show only matching subtasks
+        #[arg(long)]
+        match_subtasks_only: bool,
+        /// Show how long ago each task was added (e.g., "(3d)")
+        #[arg(long)]
+        age: bool,
+        /// Show oldest items first
+        #[arg(short = 'r', long)]
+        reverse: bool,
+        /// Color overdue tasks red and tasks due today yellow (order is unchanged)
+        #[arg(long)]
+        highlight_due: bool,
+        /// Only show tasks tagged for you ($LDR_WHO or $USER) or this machine ($LDR_HOST or hostname)
+        #[arg(long)]
+        mine: bool,
+        /// Like --mine, but also hide tasks without who/host tags
+        #[arg(long)]
+        strict_mine: bool,
+        /// Print uncolored "REF<TAB>TEXT" records for scripts
+        #[arg(long, conflicts_with = "numbers_only")]
+        plain: bool,
+        /// Print only item references (e.g., "2a")
+        #[arg(long)]
+        numbers_only: bool,
+        /// Delimit plain records with this string instead of a newline (implies --plain)
+        #[arg(long, conflicts_with = "null")]
+        separator: Option<String>,
+        /// Delimit plain records with NUL, for `xargs -0` (implies --plain)
+        #[arg(short = '0', long)]
+        null: bool,
+        /// Wrap long items to this many columns (default: terminal width; no wrapping when piped)
+        #[arg(long)]
+        width: Option<usize>,
+        /// Show due dates and ages as right-aligned columns, cutting long items short to fit
+        /// the width instead of wrapping them (80 columns when piped)
+        #[arg(long)]
+        columns: bool,
+        /// Color tasks by position (alternating) or by age (green = fresh, red = stale)
+        #[arg(long, value_enum, default_value_t = commands::ColorBy::Position)]
+        color_by: commands::ColorBy,
+        /// Order tasks alphabetically, by text length or by first @tag (numbers stay the same)
+        #[arg(long, value_enum, default_value_t = commands::SortOrder::Position)]
+        sort: commands::SortOrder,
+        /// Show only top-level tasks, without their subtasks
+        #[arg(long, conflicts_with = "subtasks_only")]
+        tasks_only: bool,
+        /// Show only subtasks, each with its full reference (e.g., "2a")
+        #[arg(long)]
+        subtasks_only: bool,
+        /// Mark tasks that are new or moved since the last `ls --since-file-mtime`
+        #[arg(long)]
+        since_file_mtime: bool,
+        /// Print the listed items as JSON, with subtasks nested under their task
+        #[arg(long, conflicts_with_all = ["plain", "numbers_only", "separator", "null"])]
+        json: bool,
+        /// Number every item, subtasks included, in one sequence (use `do`/`up`/`rm --flat`
+        /// with those numbers); with --json, emit one flat array where subtasks carry a
+        /// "parent" ref
+        #[arg(long)]
+        flat: bool,
+        /// Print only the number of matching items, counting tasks, subtasks and nested items alike (ignores -n/--all; add --tasks-only to count just tasks)
+        #[arg(long, conflicts_with_all = ["plain", "numbers_only", "separator", "null", "json"])]
+        count: bool,
+        /// List tasks under a header per @tag (numbers stay the same)
+        #[arg(long, value_enum, conflicts_with_all = ["plain", "numbers_only", "separator", "null", "json", "count"])]
+        group_by: Option<commands::GroupBy>,
+        /// Show only the task set with `ldr focus` (the default while one is set)
+        #[arg(long, conflicts_with = "no_focus")]
+        focused: bool,
+        /// Show every task even while one is focused
+        #[arg(long)]
+        no_focus: bool,
+        /// Draw subtasks as a tree with box-drawing lines, labeled with full references
+        #[arg(long, conflicts_with_all = ["plain", "numbers_only", "separator", "null", "json", "count", "flat", "subtasks_only"])]
+        tree: bool,
+        /// After the open items, show the N most recently archived ones
+        #[arg(long, value_name = "N", conflicts_with_all = ["plain", "numbers_only", "separator", "null", "json", "count"])]
+        done: Option<usize>,
+        /// Show items nested at most this deep (0 = tasks only), with a dim count of the
+        /// subitems hidden under each, e.g. " (3)"
+        #[arg(long, value_name = "DEPTH", conflicts_with_all = ["tasks_only", "subtasks_only"])]
+        max_depth: Option<usize>,
+        /// Show only tasks added since the last review (`ldr edit`); with no review yet,
+        /// every task with a creation time
+        #[arg(long, visible_alias = "since-last-review")]
+        new: bool,
+        /// Print the listed tasks on one line, e.g. "2 tasks: Buy milk | Call Bob (+3 more)",
+        /// joined with --separator and shortened to fit --width
+        #[arg(long, conflicts_with_all = ["plain", "numbers_only", "null", "json", "count", "columns", "tree", "subtasks_only", "done"])]
+        oneline: bool,
+        /// Show N tasks picked at random (with their usual numbers), for when choosing is the
+        /// hard part
+        #[arg(long, value_name = "N", conflicts_with_all = ["all", "count", "focused"])]
+        random: Option<usize>,
+        /// Seed for --random, to pick the same tasks every time
+        #[arg(long, requires = "random")]
+        seed: Option<u64>,
+    },
+    /// Print just the text of the top task, for shell prompts and status lines
+    /// (exits nonzero when there are no tasks)
+    Peek,
+    /// Search open and archived items (e.g., "did I already do this?")
+    #[command(aliases = ["grep", "search"])]
+    Find {
+        /// Text to search for (case-insensitive)
+        query: String,
+        /// Treat the query as a regular expression
+        #[arg(long)]
+        regex: bool,
     },
     /// Raise the priority of items (move toward top)
     #[command(aliases = ["u", "prioritize"])]
     Up {
         /// Item references to prioritize (e.g., "1", "2a", "3b")
         refs: Vec<String>,
+        /// Show which tasks would move without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Take the references as the continuous numbers shown by `ls --flat`
+        #[arg(long)]
+        flat: bool,
+        /// Move subtasks (e.g., "2b") to the front of their own task instead of moving
+        /// the task to the top
+        #[arg(long)]
+        within: bool,
+    },
+    /// Move tasks to the top and reset their creation time, as if just added
+    Bump {
+        /// Task references to bump (e.g., "1", "3"; a subtask bumps its parent task)
+        refs: Vec<String>,
+        /// Show which tasks would be bumped without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Archive completed items
     #[command(aliases = ["d", "done", "finish", "check"])]
     Do {
         /// Item references to archive (e.g., "1", "2a", "3b")
         refs: Vec<String>,
+        /// Archive from this named list instead of the default one (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+        /// Keep a task as a plain item when its last subtask is archived
+        #[arg(long, visible_alias = "keep-parent")]
+        keep_empty_parents: bool,
+        /// Archive every subtask of the given tasks, keeping the tasks themselves open
+        #[arg(long)]
+        all_subtasks: bool,
+        /// Show what would be archived (including auto-completed parents) without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Take the references as the continuous numbers shown by `ls --flat`
+        #[arg(long)]
+        flat: bool,
+        /// Record how the task was finished, archived as a "note:" subtask (whole tasks only;
+        /// subtasks archived alongside them get no note)
+        #[arg(long)]
+        note: Option<String>,
+        /// Archive under this day (YYYY-MM-DD) instead of today, e.g. for work finished yesterday
+        #[arg(long, value_name = "DATE")]
+        on: Option<String>,
+        /// Allow a date in the future with --on
+        #[arg(long, requires = "on")]
+        force: bool,
     },
     /// Remove items without archiving
     #[command(aliases = ["remove", "delete", "destroy", "forget"])]
     Rm {
         /// Item references to remove (e.g., "1", "2a", "3b")
         refs: Vec<String>,
+        /// Remove from this named list instead of the default one (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+        /// Don't ask for confirmation (required in non-interactive mode)
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Also remove (not archive) a task when its last subtask is removed
+        #[arg(long, conflicts_with = "subtasks")]
+        remove_empty_parents: bool,
+        /// Keep a task as a plain item when its last subtask is removed (the default)
+        #[arg(long, conflicts_with = "remove_empty_parents", hide = true)]
+        keep_empty_parents: bool,
+        /// Remove every subtask of the given tasks, keeping the tasks themselves
+        #[arg(long)]
+        subtasks: bool,
+        /// Show what would be removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Take the references as the continuous numbers shown by `ls --flat`
+        #[arg(long)]
+        flat: bool,
+    },
+    /// Archive every open task at once, asking first (`--no-archive` removes them instead)
+    Clear {
+        /// Remove the tasks without archiving them
+        #[arg(long)]
+        no_archive: bool,
+        /// Don't ask for confirmation (required in non-interactive mode)
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Clear this named list instead of the default one (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+    },
+    /// Set or replace the due date of a task
+    SetDue {
+        /// Task reference (e.g., "2")
+        reference: String,
+        /// Due date (e.g., "2025-05-01", "tomorrow", "3d" or "fri")
+        date: String,
+    },
+    /// Remove the due date from a task
+    ClearDue {
+        /// Task reference (e.g., "2")
+        reference: String,
+    },
+    /// Keep a task above the unpinned ones in `ls`, wherever it is stored
+    Pin {
+        /// Task reference (e.g., "2")
+        reference: String,
+    },
+    /// Let a pinned task take its stored place in `ls` again
+    Unpin {
+        /// Task reference (e.g., "2")
+        reference: String,
+    },
+    /// Move a task and its subtasks to the top of another list (e.g., `ldr move-list 3 --to shopping`)
+    MoveList {
+        /// Task reference (e.g., "3")
+        reference: String,
+        /// List to take the task from instead of the default one (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+        /// List to move the task to, created if it doesn't exist
+        #[arg(long)]
+        to: String,
+    },
+    /// Manage named lists; with no subcommand, show each list with its open-item count
+    Lists {
+        #[command(subcommand)]
+        action: Option<ListsCommand>,
+    },
+    /// Inspect archived items
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveCommand,
+    },
+    /// Tidy the todo file after manual edits (normalize, drop empty items, dedup subtasks)
+    Reindex,
+    /// Check todos.md and archive.md for damage from hand edits, with line numbers
+    Doctor {
+        /// Repair what can be repaired safely, keeping a .bak copy of each rewritten file
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Convert the old plain text files (note.txt, archive.txt) to Markdown; this normally
+    /// happens on its own the first time ldr runs
+    Migrate {
+        /// Convert again even if Markdown files exist, backing them up first
+        #[arg(long, conflicts_with = "status")]
+        force: bool,
+        /// Only print whether a migration is needed
+        #[arg(long)]
+        status: bool,
+    },
+    /// Show only one task in `ls` until cleared (e.g., `ldr focus 3`); without a reference,
+    /// show which task is focused
+    Focus {
+        /// Task to focus on; it stays focused when moved
+        #[arg(conflicts_with = "clear")]
+        reference: Option<String>,
+        /// Stop focusing, so `ls` shows every task again
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Check that a reference (e.g., 3 or 3a) points at an existing item; exits nonzero if not
+    ValidateRef {
+        /// Task reference to check
+        reference: String,
+    },
+    /// Show the items archived today, e.g. for a standup
+    Today {
+        /// Show a past day instead (YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Summarize archived items by ISO week, for retrospectives
+    #[command(alias = "review-summary")]
+    Weekly {
+        /// Number of weeks to show, counting the current one
+        #[arg(long, default_value_t = 4)]
+        weeks: u32,
+    },
+    /// List the @tags used in open items, with counts and item references
+    Tags,
+    /// Preview the color scheme and the detected background and color depth
+    Colors,
+    /// Show statistics about open and archived items
+    Stats {
+        /// Bucket active tasks by age (today, this week, this month, older)
+        #[arg(long)]
+        active_age_histogram: bool,
+        /// Show the current and longest runs of consecutive days with archived items
+        #[arg(long)]
+        streak: bool,
+        /// Print the counts (including the age buckets) as a JSON object, e.g. for a dashboard
+        #[arg(long)]
+        json: bool,
+    },
+    /// Prepend the tasks of a Markdown or plain-text file (e.g., `ldr import groceries.txt --plain`)
+    Import {
+        /// File to import
+        file: PathBuf,
+        /// Treat each non-blank line as a task instead of parsing Markdown
+        #[arg(long)]
+        plain: bool,
+        /// Import into this named list instead of the default one, creating it if needed
+        /// (e.g., --list Work)
+        #[arg(long)]
+        list: Option<String>,
+        /// Accepted for older scripts; --list now creates missing lists by itself
+        #[arg(long, requires = "list", hide = true)]
+        create: bool,
+    },
+    /// Export all items as Markdown, JSON or CSV (e.g., `ldr export --format csv --out todos.csv`)
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = commands::ExportFormat::Markdown)]
+        format: commands::ExportFormat,
+        /// Also export archived items
+        #[arg(long)]
+        include_archive: bool,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
     },
     /// Edit items in your $EDITOR
     #[command(aliases = ["e", "s", "scan", "r", "review"])]
-    Edit,
+    Edit {
+        /// Open the archive instead of the todo list
+        #[arg(long)]
+        archive: bool,
+        /// Open only the tasks matching this text (e.g., @work) in a temporary file, then
+        /// merge your changes back
+        #[arg(long, conflicts_with = "archive")]
+        filter: Option<String>,
+        /// Editor command to use this time instead of $EDITOR (e.g., --editor "code --wait")
+        #[arg(long)]
+        editor: Option<String>,
+    },
+    /// Revert the last change (run again to redo it)
+    Undo,
 }
 
-/// Entry point that parses CLI arguments and dispatches to appropriate command handlers.
+impl Commands {
+    /// True for commands that may change the data files, which snapshot them for `undo` first
+    fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Add { .. }
+            | Commands::Up { .. }
+            | Commands::Bump { .. }
+            | Commands::Do { .. }
+            | Commands::Rm { .. }
+            | Commands::Clear { .. }
+            | Commands::SetDue { .. }
+            | Commands::ClearDue { .. }
+            | Commands::Pin { .. }
+            | Commands::Unpin { .. }
+            | Commands::MoveList { .. }
+            | Commands::Lists { action: Some(_) }
+            | Commands::Import { .. }
+            | Commands::Reindex
+            | Commands::Migrate { status: false, .. }
+            | Commands::Doctor { fix: true }
+            | Commands::Edit { .. } => true,
+            Commands::Lists { action: None }
+            | Commands::Ls { .. }
+            | Commands::Peek
+            | Commands::Find { .. }
+            | Commands::Archive { .. }
+            | Commands::Focus { .. }
+            | Commands::ValidateRef { .. }
+            | Commands::Today { .. }
+            | Commands::Weekly { .. }
+            | Commands::Tags
+            | Commands::Colors
+            | Commands::Stats { .. }
+            | Commands::Export { .. }
+            | Commands::Migrate { status: true, .. }
+            | Commands::Doctor { fix: false }
+            | Commands::Undo => false,
+        }
+    }
+}
+
+/// Subcommands of `ldr lists`
+#[derive(Subcommand)]
+enum ListsCommand {
+    /// Set the order of list sections (e.g., `ldr lists reorder shopping work`)
+    Reorder {
+        /// List names in the desired order; unmentioned lists follow in their current order
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Move a list to the top, right after the Default list
+    Up {
+        /// Name of the list to move
+        name: String,
+    },
+    /// Create an empty list (e.g., `ldr lists create shopping`)
+    Create {
+        /// Name of the new list
+        name: String,
+    },
+    /// Rename a list (e.g., `ldr lists rename shopping groceries`); archived items keep the old name
+    Rename {
+        /// Current name of the list
+        old: String,
+        /// New name for the list
+        new: String,
+    },
+    /// Delete a list (e.g., `ldr lists rm shopping`)
+    Rm {
+        /// Name of the list to delete
+        name: String,
+        /// Delete the list even if it still has tasks
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArchiveCommand {
+    /// Show archived items by day (e.g., `ldr archive show --since 2025-05-01 --until 2025-05-31`)
+    Show {
+        /// Only show items archived on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show items archived on or before this date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<String>,
+    },
+    /// Count archived items (e.g., `ldr archive stats --top-tags --since 2025-01-01`)
+    Stats {
+        /// Only count items archived on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+        /// Rank the @tags of archived items by how often they were completed
+        #[arg(long)]
+        top_tags: bool,
+        /// Number of tags to show with --top-tags
+        #[arg(short = 'n', long = "num", default_value_t = 5)]
+        num: usize,
+    },
+}
+
+/// Entry point that runs the CLI and maps failures to a nonzero exit status.
+/// Errors whose message was already shown to the user aren't printed a second time.
+fn main() {
+    if let Err(error) = run() {
+        if error.downcast_ref::<commands::AlreadyReported>().is_none() {
+            eprintln!("Error: {:?}", error);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Environment variable naming the data directory, like `--data-dir`
+const DATA_DIR_ENV: &str = "LDR_DATA_DIR";
+
+/// Where data files live: the XDG data directory (`~/.local/share/ldr/`), or a directory
+/// chosen with `--data-dir` or `$LDR_DATA_DIR`
+struct DataDir {
+    base: BaseDirectories,
+    custom: Option<PathBuf>,
+}
+
+impl DataDir {
+    /// Uses `flag` if given, then `$LDR_DATA_DIR` if set and non-empty, then XDG
+    fn new(base: BaseDirectories, flag: Option<PathBuf>) -> Self {
+        let custom = flag.or_else(|| {
+            env::var_os(DATA_DIR_ENV)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        });
+        DataDir { base, custom }
+    }
+
+    /// Path of a data file, creating its directory if needed
+    fn place_file(&self, name: &str) -> Result<PathBuf> {
+        match &self.custom {
+            Some(dir) => {
+                fs::create_dir_all(dir).with_context(|| {
+                    format!("Failed to create data directory: {}", dir.display())
+                })?;
+                Ok(dir.join(name))
+            }
+            None => self
+                .base
+                .place_data_file(name)
+                .with_context(|| format!("Failed to create data directory for {}", name)),
+        }
+    }
+}
+
+/// Parses CLI arguments and dispatches to appropriate command handlers.
 /// Sets up XDG-compliant data directory paths and handles migration from plain text format.
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let cli = Cli::parse();
+    output::set_color_choice(cli.color);
     let base = BaseDirectories::with_prefix("ldr");
+    let config_path = base.find_config_file("config.toml");
+    let data_dir = DataDir::new(base, cli.data_dir);
 
     // Old plain text file paths
-    let note_path = base
-        .place_data_file("note.txt")
-        .context("Failed to create data directory for note.txt")?;
-    let archive_path = base
-        .place_data_file("archive.txt")
-        .context("Failed to create data directory for archive.txt")?;
+    let note_path = data_dir.place_file("note.txt")?;
+    let archive_path = data_dir.place_file("archive.txt")?;
 
     // New Markdown file paths
-    let todo_md_path = base
-        .place_data_file("todos.md")
-        .context("Failed to create data directory for todos.md")?;
-    let archive_md_path = base
-        .place_data_file("archive.md")
-        .context("Failed to create data directory for archive.md")?;
-
-    // Check if migration is needed and perform it
-    if migration::needs_migration(&note_path, &archive_path, &todo_md_path, &archive_md_path) {
-        migration::perform_migration(&note_path, &archive_path, &todo_md_path, &archive_md_path)
-            .map_err(|e| anyhow::anyhow!("Migration from plain text to Markdown failed: {}", e))?;
+    let todo_md_path = data_dir.place_file("todos.md")?;
+    let archive_md_path = data_dir.place_file("archive.md")?;
+
+    // Reading commands have their own script formats (--plain, --json); undo changes
+    // files without being snapshotted itself
+    let changes_files = cli.command.is_mutating() || matches!(cli.command, Commands::Undo);
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose, cli.porcelain && changes_files);
+
+    // Migration is automatic unless it was asked for explicitly
+    let needs_migration = || {
+        !matches!(cli.command, Commands::Migrate { .. })
+            && migration::needs_migration(
+                &note_path,
+                &archive_path,
+                &todo_md_path,
+                &archive_md_path,
+            )
+    };
+
+    // Held until this function returns, so concurrent commands can't lose each other's
+    // changes. Migrating writes the data files too, so even a reading command takes the
+    // lock when there's something to migrate.
+    let _lock = if changes_files || needs_migration() {
+        Some(lock::DataLock::acquire(&data_dir.place_file("ldr.lock")?)?)
+    } else {
+        None
+    };
+
+    // Checked again under the lock, as another ldr may have migrated in the meantime
+    if needs_migration() {
+        migration::perform_migration(
+            &note_path,
+            &archive_path,
+            &todo_md_path,
+            &archive_md_path,
+            verbosity,
+        )
+        .map_err(|e| anyhow::anyhow!("Migration from plain text to Markdown failed: {}", e))?;
     }
 
-    match cli.command {
-        Commands::Add { text, under } => {
-            commands::add_entry(&todo_md_path, &text, under).context("Failed to add entry")?
+    let config = config::Config::load(config_path.as_deref())?;
+    if cli.truecolor {
+        output::force_truecolor();
+    }
+    let theme = env::var(output::THEME_ENV)
+        .ok()
+        .filter(|theme| !theme.is_empty())
+        .or_else(|| config.theme.clone());
+    if let Some(theme) = theme {
+        output::set_theme(&theme).map_err(|e| anyhow::anyhow!(e))?;
+    }
+    if config.notifications && input::is_interactive() && !verbosity.is_quiet() {
+        notify::enable();
+    }
+
+    let data_files = [todo_md_path.as_path(), archive_md_path.as_path()];
+    let snapshot = if cli.command.is_mutating() {
+        Some(undo::save_snapshot(&data_files).context("Failed to save undo snapshot")?)
+    } else {
+        None
+    };
+
+    let result = run_command(
+        cli.command,
+        &todo_md_path,
+        &archive_md_path,
+        &data_dir,
+        &config,
+        verbosity,
+    );
+    if let Some(snapshot) = snapshot {
+        snapshot
+            .finish()
+            .context("Failed to update undo snapshot")?;
+    }
+    result
+}
+
+/// Dispatches a parsed subcommand to its handler
+fn run_command(
+    command: Commands,
+    todo_md_path: &Path,
+    archive_md_path: &Path,
+    data_dir: &DataDir,
+    config: &config::Config,
+    verbosity: Verbosity,
+) -> Result<()> {
+    match command {
+        Commands::Add {
+            text,
+            under,
+            due,
+            list,
+            create: _,
+            append,
+            after,
+            before,
+            who,
+            host,
+            recur,
+            force,
+        } => {
+            let options = commands::AddOptions {
+                under,
+                due,
+                list,
+                append,
+                after,
+                before,
+                who,
+                host,
+                recur,
+                force,
+            };
+            commands::add_entry(todo_md_path, &text, &options, verbosity)
+                .context("Failed to add entry")?
         }
-        Commands::Ls { num, all, filter } => {
-            commands::list_note(&todo_md_path, num, all, filter.as_deref())
+        Commands::Ls {
+            num,
+            all,
+            filter,
+            match_subtasks_only,
+            age,
+            reverse,
+            highlight_due,
+            mine,
+            strict_mine,
+            plain,
+            numbers_only,
+            separator,
+            null,
+            width,
+            columns,
+            color_by,
+            sort,
+            tasks_only,
+            subtasks_only,
+            since_file_mtime,
+            json,
+            flat,
+            count,
+            group_by,
+            focused,
+            no_focus,
+            tree,
+            done,
+            max_depth,
+            new,
+            oneline,
+            random,
+            seed,
+        } => {
+            let separator = if null {
+                Some("\0".to_string())
+            } else {
+                separator
+            };
+            let format = if count {
+                commands::ListFormat::Count
+            } else if oneline {
+                commands::ListFormat::Oneline
+            } else if json && flat {
+                commands::ListFormat::JsonFlat
+            } else if json {
+                commands::ListFormat::Json
+            } else if numbers_only {
+                commands::ListFormat::NumbersOnly
+            } else if plain || separator.is_some() {
+                commands::ListFormat::Plain
+            } else {
+                commands::ListFormat::Colored
+            };
+            let level = if tasks_only {
+                commands::ItemLevel::TasksOnly
+            } else if subtasks_only {
+                commands::ItemLevel::SubtasksOnly
+            } else {
+                commands::ItemLevel::All
+            };
+            let snapshot = if since_file_mtime {
+                Some(data_dir.place_file("last_seen.txt")?)
+            } else {
+                None
+            };
+            let focus = if no_focus {
+                None
+            } else {
+                commands::read_focus(&data_dir.place_file("focus.txt")?)?
+            };
+            let new_since = if new {
+                let last_review =
+                    commands::read_last_review(&data_dir.place_file("last_review.txt")?)?;
+                Some(last_review.unwrap_or(chrono::NaiveDateTime::MIN))
+            } else {
+                None
+            };
+            if focused && focus.is_none() {
+                return Err(anyhow::anyhow!(
+                    "No task is focused (use `ldr focus <ref>`)"
+                ));
+            }
+            let options = commands::ListOptions {
+                num,
+                all,
+                filter,
+                match_subtasks_only,
+                age,
+                reverse,
+                highlight_due,
+                mine: (mine || strict_mine).then(identity::Identity::current),
+                strict_mine,
+                format,
+                separator,
+                width,
+                columns,
+                snapshot,
+                color_by,
+                sort,
+                level,
+                group_by,
+                flat_numbers: flat && !json,
+                tree,
+                focus,
+                new_since,
+                random,
+                seed,
+                recently_done: done.map(|count| (archive_md_path.to_path_buf(), count)),
+                max_depth,
+            };
+            commands::list_note(todo_md_path, &options, verbosity)
                 .context("Failed to list notes")?
         }
-        Commands::Up { refs } => commands::prioritize_items(&todo_md_path, &refs)
-            .context("Failed to prioritize items")?,
-        Commands::Do { refs } => commands::archive_items(&todo_md_path, &archive_md_path, &refs)
-            .context("Failed to archive items")?,
-        Commands::Rm { refs } => {
-            commands::remove_items(&todo_md_path, &refs).context("Failed to remove items")?
+        Commands::Peek => commands::peek(todo_md_path).context("Failed to peek")?,
+        Commands::Focus { reference, clear } => commands::focus_task(
+            todo_md_path,
+            &data_dir.place_file("focus.txt")?,
+            reference.as_deref(),
+            clear,
+            verbosity,
+        )
+        .context("Failed to focus")?,
+        Commands::Find { query, regex } => {
+            commands::find_items(todo_md_path, archive_md_path, &query, regex, verbosity)
+                .context("Failed to search items")?
+        }
+        Commands::Up {
+            refs,
+            dry_run,
+            flat,
+            within,
+        } => {
+            let options = commands::PrioritizeOptions {
+                dry_run,
+                flat,
+                within,
+                ..Default::default()
+            };
+            commands::prioritize_items(todo_md_path, &refs, &options, verbosity)
+                .context("Failed to prioritize items")?
+        }
+        Commands::Bump { refs, dry_run } => {
+            let options = commands::PrioritizeOptions {
+                dry_run,
+                bump: true,
+                ..Default::default()
+            };
+            commands::prioritize_items(todo_md_path, &refs, &options, verbosity)
+                .context("Failed to bump items")?
+        }
+        Commands::Do {
+            refs,
+            list,
+            keep_empty_parents,
+            all_subtasks,
+            dry_run,
+            flat,
+            note,
+            on,
+            force,
+        } => {
+            let archive_date = on
+                .map(|date| commands::parse_archive_date(&date, force))
+                .transpose()?;
+            let options = commands::RemoveOptions {
+                keep_empty_parents: keep_empty_parents
+                    || all_subtasks
+                    || !config.auto_complete_empty_parents,
+                dry_run,
+                list,
+                flat,
+                note,
+                all_subtasks,
+                archive_date,
+                completion_log: if config.completion_log {
+                    Some(data_dir.place_file("log.jsonl")?)
+                } else {
+                    None
+                },
+                ..Default::default()
+            };
+            commands::archive_items(todo_md_path, archive_md_path, &refs, &options, verbosity)
+                .context("Failed to archive items")?
+        }
+        Commands::Rm {
+            refs,
+            list,
+            yes,
+            remove_empty_parents,
+            keep_empty_parents,
+            subtasks,
+            dry_run,
+            flat,
+        } => {
+            // Removing subtasks doesn't finish their task, so the parent stays unless asked
+            let remove_empty_parents = !subtasks
+                && (remove_empty_parents || (config.remove_empty_parents && !keep_empty_parents));
+            let options = commands::RemoveOptions {
+                assume_yes: yes,
+                keep_empty_parents: !remove_empty_parents,
+                dry_run,
+                list,
+                flat,
+                all_subtasks: subtasks,
+                ..Default::default()
+            };
+            commands::remove_items(todo_md_path, &refs, &options, verbosity)
+                .context("Failed to remove items")?
+        }
+        Commands::Clear {
+            no_archive,
+            yes,
+            list,
+        } => {
+            let options = commands::RemoveOptions {
+                assume_yes: yes,
+                keep_empty_parents: true,
+                list,
+                ..Default::default()
+            };
+            let archive_path = (!no_archive).then_some(archive_md_path);
+            commands::clear_items(todo_md_path, archive_path, &options, verbosity)
+                .context("Failed to clear items")?
+        }
+        Commands::SetDue { reference, date } => {
+            commands::set_due(todo_md_path, &reference, &date, verbosity)
+                .context("Failed to set due date")?
+        }
+        Commands::ClearDue { reference } => {
+            commands::clear_due(todo_md_path, &reference, verbosity)
+                .context("Failed to clear due date")?
+        }
+        Commands::Pin { reference } => {
+            commands::set_pinned(todo_md_path, &reference, true, verbosity)
+                .context("Failed to pin task")?
+        }
+        Commands::Unpin { reference } => {
+            commands::set_pinned(todo_md_path, &reference, false, verbosity)
+                .context("Failed to unpin task")?
+        }
+        Commands::MoveList {
+            reference,
+            list,
+            to,
+        } => commands::move_to_list(todo_md_path, &reference, list.as_deref(), &to, verbosity)
+            .context("Failed to move task")?,
+        Commands::Lists { action } => match action {
+            None => {
+                commands::show_lists(todo_md_path, verbosity).context("Failed to show lists")?
+            }
+            Some(ListsCommand::Reorder { names }) => {
+                commands::reorder_lists(todo_md_path, &names, verbosity)
+                    .context("Failed to reorder lists")?
+            }
+            Some(ListsCommand::Up { name }) => {
+                commands::reorder_lists(todo_md_path, &[name], verbosity)
+                    .context("Failed to reorder lists")?
+            }
+            Some(ListsCommand::Create { name }) => {
+                commands::create_list(todo_md_path, &name, verbosity)
+                    .context("Failed to create list")?
+            }
+            Some(ListsCommand::Rename { old, new }) => {
+                commands::rename_list(todo_md_path, &old, &new, verbosity)
+                    .context("Failed to rename list")?
+            }
+            Some(ListsCommand::Rm { name, force }) => {
+                commands::remove_list(todo_md_path, &name, force, verbosity)
+                    .context("Failed to delete list")?
+            }
+        },
+        Commands::Archive { action } => match action {
+            ArchiveCommand::Show { since, until } => commands::show_archive(
+                archive_md_path,
+                since.as_deref(),
+                until.as_deref(),
+                verbosity,
+            )
+            .context("Failed to show archive")?,
+            ArchiveCommand::Stats {
+                since,
+                top_tags,
+                num,
+            } => commands::archive_stats(
+                archive_md_path,
+                since.as_deref(),
+                top_tags.then_some(num),
+                verbosity,
+            )
+            .context("Failed to show archive stats")?,
+        },
+        Commands::Reindex => {
+            commands::reindex(todo_md_path, verbosity).context("Failed to reindex notes")?
+        }
+        Commands::Doctor { fix } => {
+            doctor::run_doctor(todo_md_path, archive_md_path, fix, verbosity)
+                .context("Failed to check data files")?
+        }
+        Commands::Migrate { force, status } => commands::migrate(
+            &data_dir.place_file("note.txt")?,
+            &data_dir.place_file("archive.txt")?,
+            todo_md_path,
+            archive_md_path,
+            force,
+            status,
+            verbosity,
+        )
+        .context("Failed to migrate")?,
+        Commands::ValidateRef { reference } => {
+            commands::validate_ref(todo_md_path, &reference, verbosity)
+                .context("Failed to validate reference")?
+        }
+        Commands::Today { date } => {
+            commands::show_completed(archive_md_path, date.as_deref(), verbosity)
+                .context("Failed to show completed items")?
+        }
+        Commands::Weekly { weeks } => commands::show_weekly(archive_md_path, weeks, verbosity)
+            .context("Failed to show weekly summary")?,
+        Commands::Tags => {
+            commands::show_tags(todo_md_path, verbosity).context("Failed to list tags")?
+        }
+        Commands::Colors => commands::show_colors().context("Failed to show colors")?,
+        Commands::Stats {
+            active_age_histogram,
+            streak,
+            json,
+        } => commands::show_stats(
+            todo_md_path,
+            archive_md_path,
+            active_age_histogram,
+            streak,
+            json,
+            verbosity,
+        )
+        .context("Failed to show stats")?,
+        Commands::Import {
+            file,
+            plain,
+            list,
+            create: _,
+        } => {
+            let options = commands::ImportOptions { plain, list };
+            commands::import_items(todo_md_path, &file, &options, verbosity)
+                .context("Failed to import items")?
+        }
+        Commands::Export {
+            format,
+            include_archive,
+            out,
+        } => commands::export(
+            todo_md_path,
+            archive_md_path,
+            format,
+            include_archive,
+            out.as_deref(),
+            verbosity,
+        )
+        .context("Failed to export")?,
+        Commands::Edit {
+            archive,
+            filter,
+            editor,
+        } => {
+            let editor = editor.unwrap_or_else(commands::default_editor);
+            if let Some(filter) = filter {
+                commands::edit_filtered(todo_md_path, &filter, &editor, verbosity)
+                    .context("Failed to edit matching tasks")?
+            } else if archive {
+                commands::edit_archive(archive_md_path, &editor, verbosity)
+                    .context("Failed to edit archive")?
+            } else {
+                commands::edit_note(
+                    todo_md_path,
+                    &data_dir.place_file("last_review.txt")?,
+                    &editor,
+                    verbosity,
+                )
+                .context("Failed to edit note")?
+            }
+        }
+        Commands::Undo => {
+            undo::undo(&[todo_md_path, archive_md_path], verbosity).context("Failed to undo")?
         }
-        Commands::Edit => commands::edit_note(&todo_md_path).context("Failed to edit note")?,
     }
 
     Ok(())
@@ -1,15 +1,193 @@
 //! Markdown parsing and generation for todo files.
 //!
 //! This module handles reading and writing Markdown-formatted todo and archive files.
-//! It supports single-level nesting (tasks with subtasks) and multiple named lists.
+//! It supports tasks with subtasks (optionally with a second level of nesting)
+//! and multiple named lists.
 
+use chrono::{Days, NaiveDate, NaiveDateTime};
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
+
+/// Date format used for due dates in the Markdown metadata
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Timestamp format used for creation times in the Markdown metadata
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// Keys recognized in a task's trailing metadata comment
+const METADATA_KEYS: [&str; 7] = ["due", "created", "who", "host", "recur", "id", "pinned"];
+
+/// Environment variable that enables two-level subtask nesting (set to "1" or "true")
+pub const NESTED_SUBTASKS_ENV: &str = "LDR_NESTED_SUBTASKS";
+
+/// How indentation deeper than one subtask level is parsed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubtaskNesting {
+    /// Deeper items are flattened into level-1 subtasks (compatibility default)
+    #[default]
+    Flatten,
+    /// Deeper items become children of the preceding subtask
+    TwoLevel,
+}
+
+impl SubtaskNesting {
+    /// Reads the nesting mode from `LDR_NESTED_SUBTASKS`, defaulting to flattening
+    pub fn from_env() -> Self {
+        match env::var(NESTED_SUBTASKS_ENV) {
+            Ok(value) if value == "1" || value.eq_ignore_ascii_case("true") => {
+                SubtaskNesting::TwoLevel
+            }
+            _ => SubtaskNesting::Flatten,
+        }
+    }
+}
+
+/// The checkbox written before a subtask's text, e.g. "[x] ", or "" without one
+pub fn checkbox(completed: Option<bool>) -> &'static str {
+    match completed {
+        Some(true) => "[x] ",
+        Some(false) => "[ ] ",
+        None => "",
+    }
+}
+
+/// A subtask, which may itself have children when two-level nesting is enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subtask {
+    pub text: String,
+    pub children: Vec<String>,
+    /// Checkbox state from a `- [ ] ` or `- [x] ` item; None when it has no checkbox
+    pub completed: Option<bool>,
+}
+
+impl Subtask {
+    pub fn new(text: String) -> Self {
+        Subtask {
+            text,
+            children: Vec::new(),
+            completed: None,
+        }
+    }
+
+    /// Reads a subtask line (without bullet), taking a leading checkbox as its state.
+    /// Without a checkbox, a backslash before `[` or `\` is the escape written by
+    /// `line_text`.
+    fn parse(text: &str) -> Self {
+        let (completed, text) = if let Some(text) = text.strip_prefix("[ ] ") {
+            (Some(false), text)
+        } else if let Some(text) = text
+            .strip_prefix("[x] ")
+            .or_else(|| text.strip_prefix("[X] "))
+        {
+            (Some(true), text)
+        } else {
+            let unescaped = text
+                .strip_prefix('\\')
+                .filter(|rest| rest.starts_with(['[', '\\']));
+            (None, unescaped.unwrap_or(text))
+        };
+        Subtask {
+            completed,
+            ..Subtask::new(text.to_string())
+        }
+    }
+
+    /// The subtask as written after its bullet: the checkbox and the text. Text without
+    /// a checkbox that would read back as one, or that starts with a backslash before `[`
+    /// or `\`, gets a backslash in front, which Markdown renders away.
+    fn line_text(&self) -> String {
+        let needs_escape = self.completed.is_none()
+            && (["[ ] ", "[x] ", "[X] "]
+                .iter()
+                .any(|checkbox| self.text.starts_with(checkbox))
+                || self.text.starts_with("\\[")
+                || self.text.starts_with("\\\\"));
+        let escape = if needs_escape { "\\" } else { "" };
+        format!("{}{}{}", checkbox(self.completed), escape, self.text)
+    }
+}
+
+impl fmt::Display for Subtask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl PartialEq<&str> for Subtask {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+impl PartialEq<str> for Subtask {
+    fn eq(&self, other: &str) -> bool {
+        self.text == other
+    }
+}
+
+/// How often a recurring task comes back after it is archived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+}
+
+impl std::str::FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "daily" => Ok(Recurrence::Daily),
+            "weekly" => Ok(Recurrence::Weekly),
+            _ => Err(format!("expected daily or weekly, got '{}'", value)),
+        }
+    }
+}
+
+impl Recurrence {
+    /// The due date of the next occurrence: `due` (or `today` when there is none) advanced
+    /// by whole intervals until it lies after `today`, so an overdue task doesn't come
+    /// back overdue
+    pub fn next_due(self, due: Option<NaiveDate>, today: NaiveDate) -> NaiveDate {
+        let interval = match self {
+            Recurrence::Daily => Days::new(1),
+            Recurrence::Weekly => Days::new(7),
+        };
+        let mut next = due.unwrap_or(today) + interval;
+        while next <= today {
+            next = next + interval;
+        }
+        next
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly => write!(f, "weekly"),
+        }
+    }
+}
 
 /// Represents a single task with optional subtasks
 #[derive(Debug, Clone, PartialEq)]
 pub struct Task {
     pub text: String,
-    pub subtasks: Vec<String>,
+    pub subtasks: Vec<Subtask>,
+    pub due: Option<NaiveDate>,
+    pub created: Option<NaiveDateTime>,
+    /// Person the task is meant for, for shared files
+    pub who: Option<String>,
+    /// Machine the task is meant for, for shared files
+    pub host: Option<String>,
+    /// Re-added with a new due date whenever it is archived
+    pub recur: Option<Recurrence>,
+    /// Short stable handle, referenced as `#id` however the task moves
+    pub id: Option<String>,
+    /// Listed above unpinned tasks by `ls`, wherever it is stored
+    pub pinned: bool,
 }
 
 impl Task {
@@ -17,16 +195,33 @@ impl Task {
         Task {
             text,
             subtasks: Vec::new(),
+            due: None,
+            created: None,
+            who: None,
+            host: None,
+            recur: None,
+            id: None,
+            pinned: false,
         }
     }
 
     #[allow(dead_code)]
     pub fn with_subtasks(text: String, subtasks: Vec<String>) -> Self {
-        Task { text, subtasks }
+        Task {
+            text,
+            subtasks: subtasks.into_iter().map(Subtask::new).collect(),
+            due: None,
+            created: None,
+            who: None,
+            host: None,
+            recur: None,
+            id: None,
+            pinned: false,
+        }
     }
 
     pub fn add_subtask(&mut self, subtask: String) {
-        self.subtasks.push(subtask);
+        self.subtasks.push(Subtask::new(subtask));
     }
 
     #[allow(dead_code)]
@@ -38,13 +233,48 @@ impl Task {
     pub fn subtask_count(&self) -> usize {
         self.subtasks.len()
     }
+
+    /// Checked subtasks out of all subtasks, when any subtask has a checkbox
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        if self
+            .subtasks
+            .iter()
+            .all(|subtask| subtask.completed.is_none())
+        {
+            return None;
+        }
+        let done = self
+            .subtasks
+            .iter()
+            .filter(|subtask| subtask.completed == Some(true))
+            .count();
+        Some((done, self.subtasks.len()))
+    }
+}
+
+/// Name of the primary list, stored without a header at the top of the file
+pub const DEFAULT_LIST: &str = "Default";
+
+/// Number of characters in the task IDs handed out by `TodoFile::new_task_id`
+pub const TASK_ID_LENGTH: usize = 4;
+
+/// A named list of tasks, stored under a `## Name` header
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskList {
+    pub name: String,
+    pub tasks: Vec<Task>,
 }
 
-/// Represents the entire todo file with a single task list
+/// Represents the entire todo file: the Default list plus any named lists
 #[derive(Debug, Clone, PartialEq)]
 pub struct TodoFile {
     pub title: String,
+    /// Tasks of the Default list
     pub tasks: Vec<Task>,
+    /// Named lists in file order
+    pub lists: Vec<TaskList>,
+    /// Bullet written before every item: `-`, `*` or `+`, following the file as read
+    pub bullet: char,
 }
 
 impl TodoFile {
@@ -52,15 +282,156 @@ impl TodoFile {
         TodoFile {
             title,
             tasks: Vec::new(),
+            lists: Vec::new(),
+            bullet: '-',
         }
     }
 
-    pub fn add_task(&mut self, task: Task) {
-        self.tasks.push(task);
+    /// A task ID no task in any list uses yet: four random base-36 characters
+    pub fn new_task_id(&self) -> String {
+        use std::hash::BuildHasher;
+
+        let taken: std::collections::HashSet<&str> = self
+            .tasks
+            .iter()
+            .chain(self.lists.iter().flat_map(|list| &list.tasks))
+            .filter_map(|task| task.id.as_deref())
+            .collect();
+        // RandomState is seeded randomly for each process
+        let state = std::collections::hash_map::RandomState::new();
+        (0u64..)
+            .map(|attempt| {
+                let mut value = state.hash_one(attempt);
+                (0..TASK_ID_LENGTH)
+                    .map(|_| {
+                        let digit = (value % 36) as u32;
+                        value /= 36;
+                        char::from_digit(digit, 36).expect("digit is below 36")
+                    })
+                    .collect::<String>()
+            })
+            .find(|id| !taken.contains(id.as_str()))
+            .expect("a free task ID exists")
+    }
+
+    /// Returns true if `name` refers to the Default list (case-insensitive)
+    pub fn is_default_list(name: &str) -> bool {
+        name.eq_ignore_ascii_case(DEFAULT_LIST)
+    }
+
+    /// Returns true if a list with this name exists (case-insensitive)
+    pub fn has_list(&self, name: &str) -> bool {
+        self.list(name).is_some()
+    }
+
+    /// Returns the tasks of the named list
+    pub fn list(&self, name: &str) -> Option<&Vec<Task>> {
+        if Self::is_default_list(name) {
+            return Some(&self.tasks);
+        }
+        self.lists
+            .iter()
+            .find(|list| list.name.eq_ignore_ascii_case(name))
+            .map(|list| &list.tasks)
+    }
+
+    /// Returns the tasks of the named list for modification
+    pub fn list_mut(&mut self, name: &str) -> Option<&mut Vec<Task>> {
+        if Self::is_default_list(name) {
+            return Some(&mut self.tasks);
+        }
+        self.lists
+            .iter_mut()
+            .find(|list| list.name.eq_ignore_ascii_case(name))
+            .map(|list| &mut list.tasks)
+    }
+
+    /// Returns the tasks of the named list, creating the list at the end if needed
+    pub fn list_or_create(&mut self, name: &str) -> &mut Vec<Task> {
+        if !self.has_list(name) {
+            self.lists.push(TaskList {
+                name: name.to_string(),
+                tasks: Vec::new(),
+            });
+        }
+        self.list_mut(name).expect("list exists after creation")
+    }
+
+    /// Moves the named lists to the front, in the given order; other lists keep their order.
+    /// The Default list always stays first, so it can't be reordered.
+    pub fn reorder_lists(&mut self, names: &[String]) -> Result<(), String> {
+        let mut remaining = self.lists.clone();
+        let mut ordered = Vec::with_capacity(remaining.len());
+        for name in names {
+            if Self::is_default_list(name) {
+                return Err(format!("The {} list always comes first", DEFAULT_LIST));
+            }
+            let Some(pos) = remaining
+                .iter()
+                .position(|list| list.name.eq_ignore_ascii_case(name))
+            else {
+                let already_listed = ordered
+                    .iter()
+                    .any(|list: &TaskList| list.name.eq_ignore_ascii_case(name));
+                return Err(if already_listed {
+                    format!("List '{}' given more than once", name)
+                } else {
+                    format!("No such list '{}'", name)
+                });
+            };
+            ordered.push(remaining.remove(pos));
+        }
+
+        ordered.append(&mut remaining);
+        self.lists = ordered;
+        Ok(())
     }
 
-    pub fn prepend_task(&mut self, task: Task) {
-        self.tasks.insert(0, task);
+    /// Renames a named list, keeping its position. Changing only the case of a name is
+    /// allowed; taking the name of another list is not.
+    pub fn rename_list(&mut self, old_name: &str, new_name: &str) -> Result<(), String> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err("List name cannot be empty".to_string());
+        }
+        if Self::is_default_list(old_name) || Self::is_default_list(new_name) {
+            return Err(format!("The {} list can't be renamed", DEFAULT_LIST));
+        }
+        if !old_name.eq_ignore_ascii_case(new_name) && self.has_list(new_name) {
+            return Err(format!("List '{}' already exists", new_name));
+        }
+        let list = self
+            .lists
+            .iter_mut()
+            .find(|list| list.name.eq_ignore_ascii_case(old_name))
+            .ok_or_else(|| format!("No such list '{}'", old_name))?;
+        list.name = new_name.to_string();
+        Ok(())
+    }
+
+    /// Deletes a named list, returning it. A list that still has tasks is only deleted
+    /// with `force`.
+    pub fn remove_list(&mut self, name: &str, force: bool) -> Result<TaskList, String> {
+        if Self::is_default_list(name) {
+            return Err(format!("The {} list can't be deleted", DEFAULT_LIST));
+        }
+        let pos = self
+            .lists
+            .iter()
+            .position(|list| list.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("No such list '{}'", name))?;
+        let task_count = self.lists[pos].tasks.len();
+        if task_count > 0 && !force {
+            return Err(format!(
+                "List '{}' still has {} task(s); use --force to delete it anyway",
+                self.lists[pos].name, task_count
+            ));
+        }
+        Ok(self.lists.remove(pos))
+    }
+
+    pub fn add_task(&mut self, task: Task) {
+        self.tasks.push(task);
     }
 
     pub fn is_empty(&self) -> bool {
@@ -72,11 +443,13 @@ impl TodoFile {
     }
 }
 
-/// Parse task reference in format "1", "2a", "10b", etc.
+/// Parse task reference in format "1", "2a", "10b", or "2a1" for a nested child
 #[derive(Debug, Clone, PartialEq)]
 pub struct TaskRef {
     pub task_index: usize,
     pub subtask_index: Option<usize>,
+    /// 0-based index of a second-level child under the subtask
+    pub child_index: Option<usize>,
 }
 
 impl TaskRef {
@@ -88,9 +461,12 @@ impl TaskRef {
         let chars: Vec<char> = input.chars().collect();
         let mut task_part = String::new();
         let mut subtask_char = None;
+        let mut child_part = String::new();
 
         for (i, &ch) in chars.iter().enumerate() {
-            if ch.is_ascii_digit() {
+            if ch.is_ascii_digit() && subtask_char.is_some() {
+                child_part.push(ch);
+            } else if ch.is_ascii_digit() {
                 task_part.push(ch);
             } else if ch.is_ascii_lowercase() {
                 if i == 0 {
@@ -133,47 +509,508 @@ impl TaskRef {
 
         let subtask_index = subtask_char.map(|ch| (ch as usize) - ('a' as usize));
 
+        let child_index = if child_part.is_empty() {
+            None
+        } else {
+            let child_num = child_part
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid child number: {}", child_part))?;
+            if child_num == 0 {
+                return Err("Child number must be at least 1".to_string());
+            }
+            Some(child_num - 1)
+        };
+
         Ok(TaskRef {
             task_index,
             subtask_index,
+            child_index,
         })
     }
 
-    #[allow(dead_code)]
+    /// Parses a reference like `parse`, also accepting `first` and `last` for the first
+    /// and last of `tasks`, `#id` for the task with that ID, and `/text` for the one task
+    /// whose text starts with (or, failing that, contains) `text`, ignoring case. These
+    /// name whole tasks only, so `firsta` is rejected.
+    pub fn resolve(input: &str, tasks: &[Task]) -> Result<TaskRef, String> {
+        if let Some(query) = input.strip_prefix('/') {
+            return TaskRef::find_by_text(query, tasks);
+        }
+        if let Some(id) = input.strip_prefix('#') {
+            return tasks
+                .iter()
+                .position(|task| {
+                    task.id
+                        .as_deref()
+                        .is_some_and(|own| own.eq_ignore_ascii_case(id))
+                })
+                .map(|task_index| TaskRef {
+                    task_index,
+                    subtask_index: None,
+                    child_index: None,
+                })
+                .ok_or_else(|| format!("no task has ID '{}'", id));
+        }
+        let len = tasks.len();
+        let lower = input.to_ascii_lowercase();
+        for (word, task_index) in [("first", 0), ("last", len.saturating_sub(1))] {
+            let Some(rest) = lower.strip_prefix(word) else {
+                continue;
+            };
+            if !rest.is_empty() {
+                return Err(format!(
+                    "'{}' refers to a whole task and can't take a subtask: {}",
+                    word, input
+                ));
+            }
+            if len == 0 {
+                return Err(format!("No tasks for '{}' to refer to", word));
+            }
+            return Ok(TaskRef {
+                task_index,
+                subtask_index: None,
+                child_index: None,
+            });
+        }
+        TaskRef::parse(input)
+    }
+
+    /// The task matching a `/text` reference; ambiguous queries list the candidates
+    fn find_by_text(query: &str, tasks: &[Task]) -> Result<TaskRef, String> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err("'/' needs some text to look for".to_string());
+        }
+        let matching = |matches: &dyn Fn(&str) -> bool| -> Vec<usize> {
+            (0..tasks.len())
+                .filter(|&idx| matches(&tasks[idx].text.to_lowercase()))
+                .collect()
+        };
+        let mut candidates = matching(&|text| text.starts_with(&query));
+        if candidates.is_empty() {
+            candidates = matching(&|text| text.contains(&query));
+        }
+        match candidates[..] {
+            [] => Err(format!("no task matches '{}'", query)),
+            [task_index] => Ok(TaskRef {
+                task_index,
+                subtask_index: None,
+                child_index: None,
+            }),
+            _ => Err(format!(
+                "'{}' matches {} tasks: {}",
+                query,
+                candidates.len(),
+                candidates
+                    .iter()
+                    .map(|&idx| format!("{}. {}", idx + 1, tasks[idx].text))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )),
+        }
+    }
+
     pub fn is_subtask(&self) -> bool {
         self.subtask_index.is_some()
     }
 }
 
-/// Parse a markdown todo file with resilient handling of user edits
+/// Split trailing `<!-- key:value ... -->` metadata off a task line.
+/// Returns the task text and the raw key/value pairs found in the comment.
+/// A comment that isn't made up entirely of known keys is left in the text.
+fn split_metadata(line: &str) -> (&str, Vec<(&str, &str)>) {
+    let trimmed = line.trim_end();
+    if let Some(body) = trimmed.strip_suffix("-->") {
+        if let Some(start) = body.rfind("<!--") {
+            let pairs: Option<Vec<_>> = body[start + 4..]
+                .split_whitespace()
+                .map(|token| {
+                    token
+                        .split_once(':')
+                        .filter(|(key, _)| METADATA_KEYS.contains(key))
+                })
+                .collect();
+            if let Some(pairs) = pairs.filter(|pairs| !pairs.is_empty()) {
+                return (body[..start].trim_end(), pairs);
+            }
+        }
+    }
+    (trimmed, Vec::new())
+}
+
+/// Build a task from its line text, reading back any stored metadata
+fn parse_task_text(line: &str) -> Task {
+    let (text, metadata) = split_metadata(line);
+    let mut task = Task::new(text.trim().to_string());
+
+    for (key, value) in metadata {
+        match key {
+            "due" => task.due = NaiveDate::parse_from_str(value, DATE_FORMAT).ok(),
+            "created" => task.created = NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).ok(),
+            "who" => task.who = Some(value.to_string()),
+            "host" => task.host = Some(value.to_string()),
+            "recur" => task.recur = value.parse().ok(),
+            "id" => task.id = Some(value.to_string()),
+            "pinned" => task.pinned = value == "true",
+            _ => {}
+        }
+    }
+
+    task
+}
+
+/// Format a task line (without bullet), appending metadata as a trailing HTML comment
+fn format_task_text(task: &Task) -> String {
+    let mut metadata = Vec::new();
+    if let Some(due) = task.due {
+        metadata.push(format!("due:{}", due.format(DATE_FORMAT)));
+    }
+    if let Some(created) = task.created {
+        metadata.push(format!("created:{}", created.format(TIMESTAMP_FORMAT)));
+    }
+    if let Some(who) = &task.who {
+        metadata.push(format!("who:{}", who));
+    }
+    if let Some(host) = &task.host {
+        metadata.push(format!("host:{}", host));
+    }
+    if let Some(recur) = task.recur {
+        metadata.push(format!("recur:{}", recur));
+    }
+    if let Some(id) = &task.id {
+        metadata.push(format!("id:{}", id));
+    }
+    if task.pinned {
+        metadata.push("pinned:true".to_string());
+    }
+
+    if metadata.is_empty() {
+        task.text.clone()
+    } else {
+        format!("{} <!-- {} -->", task.text, metadata.join(" "))
+    }
+}
+
+/// Something in a todo or archive file that was read differently from how it was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// Items nested deeper than supported, starting at `line`, were moved up to `level`
+    DeepNesting { line: usize, level: usize },
+    /// An archive line that isn't a heading or an item under a date was skipped
+    UnrecognizedLine { line: usize, text: String },
+    /// Items use different bullets, the first odd one at `line`; all will be written as `bullet`
+    MixedBullets { line: usize, bullet: char },
+    /// An indented item at `line` has no task above it in its list, so it became a task
+    OrphanedSubtask { line: usize },
+}
+
+impl ParseWarning {
+    /// The line the warning is about
+    pub fn line(&self) -> usize {
+        match self {
+            ParseWarning::DeepNesting { line, .. }
+            | ParseWarning::UnrecognizedLine { line, .. }
+            | ParseWarning::MixedBullets { line, .. }
+            | ParseWarning::OrphanedSubtask { line } => *line,
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseWarning::DeepNesting { line, level: 1 } => write!(
+                f,
+                "Deep nesting detected (line {}). Converting to level 1 subtask. Only single-level subtasks are supported.",
+                line
+            ),
+            ParseWarning::DeepNesting { line, level } => write!(
+                f,
+                "Deep nesting detected (line {}). Converting to level {} subtask. Only two levels of subtasks are supported.",
+                line, level
+            ),
+            ParseWarning::UnrecognizedLine { line, text } => {
+                write!(f, "Skipped unrecognized archive line {}: {}", line, text)
+            }
+            ParseWarning::MixedBullets { line, bullet } => write!(
+                f,
+                "Mixed list bullets (line {}). All items will be written with '{}'.",
+                line, bullet
+            ),
+            ParseWarning::OrphanedSubtask { line } => write!(
+                f,
+                "Subtask without a task above it (line {}). Treating it as a task.",
+                line
+            ),
+        }
+    }
+}
+
+/// Parse a markdown todo file with resilient handling of user edits.
+/// Warnings are printed to stderr; use `parse_todo_file_with_warnings` to handle them.
 pub fn parse_todo_file(content: &str) -> Result<TodoFile, String> {
-    let lines: Vec<&str> = content.lines().collect();
-    if lines.is_empty() {
-        return Ok(TodoFile::new("TODOs".to_string()));
+    parse_todo_file_with_nesting(content, SubtaskNesting::from_env())
+}
+
+/// Strip a `- `, `* ` or `+ ` bullet from an indented line, returning its indent width and text
+fn split_indented_bullet(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_start();
+    let indent: usize = line[..line.len() - rest.len()]
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    if indent == 0 {
+        return None;
     }
+    let text = rest
+        .strip_prefix("- ")
+        .or_else(|| rest.strip_prefix("* "))
+        .or_else(|| rest.strip_prefix("+ "))?;
+    Some((indent, text.trim()))
+}
 
-    let mut todo_file = TodoFile::new("TODOs".to_string());
-    let mut current_task: Option<Task> = None;
-    let mut warned_about_deep_nesting = false;
+/// Counts the bullets items use, to find the one most of them use
+#[derive(Default)]
+struct BulletTally {
+    /// Per bullet `-`, `*`, `+`: how many items use it and the first line that does
+    seen: [(usize, Option<usize>); 3],
+}
 
-    for (line_num, line) in lines.iter().enumerate() {
+impl BulletTally {
+    const BULLETS: [char; 3] = ['-', '*', '+'];
+
+    fn add(&mut self, line_num: usize, line: &str) {
+        let mut chars = line.trim_start().chars();
+        if let (Some(bullet), Some(' ')) = (chars.next(), chars.next()) {
+            if let Some(idx) = Self::BULLETS.iter().position(|&known| known == bullet) {
+                let (count, first_line) = &mut self.seen[idx];
+                *count += 1;
+                first_line.get_or_insert(line_num);
+            }
+        }
+    }
+
+    /// The bullet most items use (`-` on a tie or when there are none), and a warning
+    /// pointing at the first item using another one
+    fn dominant(&self) -> (char, Option<ParseWarning>) {
+        // max_by_key keeps the last of equal counts, so list the preferred bullets last
+        let idx = (0..Self::BULLETS.len())
+            .rev()
+            .max_by_key(|&idx| self.seen[idx].0)
+            .unwrap_or(0);
+        let bullet = Self::BULLETS[idx];
+        let warning = (0..Self::BULLETS.len())
+            .filter(|&other| other != idx)
+            .filter_map(|other| self.seen[other].1)
+            .min()
+            .map(|line| ParseWarning::MixedBullets { line, bullet });
+        (bullet, warning)
+    }
+}
+
+/// Parse a todo file, choosing how items nested deeper than one subtask level are handled.
+/// Warnings are printed to stderr.
+pub fn parse_todo_file_with_nesting(
+    content: &str,
+    nesting: SubtaskNesting,
+) -> Result<TodoFile, String> {
+    let (todo_file, warnings) = parse_todo_file_with_warnings(content, nesting)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(todo_file)
+}
+
+/// Parse a todo file, returning what was read differently from how it was written
+/// alongside it so the caller can decide how to show it
+pub fn parse_todo_file_with_warnings(
+    content: &str,
+    nesting: SubtaskNesting,
+) -> Result<(TodoFile, Vec<ParseWarning>), String> {
+    let mut parser = TodoParser::new(nesting, None);
+    for (line_idx, line) in content.lines().enumerate() {
+        parser.read_line(line_idx + 1, line);
+    }
+    let (todo_file, warnings, _) = parser.finish();
+    Ok((todo_file, warnings))
+}
+
+/// The top of a todo file's Default list, read by `parse_todo_head`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoHead {
+    /// The first Default tasks, with at least the requested number of items when there are
+    /// that many; named lists are left empty
+    pub todo_file: TodoFile,
+    /// Default tasks after those kept
+    pub more_tasks: usize,
+    /// Subtasks and their children under the tasks counted in `more_tasks`
+    pub more_subtasks: usize,
+    /// Whether a task counted in `more_tasks` is pinned, so belongs at the top after all
+    pub more_pinned: bool,
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Reads a todo file line by line, keeping only the first Default tasks until they hold
+/// `items` items (tasks, subtasks and children) and just counting the rest. Memory stays
+/// bounded however long the file is, for listings that only show its top. The kept tasks
+/// and the warnings are the same as `parse_todo_file_with_warnings` would give.
+pub fn parse_todo_head(
+    reader: impl std::io::BufRead,
+    items: usize,
+    nesting: SubtaskNesting,
+) -> std::io::Result<TodoHead> {
+    let mut parser = TodoParser::new(nesting, Some(items));
+    for (line_idx, line) in reader.lines().enumerate() {
+        parser.read_line(line_idx + 1, &line?);
+    }
+    // Files the last task now, so a pinned one is seen
+    parser.end_task();
+    let more_pinned = parser.skipped_pinned;
+    let (todo_file, warnings, (more_tasks, more_subtasks)) = parser.finish();
+    Ok(TodoHead {
+        todo_file,
+        more_tasks,
+        more_subtasks,
+        more_pinned,
+        warnings,
+    })
+}
+
+/// Line-by-line todo file parser with resilient handling of user edits
+struct TodoParser {
+    todo_file: TodoFile,
+    warnings: Vec<ParseWarning>,
+    nesting: SubtaskNesting,
+    bullets: BulletTally,
+    current_task: Option<Task>,
+    current_list: String,
+    warned_about_deep_nesting: bool,
+    // Indentation of the current task's first subtask and of the current subtask's first child
+    subtask_indent: usize,
+    child_indent: usize,
+    /// Stop keeping Default tasks once they hold this many items; named lists are dropped
+    item_limit: Option<usize>,
+    kept_items: usize,
+    /// Tasks and subtasks past `item_limit`
+    skipped: (usize, usize),
+    skipped_pinned: bool,
+}
+
+impl TodoParser {
+    fn new(nesting: SubtaskNesting, item_limit: Option<usize>) -> Self {
+        TodoParser {
+            todo_file: TodoFile::new("TODOs".to_string()),
+            warnings: Vec::new(),
+            nesting,
+            bullets: BulletTally::default(),
+            current_task: None,
+            current_list: DEFAULT_LIST.to_string(),
+            warned_about_deep_nesting: false,
+            subtask_indent: 0,
+            child_indent: 0,
+            item_limit,
+            kept_items: 0,
+            skipped: (0, 0),
+            skipped_pinned: false,
+        }
+    }
+
+    /// Files the finished current task under the current list
+    fn end_task(&mut self) {
+        let Some(task) = self.current_task.take() else {
+            return;
+        };
+        if let Some(limit) = self.item_limit {
+            if !TodoFile::is_default_list(&self.current_list) {
+                return;
+            }
+            let subtasks: usize = task
+                .subtasks
+                .iter()
+                .map(|subtask| 1 + subtask.children.len())
+                .sum();
+            if self.kept_items >= limit {
+                self.skipped.0 += 1;
+                self.skipped.1 += subtasks;
+                self.skipped_pinned |= task.pinned;
+                return;
+            }
+            self.kept_items += 1 + subtasks;
+        }
+        self.todo_file.list_or_create(&self.current_list).push(task);
+    }
+
+    /// Starts a new task, filing the previous one
+    fn start_task(&mut self, text: &str) {
+        self.end_task();
+        self.current_task = Some(parse_task_text(text));
+    }
+
+    /// Adds a subtask to the current task; without one, it becomes a task itself
+    /// (user error but be forgiving)
+    fn add_subtask(&mut self, line_num: usize, text: &str) {
+        match self.current_task.as_mut() {
+            Some(task) => task.subtasks.push(Subtask::parse(text.trim())),
+            None => {
+                self.warnings
+                    .push(ParseWarning::OrphanedSubtask { line: line_num });
+                self.current_task = Some(parse_task_text(text));
+            }
+        }
+    }
+
+    fn read_line(&mut self, line_num: usize, line: &str) {
+        self.bullets.add(line_num, line);
         let trimmed = line.trim();
 
         if trimmed.is_empty() {
-            continue;
+            return;
         }
 
         // Handle title - be flexible with spacing
         if let Some(title) = trimmed.strip_prefix("# ") {
-            todo_file.title = title.trim().to_string();
+            self.todo_file.title = title.trim().to_string();
         } else if trimmed.starts_with("#") && !trimmed.starts_with("##") {
             // Handle cases where user might not have space after # (but not ##)
             let title = &trimmed[1..];
-            todo_file.title = title.trim().to_string();
+            self.todo_file.title = title.trim().to_string();
         }
-        // Skip any list headers - we ignore them now since we don't support multiple lists
-        else if trimmed.starts_with("##") {
-            continue;
+        // A list header starts a new named list
+        else if let Some(list_name) = trimmed.strip_prefix("##") {
+            self.end_task();
+
+            let list_name = list_name.trim_start_matches('#').trim();
+            self.current_list = if list_name.is_empty() {
+                DEFAULT_LIST.to_string()
+            } else {
+                list_name.to_string()
+            };
+            self.todo_file.list_or_create(&self.current_list);
+        }
+        // With two-level nesting, indentation relative to the first subtask decides the level
+        else if let (SubtaskNesting::TwoLevel, Some(task), Some((indent, item_text))) = (
+            self.nesting,
+            self.current_task.as_mut(),
+            split_indented_bullet(line),
+        ) {
+            if task.subtasks.is_empty() {
+                self.subtask_indent = indent;
+            }
+            match task.subtasks.last_mut() {
+                Some(subtask) if indent > self.subtask_indent => {
+                    if subtask.children.is_empty() {
+                        self.child_indent = indent;
+                    } else if indent > self.child_indent && !self.warned_about_deep_nesting {
+                        self.warnings.push(ParseWarning::DeepNesting {
+                            line: line_num,
+                            level: 2,
+                        });
+                        self.warned_about_deep_nesting = true;
+                    }
+                    subtask.children.push(item_text.to_string());
+                }
+                _ => task.add_subtask(item_text.to_string()),
+            }
         }
         // Check for deep nesting first - warn and convert to level 1 subtask
         else if (line.starts_with("     ") || line.starts_with("\t\t"))
@@ -182,9 +1019,12 @@ pub fn parse_todo_file(content: &str) -> Result<TodoFile, String> {
                 || line.trim_start().starts_with("+ "))
         {
             // This is a deeply nested item - warn once and treat as level 1 subtask
-            if !warned_about_deep_nesting {
-                eprintln!("Warning: Deep nesting detected (line {}). Converting to level 1 subtask. Only single-level subtasks are supported.", line_num + 1);
-                warned_about_deep_nesting = true;
+            if !self.warned_about_deep_nesting {
+                self.warnings.push(ParseWarning::DeepNesting {
+                    line: line_num,
+                    level: 1,
+                });
+                self.warned_about_deep_nesting = true;
             }
 
             let item_text = line
@@ -193,88 +1033,55 @@ pub fn parse_todo_file(content: &str) -> Result<TodoFile, String> {
                 .or_else(|| line.trim_start().strip_prefix("* "))
                 .or_else(|| line.trim_start().strip_prefix("+ "))
                 .unwrap_or(line.trim_start());
-
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(item_text.trim().to_string());
-            } else {
-                // If no current task, treat as main task
-                current_task = Some(Task::new(item_text.trim().to_string()));
-            }
+            self.add_subtask(line_num, item_text);
         }
         // Handle subtasks - be flexible with indentation (2, 3, or 4 spaces, or single tab)
-        else if let Some(subtask_text) = line.strip_prefix("  - ") {
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(subtask_text.trim().to_string());
-            } else {
-                // If no current task, treat as main task (user error but be forgiving)
-                current_task = Some(Task::new(subtask_text.trim().to_string()));
-            }
-        } else if let Some(subtask_text) = line.strip_prefix("   - ") {
-            // Handle 3-space indentation
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(subtask_text.trim().to_string());
-            } else {
-                current_task = Some(Task::new(subtask_text.trim().to_string()));
-            }
-        } else if let Some(subtask_text) = line.strip_prefix("    - ") {
-            // Handle 4-space indentation
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(subtask_text.trim().to_string());
-            } else {
-                current_task = Some(Task::new(subtask_text.trim().to_string()));
-            }
-        } else if let Some(subtask_text) = line.strip_prefix("\t- ") {
-            // Handle single tab indentation
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(subtask_text.trim().to_string());
-            } else {
-                current_task = Some(Task::new(subtask_text.trim().to_string()));
-            }
+        else if let Some(subtask_text) = line
+            .strip_prefix("  - ")
+            .or_else(|| line.strip_prefix("   - "))
+            .or_else(|| line.strip_prefix("    - "))
+            .or_else(|| line.strip_prefix("\t- "))
+        {
+            self.add_subtask(line_num, subtask_text);
         }
         // Handle main tasks - flexible with spacing and different bullet styles
-        else if let Some(task_text) = trimmed.strip_prefix("- ") {
-            // Save previous task if exists
-            if let Some(task) = current_task.take() {
-                todo_file.add_task(task);
-            }
-
-            current_task = Some(Task::new(task_text.trim().to_string()));
-        } else if let Some(task_text) = trimmed.strip_prefix("* ") {
-            // Handle asterisk bullet points
-            if let Some(task) = current_task.take() {
-                todo_file.add_task(task);
-            }
-
-            current_task = Some(Task::new(task_text.trim().to_string()));
-        } else if let Some(task_text) = trimmed.strip_prefix("+ ") {
-            // Handle plus bullet points
-            if let Some(task) = current_task.take() {
-                todo_file.add_task(task);
-            }
-
-            current_task = Some(Task::new(task_text.trim().to_string()));
+        else if let Some(task_text) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+            .or_else(|| trimmed.strip_prefix("+ "))
+        {
+            self.start_task(task_text);
         }
-        // Handle non-markdown lines gracefully - ignore unknown formatting
-        else if !trimmed.is_empty() {
-            // If it looks like it might be a task without proper formatting, treat it as one
-            if !trimmed.starts_with('#') && !trimmed.starts_with('<') && !trimmed.contains("```") {
-                // Save previous task if exists
-                if let Some(task) = current_task.take() {
-                    todo_file.add_task(task);
-                }
-
-                current_task = Some(Task::new(trimmed.to_string()));
-            }
-            // Otherwise just skip unknown lines (comments, HTML, code blocks, etc.)
+        // Handle non-markdown lines gracefully - ignore unknown formatting.
+        // If it looks like it might be a task without proper formatting, treat it as one;
+        // otherwise just skip unknown lines (comments, HTML, code blocks, etc.)
+        else if !trimmed.starts_with('#') && !trimmed.starts_with('<') && !trimmed.contains("```")
+        {
+            self.start_task(trimmed);
         }
     }
 
-    // Save final task
-    if let Some(task) = current_task {
-        todo_file.add_task(task);
+    /// Files the last task, returning the parsed file, its warnings and what was skipped
+    fn finish(mut self) -> (TodoFile, Vec<ParseWarning>, (usize, usize)) {
+        self.end_task();
+        let (bullet, mixed_bullets) = self.bullets.dominant();
+        self.todo_file.bullet = bullet;
+        // The bullet warning comes first, as it is about the whole file
+        let mut warnings: Vec<ParseWarning> = mixed_bullets.into_iter().collect();
+        warnings.extend(self.warnings);
+        (self.todo_file, warnings, self.skipped)
     }
+}
 
-    Ok(todo_file)
+/// Append a task line followed by its subtasks and their children
+fn push_task_lines(content: &mut String, task: &Task, bullet: char) {
+    content.push_str(&format!("{} {}\n", bullet, format_task_text(task)));
+    for subtask in &task.subtasks {
+        content.push_str(&format!("  {} {}\n", bullet, subtask.line_text()));
+        for child in &subtask.children {
+            content.push_str(&format!("    {} {}\n", bullet, child));
+        }
+    }
 }
 
 /// Generate markdown content from a TodoFile
@@ -283,9 +1090,16 @@ pub fn generate_todo_file(todo_file: &TodoFile) -> String {
     content.push_str(&format!("# {}\n\n", todo_file.title));
 
     for task in &todo_file.tasks {
-        content.push_str(&format!("- {}\n", task.text));
-        for subtask in &task.subtasks {
-            content.push_str(&format!("  - {}\n", subtask));
+        push_task_lines(&mut content, task, todo_file.bullet);
+    }
+
+    for list in &todo_file.lists {
+        if !content.ends_with("\n\n") {
+            content.push('\n');
+        }
+        content.push_str(&format!("## {}\n\n", list.name));
+        for task in &list.tasks {
+            push_task_lines(&mut content, task, todo_file.bullet);
         }
     }
 
@@ -305,6 +1119,12 @@ pub struct ArchiveEntry {
     pub lists: HashMap<String, Vec<Task>>,
 }
 
+impl Default for ArchiveFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ArchiveFile {
     pub fn new() -> Self {
         ArchiveFile {
@@ -314,12 +1134,15 @@ impl ArchiveFile {
     }
 
     pub fn add_items_for_today(&mut self, list_name: &str, tasks: Vec<Task>) {
-        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        self.add_items_for_date(chrono::Local::now().date_naive(), list_name, tasks);
+    }
 
-        // Find today's entry position
-        let entry_pos = self.entries.iter().position(|e| e.date == today);
+    /// Files `tasks` under `date`, merging into its entry if there is one. A new entry
+    /// goes before the first older one, keeping the newest-first order.
+    pub fn add_items_for_date(&mut self, date: NaiveDate, list_name: &str, tasks: Vec<Task>) {
+        let date = date.format(DATE_FORMAT).to_string();
 
-        if let Some(pos) = entry_pos {
+        if let Some(pos) = self.entries.iter().position(|e| e.date == date) {
             // Entry exists, add tasks to it
             self.entries[pos]
                 .lists
@@ -327,25 +1150,39 @@ impl ArchiveFile {
                 .or_default()
                 .extend(tasks);
         } else {
-            // Create new entry for today
+            let pos = self
+                .entries
+                .iter()
+                .position(|e| e.date < date)
+                .unwrap_or(self.entries.len());
             let mut lists = HashMap::new();
             lists.insert(list_name.to_string(), tasks);
-            self.entries.insert(0, ArchiveEntry { date: today, lists });
+            self.entries.insert(pos, ArchiveEntry { date, lists });
         }
     }
 }
 
-/// Parse an archive file
+/// Parse an archive file. Lines that can't be read are skipped with a warning on stderr,
+/// so a bad hand edit never blocks archiving; use `parse_archive_file_with_warnings` to
+/// handle them.
 pub fn parse_archive_file(content: &str) -> Result<ArchiveFile, String> {
-    let lines: Vec<&str> = content.lines().collect();
-    if lines.is_empty() {
-        return Ok(ArchiveFile::new());
+    let (archive, warnings) = parse_archive_file_with_warnings(content);
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
     }
+    Ok(archive)
+}
 
+/// Parse an archive file, returning the lines that were skipped alongside it
+pub fn parse_archive_file_with_warnings(content: &str) -> (ArchiveFile, Vec<ParseWarning>) {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut warnings = Vec::new();
     let mut archive = ArchiveFile::new();
     let mut current_entry: Option<ArchiveEntry> = None;
     let mut current_list_name = "Default".to_string();
     let mut current_task: Option<Task> = None;
+    // Indent of the current task's first subtask; deeper items are that subtask's children
+    let mut subtask_indent: Option<usize> = None;
 
     for (line_num, line) in lines.iter().enumerate() {
         let trimmed = line.trim();
@@ -354,7 +1191,23 @@ pub fn parse_archive_file(content: &str) -> Result<ArchiveFile, String> {
             continue;
         }
 
-        if let Some(title) = trimmed.strip_prefix("# ") {
+        if let Some((indent, item_text)) = split_indented_bullet(line) {
+            match current_task.as_mut() {
+                Some(task) => {
+                    let subtask_indent = *subtask_indent.get_or_insert(indent);
+                    match task.subtasks.last_mut() {
+                        Some(subtask) if indent > subtask_indent => {
+                            subtask.children.push(item_text.to_string())
+                        }
+                        _ => task.subtasks.push(Subtask::parse(item_text)),
+                    }
+                }
+                None => warnings.push(ParseWarning::UnrecognizedLine {
+                    line: line_num + 1,
+                    text: trimmed.to_string(),
+                }),
+            }
+        } else if let Some(title) = trimmed.strip_prefix("# ") {
             archive.title = title.to_string();
         } else if let Some(date) = trimmed.strip_prefix("## ") {
             // Save previous task and entry
@@ -397,23 +1250,21 @@ pub fn parse_archive_file(content: &str) -> Result<ArchiveFile, String> {
                     .push(task);
             }
 
-            current_task = Some(Task::new(task_text.to_string()));
-        } else if let Some(subtask_text) = trimmed.strip_prefix("  - ") {
-            if let Some(ref mut task) = current_task {
-                task.add_subtask(subtask_text.to_string());
-            } else {
-                return Err(format!(
-                    "Subtask found without parent task at line {}: {}",
-                    line_num + 1,
-                    trimmed
-                ));
+            if current_entry.is_none() {
+                // Without a date there's nowhere to file the item
+                warnings.push(ParseWarning::UnrecognizedLine {
+                    line: line_num + 1,
+                    text: trimmed.to_string(),
+                });
+                continue;
             }
-        } else if !trimmed.is_empty() {
-            return Err(format!(
-                "Invalid archive format at line {}: {}",
-                line_num + 1,
-                trimmed
-            ));
+            current_task = Some(parse_task_text(task_text));
+            subtask_indent = None;
+        } else {
+            warnings.push(ParseWarning::UnrecognizedLine {
+                line: line_num + 1,
+                text: trimmed.to_string(),
+            });
         }
     }
 
@@ -425,7 +1276,28 @@ pub fn parse_archive_file(content: &str) -> Result<ArchiveFile, String> {
         archive.entries.push(entry);
     }
 
-    Ok(archive)
+    archive.entries = merge_duplicate_dates(archive.entries);
+    (archive, warnings)
+}
+
+/// Folds entries sharing a date (e.g. from a hand edit) into the first one with that date,
+/// keeping the items of each list in file order
+fn merge_duplicate_dates(entries: Vec<ArchiveEntry>) -> Vec<ArchiveEntry> {
+    let mut merged: Vec<ArchiveEntry> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match merged
+            .iter_mut()
+            .find(|existing| existing.date == entry.date)
+        {
+            Some(existing) => {
+                for (list_name, tasks) in entry.lists {
+                    existing.lists.entry(list_name).or_default().extend(tasks);
+                }
+            }
+            None => merged.push(entry),
+        }
+    }
+    merged
 }
 
 /// Generate archive file content
@@ -439,22 +1311,18 @@ pub fn generate_archive_file(archive: &ArchiveFile) -> String {
         // Add Default list items first (without header)
         if let Some(default_tasks) = entry.lists.get("Default") {
             for task in default_tasks {
-                content.push_str(&format!("- {}\n", task.text));
-                for subtask in &task.subtasks {
-                    content.push_str(&format!("  - {}\n", subtask));
-                }
+                push_task_lines(&mut content, task, '-');
             }
         }
 
-        // Add other lists with headers
-        for (list_name, tasks) in &entry.lists {
+        // Add other lists with headers, sorted so the file doesn't churn between writes
+        let mut named: Vec<_> = entry.lists.iter().collect();
+        named.sort_by(|a, b| a.0.cmp(b.0));
+        for (list_name, tasks) in named {
             if list_name != "Default" && !tasks.is_empty() {
                 content.push_str(&format!("\n### {}\n", list_name));
                 for task in tasks {
-                    content.push_str(&format!("- {}\n", task.text));
-                    for subtask in &task.subtasks {
-                        content.push_str(&format!("  - {}\n", subtask));
-                    }
+                    push_task_lines(&mut content, task, '-');
                 }
             }
         }
@@ -468,13 +1336,95 @@ pub fn generate_archive_file(archive: &ArchiveFile) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_archive_keeps_subtasks_nested() {
+        let content = "# Archive\n\n## 2025-05-01\n- Ship release\n  - note: shipped\n  - [x] Tag\n    - Push tag\n- Water plants\n";
+
+        let (archive, warnings) = parse_archive_file_with_warnings(content);
+        assert!(warnings.is_empty());
+        let tasks = &archive.entries[0].lists["Default"];
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].subtasks, vec!["note: shipped", "Tag"]);
+        assert_eq!(tasks[0].subtasks[1].completed, Some(true));
+        assert_eq!(tasks[0].subtasks[1].children, vec!["Push tag"]);
+
+        assert_eq!(generate_archive_file(&archive), content);
+    }
+
+    #[test]
+    fn test_archive_skips_unrecognized_lines() {
+        let content = "# Archive\n\n- Before any date\n\n## 2025-05-01\n- Kept\nstray text\n\n### Work\n- Also kept\n";
+        let (archive, warnings) = parse_archive_file_with_warnings(content);
+
+        assert_eq!(archive.entries.len(), 1);
+        assert_eq!(archive.entries[0].lists["Default"][0].text, "Kept");
+        assert_eq!(archive.entries[0].lists["Work"][0].text, "Also kept");
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::UnrecognizedLine {
+                    line: 3,
+                    text: "- Before any date".to_string()
+                },
+                ParseWarning::UnrecognizedLine {
+                    line: 7,
+                    text: "stray text".to_string()
+                },
+            ]
+        );
+    }
+
+    fn tasks(texts: &[&str]) -> Vec<Task> {
+        texts
+            .iter()
+            .map(|text| Task::new(text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_task_ref_first_and_last() {
+        let four = tasks(&["One", "Two", "Three", "Four"]);
+        assert_eq!(TaskRef::resolve("first", &four).unwrap().task_index, 0);
+        assert_eq!(TaskRef::resolve("Last", &four).unwrap().task_index, 3);
+        assert_eq!(
+            TaskRef::resolve("2b", &four).unwrap(),
+            TaskRef::parse("2b").unwrap()
+        );
+        assert!(TaskRef::resolve("lasta", &four).is_err());
+        assert!(TaskRef::resolve("last", &[]).is_err());
+        assert!(TaskRef::parse("first").is_err());
+    }
+
+    #[test]
+    fn test_task_ref_by_text() {
+        let tasks = tasks(&["Buy milk", "Call the bank", "Buy eggs", "Pay bank fees"]);
+        assert_eq!(TaskRef::resolve("/call", &tasks).unwrap().task_index, 1);
+        // A unique prefix wins over other tasks merely containing the text
+        assert_eq!(TaskRef::resolve("/Pay", &tasks).unwrap().task_index, 3);
+        assert_eq!(TaskRef::resolve("/eggs", &tasks).unwrap().task_index, 2);
+        assert_eq!(
+            TaskRef::resolve("/buy", &tasks).unwrap_err(),
+            "'buy' matches 2 tasks: 1. Buy milk; 3. Buy eggs"
+        );
+        assert_eq!(
+            TaskRef::resolve("/bank", &tasks).unwrap_err(),
+            "'bank' matches 2 tasks: 2. Call the bank; 4. Pay bank fees"
+        );
+        assert_eq!(
+            TaskRef::resolve("/dentist", &tasks).unwrap_err(),
+            "no task matches 'dentist'"
+        );
+        assert!(TaskRef::resolve("/", &tasks).is_err());
+    }
+
     #[test]
     fn test_task_ref_parsing() {
         assert_eq!(
             TaskRef::parse("1").unwrap(),
             TaskRef {
                 task_index: 0,
-                subtask_index: None
+                subtask_index: None,
+                child_index: None
             }
         );
 
@@ -482,7 +1432,8 @@ mod tests {
             TaskRef::parse("5a").unwrap(),
             TaskRef {
                 task_index: 4,
-                subtask_index: Some(0)
+                subtask_index: Some(0),
+                child_index: None
             }
         );
 
@@ -490,7 +1441,8 @@ mod tests {
             TaskRef::parse("10z").unwrap(),
             TaskRef {
                 task_index: 9,
-                subtask_index: Some(25)
+                subtask_index: Some(25),
+                child_index: None
             }
         );
 
@@ -500,6 +1452,19 @@ mod tests {
         assert!(TaskRef::parse("1ab").is_err());
         assert!(TaskRef::parse("1-2").is_err());
 
+        // Nested children of a subtask
+        assert_eq!(
+            TaskRef::parse("2a1").unwrap(),
+            TaskRef {
+                task_index: 1,
+                subtask_index: Some(0),
+                child_index: Some(0)
+            }
+        );
+        assert_eq!(TaskRef::parse("3b12").unwrap().child_index, Some(11));
+        assert!(TaskRef::parse("2a0").is_err());
+        assert!(TaskRef::parse("2a1b").is_err());
+
         // Test validation edge cases
         assert!(TaskRef::parse("0").is_err()); // Zero task number
         assert!(TaskRef::parse("10001").is_err()); // Too large task number
@@ -564,6 +1529,161 @@ mod tests {
         assert_eq!(todo_file.tasks[0].subtasks[1], "Deep subtask");
     }
 
+    #[test]
+    fn test_two_level_nesting_round_trip() {
+        let content = r#"# TODOs
+
+- Task
+  - Subtask
+    - Child one
+    - Child two
+  - Second subtask
+"#;
+
+        let todo_file = parse_todo_file_with_nesting(content, SubtaskNesting::TwoLevel).unwrap();
+        let subtasks = &todo_file.tasks[0].subtasks;
+        assert_eq!(subtasks.len(), 2);
+        assert_eq!(subtasks[0], "Subtask");
+        assert_eq!(subtasks[0].children, vec!["Child one", "Child two"]);
+        assert!(subtasks[1].children.is_empty());
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+
+        // The compatibility default still flattens
+        let flattened = parse_todo_file_with_nesting(content, SubtaskNesting::Flatten).unwrap();
+        assert_eq!(flattened.tasks[0].subtasks.len(), 4);
+    }
+
+    #[test]
+    fn test_two_level_nesting_is_relative_to_first_subtask() {
+        // Subtasks indented with four spaces or tabs still nest one level deeper
+        let content = "# TODOs\n\n- Task\n    - Subtask\n        - Child\n            - Grandchild\n\t- Tab subtask\n";
+
+        let todo_file = parse_todo_file_with_nesting(content, SubtaskNesting::TwoLevel).unwrap();
+        let subtasks = &todo_file.tasks[0].subtasks;
+        assert_eq!(subtasks.len(), 2);
+        // Anything deeper than two levels is flattened into level 2
+        assert_eq!(subtasks[0].children, vec!["Child", "Grandchild"]);
+        assert_eq!(subtasks[1], "Tab subtask");
+    }
+
+    #[test]
+    fn test_orphaned_subtask_becomes_a_task_with_warning() {
+        let content = "# TODOs\n\n- Task\n\n## Work\n  - Stray subtask\n- Real task\n";
+
+        let (todo_file, warnings) =
+            parse_todo_file_with_warnings(content, SubtaskNesting::Flatten).unwrap();
+        let work = &todo_file.lists[0].tasks;
+        assert_eq!(work[0].text, "Stray subtask");
+        assert_eq!(work[1].text, "Real task");
+        assert_eq!(warnings, vec![ParseWarning::OrphanedSubtask { line: 6 }]);
+        assert_eq!(warnings[0].line(), 6);
+    }
+
+    #[test]
+    fn test_deep_nesting_warning_is_returned() {
+        let content = "# TODOs\n\n- Task\n  - Subtask\n      - Too deep\n        - Deeper\n";
+
+        let (todo_file, warnings) =
+            parse_todo_file_with_warnings(content, SubtaskNesting::Flatten).unwrap();
+        assert_eq!(todo_file.tasks[0].subtasks.len(), 3);
+        // Only the first occurrence is reported
+        assert_eq!(
+            warnings,
+            vec![ParseWarning::DeepNesting { line: 5, level: 1 }]
+        );
+        assert!(warnings[0]
+            .to_string()
+            .starts_with("Deep nesting detected (line 5)"));
+
+        let (_, warnings) =
+            parse_todo_file_with_warnings("# TODOs\n\n- Task\n", SubtaskNesting::Flatten).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_archive_duplicate_dates_are_merged() {
+        let content = "# Archive\n\n## 2025-01-02\n- A\n\n### Work\n- B\n\n## 2025-01-01\n- C\n\n## 2025-01-02\n- D\n\n### Work\n- E\n\n### Home\n- F\n";
+
+        let archive = parse_archive_file(content).unwrap();
+        assert_eq!(archive.entries.len(), 2);
+        let entry = &archive.entries[0];
+        assert_eq!(entry.date, "2025-01-02");
+        let texts = |list: &str| -> Vec<String> {
+            entry.lists[list].iter().map(|t| t.text.clone()).collect()
+        };
+        assert_eq!(texts("Default"), vec!["A", "D"]);
+        assert_eq!(texts("Work"), vec!["B", "E"]);
+        assert_eq!(texts("Home"), vec!["F"]);
+        assert_eq!(archive.entries[1].date, "2025-01-01");
+    }
+
+    #[test]
+    fn test_rename_and_remove_lists() {
+        let mut todo_file =
+            parse_todo_file("# TODOs\n\n- Task\n\n## Shopping\n\n- Milk\n\n## Work\n").unwrap();
+
+        todo_file.rename_list("shopping", "Groceries").unwrap();
+        assert_eq!(todo_file.lists[0].name, "Groceries");
+        assert_eq!(todo_file.lists[0].tasks[0].text, "Milk");
+        // Changing only the case is fine, taking another list's name isn't
+        todo_file.rename_list("groceries", "groceries").unwrap();
+        assert!(todo_file.rename_list("groceries", "WORK").is_err());
+        assert!(todo_file.rename_list("Default", "Main").is_err());
+        assert!(todo_file.rename_list("Nope", "Other").is_err());
+
+        assert!(todo_file.remove_list("groceries", false).is_err());
+        assert_eq!(
+            todo_file
+                .remove_list("groceries", true)
+                .unwrap()
+                .tasks
+                .len(),
+            1
+        );
+        todo_file.remove_list("work", false).unwrap();
+        assert!(todo_file.lists.is_empty());
+        assert!(todo_file.remove_list("Default", true).is_err());
+    }
+
+    #[test]
+    fn test_markdown_significant_text_round_trips() {
+        let texts = [
+            "# not a header",
+            "## Work",
+            "- literally dashes",
+            "* starred",
+            "+ plus",
+            "> quoted",
+            "#hashtag",
+        ];
+
+        for text in texts {
+            let mut task = Task::new(text.to_string());
+            task.subtasks.push(Subtask {
+                text: text.to_string(),
+                children: vec![text.to_string()],
+                completed: None,
+            });
+            let mut todo_file = TodoFile::new("TODOs".to_string());
+            todo_file.add_task(task);
+            todo_file
+                .list_or_create("Work")
+                .push(Task::new(text.to_string()));
+
+            let content = generate_todo_file(&todo_file);
+            let parsed = parse_todo_file_with_nesting(&content, SubtaskNesting::TwoLevel).unwrap();
+            assert_eq!(parsed, todo_file, "round trip of {:?}", text);
+            assert_eq!(generate_todo_file(&parsed), content);
+
+            // Flattening keeps the text too; only the child moves up a level
+            let flat = parse_todo_file_with_nesting(&content, SubtaskNesting::Flatten).unwrap();
+            assert_eq!(flat.tasks[0].text, text);
+            assert_eq!(flat.tasks[0].subtasks[0], text);
+            assert_eq!(flat.tasks[0].subtasks[1], text);
+        }
+    }
+
     #[test]
     fn test_resilient_parsing_various_formats() {
         let content = r#"# TODOs
@@ -609,4 +1729,279 @@ This is a code block
         assert_eq!(todo_file.tasks[5].text, "This is a code block");
         // Comments are ignored but code block content is parsed
     }
+
+    #[test]
+    fn test_asterisk_bullets_round_trip() {
+        let content = "# TODOs\n\n* Task\n  * Subtask\n    * Child\n\n## Work\n\n* Ship it\n";
+        let (todo_file, warnings) =
+            parse_todo_file_with_warnings(content, SubtaskNesting::TwoLevel).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(todo_file.bullet, '*');
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_mixed_bullets_use_the_most_common() {
+        let content = "# TODOs\n\n+ One\n- Two\n+ Three\n  - Sub\n+ Four\n";
+        let (todo_file, warnings) =
+            parse_todo_file_with_warnings(content, SubtaskNesting::Flatten).unwrap();
+        assert_eq!(todo_file.bullet, '+');
+        assert_eq!(
+            warnings,
+            vec![ParseWarning::MixedBullets {
+                line: 4,
+                bullet: '+'
+            }]
+        );
+        assert_eq!(
+            generate_todo_file(&todo_file),
+            "# TODOs\n\n+ One\n+ Two\n+ Three\n  + Sub\n+ Four\n"
+        );
+
+        // A tie keeps the usual dash
+        let todo_file = parse_todo_file("# TODOs\n\n* One\n- Two\n").unwrap();
+        assert_eq!(todo_file.bullet, '-');
+    }
+
+    #[test]
+    fn test_parse_todo_head_keeps_only_the_top() {
+        let content = "# TODOs\n\n- One\n  - 1a\n- Two\n* Three\n  - 3a\n  - 3b\n\n## Work\n\n- Ship\n\n## Default\n\n- Four\n";
+        let head = parse_todo_head(content.as_bytes(), 3, SubtaskNesting::Flatten).unwrap();
+        let (full, warnings) =
+            parse_todo_file_with_warnings(content, SubtaskNesting::Flatten).unwrap();
+        // Tasks are kept whole until they hold at least 3 items
+        assert_eq!(head.todo_file.tasks, full.tasks[..2]);
+        assert_eq!((head.more_tasks, head.more_subtasks), (2, 2));
+        assert!(head.todo_file.list("Work").unwrap().is_empty());
+        assert_eq!(head.warnings, warnings);
+        assert_eq!(head.warnings.len(), 1);
+
+        let head = parse_todo_head(content.as_bytes(), 100, SubtaskNesting::Flatten).unwrap();
+        assert_eq!(head.todo_file.tasks, full.tasks);
+        assert_eq!((head.more_tasks, head.more_subtasks), (0, 0));
+    }
+
+    #[test]
+    fn test_due_date_round_trip() {
+        let content = r#"# TODOs
+
+- Pay rent <!-- due:2025-05-01 -->
+  - Transfer money
+- No due date
+"#;
+
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].text, "Pay rent");
+        assert_eq!(
+            todo_file.tasks[0].due,
+            Some(NaiveDate::from_ymd_opt(2025, 5, 1).unwrap())
+        );
+        assert_eq!(todo_file.tasks[0].subtasks, vec!["Transfer money"]);
+        assert_eq!(todo_file.tasks[1].due, None);
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_created_timestamp_round_trip() {
+        let content =
+            "# TODOs\n\n- Pay rent <!-- due:2025-05-01 created:2025-04-20T09:30 -->\n- Old task\n";
+
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].text, "Pay rent");
+        assert_eq!(
+            todo_file.tasks[0].created,
+            Some(
+                NaiveDate::from_ymd_opt(2025, 4, 20)
+                    .unwrap()
+                    .and_hms_opt(9, 30, 0)
+                    .unwrap()
+            )
+        );
+        assert_eq!(todo_file.tasks[1].created, None);
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_reorder_lists() {
+        let mut todo_file = parse_todo_file(
+            "# TODOs\n\n- Home\n\n## Work\n\n- A\n\n## Personal\n\n- B\n\n## Shopping\n\n- C\n",
+        )
+        .unwrap();
+
+        todo_file
+            .reorder_lists(&["shopping".to_string(), "Work".to_string()])
+            .unwrap();
+        let names: Vec<_> = todo_file.lists.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["Shopping", "Work", "Personal"]);
+
+        assert!(todo_file.reorder_lists(&["Errands".to_string()]).is_err());
+        assert!(todo_file.reorder_lists(&["Default".to_string()]).is_err());
+        assert!(todo_file
+            .reorder_lists(&["Work".to_string(), "work".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_who_and_host_round_trip() {
+        let content = "# TODOs\n\n- Water plants <!-- who:alice host:laptop -->\n- Shared chore\n";
+
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].text, "Water plants");
+        assert_eq!(todo_file.tasks[0].who.as_deref(), Some("alice"));
+        assert_eq!(todo_file.tasks[0].host.as_deref(), Some("laptop"));
+        assert_eq!(todo_file.tasks[1].who, None);
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_plain_trailing_comment_stays_in_text() {
+        let content = "# TODOs\n\n- Call Bob <!-- note to self -->\n- Ship it <!-- see: docs --> <!-- id:k3x9 -->\n";
+
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].text, "Call Bob <!-- note to self -->");
+        assert_eq!(todo_file.tasks[1].text, "Ship it <!-- see: docs -->");
+        assert_eq!(todo_file.tasks[1].id.as_deref(), Some("k3x9"));
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_task_id_round_trip_and_reference() {
+        let content = "# TODOs\n\n- Water plants <!-- id:k3x9 -->\n- Pay rent\n";
+
+        let mut todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].id.as_deref(), Some("k3x9"));
+        assert_eq!(todo_file.tasks[1].id, None);
+        assert_eq!(generate_todo_file(&todo_file), content);
+
+        todo_file.tasks.swap(0, 1);
+        let reference = TaskRef::resolve("#K3X9", &todo_file.tasks).unwrap();
+        assert_eq!(reference.task_index, 1);
+        assert_eq!(
+            TaskRef::resolve("#zzzz", &todo_file.tasks).unwrap_err(),
+            "no task has ID 'zzzz'"
+        );
+
+        let fresh = todo_file.new_task_id();
+        assert_eq!(fresh.len(), TASK_ID_LENGTH);
+        assert_ne!(fresh, "k3x9");
+    }
+
+    #[test]
+    fn test_subtask_checkboxes_round_trip() {
+        let content = "# TODOs\n\n- Ship release\n  - [x] Tag\n  - [ ] Publish\n  - Announce\n- Plain\n  - Step\n";
+        let todo_file = parse_todo_file(content).unwrap();
+        let subtasks = &todo_file.tasks[0].subtasks;
+        assert_eq!(subtasks[0].text, "Tag");
+        assert_eq!(subtasks[0].completed, Some(true));
+        assert_eq!(subtasks[1].completed, Some(false));
+        assert_eq!(subtasks[2].completed, None);
+        assert_eq!(todo_file.tasks[0].progress(), Some((1, 3)));
+        assert_eq!(todo_file.tasks[1].progress(), None);
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
+
+    #[test]
+    fn test_checkbox_like_subtask_text_round_trips() {
+        let mut todo_file = TodoFile::new("TODOs".to_string());
+        let mut task = Task::new("Task".to_string());
+        for text in [
+            "[ ] literal sub",
+            "[x] done?",
+            "\\[x] odd",
+            "\\\\share",
+            "\\n",
+            "[link](url)",
+        ] {
+            task.add_subtask(text.to_string());
+        }
+        task.subtasks.push(Subtask {
+            completed: Some(true),
+            ..Subtask::new("[ ] nested".to_string())
+        });
+        todo_file.add_task(task);
+
+        let content = generate_todo_file(&todo_file);
+        assert!(content.contains("  - \\[ ] literal sub\n"));
+        assert!(content.contains("  - [link](url)\n"));
+        assert_eq!(parse_todo_file(&content).unwrap(), todo_file);
+    }
+
+    #[test]
+    fn test_add_items_for_date_keeps_newest_first() {
+        let mut archive =
+            parse_archive_file("# Archive\n\n## 2025-05-03\n- C\n\n## 2025-05-01\n- A\n").unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2025, 5, day).unwrap();
+        archive.add_items_for_date(date(2), "Default", vec![Task::new("B".to_string())]);
+        archive.add_items_for_date(date(1), "Work", vec![Task::new("Ship".to_string())]);
+        archive.add_items_for_date(date(4), "Default", vec![Task::new("D".to_string())]);
+        assert_eq!(
+            generate_archive_file(&archive),
+            "# Archive\n\n## 2025-05-04\n- D\n\n## 2025-05-03\n- C\n\n## 2025-05-02\n- B\n\n## 2025-05-01\n- A\n\n### Work\n- Ship\n"
+        );
+    }
+
+    #[test]
+    fn test_recurrence_round_trip_and_next_due() {
+        let content = "# TODOs\n\n- Water plants <!-- due:2025-05-01 recur:weekly -->\n";
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks[0].recur, Some(Recurrence::Weekly));
+        assert_eq!(generate_todo_file(&todo_file), content);
+
+        let date = |day| NaiveDate::from_ymd_opt(2025, 5, day).unwrap();
+        assert_eq!(Recurrence::Daily.next_due(None, date(1)), date(2));
+        assert_eq!(Recurrence::Weekly.next_due(Some(date(1)), date(1)), date(8));
+        // An overdue task comes back on its next date after today
+        assert_eq!(
+            Recurrence::Weekly.next_due(Some(date(1)), date(10)),
+            date(15)
+        );
+        assert_eq!(
+            Recurrence::Daily.next_due(Some(date(1)), date(10)),
+            date(11)
+        );
+    }
+
+    #[test]
+    fn test_invalid_due_metadata_is_ignored() {
+        let todo_file = parse_todo_file("# TODOs\n\n- Task <!-- due:someday -->\n").unwrap();
+        assert_eq!(todo_file.tasks[0].text, "Task");
+        assert_eq!(todo_file.tasks[0].due, None);
+    }
+
+    #[test]
+    fn test_named_lists_round_trip() {
+        let content = r#"# TODOs
+
+- Default task
+  - Default subtask
+
+## Work
+
+- Ship release
+- Review PR
+
+## Shopping
+
+"#;
+
+        let todo_file = parse_todo_file(content).unwrap();
+        assert_eq!(todo_file.tasks.len(), 1);
+        assert_eq!(todo_file.tasks[0].text, "Default task");
+        assert_eq!(todo_file.lists.len(), 2);
+        assert_eq!(todo_file.lists[0].name, "Work");
+        assert_eq!(todo_file.lists[0].tasks.len(), 2);
+        assert_eq!(todo_file.lists[1].name, "Shopping");
+        assert!(todo_file.lists[1].tasks.is_empty());
+
+        // Lookup is case-insensitive and "Default" maps to the primary list
+        assert_eq!(todo_file.list("work").unwrap().len(), 2);
+        assert_eq!(todo_file.list("default").unwrap().len(), 1);
+        assert!(!todo_file.has_list("Errands"));
+
+        assert_eq!(generate_todo_file(&todo_file), content);
+    }
 }
@@ -4,8 +4,45 @@
 //! to the new Markdown format with Default list structure.
 
 use crate::markdown::{ArchiveFile, Task, TodoFile};
+use crate::output::{self, Verbosity};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Which of the plain text and Markdown data files exist
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    /// Existing `note.txt`/`archive.txt`
+    pub plain_files: Vec<PathBuf>,
+    /// Existing `todos.md`/`archive.md`
+    pub markdown_files: Vec<PathBuf>,
+}
+
+impl MigrationStatus {
+    /// True if there is plain text to convert and no Markdown file it would replace
+    pub fn needed(&self) -> bool {
+        !self.plain_files.is_empty() && self.markdown_files.is_empty()
+    }
+}
+
+/// Looks up which data files exist in each format
+pub fn migration_status(
+    note_path: &Path,
+    archive_path: &Path,
+    todo_md_path: &Path,
+    archive_md_path: &Path,
+) -> MigrationStatus {
+    let existing = |paths: [&Path; 2]| {
+        paths
+            .into_iter()
+            .filter(|path| path.exists())
+            .map(Path::to_path_buf)
+            .collect()
+    };
+    MigrationStatus {
+        plain_files: existing([note_path, archive_path]),
+        markdown_files: existing([todo_md_path, archive_md_path]),
+    }
+}
 
 /// Check if migration is needed (plain text files exist but Markdown files don't)
 pub fn needs_migration(
@@ -14,10 +51,7 @@ pub fn needs_migration(
     todo_md_path: &Path,
     archive_md_path: &Path,
 ) -> bool {
-    let has_old_files = note_path.exists() || archive_path.exists();
-    let has_new_files = todo_md_path.exists() || archive_md_path.exists();
-
-    has_old_files && !has_new_files
+    migration_status(note_path, archive_path, todo_md_path, archive_md_path).needed()
 }
 
 /// Migrate plain text note.txt to Markdown todos.md
@@ -76,36 +110,40 @@ pub fn create_empty_todo_file() -> TodoFile {
     TodoFile::new("TODOs".to_string())
 }
 
-/// Create backup files before migration
-pub fn create_backups(note_path: &Path, archive_path: &Path) -> Result<(), String> {
-    if note_path.exists() {
-        let backup_path = note_path.with_extension("txt.bak");
-        fs::copy(note_path, &backup_path)
-            .map_err(|e| format!("Failed to backup note file to {:?}: {}", backup_path, e))?;
-        println!("Created backup: {:?}", backup_path);
-    }
-
-    if archive_path.exists() {
-        let backup_path = archive_path.with_extension("txt.bak");
-        fs::copy(archive_path, &backup_path)
-            .map_err(|e| format!("Failed to backup archive file to {:?}: {}", backup_path, e))?;
-        println!("Created backup: {:?}", backup_path);
+/// Copy each existing file to a `.bak` next to it (e.g. `note.txt.bak`) before migration
+pub fn create_backups(paths: &[&Path], verbosity: Verbosity) -> Result<(), String> {
+    for path in paths.iter().filter(|path| path.exists()) {
+        let mut backup_name = path.as_os_str().to_owned();
+        backup_name.push(".bak");
+        let backup_path = PathBuf::from(backup_name);
+        fs::copy(path, &backup_path)
+            .map_err(|e| format!("Failed to backup {:?} to {:?}: {}", path, backup_path, e))?;
+        if !verbosity.is_quiet() {
+            println!("Created backup: {:?}", backup_path);
+        }
     }
 
     Ok(())
 }
 
-/// Perform the complete migration process
+/// Perform the complete migration process. Markdown files already present are
+/// replaced, so they are backed up along with the plain text files.
 pub fn perform_migration(
     note_path: &Path,
     archive_path: &Path,
     todo_md_path: &Path,
     archive_md_path: &Path,
+    verbosity: Verbosity,
 ) -> Result<(), String> {
-    println!("🔄 Migrating from plain text to Markdown format...");
+    if !verbosity.is_quiet() {
+        println!("🔄 Migrating from plain text to Markdown format...");
+    }
 
     // Create backups first
-    create_backups(note_path, archive_path)?;
+    create_backups(
+        &[note_path, archive_path, todo_md_path, archive_md_path],
+        verbosity,
+    )?;
 
     // Migrate note file
     let todo_file = migrate_note_file(note_path)?;
@@ -130,6 +168,18 @@ pub fn perform_migration(
         .map(|tasks| tasks.len())
         .unwrap_or(0);
 
+    if verbosity.is_porcelain() {
+        output::porcelain(
+            "migrated",
+            &[
+                ("tasks", todo_count.to_string()),
+                ("archived", archive_count.to_string()),
+            ],
+        );
+    }
+    if verbosity.is_quiet() {
+        return Ok(());
+    }
     println!("✅ Migration completed successfully!");
     println!("   • Migrated {} todo items", todo_count);
     println!("   • Migrated {} archive items", archive_count);
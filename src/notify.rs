@@ -0,0 +1,30 @@
+//! Desktop notifications when tasks are added or completed.
+//!
+//! Opt-in twice over: ldr has to be built with the `notify` cargo feature, which pulls
+//! in `notify-rust`, and `notifications = true` has to be set in the config. Even then
+//! nothing is sent unless ldr runs in a terminal without `--quiet`, so scripts and cron
+//! jobs stay silent. Without the feature every call here does nothing.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns notifications on for the rest of the run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Shows `message` as a desktop notification, if enabled. Failures (no notification
+/// daemon, say) are ignored: the command has already succeeded.
+pub fn send(message: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    #[cfg(feature = "notify")]
+    let _ = notify_rust::Notification::new()
+        .appname("ldr")
+        .summary(message)
+        .show();
+    #[cfg(not(feature = "notify"))]
+    let _ = message;
+}
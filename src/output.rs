@@ -0,0 +1,433 @@
+//! Output settings shared by all commands: verbosity, whether and how to color, line
+//! wrapping and truncation.
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Names of the color themes; "auto" picks dark or light from the terminal
+pub const THEMES: &[&str] = &["auto", "dark", "light", "mono", "solarized"];
+
+/// Environment variable selecting a color theme, overriding the config file
+pub const THEME_ENV: &str = "LDR_THEME";
+
+/// Set once at startup from `$LDR_THEME` or the config file
+static THEME: OnceLock<&'static str> = OnceLock::new();
+
+/// Selects the color theme by name (case-insensitive), for the rest of the run
+pub fn set_theme(name: &str) -> Result<(), String> {
+    let theme = THEMES
+        .iter()
+        .find(|theme| theme.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            format!(
+                "Unknown theme '{}' (expected one of: {})",
+                name,
+                THEMES.join(", ")
+            )
+        })?;
+    // Only the first call counts; later ones would race with colors already printed
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+/// The selected color theme, "auto" unless one was set
+pub fn theme() -> &'static str {
+    THEME.get().copied().unwrap_or("auto")
+}
+
+/// When to color output, chosen with the global `--color` flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Always emit colors, even when piped or with `NO_COLOR` set
+    Always = 0,
+    /// Color only when stdout is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto = 1,
+    /// Never emit colors
+    Never = 2,
+}
+
+/// Set by the global `--color` flag
+static COLOR_CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+/// Selects when colors are emitted, for the rest of the run
+pub fn set_color_choice(choice: ColorChoice) {
+    COLOR_CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+/// True when escape codes should be written. Every colored write goes through this, via
+/// the `color` module below.
+pub fn colors_enabled() -> bool {
+    match COLOR_CHOICE.load(Ordering::Relaxed) {
+        0 => true,
+        2 => false,
+        _ => {
+            env::var("NO_COLOR").map_or(true, |value| value.is_empty())
+                && termion::is_tty(&io::stdout())
+        }
+    }
+}
+
+/// Stand-in for `termion::color` whose `Fg` writes nothing while colors are off
+pub mod color {
+    use std::fmt;
+    pub use termion::color::{Color, Green, Magenta, Red, Reset, Yellow};
+
+    /// Foreground color, written only when `colors_enabled`
+    pub struct Fg<C: Color>(pub C);
+
+    impl<C: Color + Copy> fmt::Display for Fg<C> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            if super::colors_enabled() {
+                write!(f, "{}", termion::color::Fg(self.0))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Bold, dim or reset style, written only when `colors_enabled`
+pub fn style<S: std::fmt::Display>(style: S) -> String {
+    if colors_enabled() {
+        style.to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// True when URLs should be written as clickable terminal hyperlinks: only with colors on
+/// and stdout attached to a terminal, since the escapes would be noise anywhere else
+pub fn hyperlinks_enabled() -> bool {
+    colors_enabled() && termion::is_tty(&io::stdout())
+}
+
+/// Wraps each `http://` or `https://` URL in `text` in an OSC 8 hyperlink escape, so
+/// terminals that support it make the link clickable while showing the same text.
+/// A URL ends at whitespace, quotes or angle brackets, minus trailing punctuation; one
+/// cut short with "…" is left alone, as it would link to the wrong address.
+pub fn hyperlink_urls(text: &str) -> String {
+    let mut linked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let (before, from_url) = rest.split_at(start);
+        let end = from_url
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(from_url.len());
+        let url = from_url[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '\'']);
+        linked.push_str(before);
+        if url.ends_with('…') || url.ends_with("://") {
+            linked.push_str(url);
+        } else {
+            linked.push_str(&format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, url));
+        }
+        rest = &from_url[url.len()..];
+    }
+    linked.push_str(rest);
+    linked
+}
+
+/// Set by the global `--truecolor` flag
+static FORCE_TRUECOLOR: AtomicBool = AtomicBool::new(false);
+
+/// Forces 24-bit color output regardless of `COLORTERM`
+pub fn force_truecolor() {
+    FORCE_TRUECOLOR.store(true, Ordering::Relaxed);
+}
+
+/// True when colors should be emitted as 24-bit escapes instead of 256-color palette indexes:
+/// either `--truecolor` was passed or the terminal advertises `COLORTERM=truecolor` (or `24bit`)
+pub fn truecolor_enabled() -> bool {
+    FORCE_TRUECOLOR.load(Ordering::Relaxed)
+        || env::var("COLORTERM")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("truecolor") || value == "24bit")
+}
+
+/// How long to wait for the terminal to report its background color
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How much longer to keep reading a reply that started but didn't finish in time
+const BACKGROUND_QUERY_DRAIN: Duration = Duration::from_millis(100);
+
+/// Whether the terminal's background is dark, as the terminal itself reports it.
+/// None when output isn't colored on a terminal or the terminal doesn't answer in time.
+/// The terminal is asked at most once per run.
+pub fn terminal_background_is_dark() -> Option<bool> {
+    static DARK: OnceLock<Option<bool>> = OnceLock::new();
+    *DARK.get_or_init(|| {
+        if !colors_enabled() || !termion::is_tty(&io::stdin()) || !termion::is_tty(&io::stdout()) {
+            return None;
+        }
+        let reply = query_background()?;
+        parse_background_reply(&reply).map(is_dark_color)
+    })
+}
+
+/// Asks the terminal for its background color with an OSC 11 query and returns the raw
+/// reply, e.g. `\x1b]11;rgb:1e1e/1e1e/1e1e\x07`. The terminal is put in raw mode so the
+/// reply is neither echoed nor held back until a newline. Reading waits with `poll`, so
+/// a terminal that never answers only costs the timeout, and nothing is left reading
+/// the terminal afterwards. Anything unread when the query gives up, such as the start
+/// of a late reply, is discarded before raw mode ends so it isn't echoed or taken as
+/// input.
+fn query_background() -> Option<String> {
+    use termion::raw::IntoRawMode;
+
+    let mut tty = termion::get_tty().ok()?.into_raw_mode().ok()?;
+    write!(tty, "\x1b]11;?\x07").ok()?;
+    tty.flush().ok()?;
+
+    let started = Instant::now();
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    let complete = loop {
+        // A reply already under way gets a little longer to finish
+        let limit = if reply.is_empty() {
+            BACKGROUND_QUERY_TIMEOUT
+        } else {
+            BACKGROUND_QUERY_TIMEOUT + BACKGROUND_QUERY_DRAIN
+        };
+        let Some(wait) = limit.checked_sub(started.elapsed()) else {
+            break false;
+        };
+        // One byte at a time, so nothing typed after the reply is swallowed
+        if !wait_readable(tty.as_raw_fd(), wait) || !matches!(tty.read(&mut byte), Ok(1)) {
+            break false;
+        }
+        reply.push(byte[0]);
+        // Replies end with BEL or ST (ESC \)
+        if byte[0] == 0x07 || reply.ends_with(b"\x1b\\") {
+            break true;
+        }
+        if reply.len() > 64 {
+            break false;
+        }
+    };
+    if !complete {
+        // SAFETY: tcflush only discards pending input on a terminal we have open
+        unsafe { libc::tcflush(tty.as_raw_fd(), libc::TCIFLUSH) };
+        return None;
+    }
+    Some(String::from_utf8_lossy(&reply).into_owned())
+}
+
+/// Waits up to `timeout` for `fd` to have input, returning false on timeout or error
+fn wait_readable(fd: RawFd, timeout: Duration) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // Round up so a wait of under a millisecond doesn't turn into a busy loop
+    let millis = timeout.as_millis().clamp(1, i32::MAX as u128) as libc::c_int;
+    // SAFETY: poll_fd is a single valid pollfd that outlives the call
+    unsafe { libc::poll(&mut poll_fd, 1, millis) > 0 }
+}
+
+/// The `rgb:RRRR/GGGG/BBBB` color in an OSC 11 reply, each channel as a fraction of full
+/// brightness. Channels may have one to four hex digits.
+fn parse_background_reply(reply: &str) -> Option<(f32, f32, f32)> {
+    let (_, rgb) = reply.split_once("rgb:")?;
+    let mut channels = rgb.split('/').map(|channel| {
+        let hex: String = channel
+            .chars()
+            .take_while(char::is_ascii_hexdigit)
+            .collect();
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(&hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some(value as f32 / max as f32)
+    });
+    Some((channels.next()??, channels.next()??, channels.next()??))
+}
+
+/// True for colors whose relative luminance is below half
+fn is_dark_color((red, green, blue): (f32, f32, f32)) -> bool {
+    0.2126 * red + 0.7152 * green + 0.0722 * blue < 0.5
+}
+
+/// Width of the terminal stdout is attached to, or None when output is piped
+pub fn terminal_width() -> Option<usize> {
+    if !termion::is_tty(&io::stdout()) {
+        return None;
+    }
+    termion::terminal_size().ok().map(|(cols, _)| cols as usize)
+}
+
+/// Soft-wraps `line` at word boundaries so no row exceeds `width` columns.
+/// The first `indent` characters (an item's number prefix) stay on the first row and
+/// continuation rows are indented to align under the text that follows them.
+/// Words longer than the available space are left unbroken on their own row.
+pub fn wrap_line(line: &str, width: usize, indent: usize) -> String {
+    let available = width.saturating_sub(indent);
+    if line.chars().count() <= width || available == 0 {
+        return line.to_string();
+    }
+
+    let split_at = line
+        .char_indices()
+        .nth(indent)
+        .map_or(line.len(), |(idx, _)| idx);
+    let (prefix, body) = line.split_at(split_at);
+
+    let mut rows: Vec<String> = Vec::new();
+    let mut row = String::new();
+    for word in body.split_whitespace() {
+        let row_len = row.chars().count();
+        if row_len > 0 && row_len + 1 + word.chars().count() > available {
+            rows.push(std::mem::take(&mut row));
+        }
+        if !row.is_empty() {
+            row.push(' ');
+        }
+        row.push_str(word);
+    }
+    rows.push(row);
+
+    let continuation = format!("\n{}", " ".repeat(indent));
+    format!("{}{}", prefix, rows.join(&continuation))
+}
+
+/// Display width of `text`, counting each grapheme cluster as one column
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Cuts `text` to at most `width` columns, ending it with "…" when anything was dropped.
+/// Splits only between grapheme clusters, so accents and emoji are never broken apart.
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.graphemes(true).take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// How much non-error output a command prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Print nothing except errors and the output of commands that only show data
+    Quiet,
+    /// Print the usual colored confirmations
+    #[default]
+    Normal,
+    /// Also print a `key=value` summary of the data file that was used
+    Verbose,
+    /// Print one stable `event key=value` line per change instead of the confirmations
+    Porcelain,
+}
+
+impl Verbosity {
+    /// Builds the verbosity from the global `--quiet`, `--verbose` and `--porcelain` flags
+    pub fn from_flags(quiet: bool, verbose: bool, porcelain: bool) -> Self {
+        if quiet {
+            Verbosity::Quiet
+        } else if porcelain {
+            Verbosity::Porcelain
+        } else if verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    /// True when the usual messages are off, which `--porcelain` replaces with its own
+    pub fn is_quiet(self) -> bool {
+        matches!(self, Verbosity::Quiet | Verbosity::Porcelain)
+    }
+
+    pub fn is_porcelain(self) -> bool {
+        self == Verbosity::Porcelain
+    }
+
+    pub fn is_verbose(self) -> bool {
+        self == Verbosity::Verbose
+    }
+}
+
+/// Prints a `--porcelain` line: the event name followed by space-separated `key=value`
+/// fields. Free text goes through `quote`, so the line splits into fields the way a shell
+/// splits words: on spaces, except those inside double quotes.
+pub fn porcelain(event: &str, fields: &[(&str, String)]) {
+    let mut line = event.to_string();
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    println!("{}", line);
+}
+
+/// Double-quotes text for a porcelain field, escaping quotes, backslashes and control
+/// characters the way Rust string literals do
+pub fn quote(text: &str) -> String {
+    format!("{:?}", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_keeps_graphemes_whole() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly", 7), "exactly");
+        assert_eq!(truncate("Write report", 6), "Write…");
+        // "é" written as e + combining accent is one column and is never split
+        assert_eq!(truncate("cafe\u{301} au lait", 5), "cafe\u{301}…");
+        assert_eq!(truncate("👍🏽👍🏽👍🏽", 2), "👍🏽…");
+        assert_eq!(truncate("anything", 0), "");
+    }
+
+    #[test]
+    fn test_hyperlink_urls_wraps_only_the_url() {
+        assert_eq!(
+            hyperlink_urls("Read https://example.com/a?b=1, then reply"),
+            "Read \x1b]8;;https://example.com/a?b=1\x1b\\https://example.com/a?b=1\x1b]8;;\x1b\\, then reply"
+        );
+        assert_eq!(
+            hyperlink_urls("(see http://x.org)"),
+            "(see \x1b]8;;http://x.org\x1b\\http://x.org\x1b]8;;\x1b\\)"
+        );
+        assert_eq!(hyperlink_urls("No links here"), "No links here");
+        assert_eq!(hyperlink_urls("Cut https://exa…"), "Cut https://exa…");
+        assert_eq!(
+            hyperlink_urls("Bare https:// scheme"),
+            "Bare https:// scheme"
+        );
+    }
+
+    #[test]
+    fn test_background_reply_decides_dark_or_light() {
+        let dark = parse_background_reply("\x1b]11;rgb:1e1e/1e1e/2828\x07").unwrap();
+        assert!(is_dark_color(dark));
+        let light = parse_background_reply("\x1b]11;rgb:ffff/fafa/f0f0\x1b\\").unwrap();
+        assert!(!is_dark_color(light));
+        // Two-digit channels scale the same way
+        assert_eq!(
+            parse_background_reply("\x1b]11;rgb:ff/00/80\x07"),
+            Some((1.0, 0.0, 128.0 / 255.0))
+        );
+        assert_eq!(parse_background_reply("\x1b]11;rgb:ffff/ffff\x07"), None);
+        assert_eq!(parse_background_reply("garbage"), None);
+    }
+
+    #[test]
+    fn test_quote_escapes_for_porcelain() {
+        assert_eq!(quote("Buy milk"), "\"Buy milk\"");
+        assert_eq!(quote("Say \"hi\"\n"), "\"Say \\\"hi\\\"\\n\"");
+        assert_eq!(quote("café"), "\"café\"");
+    }
+}
@@ -0,0 +1,107 @@
+//! Lazily loaded `todos.md`.
+//!
+//! `TodoStore` owns the path of the todo file and reads and parses it on first use,
+//! keeping the parsed `TodoFile` so that several operations share one read. Changes are
+//! written back in one place, either with `save` or through `mutate`.
+
+use crate::markdown::{generate_todo_file, parse_todo_file, TodoFile};
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct TodoStore {
+    path: PathBuf,
+    todo_file: Option<TodoFile>,
+}
+
+impl TodoStore {
+    pub fn new(path: &Path) -> Self {
+        TodoStore {
+            path: path.to_path_buf(),
+            todo_file: None,
+        }
+    }
+
+    /// True if the todo file exists on disk
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// The parsed todo file, read on first use. A missing file is an empty one.
+    pub fn get(&mut self) -> Result<&mut TodoFile> {
+        if self.todo_file.is_none() {
+            let todo_file = if self.path.exists() {
+                let content = fs::read_to_string(&self.path)
+                    .with_context(|| format!("Failed to read file: {}", self.path.display()))?;
+                parse_todo_file(&content).map_err(|e| anyhow!("Failed to parse file: {}", e))?
+            } else {
+                TodoFile::new("TODOs".to_string())
+            };
+            self.todo_file = Some(todo_file);
+        }
+        Ok(self.todo_file.as_mut().expect("todo file was loaded above"))
+    }
+
+    /// Writes the cached todo file back; does nothing if it was never loaded
+    pub fn save(&self) -> Result<()> {
+        let Some(todo_file) = &self.todo_file else {
+            return Ok(());
+        };
+        fs::write(&self.path, generate_todo_file(todo_file))
+            .with_context(|| format!("Failed to write file: {}", self.path.display()))
+    }
+
+    /// Applies `change` to the todo file and writes it back once. Nothing is written if
+    /// the change fails or leaves the file as it was.
+    pub fn mutate<T>(&mut self, change: impl FnOnce(&mut TodoFile) -> Result<T>) -> Result<T> {
+        let todo_file = self.get()?;
+        let before = todo_file.clone();
+        let result = change(todo_file);
+        if result.is_err() {
+            // Drop the partial change so later reads see the file as it is on disk
+            *todo_file = before;
+            return result;
+        }
+        if *todo_file != before {
+            self.save()?;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::Task;
+
+    #[test]
+    fn test_mutate_writes_only_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("todos.md");
+        fs::write(&path, "# TODOs\n\n-   Spaced   out\n").unwrap();
+
+        let mut store = TodoStore::new(&path);
+        store.mutate(|_| Ok(())).unwrap();
+        assert!(store
+            .mutate(|todo_file| {
+                todo_file.add_task(Task::new("Dropped".to_string()));
+                Err::<(), _>(anyhow!("nope"))
+            })
+            .is_err());
+        // Neither an unchanged file nor a failed change is written
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# TODOs\n\n-   Spaced   out\n"
+        );
+        assert_eq!(store.get().unwrap().tasks.len(), 1);
+
+        store
+            .mutate(|todo_file| {
+                todo_file.add_task(Task::new("Kept".to_string()));
+                Ok(())
+            })
+            .unwrap();
+        let saved = parse_todo_file(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.tasks.len(), 2);
+    }
+}
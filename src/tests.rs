@@ -4,7 +4,8 @@
 //! command operations, filtering, and edge cases.
 
 use crate::commands::*;
-use crate::content::*;
+use crate::output::Verbosity;
+use ldr::content::*;
 
 /// Tests adding an entry to empty content
 #[test]
@@ -184,7 +185,7 @@ fn test_archive_items_in_content_duplicates() {
 /// Tests case-insensitive filtering functionality
 #[test]
 fn test_filter_functionality() {
-    let lines = vec![
+    let lines = [
         "Read: Book about Rust".to_string(),
         "Listen: Podcast episode".to_string(),
         "@work: Review PR".to_string(),
@@ -220,7 +221,7 @@ fn test_filter_functionality() {
 /// Tests that filtering is case-insensitive
 #[test]
 fn test_case_insensitive_filtering() {
-    let lines = vec![
+    let lines = [
         "READ: Book about Rust".to_string(),
         "read: Another book".to_string(),
         "ReAd: Mixed case".to_string(),
@@ -269,7 +270,16 @@ mod list_tests {
 
         // Test that all=true shows all items regardless of num
         let result = std::panic::catch_unwind(|| {
-            list_note(file.path(), 3, true, None).unwrap();
+            list_note(
+                file.path(),
+                &ListOptions {
+                    num: 3,
+                    all: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
     }
@@ -283,7 +293,15 @@ mod list_tests {
 
         // Test that all=false respects num limit
         let result = std::panic::catch_unwind(|| {
-            list_note(file.path(), 3, false, None).unwrap();
+            list_note(
+                file.path(),
+                &ListOptions {
+                    num: 3,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
     }
@@ -300,7 +318,17 @@ mod list_tests {
 
         // Test that all=true with filter shows all matching items
         let result = std::panic::catch_unwind(|| {
-            list_note(file.path(), 1, true, Some("read:")).unwrap();
+            list_note(
+                file.path(),
+                &ListOptions {
+                    num: 1,
+                    all: true,
+                    filter: Some("read:".to_string()),
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
     }
@@ -311,7 +339,7 @@ mod list_tests {
         let file = create_markdown_test_file(&[]);
 
         let result = std::panic::catch_unwind(|| {
-            list_note(file.path(), 5, false, None).unwrap();
+            list_note(file.path(), &ListOptions::default(), Verbosity::default()).unwrap();
         });
         assert!(result.is_ok());
     }
@@ -325,7 +353,7 @@ mod review_tests {
     #[test]
     fn test_review_prioritization_order() {
         // Test that items prioritized during review maintain their relative order
-        let lines = vec![
+        let lines = [
             "First item".to_string(),
             "Second item".to_string(),
             "Third item".to_string(),
@@ -373,7 +401,7 @@ mod review_tests {
     #[test]
     fn test_review_quit_saves_changes() {
         // Test that quitting review still saves any changes made
-        let lines = vec![
+        let lines = [
             "First item".to_string(),
             "Second item".to_string(),
             "Third item".to_string(),
@@ -424,7 +452,7 @@ mod review_tests {
         ];
 
         // Command: arn up 5 2 4 (prioritize items 5, 2, 4 in that order)
-        let result = crate::content::prioritize_items_in_content(&lines, &[5, 2, 4]);
+        let result = ldr::content::prioritize_items_in_content(&lines, &[5, 2, 4]);
 
         assert!(result.is_ok());
         let (new_content, prioritized) = result.unwrap();
@@ -448,7 +476,7 @@ mod review_tests {
         ];
 
         // Command: arn up 3 1 3 2 1 (should become 3, 1, 2)
-        let result = crate::content::prioritize_items_in_content(&lines, &[3, 1, 3, 2, 1]);
+        let result = ldr::content::prioritize_items_in_content(&lines, &[3, 1, 3, 2, 1]);
 
         assert!(result.is_ok());
         let (new_content, prioritized) = result.unwrap();
@@ -511,7 +539,16 @@ mod remove_tests {
         let file = create_markdown_test_file(&["First item", "Second item", "Third item"]);
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["2".to_string()]).unwrap();
+            remove_items(
+                file.path(),
+                &["2".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -528,7 +565,16 @@ mod remove_tests {
             create_markdown_test_file(&["First item", "Second item", "Third item", "Fourth item"]);
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["1".to_string(), "3".to_string()]).unwrap();
+            remove_items(
+                file.path(),
+                &["1".to_string(), "3".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -544,7 +590,16 @@ mod remove_tests {
         let file = create_markdown_test_file(&["First item", "Second item"]);
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["1".to_string(), "2".to_string()]).unwrap();
+            remove_items(
+                file.path(),
+                &["1".to_string(), "2".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -560,10 +615,16 @@ mod remove_tests {
         let file = create_markdown_test_file(&["First item", "Second item"]);
         let original_content = std::fs::read_to_string(file.path()).unwrap();
 
-        let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["3".to_string()]).unwrap();
-        });
-        assert!(result.is_ok());
+        let result = remove_items(
+            file.path(),
+            &["3".to_string()],
+            &RemoveOptions {
+                assume_yes: true,
+                ..Default::default()
+            },
+            Verbosity::default(),
+        );
+        assert!(result.is_err());
 
         // Verify the file content is unchanged
         let updated_content = std::fs::read_to_string(file.path()).unwrap();
@@ -579,6 +640,11 @@ mod remove_tests {
             remove_items(
                 file.path(),
                 &["2".to_string(), "2".to_string(), "1".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
             )
             .unwrap();
         });
@@ -597,7 +663,16 @@ mod remove_tests {
         let nonexistent_path = temp_dir.path().join("nonexistent.txt");
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(&nonexistent_path, &["1".to_string()]).unwrap();
+            remove_items(
+                &nonexistent_path,
+                &["1".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -611,7 +686,16 @@ mod remove_tests {
         let file = create_markdown_test_file(&[]);
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["1".to_string()]).unwrap();
+            remove_items(
+                file.path(),
+                &["1".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -629,7 +713,16 @@ mod remove_tests {
         let archive_path = temp_dir.path().join("archive.md");
 
         let result = std::panic::catch_unwind(|| {
-            remove_items(file.path(), &["1".to_string()]).unwrap();
+            remove_items(
+                file.path(),
+                &["1".to_string()],
+                &RemoveOptions {
+                    assume_yes: true,
+                    ..Default::default()
+                },
+                Verbosity::default(),
+            )
+            .unwrap();
         });
         assert!(result.is_ok());
 
@@ -660,6 +753,8 @@ mod remove_tests {
                 file.path(),
                 &archive_path,
                 &["2a".to_string(), "2b".to_string()],
+                &RemoveOptions::default(),
+                Verbosity::default(),
             )
             .unwrap();
         });
@@ -679,4 +774,353 @@ mod remove_tests {
         assert!(archive_content.contains("- Subtask 2"));
         assert!(archive_content.contains("- Task B"));
     }
+
+    /// Tests that removing a task's subtasks keeps the task itself
+    #[test]
+    fn test_remove_subtasks_keeps_task() {
+        let file = create_markdown_test_file_with_subtasks(&[
+            ("Task A", &["Step 1"]),
+            ("Task B", &["Subtask 1", "Subtask 2"]),
+        ]);
+
+        remove_items(
+            file.path(),
+            &["2".to_string()],
+            &RemoveOptions {
+                assume_yes: true,
+                keep_empty_parents: true,
+                all_subtasks: true,
+                ..Default::default()
+            },
+            Verbosity::default(),
+        )
+        .unwrap();
+
+        let updated_content = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(
+            updated_content,
+            "# TODOs\n\n- Task A\n  - Step 1\n- Task B\n"
+        );
+
+        // A task without subtasks is refused rather than removed
+        let result = remove_items(
+            file.path(),
+            &["2".to_string()],
+            &RemoveOptions {
+                assume_yes: true,
+                keep_empty_parents: true,
+                all_subtasks: true,
+                ..Default::default()
+            },
+            Verbosity::default(),
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            std::fs::read_to_string(file.path()).unwrap(),
+            updated_content
+        );
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::markdown::Task;
+    use chrono::{Duration, NaiveDate};
+
+    fn task_created_days_ago(text: &str, today: NaiveDate, days: i64) -> Task {
+        let mut task = Task::new(text.to_string());
+        task.created = Some((today - Duration::days(days)).and_hms_opt(9, 0, 0).unwrap());
+        task
+    }
+
+    /// Tests that active tasks are bucketed by age, with untimestamped tasks as unknown
+    #[test]
+    fn test_active_age_histogram_buckets() {
+        let today = NaiveDate::from_ymd_opt(2025, 6, 30).unwrap();
+        let tasks = vec![
+            task_created_days_ago("Added today", today, 0),
+            task_created_days_ago("Also today", today, 0),
+            task_created_days_ago("Yesterday", today, 1),
+            task_created_days_ago("Six days ago", today, 6),
+            task_created_days_ago("Last week", today, 7),
+            task_created_days_ago("Ancient", today, 120),
+            Task::new("No timestamp".to_string()),
+        ];
+
+        let histogram = AgeHistogram::from_tasks(&tasks, today);
+        assert_eq!(
+            histogram,
+            AgeHistogram {
+                today: 2,
+                this_week: 2,
+                this_month: 1,
+                older: 1,
+                unknown: 1,
+            }
+        );
+    }
+
+    /// Tests that archived items are counted for today and for the last seven days
+    #[test]
+    fn test_stats_archived_periods() {
+        use crate::markdown::{parse_archive_file, TodoFile};
+
+        let today = NaiveDate::from_ymd_opt(2025, 6, 30).unwrap();
+        let archive = parse_archive_file(
+            "# Archive\n\n## 2025-06-30\n- A\n  - A1\n\n## 2025-06-24\n- B\n\n## 2025-06-23\n- C\n\n## someday\n- D\n",
+        )
+        .unwrap();
+        let mut todo_file = TodoFile::new("TODOs".to_string());
+        todo_file.add_task(Task::with_subtasks(
+            "Open".to_string(),
+            vec!["Sub".to_string()],
+        ));
+
+        let stats = Stats::compute(&todo_file, &archive, today);
+        assert_eq!(stats.open_tasks, 1);
+        assert_eq!(stats.subtasks, 1);
+        assert_eq!(stats.archived_total, 5);
+        assert_eq!(stats.archived_today, 2);
+        assert_eq!(stats.archived_week, 3);
+        assert_eq!(stats.age.unknown, 1);
+    }
+
+    /// Tests the current and longest streaks across gaps, and a streak that ended
+    /// yesterday while today has nothing archived yet
+    #[test]
+    fn test_streak_across_gaps() {
+        use crate::markdown::parse_archive_file;
+
+        let archive = parse_archive_file(
+            "# Archive\n\n## 2025-06-29\n- A\n\n## 2025-06-28\n- B\n\n## 2025-06-25\n- C\n\n\
+             ## 2025-06-24\n- D\n\n## 2025-06-23\n- E\n\n## 2025-06-20\n- F\n",
+        )
+        .unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2025, 6, 30).unwrap();
+        let streak = Streak::compute(&archive, today);
+        assert_eq!(
+            streak,
+            Streak {
+                current: 2,
+                longest: 3,
+                pending_today: true,
+            }
+        );
+
+        let streak = Streak::compute(&archive, today.succ_opt().unwrap());
+        assert_eq!(streak.current, 0);
+        assert_eq!(streak.longest, 3);
+
+        let streak = Streak::compute(&archive, NaiveDate::from_ymd_opt(2025, 6, 29).unwrap());
+        assert_eq!((streak.current, streak.pending_today), (2, false));
+    }
+}
+
+#[cfg(test)]
+mod highlight_due_tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Tests that only overdue and due-today tasks are highlighted
+    #[test]
+    fn test_due_highlight_color() {
+        // Tests don't run on a terminal, so colors have to be forced on to tell them apart
+        crate::output::set_color_choice(crate::output::ColorChoice::Always);
+        let today = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        let overdue = due_highlight_color(NaiveDate::from_ymd_opt(2025, 4, 30), today);
+        let due_today = due_highlight_color(Some(today), today);
+
+        assert!(overdue.is_some());
+        assert!(due_today.is_some());
+        assert_ne!(overdue, due_today);
+        assert_eq!(
+            due_highlight_color(NaiveDate::from_ymd_opt(2025, 5, 2), today),
+            None
+        );
+        assert_eq!(due_highlight_color(None, today), None);
+    }
+}
+
+#[cfg(test)]
+mod reindex_tests {
+    use super::*;
+    use crate::markdown::{parse_todo_file, Task, TodoFile};
+
+    /// Tests that empty items are stripped and consecutive duplicate subtasks collapsed
+    #[test]
+    fn test_canonicalize_todo_file() {
+        let mut todo_file = TodoFile::new("TODOs".to_string());
+        todo_file.add_task(Task::with_subtasks(
+            "Plan trip".to_string(),
+            vec![
+                "Book hotel".to_string(),
+                "Book hotel".to_string(),
+                "".to_string(),
+                "Pack".to_string(),
+                "Book hotel".to_string(),
+            ],
+        ));
+        todo_file.add_task(Task::new("-".to_string()));
+        todo_file.add_task(Task::with_subtasks(
+            "".to_string(),
+            vec!["Orphaned subtask".to_string()],
+        ));
+
+        let summary = canonicalize_todo_file(&mut todo_file);
+        assert_eq!(
+            summary,
+            ReindexSummary {
+                empty_tasks: 2,
+                empty_subtasks: 1,
+                duplicate_subtasks: 1,
+            }
+        );
+        assert_eq!(todo_file.tasks.len(), 2);
+        // Only consecutive duplicates are collapsed
+        assert_eq!(
+            todo_file.tasks[0].subtasks,
+            vec!["Book hotel", "Pack", "Book hotel"]
+        );
+        // Subtasks of a blank task are promoted rather than dropped
+        assert_eq!(todo_file.tasks[1].text, "Orphaned subtask");
+        assert!(todo_file.tasks[1].subtasks.is_empty());
+    }
+
+    /// Tests that named lists are tidied too
+    #[test]
+    fn test_canonicalize_named_lists() {
+        let mut todo_file =
+            parse_todo_file("# TODOs\n\n- Home\n\n## Work\n\n- Ship\n  - Tag\n  - Tag\n-\n")
+                .unwrap();
+
+        let summary = canonicalize_todo_file(&mut todo_file);
+        assert_eq!(summary.empty_tasks, 1);
+        assert_eq!(summary.duplicate_subtasks, 1);
+        assert_eq!(todo_file.list("Work").unwrap().len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use super::*;
+    use crate::markdown::{Task, TodoFile};
+
+    /// Tests that tags are collected from subtasks and children under their own references
+    #[test]
+    fn test_collect_tags_includes_subtasks() {
+        let mut todo_file = TodoFile::new("TODOs".to_string());
+        todo_file.add_task(Task::new("Email Bob @work".to_string()));
+        todo_file.add_task(Task::with_subtasks(
+            "Plan trip".to_string(),
+            vec![
+                "Buy adapter @errands".to_string(),
+                "Ask @Work for leave,".to_string(),
+            ],
+        ));
+        todo_file.tasks[1].subtasks[0]
+            .children
+            .push("Compare prices @errands @errands".to_string());
+
+        let tags = collect_tags(&todo_file);
+        assert_eq!(
+            tags,
+            vec![
+                TagSummary {
+                    tag: "@errands".to_string(),
+                    refs: vec!["2a".to_string(), "2a1".to_string()],
+                },
+                TagSummary {
+                    tag: "@work".to_string(),
+                    refs: vec!["1".to_string(), "2b".to_string()],
+                },
+            ]
+        );
+    }
+
+    /// Tests that email addresses and bare "@" aren't mistaken for tags
+    #[test]
+    fn test_collect_tags_ignores_non_tags() {
+        let mut todo_file = TodoFile::new("TODOs".to_string());
+        todo_file.add_task(Task::new("Mail bob@example.com @ noon".to_string()));
+        assert!(collect_tags(&todo_file).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod task_order_tests {
+    use super::*;
+
+    fn previous(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    /// Tests that prepending a task only flags the new one
+    #[test]
+    fn test_diff_task_order_new_task() {
+        let changes = diff_task_order(&previous(&["B", "C"]), &["A", "B", "C"]);
+        assert_eq!(changes, vec![Some(TaskChange::New), None, None]);
+    }
+
+    /// Tests that a prioritized task is flagged as moved and the rest are unchanged
+    #[test]
+    fn test_diff_task_order_moved_task() {
+        let changes = diff_task_order(&previous(&["A", "B", "C"]), &["C", "A", "B"]);
+        assert_eq!(changes, vec![Some(TaskChange::Moved), None, None]);
+    }
+}
+
+/// Tests that comma- and space-separated references are split and empty fragments dropped
+#[test]
+fn test_split_refs() {
+    let refs = vec!["1,2".to_string(), "3".to_string(), "4a, 5,".to_string()];
+    assert_eq!(split_refs(&refs), vec!["1", "2", "3", "4a", "5"]);
+    let refs = vec!["/buy milk, eggs".to_string(), "2,3".to_string()];
+    assert_eq!(split_refs(&refs), vec!["/buy milk, eggs", "2", "3"]);
+}
+
+/// Tests that CSV fields are quoted only when they contain delimiters, quotes or newlines
+#[test]
+fn test_csv_field() {
+    assert_eq!(csv_field("Buy milk"), "Buy milk");
+    assert_eq!(csv_field("Buy eggs, milk"), "\"Buy eggs, milk\"");
+    assert_eq!(csv_field("Read \"Dune\""), "\"Read \"\"Dune\"\"\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+}
+
+/// Tests the checks run on the todo file after `ldr edit`
+#[test]
+fn test_edit_problems() {
+    let content = "# TODOs\n\n- Task\n  - Subtask\n";
+    assert!(edit_problems(content, Some((1, 0)), (1, 1)).is_empty());
+    assert!(edit_problems(content, None, (1, 1)).is_empty());
+
+    let problems = edit_problems("# TODOs\n", Some((3, 1)), (0, 0));
+    assert_eq!(
+        problems,
+        vec!["no tasks found (there were 3 before editing)"]
+    );
+    assert!(edit_problems("# TODOs\n", Some((0, 0)), (0, 0)).is_empty());
+
+    let problems = edit_problems("# TODOs\n\n  - Stray\n- Task\n", None, (2, 0));
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains("first item is indented"));
+}
+
+/// Tests that age hues run from green for the newest task to red for the oldest
+#[test]
+fn test_age_hues() {
+    let at = |day| {
+        chrono::NaiveDate::from_ymd_opt(2025, 5, day)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+    };
+    assert_eq!(
+        age_hues(&[(1, at(11)), (2, at(6)), (4, at(1))]),
+        vec![(1, 120.0), (2, 60.0), (4, 0.0)]
+    );
+    assert_eq!(age_hues(&[(3, at(1))]), vec![(3, 120.0)]);
+    assert!(age_hues(&[]).is_empty());
 }
@@ -0,0 +1,120 @@
+//! Single-level undo for the data files.
+//!
+//! Before a mutating command runs, each data file is copied to a `.prev` snapshot next
+//! to it (e.g. `todos.md.prev`). `ldr undo` swaps the snapshots with the current files,
+//! so running it a second time redoes the change.
+
+use crate::output::{self, color, Verbosity};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the snapshot kept for a data file, e.g. `todos.md.prev`
+pub fn prev_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".prev");
+    PathBuf::from(name)
+}
+
+/// Reads a file, treating a missing file as `None`
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .with_context(|| format!("Failed to read file: {}", path.display()))
+}
+
+/// Writes `content` to `path`, or removes the file when there is no content to restore
+fn write_optional(path: &Path, content: Option<&str>) -> Result<()> {
+    match content {
+        Some(content) => fs::write(path, content)
+            .with_context(|| format!("Failed to write file: {}", path.display())),
+        None if path.exists() => fs::remove_file(path)
+            .with_context(|| format!("Failed to remove file: {}", path.display())),
+        None => Ok(()),
+    }
+}
+
+/// A data file as it was before a command, and the snapshot that command replaced
+struct SnapshotEntry {
+    path: PathBuf,
+    before: Option<String>,
+    replaced: Option<String>,
+}
+
+/// Snapshots taken before a command, kept so that a command which ends up changing
+/// nothing (it failed, or skipped a duplicate) doesn't cost the user their undo
+pub struct Snapshot(Vec<SnapshotEntry>);
+
+impl Snapshot {
+    /// Puts the replaced snapshots back if none of the data files changed
+    pub fn finish(self) -> Result<()> {
+        for entry in &self.0 {
+            if read_optional(&entry.path)? != entry.before {
+                return Ok(());
+            }
+        }
+        for entry in &self.0 {
+            write_optional(&prev_path(&entry.path), entry.replaced.as_deref())?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies each data file to its `.prev` snapshot before a mutating command.
+/// A data file that doesn't exist yet has its stale snapshot removed.
+pub fn save_snapshot(paths: &[&Path]) -> Result<Snapshot> {
+    let mut entries = Vec::new();
+    for path in paths {
+        let prev = prev_path(path);
+        let entry = SnapshotEntry {
+            path: path.to_path_buf(),
+            before: read_optional(path)?,
+            replaced: read_optional(&prev)?,
+        };
+        write_optional(&prev, entry.before.as_deref())?;
+        entries.push(entry);
+    }
+    Ok(Snapshot(entries))
+}
+
+/// Restores the state before the last mutating command by swapping each data file
+/// with its snapshot
+pub fn undo(paths: &[&Path], verbosity: Verbosity) -> Result<()> {
+    if !paths.iter().any(|path| prev_path(path).exists()) {
+        if verbosity.is_porcelain() {
+            output::porcelain("undone", &[("changed", "false".to_string())]);
+        }
+        if !verbosity.is_quiet() {
+            println!(
+                "{}Nothing to undo.{}",
+                color::Fg(color::Yellow),
+                color::Fg(color::Reset)
+            );
+        }
+        return Ok(());
+    }
+
+    for path in paths {
+        let prev = prev_path(path);
+        let current = read_optional(path)?;
+        let previous = read_optional(&prev)?;
+        write_optional(path, previous.as_deref())?;
+        write_optional(&prev, current.as_deref())?;
+    }
+
+    if verbosity.is_porcelain() {
+        output::porcelain("undone", &[("changed", "true".to_string())]);
+    }
+    if !verbosity.is_quiet() {
+        println!(
+            "{}✓ Restored the previous state (run undo again to redo){}",
+            color::Fg(color::Green),
+            color::Fg(color::Reset)
+        );
+    }
+
+    Ok(())
+}
@@ -13,6 +13,7 @@ use tempfile::TempDir;
 struct TestEnv {
     _temp_dir: TempDir,
     data_dir: PathBuf,
+    config_dir: PathBuf,
     binary_path: PathBuf,
 }
 
@@ -21,6 +22,7 @@ impl TestEnv {
         let temp_dir = TempDir::new().expect("Failed to create temp directory");
         let data_dir = temp_dir.path().join("ldr");
         fs::create_dir_all(&data_dir).expect("Failed to create ldr data directory");
+        let config_dir = temp_dir.path().join("config");
 
         // Build the binary if it doesn't exist or is out of date
         let binary_path = Self::ensure_binary_built();
@@ -28,13 +30,14 @@ impl TestEnv {
         Self {
             _temp_dir: temp_dir,
             data_dir,
+            config_dir,
             binary_path,
         }
     }
 
     fn ensure_binary_built() -> PathBuf {
         let output = Command::new("cargo")
-            .args(&["build", "--bin", "ldr"])
+            .args(["build", "--bin", "ldr"])
             .output()
             .expect("Failed to build ldr binary");
 
@@ -52,9 +55,19 @@ impl TestEnv {
 
     /// Run ldr command with arguments in the isolated environment
     fn run_ldr(&self, args: &[&str]) -> CommandResult {
+        self.run_ldr_with_env(args, &[])
+    }
+
+    /// Run ldr command with extra environment variables set
+    fn run_ldr_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> CommandResult {
         let output = Command::new(&self.binary_path)
             .args(args)
             .env("XDG_DATA_HOME", &self.data_dir)
+            .env("XDG_CONFIG_HOME", &self.config_dir)
+            .env("XDG_CONFIG_DIRS", &self.config_dir)
+            .env_remove("LDR_DATA_DIR")
+            .env_remove("LDR_THEME")
+            .envs(vars.iter().copied())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
@@ -67,6 +80,13 @@ impl TestEnv {
         }
     }
 
+    /// Write the ldr config file read by commands in this environment
+    fn write_config(&self, content: &str) {
+        let ldr_config_dir = self.config_dir.join("ldr");
+        fs::create_dir_all(&ldr_config_dir).expect("Failed to create config directory");
+        fs::write(ldr_config_dir.join("config.toml"), content).expect("Failed to write config");
+    }
+
     /// Get the path to the todos.md file
     fn todos_path(&self) -> PathBuf {
         self.data_dir.join("ldr/todos.md")
@@ -106,14 +126,29 @@ impl CommandResult {
     }
 
     fn assert_failure(&self) {
-        // For LDR, failure is indicated by error messages in stdout, not exit code
-        if !self.stdout.contains("Invalid")
-            && !self.stderr.contains("Error")
-            && !self.stdout.is_empty()
-        {
+        if self.status == 0 {
             panic!("Command unexpectedly succeeded: stdout: {}", self.stdout);
         }
     }
+
+    /// Returns the non-error output with color escape sequences removed
+    fn plain_stdout(&self) -> String {
+        strip_ansi(&self.stdout)
+    }
+}
+
+/// Removes color escape sequences so rows can be compared as plain text
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
 }
 
 #[cfg(test)]
@@ -166,9 +201,9 @@ mod basic_operations {
         let result = env.run_ldr(&["ls"]);
         result.assert_success();
         let line_count = result.stdout.lines().count();
-        // Should show 5 items plus "... and X more items" line
+        // Should show 5 items plus "... and X more tasks" line
         assert!(line_count >= 5);
-        assert!(result.stdout.contains("and 5 more items"));
+        assert!(result.stdout.contains("and 5 more tasks"));
 
         // Test custom limit
         let result = env.run_ldr(&["ls", "-n", "3"]);
@@ -179,7 +214,279 @@ mod basic_operations {
         // Test show all
         let result = env.run_ldr(&["ls", "--all"]);
         result.assert_success();
-        assert!(!result.stdout.contains("more items"));
+        assert!(!result.stdout.contains("... and"));
+    }
+}
+
+#[cfg(test)]
+mod highlight_due_tests {
+    use super::*;
+
+    /// Returns the color escape that starts the output line containing `text`
+    fn line_color(stdout: &str, text: &str) -> String {
+        let line = stdout.lines().find(|line| line.contains(text)).unwrap();
+        let end = line.find('m').unwrap();
+        line[..=end].to_string()
+    }
+
+    #[test]
+    fn test_highlight_due_colors_overdue_tasks() {
+        let env = TestEnv::new();
+        let today = chrono::Local::now()
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string();
+
+        env.run_ldr(&["add", "Normal task"]);
+        env.run_ldr(&["add", "Overdue task", "--due", "2000-01-01"]);
+        env.run_ldr(&["add", "Today task", "--due", &today]);
+        env.run_ldr(&["add", "Later task"]);
+
+        let plain = env.run_ldr(&["ls", "--color=always"]);
+        let highlighted = env.run_ldr(&["ls", "--highlight-due", "--color=always"]);
+        plain.assert_success();
+        highlighted.assert_success();
+
+        // Overdue and due-today tasks get their own colors
+        let overdue = line_color(&highlighted.stdout, "Overdue task");
+        let due_today = line_color(&highlighted.stdout, "Today task");
+        let normal = line_color(&highlighted.stdout, "Normal task");
+        assert_ne!(overdue, normal);
+        assert_ne!(overdue, line_color(&plain.stdout, "Overdue task"));
+        assert_ne!(due_today, line_color(&plain.stdout, "Today task"));
+        assert_ne!(overdue, due_today);
+
+        // Other tasks keep their normal colors and order is unchanged
+        assert_eq!(normal, line_color(&plain.stdout, "Normal task"));
+        assert_eq!(
+            line_color(&highlighted.stdout, "Later task"),
+            line_color(&plain.stdout, "Later task")
+        );
+        assert!(highlighted.stdout.contains("3. Overdue task"));
+        assert!(highlighted.stdout.contains("4. Normal task"));
+    }
+}
+
+#[cfg(test)]
+mod truecolor_tests {
+    use super::*;
+
+    #[test]
+    fn test_truecolor_escapes() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Colorful task"]);
+        env.run_ldr(&["add", "Subtask", "--under", "1"]);
+
+        // The 256-color palette stays the default
+        let result = env.run_ldr_with_env(&["ls", "--color=always"], &[("COLORTERM", "")]);
+        result.assert_success();
+        assert!(result.stdout.contains("\x1b[38;5;"));
+        assert!(!result.stdout.contains("\x1b[38;2;"));
+
+        let result = env.run_ldr_with_env(&["ls", "--color=always"], &[("COLORTERM", "truecolor")]);
+        result.assert_success();
+        assert!(result.stdout.contains("\x1b[38;2;"));
+        assert!(!result.stdout.contains("\x1b[38;5;"));
+
+        let result = env.run_ldr_with_env(
+            &["ls", "--truecolor", "--color=always"],
+            &[("COLORTERM", "")],
+        );
+        result.assert_success();
+        assert!(result.stdout.contains("\x1b[38;2;"));
+    }
+
+    #[test]
+    fn test_color_by_age() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Fresh <!-- created:2025-05-10T09:00 -->\n- No timestamp\n- Stale <!-- created:2025-01-01T09:00 -->\n",
+        )
+        .unwrap();
+
+        let vars = [("COLORFGBG", "15;0"), ("COLORTERM", "truecolor")];
+        let result = env.run_ldr_with_env(&["ls", "--color-by", "age", "--color=always"], &vars);
+        result.assert_success();
+        assert!(result.stdout.contains("\x1b[38;2;72;242;72m  1. Fresh"));
+        assert!(result.stdout.contains("\x1b[38;2;242;72;72m  3. Stale"));
+
+        // Tasks without a timestamp keep the alternating color of their position
+        let plain = env.run_ldr_with_env(&["ls", "--color=always"], &vars);
+        let untimed = |stdout: &str| {
+            stdout
+                .lines()
+                .find(|line| line.contains("No timestamp"))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(untimed(&result.stdout), untimed(&plain.stdout));
+    }
+
+    #[test]
+    fn test_colors_reports_detected_mode() {
+        let env = TestEnv::new();
+
+        let result = env.run_ldr_with_env(
+            &["colors", "--color=always"],
+            &[("COLORFGBG", "0;15"), ("COLORTERM", ""), ("NO_COLOR", "")],
+        );
+        result.assert_success();
+        assert!(result.stdout.contains("Background: light"));
+        assert!(result.stdout.contains("Colors:     256-color palette"));
+        assert!(result.stdout.contains("\x1b[38;5;"));
+
+        let result = env.run_ldr_with_env(
+            &["colors"],
+            &[("COLORFGBG", "15;0"), ("COLORTERM", "truecolor")],
+        );
+        result.assert_success();
+        assert!(result.stdout.contains("Background: dark"));
+        assert!(result.stdout.contains("Colors:     truecolor (24-bit)"));
+    }
+
+    #[test]
+    fn test_theme_from_env_and_config() {
+        let env = TestEnv::new();
+        let described = |result: &CommandResult| -> Vec<String> {
+            result
+                .stdout
+                .lines()
+                .filter(|line| line.contains(". task "))
+                .map(|line| line.trim().split_once(' ').unwrap().1.to_string())
+                .collect()
+        };
+
+        // Mono drops the alternating hues
+        let result = env.run_ldr_with_env(
+            &["colors"],
+            &[("LDR_THEME", "mono"), ("NO_COLOR", "1"), ("COLORTERM", "")],
+        );
+        result.assert_success();
+        assert!(result.stdout.contains("Theme:      mono"));
+        assert!(!result.stdout.contains("Background:"));
+        let mono = described(&result);
+        assert_eq!(mono.len(), 4);
+        assert!(mono.iter().all(|line| *line == mono[0]));
+
+        // A theme overrides the detected background
+        let light = env.run_ldr_with_env(
+            &["colors"],
+            &[("COLORFGBG", "0;15"), ("NO_COLOR", "1"), ("COLORTERM", "")],
+        );
+        let forced = env.run_ldr_with_env(
+            &["colors"],
+            &[
+                ("COLORFGBG", "15;0"),
+                ("LDR_THEME", "Light"),
+                ("NO_COLOR", "1"),
+                ("COLORTERM", ""),
+            ],
+        );
+        assert_eq!(described(&forced), described(&light));
+
+        // The config file sets a theme, and $LDR_THEME wins over it
+        env.write_config("theme = \"solarized\"\n");
+        let result = env.run_ldr(&["colors"]);
+        assert!(result.stdout.contains("Theme:      solarized"));
+        let result = env.run_ldr_with_env(&["colors"], &[("LDR_THEME", "dark")]);
+        assert!(result.stdout.contains("Theme:      dark"));
+    }
+
+    #[test]
+    fn test_unknown_theme_is_an_error() {
+        let env = TestEnv::new();
+        let result = env.run_ldr_with_env(&["ls"], &[("LDR_THEME", "neon")]);
+        result.assert_failure();
+        assert!(result.stderr.contains(
+            "Unknown theme 'neon' (expected one of: auto, dark, light, mono, solarized)"
+        ));
+    }
+
+    #[test]
+    fn test_colors_described_with_no_color() {
+        let env = TestEnv::new();
+
+        let result = env.run_ldr_with_env(&["colors"], &[("NO_COLOR", "1"), ("COLORTERM", "")]);
+        result.assert_success();
+        assert!(!result.stdout.contains('\x1b'));
+        assert!(result.stdout.contains("  1. task palette "));
+    }
+
+    #[test]
+    fn test_color_choice() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Colorful task"]);
+
+        // Output is piped here, so auto leaves the escapes out
+        let result = env.run_ldr(&["ls"]);
+        result.assert_success();
+        assert!(result.stdout.contains("  1. Colorful task"));
+        assert!(!result.stdout.contains('\x1b'));
+        let result = env.run_ldr(&["ls", "--color=auto"]);
+        assert!(!result.stdout.contains('\x1b'));
+        let result = env.run_ldr(&["add", "Another task"]);
+        assert!(result.stdout.starts_with("✓ Added: Another task"));
+
+        // always wins over NO_COLOR; never wins over everything
+        let result = env.run_ldr_with_env(&["ls", "--color", "always"], &[("NO_COLOR", "1")]);
+        assert!(result.stdout.contains("\x1b[38;5;"));
+        let result = env.run_ldr_with_env(&["ls", "--color=never", "--truecolor"], &[]);
+        assert!(!result.stdout.contains('\x1b'));
+
+        let result = env.run_ldr(&["ls", "--color=sometimes"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod reverse_listing_tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_shows_oldest_first() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Oldest task"]);
+        env.run_ldr(&["add", "Middle task"]);
+        env.run_ldr(&["add", "Newest task"]);
+
+        let result = env.run_ldr(&["ls", "--reverse"]);
+        result.assert_success();
+        let oldest = result.stdout.find("3. Oldest task").unwrap();
+        let middle = result.stdout.find("2. Middle task").unwrap();
+        let newest = result.stdout.find("1. Newest task").unwrap();
+        assert!(oldest < middle && middle < newest);
+
+        // Limits apply after reversing
+        let result = env.run_ldr(&["ls", "--reverse", "-n", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains("3. Oldest task"));
+        assert!(!result.stdout.contains("Newest task"));
+        assert!(result.stdout.contains("and 2 more tasks"));
+    }
+
+    #[test]
+    fn test_reverse_keeps_subtasks_under_parent_and_filters() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "read: old book"]);
+        env.run_ldr(&["add", "Chapter one", "--under", "1"]);
+        env.run_ldr(&["add", "write: article"]);
+        env.run_ldr(&["add", "read: new book"]);
+
+        let result = env.run_ldr(&["ls", "--reverse", "--all", "read:"]);
+        result.assert_success();
+        let old_book = result.stdout.find("3. read: old book").unwrap();
+        let chapter = result.stdout.find("a. Chapter one").unwrap();
+        let new_book = result.stdout.find("1. read: new book").unwrap();
+        assert!(old_book < chapter && chapter < new_book);
+        assert!(!result.stdout.contains("write: article"));
+
+        // Refs still target stored positions
+        env.run_ldr(&["do", "3"]).assert_success();
+        assert!(!env.read_todos().contains("read: old book"));
     }
 }
 
@@ -251,6 +558,249 @@ mod subtask_operations {
     }
 }
 
+#[cfg(test)]
+mod age_tests {
+    use super::*;
+
+    #[test]
+    fn test_add_records_creation_timestamp() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Fresh task"]);
+        assert!(env.read_todos().contains("- Fresh task <!-- created:"));
+
+        let result = env.run_ldr(&["ls", "--age"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Fresh task (0m)"));
+
+        // Without --age the timestamp stays hidden
+        let result = env.run_ldr(&["ls"]);
+        assert!(result.stdout.contains("1. Fresh task"));
+        assert!(!result.stdout.contains("(0m)"));
+        assert!(!result.stdout.contains("created"));
+    }
+
+    #[test]
+    fn test_age_display_and_round_trip() {
+        let env = TestEnv::new();
+
+        let three_days_ago = (chrono::Local::now() - chrono::Duration::days(3))
+            .format("%Y-%m-%dT%H:%M")
+            .to_string();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            format!(
+                "# TODOs\n\n- Old task <!-- created:{} -->\n- Legacy task\n",
+                three_days_ago
+            ),
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["ls", "--age", "--color=always"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Old task (3d)"));
+        // Tasks without a timestamp show no age
+        assert!(result.stdout.contains("2. Legacy task\u{1b}"));
+
+        // Adding another task keeps existing timestamps
+        env.run_ldr(&["add", "New task"]);
+        assert!(env
+            .read_todos()
+            .contains(&format!("- Old task <!-- created:{} -->", three_days_ago)));
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_counts() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Subtask", "--under", "1"]);
+        env.run_ldr(&["add", "Task B"]);
+        env.run_ldr(&["do", "1"]);
+
+        let result = env.run_ldr(&["stats"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Open tasks: 1 (1 subtasks)"));
+        assert!(result.stdout.contains("Archived:   1 (1 today)"));
+        assert!(!result.stdout.contains("Active task age"));
+    }
+
+    #[test]
+    fn test_stats_json() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Subtask", "--under", "1"]);
+        env.run_ldr(&["add", "Task B"]);
+        env.run_ldr(&["do", "1"]);
+
+        let result = env.run_ldr(&["stats", "--json"]);
+        result.assert_success();
+        assert!(!result.stdout.contains('\x1b'));
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(json["open_tasks"], 1);
+        assert_eq!(json["subtasks"], 1);
+        assert_eq!(json["archived_total"], 1);
+        assert_eq!(json["archived_today"], 1);
+        assert_eq!(json["archived_week"], 1);
+        assert_eq!(json["active_age"]["today"], 1);
+    }
+
+    #[test]
+    fn test_stats_active_age_histogram() {
+        let env = TestEnv::new();
+
+        let ten_days_ago = (chrono::Local::now() - chrono::Duration::days(10))
+            .format("%Y-%m-%dT%H:%M")
+            .to_string();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            format!(
+                "# TODOs\n\n- Month old <!-- created:{} -->\n- Unknown age\n",
+                ten_days_ago
+            ),
+        )
+        .unwrap();
+        env.run_ldr(&["add", "Fresh"]);
+
+        let result = env.run_ldr(&["stats", "--active-age-histogram"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Active task age:"));
+        let count_for = |label: &str| -> String {
+            let line = result
+                .stdout
+                .lines()
+                .find(|line| line.trim_start().starts_with(label))
+                .unwrap();
+            line[label.len() + 2..]
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(count_for("Today"), "1");
+        assert_eq!(count_for("This week"), "0");
+        assert_eq!(count_for("This month"), "1");
+        assert_eq!(count_for("Older"), "0");
+        assert_eq!(count_for("Unknown"), "1");
+    }
+}
+
+#[cfg(test)]
+mod due_date_tests {
+    use super::*;
+
+    #[test]
+    fn test_add_with_due_date() {
+        let env = TestEnv::new();
+
+        let result = env.run_ldr(&["add", "Pay rent", "--due", "2025-05-01"]);
+        result.assert_success();
+        assert!(env
+            .read_todos()
+            .contains("- Pay rent <!-- due:2025-05-01 created:"));
+
+        let result = env.run_ldr(&["ls"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Pay rent (due 2025-05-01)"));
+
+        let result = env.run_ldr(&["add", "Bad date", "--due", "soon-ish"]);
+        assert_ne!(result.status, 0);
+        assert!(result.stderr.contains("Invalid due date"));
+    }
+
+    #[test]
+    fn test_relative_due_dates_are_stored_absolute() {
+        let env = TestEnv::new();
+        let tomorrow = (chrono::Local::now().date_naive() + chrono::Duration::days(1))
+            .format("%Y-%m-%d")
+            .to_string();
+
+        env.run_ldr(&["add", "Call bank", "--due", "tomorrow"])
+            .assert_success();
+        assert!(env
+            .read_todos()
+            .contains(&format!("- Call bank <!-- due:{} created:", tomorrow)));
+
+        env.run_ldr(&["set-due", "1", "1d"]).assert_success();
+        assert!(env.read_todos().contains(&format!("due:{}", tomorrow)));
+
+        let result = env.run_ldr(&["set-due", "1", "next week"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("a number of days like 3d"));
+    }
+
+    #[test]
+    fn test_set_due() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Task B"]);
+
+        let result = env.run_ldr(&["set-due", "2", "2025-05-01"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Task 2 due 2025-05-01: Task A"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("- Task A <!-- due:2025-05-01 created:"));
+        assert!(!todos.contains("- Task B <!-- due:"));
+    }
+
+    #[test]
+    fn test_set_due_replaces_existing_date() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A", "--due", "2025-05-01"]);
+
+        let result = env.run_ldr(&["set-due", "1", "2025-06-15"]);
+        result.assert_success();
+        assert!(result.stdout.contains("from 2025-05-01 to 2025-06-15"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("- Task A <!-- due:2025-06-15 created:"));
+        assert!(!todos.contains("2025-05-01"));
+    }
+
+    #[test]
+    fn test_clear_due() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A", "--due", "2025-05-01"]);
+
+        let result = env.run_ldr(&["clear-due", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Cleared due date of task 1"));
+        assert!(!env.read_todos().contains("due:"));
+
+        // Clearing again is a no-op with a note
+        let result = env.run_ldr(&["clear-due", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains("has no due date"));
+    }
+
+    #[test]
+    fn test_due_rejects_subtasks_and_bad_refs() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Subtask", "--under", "1"]);
+
+        let result = env.run_ldr(&["set-due", "1a", "2025-05-01"]);
+        assert_ne!(result.status, 0);
+
+        let result = env.run_ldr(&["set-due", "5", "2025-05-01"]);
+        assert_ne!(result.status, 0);
+        assert!(result.stderr.contains("Invalid task number"));
+    }
+}
+
 #[cfg(test)]
 mod prioritization_tests {
     use super::*;
@@ -335,6 +885,42 @@ mod prioritization_tests {
         let result = env.run_ldr(&["up", "1z"]); // Invalid subtask
         result.assert_failure();
     }
+
+    #[test]
+    fn test_prioritize_within_reorders_subtasks() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Task A\n- Plan trip\n  - Flights\n  - Hotel\n  - Car\n  - Visa\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["up", "--within", "2c", "2d", "2c"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Prioritized 2 subtask(s) within their task"));
+        // Subtasks are listed at the positions they moved to
+        assert!(result.stdout.contains("2a. Car"));
+        assert!(result.stdout.contains("2b. Visa"));
+        // The parent stays in place; the chosen subtasks lead in the order given
+        assert_eq!(
+            env.read_todos(),
+            "# TODOs\n\n- Task A\n- Plan trip\n  - Car\n  - Visa\n  - Flights\n  - Hotel\n"
+        );
+
+        let before = env.read_todos();
+        let result = env.run_ldr(&["--porcelain", "up", "--within", "--dry-run", "2d"]);
+        assert_eq!(result.stdout, "prioritized refs=2d dry_run=true\n");
+        assert_eq!(env.read_todos(), before);
+
+        // Whole tasks can't be reordered within anything
+        let result = env.run_ldr(&["up", "--within", "2a", "1"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("'1' is a task"));
+        assert_eq!(env.read_todos(), before);
+    }
 }
 
 #[cfg(test)]
@@ -361,6 +947,25 @@ mod archiving_tests {
         assert!(archive.contains(&chrono::Local::now().format("%Y-%m-%d").to_string()));
     }
 
+    #[test]
+    fn test_archive_reports_archive_path() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Task to complete"]);
+        env.run_ldr(&["add", "Task to remove"]);
+
+        let result = env.run_ldr(&["do", "2"]);
+        result.assert_success();
+        let expected = format!("archived to {}", env.archive_path().display());
+        assert!(result.stdout.contains(&expected));
+
+        // Quiet mode and plain removal don't mention the archive
+        let result = env.run_ldr(&["-q", "do", "1"]);
+        assert!(result.stdout.is_empty());
+        env.run_ldr(&["add", "Another task"]);
+        let result = env.run_ldr(&["rm", "-y", "1"]);
+        assert!(!result.stdout.contains("archived to"));
+    }
+
     #[test]
     fn test_archive_subtask() {
         let env = TestEnv::new();
@@ -410,47 +1015,272 @@ mod archiving_tests {
     }
 
     #[test]
-    fn test_archive_whole_task_with_subtasks() {
+    fn test_keep_empty_parents_on_do() {
         let env = TestEnv::new();
 
-        env.run_ldr(&["add", "Complete project"]);
-        env.run_ldr(&["add", "Write code", "--under", "1"]);
-        env.run_ldr(&["add", "Write tests", "--under", "1"]);
-
-        // Archive entire task (should include all subtasks)
-        let result = env.run_ldr(&["do", "1"]);
-        result.assert_success();
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
 
-        // Nothing should remain in todos
-        let result = env.run_ldr(&["ls"]);
+        let result = env.run_ldr(&["do", "1a", "--keep-empty-parents"]);
         result.assert_success();
-        assert!(result.stdout.contains("No notes yet"));
 
-        // Archive should contain complete task structure
+        // The parent stays behind as a plain task and isn't archived
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tMain task\n");
         let archive = env.read_archive();
-        assert!(archive.contains("Complete project"));
-        assert!(archive.contains("Write code"));
-        assert!(archive.contains("Write tests"));
+        assert!(archive.contains("Subtask A"));
+        assert!(!archive.contains("Main task"));
     }
-}
-
-#[cfg(test)]
-mod removal_tests {
-    use super::*;
 
     #[test]
-    fn test_remove_vs_archive() {
+    fn test_do_on_past_date() {
         let env = TestEnv::new();
+        env.run_ldr(&["add", "Today's work"]);
+        env.run_ldr(&["add", "Yesterday's work"]);
 
-        env.run_ldr(&["add", "Task to remove"]);
-        env.run_ldr(&["add", "Task to archive"]);
+        let result = env.run_ldr(&["do", "1", "--on", "2025-05-01"]);
+        assert!(result
+            .stdout
+            .contains("✓ Archived 1 item(s) under 2025-05-01"));
+        env.run_ldr(&["do", "1"]).assert_success();
+        let archive = fs::read_to_string(env.archive_path()).unwrap();
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let today_pos = archive.find(&format!("## {}\n- Today's work", today));
+        let past_pos = archive.find("## 2025-05-01\n- Yesterday's work");
+        assert!(today_pos.unwrap() < past_pos.unwrap());
+
+        env.run_ldr(&["add", "Later"]);
+        let result = env.run_ldr(&["do", "1", "--on", "2999-01-01"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("2999-01-01 is in the future"));
+        let result = env.run_ldr(&["do", "1", "--on", "May 1"]);
+        assert!(result.stderr.contains("expected YYYY-MM-DD"));
+        env.run_ldr(&["do", "1", "--on", "2999-01-01", "--force"])
+            .assert_success();
+        assert!(fs::read_to_string(env.archive_path())
+            .unwrap()
+            .contains("# Archive\n\n## 2999-01-01\n- Later"));
+    }
 
-        // Remove one task
-        let result = env.run_ldr(&["rm", "2"]);
-        result.assert_success();
+    #[test]
+    fn test_do_all_subtasks_keeps_parent() {
+        let env = TestEnv::new();
+        // Even where emptied parents are normally completed, the project stays open
+        env.write_config("auto_complete_empty_parents = true\n");
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Garden project\n  - Buy seeds\n  - Dig beds\n  - Plant\n- Pay rent\n",
+        )
+        .unwrap();
 
-        // Archive another task
-        let result = env.run_ldr(&["do", "1"]);
+        let result = env.run_ldr(&["do", "--all-subtasks", "1"]);
+        result.assert_success();
+        assert_eq!(
+            env.read_todos(),
+            "# TODOs\n\n- Garden project\n- Pay rent\n"
+        );
+        let archive = env.read_archive();
+        for subtask in ["Buy seeds", "Dig beds", "Plant"] {
+            assert!(archive.contains(subtask));
+        }
+        assert!(!archive.contains("Garden project"));
+
+        // The task has no subtasks left to archive, and subtasks can't be expanded
+        let result = env.run_ldr(&["do", "--all-subtasks", "1"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("'1' has none"));
+    }
+
+    #[test]
+    fn test_auto_complete_enabled_in_config() {
+        let env = TestEnv::new();
+        env.write_config("auto_complete_empty_parents = true\n");
+
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
+
+        env.run_ldr(&["do", "1a"]).assert_success();
+        assert!(!env.read_todos().contains("Main task"));
+        assert!(env.read_archive().contains("Main task"));
+    }
+
+    #[test]
+    fn test_auto_complete_disabled_in_config() {
+        let env = TestEnv::new();
+        env.write_config("auto_complete_empty_parents = false\n");
+
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
+
+        env.run_ldr(&["do", "1a"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tMain task\n");
+        let archive = env.read_archive();
+        assert!(archive.contains("Subtask A"));
+        assert!(!archive.contains("Main task"));
+    }
+
+    #[test]
+    fn test_invalid_config_is_reported() {
+        let env = TestEnv::new();
+        env.write_config("auto_complete_empty_parents = maybe\n");
+
+        let result = env.run_ldr(&["ls"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("line 1: expected true or false, got 'maybe'"));
+    }
+
+    #[test]
+    fn test_rm_last_subtask_keeps_parent_by_default() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
+
+        let result = env.run_ldr(&["rm", "-y", "1a"]);
+        result.assert_success();
+        assert!(!result.stdout.contains("Main task"));
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tMain task\n");
+    }
+
+    #[test]
+    fn test_rm_remove_empty_parents_drops_parent_unarchived() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
+
+        let result = env.run_ldr(&["rm", "--dry-run", "--remove-empty-parents", "1a"]);
+        assert!(result
+            .stdout
+            .contains("Main task (would be removed too - no subtasks left)"));
+
+        let result = env.run_ldr(&["rm", "-y", "--remove-empty-parents", "1a"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Main task (removed too - no subtasks left)"));
+        assert!(!env.read_todos().contains("Main task"));
+        assert!(!env.read_archive().contains("Main task"));
+    }
+
+    #[test]
+    fn test_rm_empty_parents_config() {
+        let env = TestEnv::new();
+        // Auto-completion on `do` doesn't reach `rm`
+        env.write_config("auto_complete_empty_parents = true\nremove_empty_parents = true\n");
+
+        for task in ["Older task", "Newer task"] {
+            env.run_ldr(&["add", task]);
+            env.run_ldr(&["add", &format!("Step of {}", task), "--under", "1"]);
+        }
+        env.run_ldr(&["rm", "-y", "--keep-empty-parents", "1a"])
+            .assert_success();
+        env.run_ldr(&["rm", "-y", "2a"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tNewer task\n");
+    }
+
+    #[test]
+    fn test_keep_empty_parents_on_rm() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Subtask A", "--under", "1"]);
+
+        env.run_ldr(&["rm", "-y", "--keep-empty-parents", "1a"])
+            .assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tMain task\n");
+    }
+
+    #[test]
+    fn test_archive_whole_task_with_subtasks() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Complete project"]);
+        env.run_ldr(&["add", "Write code", "--under", "1"]);
+        env.run_ldr(&["add", "Write tests", "--under", "1"]);
+
+        // Archive entire task (should include all subtasks)
+        let result = env.run_ldr(&["do", "1"]);
+        result.assert_success();
+
+        // Nothing should remain in todos
+        let result = env.run_ldr(&["ls"]);
+        result.assert_success();
+        assert!(result.stdout.contains("No notes yet"));
+
+        // Archive should contain complete task structure
+        let archive = env.read_archive();
+        assert!(archive.contains("Complete project"));
+        assert!(archive.contains("Write code"));
+        assert!(archive.contains("Write tests"));
+    }
+
+    #[test]
+    fn test_archive_with_completion_note() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Release ldr"]);
+        env.run_ldr(&["add", "Tag the commit", "--under", "1"]);
+
+        env.run_ldr(&["do", "1", "--note", "shipped in v1.2"])
+            .assert_success();
+        let archive = env.read_archive();
+        assert!(archive.contains("  - Tag the commit\n  - note: shipped in v1.2\n"));
+
+        // Subtasks on their own don't take a note
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+        let result = env.run_ldr(&["do", "1a", "--note", "booked"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("whole tasks"));
+        assert!(!env.read_archive().contains("booked"));
+    }
+
+    #[test]
+    fn test_completion_note_survives_later_archiving() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "A"]);
+        env.run_ldr(&["add", "B"]);
+
+        env.run_ldr(&["do", "1", "--note", "shipped"])
+            .assert_success();
+        env.run_ldr(&["do", "1"]).assert_success();
+
+        let archive = env.read_archive();
+        assert!(archive.contains("\n  - note: shipped\n- A"));
+        assert!(!archive.contains("\n- note: shipped"));
+
+        let result = env.run_ldr(&["today"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("  - B\n    - note: shipped\n  - A\n"));
+    }
+}
+
+#[cfg(test)]
+mod removal_tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_vs_archive() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task to remove"]);
+        env.run_ldr(&["add", "Task to archive"]);
+
+        // Remove one task
+        let result = env.run_ldr(&["rm", "-y", "2"]);
+        result.assert_success();
+
+        // Archive another task
+        let result = env.run_ldr(&["do", "1"]);
         result.assert_success();
 
         // Only archived task should be in archive
@@ -473,7 +1303,7 @@ mod removal_tests {
         env.run_ldr(&["add", "Remove this", "--under", "1"]);
 
         // Remove second subtask (1b)
-        let result = env.run_ldr(&["rm", "1b"]);
+        let result = env.run_ldr(&["rm", "-y", "1b"]);
         result.assert_success();
 
         // Main task should remain with first subtask only
@@ -489,6 +1319,64 @@ mod removal_tests {
     }
 }
 
+#[cfg(test)]
+mod confirmation_tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_rm_refuses_without_yes_in_non_interactive_mode() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Task B"]);
+
+        // stdin is closed, so there's nobody to answer the prompt
+        let output = Command::new(&env.binary_path)
+            .args(["rm", "1", "2"])
+            .env("XDG_DATA_HOME", &env.data_dir)
+            .env("XDG_CONFIG_HOME", &env.config_dir)
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to execute ldr command");
+
+        assert_ne!(output.status.code(), Some(0));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("refusing destructive operation without --yes"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("Task A"));
+        assert!(todos.contains("Task B"));
+    }
+
+    #[test]
+    fn test_single_rm_also_requires_yes_in_non_interactive_mode() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+
+        let result = env.run_ldr(&["rm", "1"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("refusing destructive operation"));
+        assert!(env.read_todos().contains("Task A"));
+
+        let result = env.run_ldr(&["rm", "-y", "1"]);
+        result.assert_success();
+        assert!(!env.read_todos().contains("Task A"));
+    }
+
+    #[test]
+    fn test_bulk_rm_with_yes() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+        env.run_ldr(&["add", "Task B"]);
+
+        let result = env.run_ldr(&["rm", "--yes", "1", "2"]);
+        result.assert_success();
+        assert!(!env.read_todos().contains("Task"));
+    }
+}
+
 #[cfg(test)]
 mod filtering_tests {
     use super::*;
@@ -563,6 +1451,29 @@ mod filtering_tests {
         assert_eq!(matching_lines.len(), 10);
     }
 
+    #[test]
+    fn test_filtering_match_subtasks_only() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Garden work"]);
+        env.run_ldr(&["add", "Buy seeds", "--under", "1"]);
+        env.run_ldr(&["add", "Garden hose repair", "--under", "1"]);
+
+        // Default: a matching parent shows all of its subtasks
+        let result = env.run_ldr(&["ls", "garden"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Garden work"));
+        assert!(result.stdout.contains("a. Buy seeds"));
+        assert!(result.stdout.contains("b. Garden hose repair"));
+
+        // Tight matching: only subtasks that match on their own are shown
+        let result = env.run_ldr(&["ls", "--match-subtasks-only", "garden"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Garden work"));
+        assert!(!result.stdout.contains("Buy seeds"));
+        assert!(result.stdout.contains("b. Garden hose repair"));
+    }
+
     #[test]
     fn test_filtering_no_matches() {
         let env = TestEnv::new();
@@ -578,6 +1489,79 @@ mod filtering_tests {
     }
 }
 
+#[cfg(test)]
+mod find_tests {
+    use super::*;
+
+    #[test]
+    fn test_find_searches_open_and_archived() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Renew passport"]);
+        env.run_ldr(&["add", "Book flights"]);
+        env.run_ldr(&["add", "Pack passport holder"]);
+        env.run_ldr(&["do", "3"]); // Archive "Renew passport"
+
+        let result = env.run_ldr(&["find", "PASSPORT"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Open"));
+        assert!(result.stdout.contains("1. Pack passport holder"));
+        assert!(result.stdout.contains("Archived ("));
+        assert!(result.stdout.contains("- Renew passport"));
+        assert!(!result.stdout.contains("Book flights"));
+
+        // Open matches are listed before archived ones
+        let open_pos = result.stdout.find("Pack passport holder").unwrap();
+        let archived_pos = result.stdout.find("Renew passport").unwrap();
+        assert!(open_pos < archived_pos);
+    }
+
+    #[test]
+    fn test_find_matches_subtasks() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Buy sunscreen", "--under", "1"]);
+        env.run_ldr(&["add", "Reserve hotel", "--under", "1"]);
+
+        let result = env.run_ldr(&["grep", "sunscreen"]);
+        result.assert_success();
+        assert!(result.stdout.contains("1. Plan trip"));
+        assert!(result.stdout.contains("a. Buy sunscreen"));
+        assert!(!result.stdout.contains("Reserve hotel"));
+    }
+
+    #[test]
+    fn test_find_regex() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Call Bob"]);
+        env.run_ldr(&["add", "Email Alice"]);
+
+        let result = env.run_ldr(&["find", "--regex", "^(call|text) "]);
+        result.assert_success();
+        assert!(result.stdout.contains("Call Bob"));
+        assert!(!result.stdout.contains("Email Alice"));
+
+        let result = env.run_ldr(&["find", "--regex", "(unclosed"]);
+        assert_ne!(result.status, 0);
+        assert!(result.stderr.contains("Invalid regex"));
+    }
+
+    #[test]
+    fn test_find_no_matches() {
+        let env = TestEnv::new();
+
+        env.run_ldr(&["add", "Task A"]);
+
+        let result = env.run_ldr(&["find", "nonexistent"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("No items found matching: \"nonexistent\""));
+    }
+}
+
 #[cfg(test)]
 mod error_handling {
     use super::*;
@@ -612,7 +1596,7 @@ mod error_handling {
         result.assert_success();
         assert!(result.stdout.contains("No notes found"));
 
-        let result = env.run_ldr(&["rm", "1"]);
+        let result = env.run_ldr(&["rm", "-y", "1"]);
         result.assert_success();
     }
 
@@ -629,7 +1613,7 @@ mod error_handling {
         let result = env.run_ldr(&["do", "1a"]);
         result.assert_failure();
 
-        let result = env.run_ldr(&["rm", "1a"]);
+        let result = env.run_ldr(&["rm", "-y", "1a"]);
         result.assert_failure();
     }
 }
@@ -644,8 +1628,9 @@ mod edit_functionality {
 
         // Set EDITOR to a command that just touches the file and exits
         let result = Command::new(&env.binary_path)
-            .args(&["edit"])
+            .args(["edit"])
             .env("XDG_DATA_HOME", &env.data_dir)
+            .env("XDG_CONFIG_HOME", &env.config_dir)
             .env("EDITOR", "touch") // Will just touch the file
             .output()
             .expect("Failed to execute edit command");
@@ -658,47 +1643,225 @@ mod edit_functionality {
         assert!(todos.contains("# TODOs"));
     }
 
+    /// Writes an executable script that replaces the file it is given with `content`
+    fn fake_editor(env: &TestEnv, content: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = env.data_dir.join("fake-editor.sh");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\ncat > \"$1\" <<'EOF'\n{}EOF\n", content),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        script.to_str().unwrap().to_string()
+    }
+
     #[test]
-    fn test_edit_aliases() {
-        let env = TestEnv::new();
+    fn test_edit_with_editor_arguments() {
+        use std::os::unix::fs::PermissionsExt;
 
-        // Test all aliases work by using echo to verify they're called
-        let aliases = vec!["edit", "e", "scan", "s", "review", "r"];
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Old task"]);
+        // The script writes its first argument as the new file content, so the
+        // arguments in the editor command must reach it before the file path
+        let script = env.data_dir.join("fake editor.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\nprintf '# TODOs\\n\\n- %s\\n' \"$1\" > \"$2\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let command = format!("'{}' 'From args'", script.display());
+
+        let result = env.run_ldr_with_env(&["edit"], &[("EDITOR", &command)]);
+        result.assert_success();
+        assert!(env.read_todos().contains("- From args\n"));
 
-        for alias in aliases {
-            let result = Command::new(&env.binary_path)
-                .args(&[alias])
-                .env("XDG_DATA_HOME", &env.data_dir)
-                .env("EDITOR", "/bin/echo")
-                .output()
-                .expect(&format!("Failed to execute {} command", alias));
+        // --editor wins over $EDITOR
+        let flag = format!("'{}' 'From flag'", script.display());
+        let result = env.run_ldr_with_env(&["edit", "--editor", &flag], &[("EDITOR", "false")]);
+        result.assert_success();
+        assert!(env.read_todos().contains("- From flag\n"));
 
-            // Should succeed and echo the file path
-            assert_eq!(result.status.code().unwrap_or(-1), 0);
-            let stdout = String::from_utf8_lossy(&result.stdout);
-            assert!(
-                stdout.contains("todos.md"),
-                "Alias '{}' didn't work: {}",
-                alias,
-                stdout
-            );
-        }
+        let result = env.run_ldr_with_env(&["edit"], &[("EDITOR", "no-such-editor --wait")]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Editor 'no-such-editor' not found; set $EDITOR"));
     }
-}
-
-#[cfg(test)]
-mod migration_tests {
-    use super::*;
 
     #[test]
-    fn test_migration_from_plain_text() {
+    fn test_edit_reports_item_counts() {
         let env = TestEnv::new();
+        env.run_ldr(&["add", "Old task"]);
+        let editor = fake_editor(&env, "# TODOs\n\n- Task one\n  - Sub\n- Task two\n");
 
-        // Create old-style plain text files
-        let old_note_path = env.data_dir.join("ldr/note.txt");
-        let old_archive_path = env.data_dir.join("ldr/archive.txt");
-
-        fs::create_dir_all(old_note_path.parent().unwrap()).unwrap();
+        let result = env.run_ldr_with_env(&["edit"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Found 2 tasks and 1 subtask (was 1 task and 0 subtasks)"));
+        assert!(!result.stdout.contains("Warning"));
+    }
+
+    #[test]
+    fn test_edit_warns_when_all_tasks_disappear() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Old task"]);
+        let editor = fake_editor(&env, "# TODOs\n");
+
+        // Not a terminal, so the warning is shown without offering to reopen the editor
+        let result = env.run_ldr_with_env(&["edit"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Warning: no tasks found (there were 1 before editing)"));
+    }
+
+    #[test]
+    fn test_edit_reports_parse_warnings() {
+        let env = TestEnv::new();
+        let editor = fake_editor(&env, "# TODOs\n\n- Task\n  - Sub\n      - Too deep\n");
+
+        let result = env.run_ldr_with_env(&["edit"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Warning: Deep nesting detected (line 5)"));
+        // Reported once, by edit itself rather than by the parser
+        assert!(!result.stderr.contains("Deep nesting"));
+    }
+
+    #[test]
+    fn test_edit_archive() {
+        let env = TestEnv::new();
+
+        // A missing archive is created before the editor opens it
+        let result = env.run_ldr_with_env(&["edit", "--archive"], &[("EDITOR", "touch")]);
+        result.assert_success();
+        assert!(env.read_archive().starts_with("# Archive"));
+        assert!(result.stdout.contains("Found 0 items archived over 0 days"));
+
+        let editor = fake_editor(
+            &env,
+            "# Archive\n\n## 2025-01-02\n- Shipped it\n- Fixed bug\noops\n",
+        );
+        let result = env.run_ldr_with_env(&["edit", "--archive"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        assert!(result.stdout.contains("Found 2 items archived over 1 day"));
+        assert!(result
+            .stdout
+            .contains("Warning: Skipped unrecognized archive line 6: oops"));
+        // The todo file is left alone
+        assert!(!env.read_todos().contains("Shipped it"));
+    }
+
+    #[test]
+    fn test_edit_aliases() {
+        let env = TestEnv::new();
+
+        // Test all aliases work by using echo to verify they're called
+        let aliases = vec!["edit", "e", "scan", "s", "review", "r"];
+
+        for alias in aliases {
+            let result = Command::new(&env.binary_path)
+                .args([alias])
+                .env("XDG_DATA_HOME", &env.data_dir)
+                .env("XDG_CONFIG_HOME", &env.config_dir)
+                .env("EDITOR", "/bin/echo")
+                .output()
+                .unwrap_or_else(|_| panic!("Failed to execute {} command", alias));
+
+            // Should succeed and echo the file path
+            assert_eq!(result.status.code().unwrap_or(-1), 0);
+            let stdout = String::from_utf8_lossy(&result.stdout);
+            assert!(
+                stdout.contains("todos.md"),
+                "Alias '{}' didn't work: {}",
+                alias,
+                stdout
+            );
+        }
+    }
+
+    #[test]
+    fn test_edit_filter_merges_matching_tasks_back() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Fix bug @work\n- Buy milk\n- Plan @work\n  - Draft\n- Email boss @work\n",
+        )
+        .unwrap();
+        let editor = fake_editor(
+            &env,
+            "# Tasks\n\n- [1] Fix bug quickly @work\n- Call client @work\n- [3] Plan @work\n  - Draft\n",
+        );
+
+        let result = env.run_ldr_with_env(&["edit", "--filter", "@work"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Changed 1, added 1 and removed 1 of the matching tasks"));
+        let result = env.run_ldr(&["ls", "--plain", "-a"]);
+        assert_eq!(
+            result.stdout,
+            "1\tCall client @work\n2\tFix bug quickly @work\n3\tBuy milk\n4\tPlan @work\n4a\tDraft\n"
+        );
+
+        // A number that wasn't handed out leaves the file alone
+        let before = env.read_todos();
+        let editor = fake_editor(&env, "# Tasks\n\n- [3] Buy oat milk\n");
+        let result = env.run_ldr_with_env(&["edit", "--filter", "@work"], &[("EDITOR", &editor)]);
+        result.assert_failure();
+        assert!(result.stdout.contains("[3] isn't a task being edited"));
+        assert_eq!(env.read_todos(), before);
+
+        // The rejected edits are kept for the user
+        let kept = result.stdout.split("your edits are in ").nth(1).unwrap();
+        let kept = kept.split(')').next().unwrap();
+        assert!(fs::read_to_string(kept).unwrap().contains("Buy oat milk"));
+        fs::remove_file(kept).unwrap();
+    }
+
+    #[test]
+    fn test_edit_filter_gives_added_tasks_unique_ids() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Fix bug @work <!-- id:aaaa -->\n- Buy milk <!-- id:bbbb -->\n- Plan @work <!-- id:cccc -->\n",
+        )
+        .unwrap();
+        let editor = fake_editor(
+            &env,
+            "# Tasks\n\n- [1] Fix bug @work <!-- id:aaaa -->\n- [3] Plan @work <!-- id:cccc -->\n- Call client @work\n- Book room @work\n",
+        );
+
+        let result = env.run_ldr_with_env(&["edit", "--filter", "@work"], &[("EDITOR", &editor)]);
+        result.assert_success();
+        let todos = env.read_todos();
+        let ids: Vec<&str> = todos.split("id:").skip(1).map(|rest| &rest[..4]).collect();
+        assert_eq!(ids.len(), 5);
+        let unique: std::collections::HashSet<&str> = ids.iter().copied().collect();
+        assert_eq!(unique.len(), 5);
+    }
+}
+
+#[cfg(test)]
+mod migration_tests {
+    use super::*;
+
+    #[test]
+    fn test_migration_from_plain_text() {
+        let env = TestEnv::new();
+
+        // Create old-style plain text files
+        let old_note_path = env.data_dir.join("ldr/note.txt");
+        let old_archive_path = env.data_dir.join("ldr/archive.txt");
+
+        fs::create_dir_all(old_note_path.parent().unwrap()).unwrap();
 
         // Write old-style content
         fs::write(&old_note_path, "Task A\nTask B with details\nTask C\n").unwrap();
@@ -735,7 +1898,7 @@ mod migration_tests {
 
         // Create markdown file first
         fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
-        fs::write(&env.todos_path(), "# TODOs\n\n- Existing task\n").unwrap();
+        fs::write(env.todos_path(), "# TODOs\n\n- Existing task\n").unwrap();
 
         // Create old-style file
         let old_note_path = env.data_dir.join("ldr/note.txt");
@@ -750,6 +1913,62 @@ mod migration_tests {
         assert!(todos.contains("Existing task"));
         assert!(!todos.contains("Old task"));
     }
+
+    #[test]
+    fn test_migrate_status_reports_without_migrating() {
+        let env = TestEnv::new();
+        let old_note_path = env.data_dir.join("ldr/note.txt");
+        fs::create_dir_all(old_note_path.parent().unwrap()).unwrap();
+        fs::write(&old_note_path, "Old task\n").unwrap();
+
+        let result = env.run_ldr(&["migrate", "--status"]);
+        result.assert_success();
+        assert_eq!(
+            result.stdout,
+            "Migration needed: found note.txt and no Markdown files\n"
+        );
+        assert!(!env.todos_path().exists());
+
+        let result = env.run_ldr(&["migrate"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Plain text files: note.txt\n"));
+        assert!(result.stdout.contains("Markdown files:   none\n"));
+        assert!(env.read_todos().contains("- Old task"));
+
+        let result = env.run_ldr(&["migrate", "--status"]);
+        assert_eq!(
+            result.stdout,
+            "No migration needed: todos.md, archive.md already exist (use --force to convert note.txt again)\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_force_backs_up_markdown() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(env.todos_path(), "# TODOs\n\n- Existing task\n").unwrap();
+
+        // Without plain text files there is nothing to convert
+        let result = env.run_ldr(&["migrate", "--force"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Nothing to migrate"));
+
+        fs::write(env.data_dir.join("ldr/note.txt"), "Old task\n").unwrap();
+        let result = env.run_ldr(&["migrate"]);
+        result.assert_success();
+        assert!(!env.read_todos().contains("Old task"));
+
+        let result = env.run_ldr(&["--porcelain", "migrate", "--force"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "migrated tasks=1 archived=0\n");
+        assert!(env.read_todos().contains("- Old task"));
+        let backup = fs::read_to_string(env.data_dir.join("ldr/todos.md.bak")).unwrap();
+        assert!(backup.contains("- Existing task"));
+
+        // The replaced list comes back with undo
+        env.run_ldr(&["undo"]).assert_success();
+        assert!(env.read_todos().contains("- Existing task"));
+    }
 }
 
 #[cfg(test)]
@@ -829,3 +2048,3215 @@ mod pols_compliance {
         assert!(archive.contains("Big project"));
     }
 }
+
+#[cfg(test)]
+mod named_list_tests {
+    use super::*;
+
+    #[test]
+    fn test_add_to_missing_list_creates_it() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Default task"]);
+
+        let result = env.run_ldr(&["add", "Ship release", "--list", "Work"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Added to Work (new list): Ship release"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("- Default task"));
+        assert!(todos.contains("## Work\n\n- Ship release"));
+
+        // Existing lists are matched case-insensitively; --create is still accepted
+        let result = env.run_ldr(&["add", "Review PR", "--list", "work", "--create"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Added to work: Review PR"));
+        let result = env.run_ldr(&["add", "Update changelog", "--list", "Work", "--under", "1"]);
+        result.assert_success();
+
+        let todos = env.read_todos();
+        assert_eq!(todos.matches("## Work").count(), 1);
+        assert!(todos.contains("- Review PR"));
+        assert!(todos.contains("  - Update changelog"));
+        let review = todos.find("- Review PR").unwrap();
+        let ship = todos.find("- Ship release").unwrap();
+        assert!(review < ship);
+    }
+
+    #[test]
+    fn test_append_adds_to_bottom_of_list() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "First", "--list", "Work"]);
+        env.run_ldr(&["add", "Second", "--list", "Work"]);
+
+        let result = env.run_ldr(&["add", "Last", "--list", "Work", "--append"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Added to Work at the bottom: Last"));
+        let result = env.run_ldr(&["add", "Very last", "--bottom"]);
+        assert!(result.stdout.contains("✓ Added at the bottom: Very last"));
+
+        let todos = env.read_todos();
+        let positions: Vec<usize> = ["## Work", "- Second", "- First", "- Last"]
+            .iter()
+            .map(|line| todos.find(line).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(todos.starts_with("# TODOs\n\n- Very last"));
+
+        let result = env.run_ldr(&["add", "Sub", "--under", "1", "--append"]);
+        result.assert_failure();
+    }
+
+    #[test]
+    fn test_create_requires_list() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["add", "Task", "--create"]);
+        result.assert_failure();
+    }
+
+    #[test]
+    fn test_default_list_needs_no_create() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["add", "Task", "--list", "Default"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Added: Task"));
+        assert!(!env.read_todos().contains("##"));
+    }
+}
+
+#[cfg(test)]
+mod verbosity_tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_suppresses_confirmations() {
+        let env = TestEnv::new();
+
+        let result = env.run_ldr(&["--quiet", "add", "First task"]);
+        result.assert_success();
+        assert!(result.stdout.is_empty());
+
+        // The flag is global, so it also works after the subcommand
+        let result = env.run_ldr(&["add", "Second task", "-q"]);
+        result.assert_success();
+        assert!(result.stdout.is_empty());
+
+        for args in [
+            &["-q", "up", "2"][..],
+            &["-q", "set-due", "1", "2025-05-01"],
+            &["-q", "clear-due", "1"],
+            &["-q", "do", "1"],
+            &["-q", "rm", "-y", "1"],
+        ] {
+            let result = env.run_ldr(args);
+            result.assert_success();
+            assert!(result.stdout.is_empty(), "{:?} printed output", args);
+        }
+        assert!(env.read_archive().contains("First task"));
+    }
+
+    #[test]
+    fn test_quiet_keeps_data_output() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Only task"]);
+
+        let result = env.run_ldr(&["-q", "ls", "--json"]);
+        result.assert_success();
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(json[0]["text"], "Only task");
+
+        for args in [&["-q", "ls"][..], &["-q", "find", "only"], &["-q", "peek"]] {
+            let result = env.run_ldr(args);
+            result.assert_success();
+            assert!(
+                result.stdout.contains("Only task"),
+                "{:?} printed nothing",
+                args
+            );
+        }
+        let result = env.run_ldr(&["-q", "stats", "--json"]);
+        result.assert_success();
+        assert!(result.stdout.contains("\"open_tasks\": 1"));
+    }
+
+    #[test]
+    fn test_quiet_still_reports_errors() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Only task"]);
+
+        let result = env.run_ldr(&["-q", "do", "5"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("Invalid task number"));
+
+        let result = env.run_ldr(&["-q", "add", ""]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Cannot add empty task"));
+    }
+
+    #[test]
+    fn test_verbose_prints_file_summary() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "First task"]);
+
+        let result = env.run_ldr(&["--verbose", "add", "Subtask", "--under", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Added subtask to task 1: Subtask"));
+        let summary = format!("file={} tasks=1 subtasks=1", env.todos_path().display());
+        assert!(result.stdout.contains(&summary));
+
+        let result = env.run_ldr(&["-v", "do", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains(&format!(
+            "file={} tasks=0 subtasks=0",
+            env.todos_path().display()
+        )));
+        assert!(result.stdout.contains(&format!(
+            "file={} tasks=1 subtasks=1",
+            env.archive_path().display()
+        )));
+    }
+
+    #[test]
+    fn test_quiet_and_verbose_conflict() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["-q", "-v", "ls"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod reindex_tests {
+    use super::*;
+
+    #[test]
+    fn test_reindex_canonicalizes_messy_file() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        let messy = "#TODOs\n\n\n* Plan trip\n\t- Book hotel\n    - Book hotel\n   - \n+ Call mom\n-\n  -   Stray subtask\n\n\n- Pay rent <!-- due:2025-05-01 -->\n";
+        fs::write(env.todos_path(), messy).unwrap();
+
+        let result = env.run_ldr(&["reindex"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Reindexed"));
+        assert!(result.stdout.contains("1 empty task(s) removed"));
+        assert!(result.stdout.contains("1 empty subtask(s) removed"));
+        assert!(result.stdout.contains("1 duplicate subtask(s) removed"));
+        assert!(result.stdout.contains("Bullets and indentation normalized"));
+
+        assert_eq!(
+            env.read_todos(),
+            "# TODOs\n\n- Plan trip\n  - Book hotel\n- Call mom\n- Stray subtask\n- Pay rent <!-- due:2025-05-01 -->\n"
+        );
+
+        // The original is kept as a backup
+        let backup = env.todos_path().with_extension("md.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), messy);
+
+        // A second run has nothing left to do
+        let result = env.run_ldr(&["reindex"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Already tidy"));
+    }
+}
+
+#[cfg(test)]
+mod nested_subtask_tests {
+    use super::*;
+
+    const NESTED: &[(&str, &str)] = &[("LDR_NESTED_SUBTASKS", "1")];
+
+    fn write_nested_file(env: &TestEnv) {
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Plan trip\n  - Book travel\n    - Flights\n    - Hotel\n  - Pack\n",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_nested_children_listed_and_archived() {
+        let env = TestEnv::new();
+        write_nested_file(&env);
+
+        let result = env.run_ldr_with_env(&["ls"], NESTED);
+        result.assert_success();
+        assert!(result.stdout.contains("a. Book travel"));
+        assert!(result.stdout.contains("        1. Flights"));
+        assert!(result.stdout.contains("        2. Hotel"));
+        assert!(result.stdout.contains("b. Pack"));
+
+        let result = env.run_ldr_with_env(&["do", "1a2"], NESTED);
+        result.assert_success();
+        assert!(result.stdout.contains("Hotel"));
+        assert_eq!(
+            env.read_todos(),
+            "# TODOs\n\n- Plan trip\n  - Book travel\n    - Flights\n  - Pack\n"
+        );
+        assert!(env.read_archive().contains("- Hotel"));
+
+        let result = env.run_ldr_with_env(&["do", "1a5"], NESTED);
+        result.assert_failure();
+        assert!(result.stdout.contains("Invalid child '1a5'"));
+    }
+
+    #[test]
+    fn test_flattening_is_the_default() {
+        let env = TestEnv::new();
+        write_nested_file(&env);
+
+        let result = env.run_ldr(&["ls"]);
+        result.assert_success();
+        assert!(result.stdout.contains("b. Flights"));
+        assert!(result.stdout.contains("c. Hotel"));
+        assert!(result.stdout.contains("d. Pack"));
+    }
+}
+
+#[cfg(test)]
+mod mine_tests {
+    use super::*;
+
+    const ALICE_ON_LAPTOP: &[(&str, &str)] = &[("LDR_WHO", "alice"), ("LDR_HOST", "laptop")];
+
+    fn add_tagged_tasks(env: &TestEnv) {
+        env.run_ldr(&["add", "Shared chore"]).assert_success();
+        env.run_ldr(&["add", "Alice task", "--who", "alice"])
+            .assert_success();
+        env.run_ldr(&["add", "Bob task", "--who", "bob"])
+            .assert_success();
+        env.run_ldr(&["add", "Laptop task", "--host", "laptop"])
+            .assert_success();
+        env.run_ldr(&["add", "Desktop task", "--host", "desktop"])
+            .assert_success();
+    }
+
+    #[test]
+    fn test_ls_mine_shows_only_matching_tasks() {
+        let env = TestEnv::new();
+        add_tagged_tasks(&env);
+        assert!(env.read_todos().contains("- Alice task <!-- created:"));
+        assert!(env.read_todos().contains(" who:alice "));
+
+        let result = env.run_ldr_with_env(&["ls", "-a", "--mine"], ALICE_ON_LAPTOP);
+        result.assert_success();
+        assert!(result.stdout.contains("2. Laptop task"));
+        assert!(result.stdout.contains("4. Alice task"));
+        assert!(result.stdout.contains("5. Shared chore"));
+        assert!(!result.stdout.contains("Bob task"));
+        assert!(!result.stdout.contains("Desktop task"));
+
+        // Without --mine everything is shown
+        let result = env.run_ldr_with_env(&["ls", "-a"], ALICE_ON_LAPTOP);
+        assert!(result.stdout.contains("Bob task"));
+    }
+
+    #[test]
+    fn test_ls_strict_mine_hides_untagged_tasks() {
+        let env = TestEnv::new();
+        add_tagged_tasks(&env);
+
+        let result = env.run_ldr_with_env(&["ls", "-a", "--strict-mine"], ALICE_ON_LAPTOP);
+        result.assert_success();
+        assert!(result.stdout.contains("Alice task"));
+        assert!(result.stdout.contains("Laptop task"));
+        assert!(!result.stdout.contains("Shared chore"));
+    }
+
+    #[test]
+    fn test_add_rejects_invalid_tags() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Parent"]);
+
+        let result = env.run_ldr(&["add", "Task", "--who", "two words"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Expected a single word"));
+
+        let result = env.run_ldr(&["add", "Sub", "--under", "1", "--who", "alice"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod insert_subtask_tests {
+    use super::*;
+
+    #[test]
+    fn test_under_subtask_inserts_after_it() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Trip"]);
+        env.run_ldr(&["add", "Book flights", "--under", "1"]);
+        env.run_ldr(&["add", "Pack", "--under", "1"]);
+
+        let result = env.run_ldr(&["add", "Book hotel", "--under", "1a"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Added subtask to task 1 as 1b: Book hotel"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("  - Book flights\n  - Book hotel\n  - Pack\n"));
+
+        // Inserting after the last subtask is the same as appending
+        env.run_ldr(&["add", "Leave", "--under", "1c"])
+            .assert_success();
+        assert!(env.read_todos().ends_with("  - Pack\n  - Leave\n"));
+    }
+
+    #[test]
+    fn test_under_task_number_still_appends() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Trip"]);
+        env.run_ldr(&["add", "First", "--under", "1"]);
+        env.run_ldr(&["add", "Second", "--under", "1"]);
+
+        assert!(env.read_todos().ends_with("  - First\n  - Second\n"));
+    }
+
+    #[test]
+    fn test_under_invalid_references() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Trip"]);
+        env.run_ldr(&["add", "First", "--under", "1"]);
+
+        let result = env.run_ldr(&["add", "Nope", "--under", "1c"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Invalid subtask: 1c. Task 1 has 1 subtasks"));
+
+        let result = env.run_ldr(&["add", "Nope", "--under", "2a"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Invalid task number: 2"));
+
+        let result = env.run_ldr(&["add", "Nope", "--under", "x"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Invalid --under reference"));
+    }
+}
+
+#[cfg(test)]
+mod duplicate_tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_add_is_skipped() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Buy milk"]);
+        env.run_ldr(&["add", "Groceries"]);
+        env.run_ldr(&["add", "Eggs", "--under", "1"]);
+
+        // Case and surrounding whitespace are ignored
+        let result = env.run_ldr(&["add", "  buy MILK "]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Similar item already exists at 2; not adding (use --force to add anyway)"));
+        assert_eq!(
+            env.read_todos().to_lowercase().matches("buy milk").count(),
+            1
+        );
+
+        // Subtasks count too
+        let result = env.run_ldr(&["add", "eggs"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Similar item already exists at 1a"));
+        assert_eq!(env.read_todos().to_lowercase().matches("eggs").count(), 1);
+    }
+
+    #[test]
+    fn test_force_adds_duplicate() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Buy milk"]);
+
+        let result = env.run_ldr(&["add", "Buy milk", "--force"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Added: Buy milk"));
+        assert_eq!(env.read_todos().matches("Buy milk").count(), 2);
+    }
+}
+
+#[cfg(test)]
+mod list_order_tests {
+    use super::*;
+
+    fn add_three_lists(env: &TestEnv) {
+        env.run_ldr(&["add", "Home task"]);
+        for (task, list) in [
+            ("Ship", "Work"),
+            ("Call mom", "Personal"),
+            ("Milk", "Shopping"),
+        ] {
+            env.run_ldr(&["add", task, "--list", list, "--create"])
+                .assert_success();
+        }
+    }
+
+    fn section_order(todos: &str) -> Vec<String> {
+        todos
+            .lines()
+            .filter_map(|line| line.strip_prefix("## "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_lists_reorder() {
+        let env = TestEnv::new();
+        add_three_lists(&env);
+        assert_eq!(
+            section_order(&env.read_todos()),
+            vec!["Work", "Personal", "Shopping"]
+        );
+
+        let result = env.run_ldr(&["lists", "reorder", "shopping", "work", "personal"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ List order: Default, Shopping, Work, Personal"));
+
+        let todos = env.read_todos();
+        assert_eq!(section_order(&todos), vec!["Shopping", "Work", "Personal"]);
+        // Tasks move with their sections and the Default list stays on top
+        assert!(todos.starts_with("# TODOs\n\n- Home task"));
+        assert!(todos.contains("## Shopping\n\n- Milk"));
+        assert!(todos.contains("## Work\n\n- Ship"));
+    }
+
+    #[test]
+    fn test_lists_up() {
+        let env = TestEnv::new();
+        add_three_lists(&env);
+
+        env.run_ldr(&["lists", "up", "Personal"]).assert_success();
+        assert_eq!(
+            section_order(&env.read_todos()),
+            vec!["Personal", "Work", "Shopping"]
+        );
+    }
+
+    #[test]
+    fn test_lists_reorder_rejects_unknown_lists() {
+        let env = TestEnv::new();
+        add_three_lists(&env);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["lists", "reorder", "Work", "Errands"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("No such list 'Errands'"));
+        assert_eq!(env.read_todos(), before);
+    }
+
+    #[test]
+    fn test_lists_shows_counts() {
+        let env = TestEnv::new();
+        add_three_lists(&env);
+        env.run_ldr(&["add", "Eggs", "--list", "Shopping"]);
+        env.run_ldr(&["add", "Brown", "--under", "1", "--list", "Shopping"])
+            .assert_success();
+
+        let result = env.run_ldr(&["lists"]);
+        result.assert_success();
+        let stdout = strip_ansi(&result.stdout);
+        let rows: Vec<Vec<&str>> = stdout
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["Default", "1"],
+                vec!["Work", "1"],
+                vec!["Personal", "1"],
+                vec!["Shopping", "2", "(+1", "subtask)"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lists_create_and_rename() {
+        let env = TestEnv::new();
+        env.run_ldr(&["lists", "create", "Errands"])
+            .assert_success();
+        assert!(env.read_todos().contains("## Errands"));
+        env.run_ldr(&["lists", "create", "errands"])
+            .assert_failure();
+
+        env.run_ldr(&["add", "Milk", "--list", "errands"])
+            .assert_success();
+        env.run_ldr(&["do", "1", "--list", "errands"])
+            .assert_success();
+        env.run_ldr(&["add", "Bread", "--list", "errands"])
+            .assert_success();
+
+        let result = env.run_ldr(&["lists", "rename", "errands", "Groceries"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Renamed list 'errands' to 'Groceries'"));
+        let todos = env.read_todos();
+        assert!(todos.contains("## Groceries\n\n- Bread"));
+        assert!(!todos.contains("Errands"));
+        // Archived items keep the name they were archived under
+        assert!(env.read_archive().contains("Errands"));
+
+        let result = env.run_ldr(&["lists", "rename", "Groceries", "Default"]);
+        result.assert_failure();
+    }
+
+    #[test]
+    fn test_lists_rm_requires_force_when_not_empty() {
+        let env = TestEnv::new();
+        add_three_lists(&env);
+
+        let result = env.run_ldr(&["lists", "rm", "shopping"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("use --force"));
+        assert!(env.read_todos().contains("## Shopping"));
+
+        let result = env.run_ldr(&["lists", "rm", "shopping", "--force"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Deleted list 'Shopping' and its 1 task"));
+        let todos = env.read_todos();
+        assert!(!todos.contains("Shopping"));
+        assert!(!todos.contains("Milk"));
+
+        env.run_ldr(&["lists", "rm", "Default", "--force"])
+            .assert_failure();
+        env.run_ldr(&["lists", "create", "Empty"]).assert_success();
+        env.run_ldr(&["lists", "rm", "empty"]).assert_success();
+    }
+}
+
+#[cfg(test)]
+mod plain_output_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Buy milk"]);
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+    }
+
+    #[test]
+    fn test_ls_plain() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--plain"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "1\tPlan trip\n1a\tBook hotel\n2\tBuy milk\n");
+    }
+
+    #[test]
+    fn test_ls_null_separated_numbers() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "-0", "--numbers-only"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "1\x001a\x002");
+    }
+
+    #[test]
+    fn test_ls_separator_implies_plain() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--separator", " | "]);
+        result.assert_success();
+        assert_eq!(result.stdout, "1\tPlan trip | 1a\tBook hotel | 2\tBuy milk");
+    }
+}
+
+#[cfg(test)]
+mod wrap_tests {
+    use super::*;
+
+    const LONG_TASK: &str = "Renew the passport before the summer trip to Lisbon";
+
+    #[test]
+    fn test_ls_wraps_to_width() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", LONG_TASK]);
+        env.run_ldr(&[
+            "add",
+            "Find the old passport photos in the drawer",
+            "--under",
+            "1",
+        ]);
+
+        let result = env.run_ldr(&["ls", "--width", "30"]);
+        result.assert_success();
+        let lines: Vec<_> = result.stdout.lines().map(strip_ansi).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "  1. Renew the passport before",
+                "     the summer trip to Lisbon",
+                "     a. Find the old passport",
+                "        photos in the drawer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ls_does_not_wrap_when_piped() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", LONG_TASK]);
+
+        let result = env.run_ldr(&["ls"]);
+        result.assert_success();
+        assert_eq!(result.stdout.lines().count(), 1);
+        assert!(result.stdout.contains(LONG_TASK));
+    }
+
+    #[test]
+    fn test_ls_columns_truncates_and_aligns_due_dates() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Café crème for the whole team on Friday"]);
+        env.run_ldr(&["add", "Pay rent", "--due", "2030-05-01"]);
+        env.run_ldr(&["add", "Look up the trip dates", "--under", "1"]);
+
+        let result = env.run_ldr(&["ls", "--columns", "--width", "40"]);
+        result.assert_success();
+        let lines: Vec<_> = result.stdout.lines().map(strip_ansi).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "  1. Pay rent             due 2030-05-01",
+                "     a. Look up the tri…                ",
+                "  2. Café crème for the…                ",
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod validate_ref_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+    }
+
+    #[test]
+    fn test_validate_ref_valid() {
+        let env = TestEnv::new();
+        setup(&env);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["validate-ref", "1a"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ 1a: Book hotel"));
+        assert_eq!(env.read_todos(), before);
+
+        let quiet = env.run_ldr(&["-q", "validate-ref", "1"]);
+        quiet.assert_success();
+        assert!(quiet.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_validate_ref_out_of_range() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["validate-ref", "3"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Invalid task number in '3': 3. Valid range: 1-1"));
+
+        let result = env.run_ldr(&["validate-ref", "1b"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Task 1 has 1 subtasks"));
+    }
+
+    #[test]
+    fn test_validate_ref_malformed() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["validate-ref", "a1"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Invalid task reference 'a1'"));
+    }
+}
+
+#[cfg(test)]
+mod count_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_count_honors_filter_and_ignores_num() {
+        let env = TestEnv::new();
+        for i in 1..=7 {
+            env.run_ldr(&["add", &format!("@work item {}", i)]);
+        }
+        env.run_ldr(&["add", "@home chore"]);
+        env.run_ldr(&["add", "@work subitem", "--under", "2"]);
+
+        // The subtask is an item of its own
+        let result = env.run_ldr(&["ls", "--count"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "9\n");
+        let result = env.run_ldr(&["ls", "--count", "--tasks-only"]);
+        assert_eq!(result.stdout, "8\n");
+
+        let result = env.run_ldr(&["ls", "--count", "-n", "2", "@work"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "8\n");
+    }
+
+    #[test]
+    fn test_ls_count_without_matches() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["ls", "--count"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "0\n");
+
+        env.run_ldr(&["add", "Buy milk"]);
+        let result = env.run_ldr(&["ls", "--count", "nothing"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "0\n");
+    }
+}
+
+#[cfg(test)]
+mod tags_tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_counts_subtask_only_tag() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Email Bob @work\n- Plan trip\n  - Buy adapter @errands\n  - Ask @work for leave\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["tags"]);
+        result.assert_success();
+        let rows: Vec<Vec<_>> = result
+            .stdout
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0][0].ends_with("@work"));
+        assert_eq!(rows[0][1..3], ["2", "1,"]);
+        assert!(rows[0][3].starts_with("2b"));
+        assert!(rows[1][0].ends_with("@errands"));
+        assert_eq!(rows[1][1], "1");
+        assert!(rows[1][2].starts_with("2a"));
+    }
+
+    #[test]
+    fn test_tags_without_tags() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Buy milk"]);
+
+        let result = env.run_ldr(&["tags"]);
+        result.assert_success();
+        assert!(result.stdout.contains("No tags yet."));
+    }
+}
+
+#[cfg(test)]
+mod since_last_seen_tests {
+    use super::*;
+
+    #[test]
+    fn test_since_file_mtime_flags_new_task() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Old task"]);
+
+        let first = env.run_ldr(&["ls", "--since-file-mtime"]);
+        first.assert_success();
+        assert!(!first.stdout.contains("(new)"));
+
+        env.run_ldr(&["add", "Fresh task"]);
+        let second = env.run_ldr(&["ls", "--since-file-mtime"]);
+        second.assert_success();
+        assert!(second.stdout.contains("1. Fresh task (new)"));
+        assert!(!second.stdout.contains("Old task ("));
+
+        // The snapshot is updated, so nothing is new the next time
+        let third = env.run_ldr(&["ls", "--since-file-mtime"]);
+        assert!(!third.stdout.contains("(new)"));
+    }
+}
+
+#[cfg(test)]
+mod today_tests {
+    use super::*;
+
+    #[test]
+    fn test_today_shows_items_archived_today() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Still open"]);
+        env.run_ldr(&["add", "Write report"]);
+        env.run_ldr(&["add", "Ship", "--list", "Work", "--create"]);
+        env.run_ldr(&["do", "1"]).assert_success();
+
+        let result = env.run_ldr(&["today"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Completed "));
+        assert!(result.stdout.contains("- Write report"));
+        assert!(!result.stdout.contains("Still open"));
+    }
+
+    #[test]
+    fn test_today_with_nothing_archived() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["today"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Nothing completed today yet."));
+    }
+
+    #[test]
+    fn test_today_for_past_date() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\n## 2025-05-01\n- Filed taxes\n  - Printed receipts\n\n### Work\n- Sent invoice\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["today", "--date", "2025-05-01"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Completed 2025-05-01"));
+        assert!(result.stdout.contains("- Filed taxes"));
+        assert!(result.stdout.contains("- Printed receipts"));
+        assert!(result.stdout.contains("- Sent invoice [Work]"));
+
+        let result = env.run_ldr(&["today", "--date", "2025-05-02"]);
+        assert!(result.stdout.contains("Nothing completed on 2025-05-02."));
+
+        env.run_ldr(&["today", "--date", "May 1"]).assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod separated_refs_tests {
+    use super::*;
+
+    fn add_items(env: &TestEnv) {
+        for item in ["Item 5", "Item 4", "Item 3", "Item 2", "Item 1"] {
+            env.run_ldr(&["add", item]);
+        }
+    }
+
+    #[test]
+    fn test_do_with_comma_separated_refs() {
+        let env = TestEnv::new();
+        add_items(&env);
+
+        let result = env.run_ldr(&["do", "1,2", "3,"]);
+        result.assert_success();
+
+        let todos = env.read_todos();
+        assert!(!todos.contains("Item 1"));
+        assert!(!todos.contains("Item 2"));
+        assert!(!todos.contains("Item 3"));
+        assert!(todos.contains("Item 4"));
+        let archive = env.read_archive();
+        assert!(
+            archive.contains("Item 1") && archive.contains("Item 2") && archive.contains("Item 3")
+        );
+    }
+
+    #[test]
+    fn test_up_with_comma_separated_refs() {
+        let env = TestEnv::new();
+        add_items(&env);
+
+        env.run_ldr(&["up", "4,5"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain", "-n", "3"]);
+        assert_eq!(result.stdout, "1\tItem 4\n2\tItem 5\n3\tItem 1\n");
+    }
+}
+
+#[cfg(test)]
+mod archive_range_tests {
+    use super::*;
+
+    fn write_archive(env: &TestEnv) {
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\n\
+             ## 2025-06-02\n- Deploy\n\n\
+             ## 2025-05-31\n- Review\n\n### Work\n- Sent invoice\n\n\
+             ## 2025-05-01\n- Fix bug\n  - Add test\n\n\
+             ## 2025-04-30\n- Mow lawn\n",
+        )
+        .unwrap();
+    }
+
+    fn shown_days(stdout: &str) -> Vec<&str> {
+        stdout
+            .lines()
+            .filter_map(|line| line.strip_prefix("Completed "))
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_show_inclusive_range() {
+        let env = TestEnv::new();
+        write_archive(&env);
+
+        let result = env.run_ldr(&[
+            "archive",
+            "show",
+            "--since",
+            "2025-05-01",
+            "--until",
+            "2025-05-31",
+        ]);
+        result.assert_success();
+        let stdout = strip_ansi(&result.stdout);
+        assert_eq!(shown_days(&stdout), vec!["2025-05-31", "2025-05-01"]);
+        assert!(stdout.contains("  - Sent invoice [Work]"));
+        assert!(stdout.contains("- Add test"));
+        assert!(!stdout.contains("Deploy"));
+        assert!(!stdout.contains("Mow lawn"));
+    }
+
+    #[test]
+    fn test_archive_show_open_ended() {
+        let env = TestEnv::new();
+        write_archive(&env);
+
+        let result = env.run_ldr(&["archive", "show", "--since", "2025-05-31"]);
+        let stdout = strip_ansi(&result.stdout);
+        assert_eq!(shown_days(&stdout), vec!["2025-06-02", "2025-05-31"]);
+
+        let result = env.run_ldr(&["archive", "show", "--until", "2025-04-30"]);
+        assert_eq!(shown_days(&strip_ansi(&result.stdout)), vec!["2025-04-30"]);
+
+        let result = env.run_ldr(&["archive", "show"]);
+        assert_eq!(shown_days(&strip_ansi(&result.stdout)).len(), 4);
+
+        let result = env.run_ldr(&["archive", "show", "--since", "2026-01-01"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("No archived items since 2026-01-01."));
+    }
+
+    #[test]
+    fn test_archive_show_rejects_bad_dates() {
+        let env = TestEnv::new();
+        write_archive(&env);
+
+        let result = env.run_ldr(&["archive", "show", "--since", "May 1"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Invalid date 'May 1': expected YYYY-MM-DD"));
+
+        let result = env.run_ldr(&[
+            "archive",
+            "show",
+            "--since",
+            "2025-06-01",
+            "--until",
+            "2025-05-01",
+        ]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("--since 2025-06-01 is after --until 2025-05-01"));
+    }
+}
+
+#[cfg(test)]
+mod archive_stats_tests {
+    use super::*;
+
+    fn write_archive(env: &TestEnv) {
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\n\
+             ## 2025-06-02\n- Deploy @work\n  - Tag release @work\n- Water plants @home\n\n\
+             ## 2025-05-01\n- Fix bug @work\n- Mow lawn @home\n- Buy paint @home @errands\n",
+        )
+        .unwrap();
+    }
+
+    fn tag_ranking(stdout: &str) -> Vec<(String, String)> {
+        stdout
+            .lines()
+            .skip_while(|line| !line.starts_with("Top tags:"))
+            .skip(1)
+            .map(|line| {
+                let fields: Vec<_> = line.split_whitespace().collect();
+                (fields[0].to_string(), fields[1].to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_stats_top_tags() {
+        let env = TestEnv::new();
+        write_archive(&env);
+
+        let result = env.run_ldr(&["archive", "stats", "--top-tags"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Archived: 6 items"));
+        assert_eq!(
+            tag_ranking(&result.plain_stdout()),
+            vec![
+                ("@home".to_string(), "3".to_string()),
+                ("@work".to_string(), "3".to_string()),
+                ("@errands".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archive_stats_since() {
+        let env = TestEnv::new();
+        write_archive(&env);
+
+        let result = env.run_ldr(&[
+            "archive",
+            "stats",
+            "--top-tags",
+            "-n",
+            "1",
+            "--since",
+            "2025-06-01",
+        ]);
+        result.assert_success();
+        assert!(result.stdout.contains("Archived: 3 items since 2025-06-01"));
+        assert_eq!(
+            tag_ranking(&result.plain_stdout()),
+            vec![("@work".to_string(), "2".to_string())]
+        );
+    }
+}
+
+#[cfg(test)]
+mod undo_tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_restores_removed_item_and_redoes() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Keep me"]);
+        env.run_ldr(&["add", "Oops"]);
+        let before = env.read_todos();
+
+        env.run_ldr(&["rm", "-y", "2"]).assert_success();
+        let after = env.read_todos();
+        assert!(!after.contains("Keep me"));
+
+        let result = env.run_ldr(&["undo"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Restored the previous state"));
+        assert_eq!(env.read_todos(), before);
+
+        // Undo is itself undoable once
+        env.run_ldr(&["undo"]).assert_success();
+        assert_eq!(env.read_todos(), after);
+    }
+
+    #[test]
+    fn test_undo_restores_archive() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Finish report"]);
+        env.run_ldr(&["do", "1"]).assert_success();
+        assert!(env.archive_path().exists());
+
+        env.run_ldr(&["undo"]).assert_success();
+        assert!(env.read_todos().contains("Finish report"));
+        assert!(!env.archive_path().exists());
+    }
+
+    #[test]
+    fn test_read_only_and_failed_commands_keep_undo() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "First"]);
+        let before = env.read_todos();
+        env.run_ldr(&["add", "Second"]);
+
+        env.run_ldr(&["ls"]).assert_success();
+        env.run_ldr(&["do", "9"]).assert_failure();
+        env.run_ldr(&["add", "Second"]).assert_success(); // skipped as a duplicate
+
+        env.run_ldr(&["undo"]).assert_success();
+        assert_eq!(env.read_todos(), before);
+    }
+
+    #[test]
+    fn test_undo_with_nothing_to_undo() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["undo"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Nothing to undo."));
+    }
+}
+
+#[cfg(test)]
+mod dry_run_tests {
+    use super::*;
+
+    #[test]
+    fn test_do_dry_run_shows_auto_completion_without_writing() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Main task"]);
+        env.run_ldr(&["add", "Only subtask", "--under", "1"]);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["do", "--dry-run", "1a"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Would archive 2 item(s):"));
+        assert!(result.stdout.contains("Only subtask"));
+        assert!(result
+            .stdout
+            .contains("Main task (would be auto-completed - all subtasks done)"));
+        assert!(!result.stdout.contains("archived to"));
+
+        assert_eq!(env.read_todos(), before);
+        assert!(!env.archive_path().exists());
+    }
+
+    #[test]
+    fn test_rm_dry_run_needs_no_confirmation() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Keep me"]);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["rm", "--dry-run", "1"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Would remove 1 item(s):"));
+        assert_eq!(env.read_todos(), before);
+    }
+
+    #[test]
+    fn test_up_dry_run() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Second"]);
+        env.run_ldr(&["add", "First"]);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["up", "--dry-run", "2"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Would prioritize 1 task(s):"));
+        assert!(result.stdout.contains("Second"));
+        assert_eq!(env.read_todos(), before);
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Other task"]);
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+        env.run_ldr(&["add", "Pack", "--under", "1"]);
+    }
+
+    #[test]
+    fn test_ls_json_nested() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--json"]);
+        result.assert_success();
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "ref": "1",
+                    "text": "Plan trip",
+                    "subtasks": [
+                        { "ref": "1a", "text": "Book hotel", "children": [] },
+                        { "ref": "1b", "text": "Pack", "children": [] },
+                    ],
+                },
+                { "ref": "2", "text": "Other task", "subtasks": [] },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ls_json_flat() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--json", "--flat", "Plan"]);
+        result.assert_success();
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "ref": "1", "text": "Plan trip" },
+                { "ref": "1a", "text": "Book hotel", "parent": "1" },
+                { "ref": "1b", "text": "Pack", "parent": "1" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ls_json_without_items() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["ls", "--json"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "[]\n");
+    }
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+
+    #[test]
+    fn test_export_csv_escapes_commas() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Buy eggs, milk"]);
+        env.run_ldr(&["add", "Say \"hi\"", "--under", "1"]);
+
+        let result = env.run_ldr(&["export", "--format", "csv"]);
+        result.assert_success();
+        assert_eq!(
+            result.stdout,
+            "ref,parent,text,list,archived\n\
+             1,,\"Buy eggs, milk\",Default,\n\
+             1a,1,\"Say \"\"hi\"\"\",Default,\n"
+        );
+    }
+
+    #[test]
+    fn test_export_markdown_matches_todo_file() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Task one"]);
+        env.run_ldr(&["add", "Ship it", "--list", "Work", "--create"]);
+
+        let result = env.run_ldr(&["export"]);
+        result.assert_success();
+        assert_eq!(result.stdout, env.read_todos());
+    }
+
+    #[test]
+    fn test_export_json_with_archive_to_file() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Done task"]);
+        env.run_ldr(&["add", "Open task"]);
+        env.run_ldr(&["do", "2"]);
+
+        let out = env.data_dir.join("export.json");
+        let out_str = out.to_str().unwrap();
+        let result = env.run_ldr(&[
+            "export",
+            "--format",
+            "json",
+            "--include-archive",
+            "--out",
+            out_str,
+        ]);
+        result.assert_success();
+        assert!(result.stdout.contains("Exported to"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(
+            json["lists"],
+            serde_json::json!([{
+                "name": "Default",
+                "tasks": [{ "ref": "1", "text": "Open task", "subtasks": [] }],
+            }])
+        );
+        assert_eq!(json["archive"][0]["list"], "Default");
+        assert_eq!(json["archive"][0]["tasks"][0]["text"], "Done task");
+    }
+
+    #[test]
+    fn test_export_json_includes_parse_warnings() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Seed"]);
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Task\n  - Subtask\n      - Too deep\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["export", "--format", "json"]);
+        result.assert_success();
+        assert!(!result.stderr.contains("Deep nesting"));
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(json["warnings"][0]["line"], 5);
+        assert!(json["warnings"][0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Deep nesting detected"));
+
+        // Other formats still mention the warning on stderr
+        let result = env.run_ldr(&["export", "--format", "csv"]);
+        assert!(result
+            .stderr
+            .contains("Warning: Deep nesting detected (line 5)"));
+    }
+
+    #[test]
+    fn test_export_csv_leaves_out_archive_by_default() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Done task"]);
+        env.run_ldr(&["do", "1"]);
+
+        let result = env.run_ldr(&["export", "--format", "csv"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "ref,parent,text,list,archived\n");
+
+        let result = env.run_ldr(&["export", "--format", "csv", "--include-archive"]);
+        assert!(result.stdout.contains(",Done task,Default,20"));
+    }
+
+    #[test]
+    fn test_export_ical_archived_tasks() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        let long = "word ".repeat(30);
+        fs::write(
+            env.archive_path(),
+            format!(
+                "# Archive\n\n## 2025-05-01\n- Pay rent, gas <!-- due:2025-04-30 -->\n\n### Work\n- {}\n",
+                long.trim()
+            ),
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["export", "--format", "ical"]);
+        result.assert_success();
+        let ical = &result.stdout;
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ical.matches("BEGIN:VEVENT\r\n").count(), 2);
+        assert!(ical.contains(
+            "DTSTART;VALUE=DATE:20250501\r\nDTEND;VALUE=DATE:20250502\r\nSUMMARY:Pay rent\\, gas\r\nDESCRIPTION:Due: 2025-04-30\r\n"
+        ));
+        assert!(ical.contains("CATEGORIES:Work\r\n"));
+        // Long lines are folded at 75 bytes, continuing after a space
+        for line in ical.split("\r\n") {
+            assert!(line.len() <= 75, "{:?}", line);
+        }
+        let unfolded = ical.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", long.trim())));
+
+        // The UIDs stay the same from one export to the next
+        let uids = |ical: &str| -> Vec<String> {
+            ical.lines()
+                .filter(|line| line.starts_with("UID:"))
+                .map(String::from)
+                .collect()
+        };
+        let again = env.run_ldr(&["export", "--format", "ical"]);
+        assert_eq!(uids(ical), uids(&again.stdout));
+
+        // Archiving more tasks the same day doesn't change them, and a task's ID is used
+        let content = env.read_archive().replacen(
+            "## 2025-05-01\n",
+            "## 2025-05-01\n- Newer <!-- id:k3x9 -->\n",
+            1,
+        );
+        fs::write(env.archive_path(), content).unwrap();
+        let more = env.run_ldr(&["export", "--format", "ical"]);
+        let more_uids = uids(&more.stdout);
+        assert_eq!(more_uids[0], "UID:20250501-k3x9@ldr");
+        assert_eq!(more_uids[1..], uids(ical)[..]);
+    }
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+
+    fn write_file(env: &TestEnv, name: &str, content: &str) -> String {
+        let path = env.data_dir.join(name);
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_import_markdown_keeps_subtasks_and_prepends() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Existing task"]);
+        let file = write_file(
+            &env,
+            "other.md",
+            "# Other app\n\n- Plan trip\n  - Book hotel\n  - Pack\n- Call mom\n",
+        );
+
+        let result = env.run_ldr(&["import", &file]);
+        result.assert_success();
+        assert!(result.stdout.contains("Imported 2 task(s)"));
+
+        let result = env.run_ldr(&["ls", "--plain", "-a"]);
+        assert_eq!(
+            result.stdout,
+            "1\tPlan trip\n1a\tBook hotel\n1b\tPack\n2\tCall mom\n3\tExisting task\n"
+        );
+    }
+
+    #[test]
+    fn test_import_plain_skips_blank_lines() {
+        let env = TestEnv::new();
+        let file = write_file(
+            &env,
+            "list.txt",
+            "Buy milk\n\n   \n- not a subtask\nCall bank\n",
+        );
+
+        env.run_ldr(&["import", "--plain", &file]).assert_success();
+
+        let result = env.run_ldr(&["ls", "--plain", "-a"]);
+        assert_eq!(
+            result.stdout,
+            "1\tBuy milk\n2\t- not a subtask\n3\tCall bank\n"
+        );
+    }
+
+    #[test]
+    fn test_import_into_named_list() {
+        let env = TestEnv::new();
+        let file = write_file(&env, "work.txt", "Ship release\n");
+
+        // A missing list is created, like with `add --list`
+        let result = env.run_ldr(&["import", "--plain", "--list", "Work", &file]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("Imported 1 task(s) to Work (new list)"));
+        let todos = env.read_todos();
+        assert!(todos.contains("## Work\n\n- Ship release"));
+        assert!(env.run_ldr(&["ls"]).stdout.contains("No notes yet."));
+
+        // --create is still accepted
+        env.run_ldr(&["import", "--plain", "--list", "Home", "--create", &file])
+            .assert_success();
+        assert!(env.read_todos().contains("## Home\n\n- Ship release"));
+    }
+
+    #[test]
+    fn test_import_respects_max_tasks() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Existing task"]);
+        let lines: Vec<String> = (1..=1000).map(|n| format!("Task {}", n)).collect();
+        let file = write_file(&env, "big.txt", &lines.join("\n"));
+
+        let result = env.run_ldr(&["import", "--plain", &file]);
+        result.assert_failure();
+        assert!(result.stderr.contains("maximum number of tasks (1000)"));
+        assert!(!env.read_todos().contains("Task 1"));
+    }
+
+    #[test]
+    fn test_import_empty_file() {
+        let env = TestEnv::new();
+        let file = write_file(&env, "empty.txt", "\n\n");
+
+        let result = env.run_ldr(&["import", "--plain", &file]);
+        result.assert_success();
+        assert!(result.stdout.contains("Nothing to import"));
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "banana bread @home"]);
+        env.run_ldr(&["add", "Apple pie"]);
+        env.run_ldr(&["add", "Cherries @errands"]);
+        env.run_ldr(&["add", "Wash", "--under", "2"]);
+    }
+
+    #[test]
+    fn test_sort_alpha_keeps_stored_numbers() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--plain", "--sort", "alpha"]);
+        result.assert_success();
+        assert_eq!(
+            result.stdout,
+            "2\tApple pie\n2a\tWash\n3\tbanana bread @home\n1\tCherries @errands\n"
+        );
+
+        // Refs still target stored positions after a sorted listing
+        env.run_ldr(&["do", "1"]).assert_success();
+        assert!(env.read_archive().contains("Cherries"));
+    }
+
+    #[test]
+    fn test_sort_length_and_reverse() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--numbers-only", "--sort", "length"]);
+        assert_eq!(result.stdout, "2\n2a\n1\n3\n");
+
+        let result = env.run_ldr(&["ls", "--numbers-only", "--sort", "length", "-r"]);
+        assert_eq!(result.stdout, "3\n1\n2\n2a\n");
+    }
+
+    #[test]
+    fn test_sort_tag_puts_untagged_last() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--numbers-only", "--sort", "tag"]);
+        assert_eq!(result.stdout, "1\n3\n2\n2a\n");
+    }
+
+    #[test]
+    fn test_sort_applies_before_limit() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--numbers-only", "--sort", "alpha", "-n", "2"]);
+        assert_eq!(result.stdout, "2\n2a\n");
+    }
+}
+
+#[cfg(test)]
+mod data_dir_tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_flag_creates_and_uses_directory() {
+        let env = TestEnv::new();
+        let dir = env.data_dir.join("elsewhere/nested");
+
+        let dir_str = dir.to_str().unwrap();
+        env.run_ldr(&["--data-dir", dir_str, "add", "Custom task"])
+            .assert_success();
+
+        let todos = fs::read_to_string(dir.join("todos.md")).unwrap();
+        assert!(todos.contains("- Custom task"));
+        assert!(!env.todos_path().exists());
+
+        // The flag is global, so it also works after the subcommand
+        let result = env.run_ldr(&["ls", "--plain", "--data-dir", dir_str]);
+        assert_eq!(result.stdout, "1\tCustom task\n");
+        assert!(env.run_ldr(&["ls"]).stdout.contains("No notes yet."));
+    }
+
+    #[test]
+    fn test_data_dir_from_env() {
+        let env = TestEnv::new();
+        let dir = env.data_dir.join("from-env");
+        let dir_str = dir.to_str().unwrap();
+
+        env.run_ldr_with_env(&["add", "Env task"], &[("LDR_DATA_DIR", dir_str)])
+            .assert_success();
+        assert!(fs::read_to_string(dir.join("todos.md"))
+            .unwrap()
+            .contains("- Env task"));
+
+        // The flag wins over the environment
+        let flag_dir = env.data_dir.join("from-flag");
+        let flag_dir_str = flag_dir.to_str().unwrap();
+        env.run_ldr_with_env(
+            &["--data-dir", flag_dir_str, "add", "Flag task"],
+            &[("LDR_DATA_DIR", dir_str)],
+        )
+        .assert_success();
+        assert!(fs::read_to_string(flag_dir.join("todos.md"))
+            .unwrap()
+            .contains("- Flag task"));
+        assert!(!fs::read_to_string(dir.join("todos.md"))
+            .unwrap()
+            .contains("Flag task"));
+    }
+
+    #[test]
+    fn test_data_dir_migrates_plain_text() {
+        let env = TestEnv::new();
+        let dir = env.data_dir.join("old-notes");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("note.txt"), "Task A\nTask B\n").unwrap();
+
+        let result = env.run_ldr(&["--data-dir", dir.to_str().unwrap(), "ls", "--plain"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Migration completed"));
+        assert!(result.stdout.ends_with("1\tTask A\n2\tTask B\n"));
+        assert!(dir.join("todos.md").exists());
+    }
+}
+
+#[cfg(test)]
+mod bump_tests {
+    use super::*;
+
+    #[test]
+    fn test_bump_moves_to_top_and_refreshes_timestamp() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Seed"]);
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- First <!-- created:2025-01-01T09:00 -->\n- Water plants <!-- due:2025-06-01 created:2025-01-02T09:00 -->\n  - Ferns\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["bump", "2"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Bumped 1 task(s)"));
+
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tWater plants\n1a\tFerns\n2\tFirst\n");
+        let todos = env.read_todos();
+        assert!(!todos.contains("created:2025-01-02"));
+        assert!(todos.contains("due:2025-06-01"));
+        // Tasks that weren't bumped keep their timestamp
+        assert!(todos.contains("- First <!-- created:2025-01-01T09:00 -->"));
+    }
+
+    #[test]
+    fn test_up_keeps_timestamp() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Seed"]);
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- First\n- Second <!-- created:2025-01-02T09:00 -->\n",
+        )
+        .unwrap();
+
+        env.run_ldr(&["up", "2"]).assert_success();
+        assert!(env
+            .read_todos()
+            .contains("- Second <!-- created:2025-01-02T09:00 -->"));
+    }
+
+    #[test]
+    fn test_bump_dry_run() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "First"]);
+        env.run_ldr(&["add", "Second"]);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["bump", "--dry-run", "2"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Would bump 1 task(s):"));
+        assert_eq!(env.read_todos(), before);
+    }
+}
+
+#[cfg(test)]
+mod list_archive_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Default chore"]);
+        env.run_ldr(&["add", "Ship release", "--list", "Work", "--create"]);
+        env.run_ldr(&["add", "Write notes", "--list", "Work"]);
+        env.run_ldr(&["add", "Buy milk", "--list", "shopping", "--create"]);
+    }
+
+    #[test]
+    fn test_do_archives_under_list_sections() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        env.run_ldr(&["do", "1"]).assert_success();
+        // List names match case-insensitively but are archived as written in the file
+        env.run_ldr(&["do", "--list", "work", "2"]).assert_success();
+        env.run_ldr(&["do", "--list", "shopping", "1"])
+            .assert_success();
+
+        let archive = env.read_archive();
+        let default_pos = archive.find("- Default chore").unwrap();
+        let shopping_pos = archive.find("### shopping\n- Buy milk").unwrap();
+        let work_pos = archive.find("### Work\n- Ship release").unwrap();
+        // Default items come first without a header
+        assert!(default_pos < work_pos && default_pos < shopping_pos);
+        assert!(!archive.contains("### Default"));
+
+        let todos = env.read_todos();
+        assert!(todos.contains("## Work\n\n- Write notes"));
+        assert!(!todos.contains("Ship release"));
+        assert!(!todos.contains("Buy milk"));
+    }
+
+    #[test]
+    fn test_do_auto_completed_parent_uses_list() {
+        let env = TestEnv::new();
+        setup(&env);
+        env.run_ldr(&["add", "Step one", "--under", "1", "--list", "Work"]);
+
+        env.run_ldr(&["do", "--list", "Work", "1a"])
+            .assert_success();
+        let archive = env.read_archive();
+        let work_section = &archive[archive.find("### Work").unwrap()..];
+        assert!(work_section.contains("- Step one"));
+        assert!(work_section.contains("- Write notes"));
+    }
+
+    #[test]
+    fn test_rm_from_list_and_unknown_list() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        env.run_ldr(&["rm", "-y", "--list", "shopping", "1"])
+            .assert_success();
+        assert!(!env.read_todos().contains("Buy milk"));
+
+        let result = env.run_ldr(&["do", "--list", "Nope", "1"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("No such list 'Nope'"));
+
+        let result = env.run_ldr(&["do", "--list", "Work", "5"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("Valid range: 1-2"));
+    }
+}
+
+#[cfg(test)]
+mod archive_merge_tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_archive_dates_merge_on_next_write() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "New task"]);
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        fs::write(
+            env.archive_path(),
+            format!(
+                "# Archive\n\n## {today}\n- First\n\n## 2025-01-01\n- Old\n\n## {today}\n- Second\n"
+            ),
+        )
+        .unwrap();
+
+        env.run_ldr(&["do", "1"]).assert_success();
+        let archive = env.read_archive();
+        assert_eq!(archive.matches(&format!("## {}", today)).count(), 1);
+        // The archived task keeps its creation time as metadata
+        assert!(archive.contains(&format!("## {}\n- First\n- Second\n- New task <!--", today)));
+    }
+}
+
+#[cfg(test)]
+mod level_filter_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Chores"]);
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+        env.run_ldr(&["add", "Pack", "--under", "1"]);
+        env.run_ldr(&["add", "Laundry", "--under", "2"]);
+    }
+
+    #[test]
+    fn test_tasks_only() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--plain", "--tasks-only"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "1\tPlan trip\n2\tChores\n");
+        assert_eq!(
+            env.run_ldr(&["ls", "--count", "--tasks-only"]).stdout,
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_subtasks_only() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--plain", "--subtasks-only"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "1a\tBook hotel\n1b\tPack\n2a\tLaundry\n");
+
+        // The colored view prefixes each subtask with its full reference
+        let result = env.run_ldr(&["ls", "--subtasks-only", "Laundry"]);
+        assert_eq!(result.plain_stdout(), "   2a. Laundry\n");
+
+        let result = env.run_ldr(&["ls", "--json", "--subtasks-only", "Laundry"]);
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{ "ref": "2a", "text": "Laundry", "parent": "2" }])
+        );
+    }
+
+    #[test]
+    fn test_level_flags_conflict() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["ls", "--tasks-only", "--subtasks-only"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod markdown_text_tests {
+    use super::*;
+
+    #[test]
+    fn test_add_text_starting_with_markdown_characters() {
+        let env = TestEnv::new();
+        for text in ["> quoted", "+ plus", "* starred", "# not a header"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+        // Text starting with "-" isn't mistaken for a flag
+        env.run_ldr(&["add", "- literally dashes"]).assert_success();
+        env.run_ldr(&["add", "-5 degrees", "--under", "1"])
+            .assert_success();
+
+        let result = env.run_ldr(&["ls", "--plain", "-a"]);
+        assert_eq!(
+            result.stdout,
+            "1\t- literally dashes\n1a\t-5 degrees\n2\t# not a header\n3\t* starred\n4\t+ plus\n5\t> quoted\n"
+        );
+
+        env.run_ldr(&["do", "2"]).assert_success();
+        assert!(env.read_archive().contains("- # not a header"));
+    }
+}
+
+#[cfg(test)]
+mod group_by_tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_tag() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Plain task"]);
+        env.run_ldr(&["add", "Fix sink @home"]);
+        env.run_ldr(&["add", "Call plumber @home @work"]);
+        env.run_ldr(&["add", "Get quote", "--under", "1"]);
+        env.run_ldr(&["add", "Ship release @work"]);
+
+        let result = env.run_ldr(&["ls", "--group-by", "tag", "--width", "200"]);
+        result.assert_success();
+        let lines: Vec<String> = strip_ansi(&result.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "@home (2)",
+                "2. Call plumber @home @work",
+                "a. Get quote",
+                "3. Fix sink @home",
+                "",
+                "@work (2)",
+                "1. Ship release @work",
+                "2. Call plumber @home @work",
+                "a. Get quote",
+                "",
+                "(untagged) (1)",
+                "4. Plain task",
+            ]
+        );
+
+        // Refs shown under a group still point at stored positions
+        env.run_ldr(&["do", "3"]).assert_success();
+        assert!(env.read_archive().contains("Fix sink"));
+    }
+
+    #[test]
+    fn test_group_by_conflicts_with_json() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Task @home"]);
+        env.run_ldr(&["ls", "--group-by", "tag", "--json"])
+            .assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod first_last_ref_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        for text in ["Oldest", "Middle", "Newest"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+    }
+
+    #[test]
+    fn test_do_first_and_up_last() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        env.run_ldr(&["up", "last"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tOldest\n2\tNewest\n3\tMiddle\n");
+
+        env.run_ldr(&["do", "first"]).assert_success();
+        assert!(env.read_archive().contains("Oldest"));
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tNewest\n2\tMiddle\n");
+    }
+
+    #[test]
+    fn test_first_last_reject_subtask_letters() {
+        let env = TestEnv::new();
+        setup(&env);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["do", "lasta"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("can't take a subtask"));
+        assert_eq!(env.read_todos(), before);
+    }
+
+    #[test]
+    fn test_text_prefix_refs() {
+        let env = TestEnv::new();
+        for text in ["Buy milk", "Call the bank", "Buy eggs"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["do", "/buy"]);
+        result.assert_failure();
+        assert!(result
+            .stdout
+            .contains("'buy' matches 2 tasks: 1. Buy eggs; 3. Buy milk"));
+        assert_eq!(env.read_todos(), before);
+
+        env.run_ldr(&["do", "/CALL"]).assert_success();
+        assert!(env.read_archive().contains("Call the bank"));
+        env.run_ldr(&["up", "/milk"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tBuy milk\n2\tBuy eggs\n");
+    }
+
+    #[test]
+    fn test_text_refs_with_spaces() {
+        let env = TestEnv::new();
+        for text in ["Buy milk", "Buy eggs", "Call the bank", "Call the plumber"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+
+        env.run_ldr(&["up", "/buy milk"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert!(result.stdout.starts_with("1\tBuy milk\n"));
+
+        env.run_ldr(&["do", "/call the bank"]).assert_success();
+        assert!(env.read_archive().contains("Call the bank"));
+
+        env.run_ldr(&["rm", "-y", "/buy eggs"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tBuy milk\n2\tCall the plumber\n");
+    }
+}
+
+#[cfg(test)]
+mod peek_tests {
+    use super::*;
+
+    #[test]
+    fn test_peek_prints_top_task_text() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Older task"]);
+        env.run_ldr(&["add", "Write the report @work"]);
+        env.run_ldr(&["add", "Outline", "--under", "1"]);
+
+        let result = env.run_ldr(&["peek"]);
+        result.assert_success();
+        assert_eq!(result.stdout, "Write the report @work\n");
+        assert_eq!(result.stderr, "");
+    }
+
+    #[test]
+    fn test_peek_fails_quietly_when_empty() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["peek"]);
+        result.assert_failure();
+        assert_eq!(result.stdout, "");
+        assert_eq!(result.stderr, "");
+    }
+}
+
+#[cfg(test)]
+mod flat_numbering_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Pay bills"]);
+        env.run_ldr(&["add", "Plan trip"]);
+        env.run_ldr(&["add", "Book flights", "--under", "1"]);
+        env.run_ldr(&["add", "Book hotel", "--under", "1"]);
+    }
+
+    #[test]
+    fn test_ls_flat_numbers_every_item() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "--flat", "--width", "200"]);
+        result.assert_success();
+        assert_eq!(
+            strip_ansi(&result.stdout),
+            "  1. Plan trip\n    2. Book flights\n    3. Book hotel\n  4. Pay bills\n"
+        );
+
+        // Numbers belong to items, so a filter doesn't renumber them
+        let result = env.run_ldr(&["ls", "--flat", "--plain", "hotel"]);
+        assert_eq!(result.stdout, "1\tPlan trip\n3\tBook hotel\n");
+
+        // The hierarchical scheme is unchanged without --flat
+        let result = env.run_ldr(&["ls", "--numbers-only"]);
+        assert_eq!(result.stdout, "1\n1a\n1b\n2\n");
+    }
+
+    #[test]
+    fn test_do_up_and_rm_accept_flat_numbers() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        env.run_ldr(&["do", "--flat", "3"]).assert_success();
+        assert!(env.read_archive().contains("Book hotel"));
+        assert!(env.read_todos().contains("Book flights"));
+
+        env.run_ldr(&["up", "--flat", "3"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(
+            result.stdout,
+            "1\tPay bills\n2\tPlan trip\n2a\tBook flights\n"
+        );
+
+        env.run_ldr(&["rm", "-y", "--flat", "3"]).assert_success();
+        assert!(!env.read_todos().contains("Book flights"));
+
+        let result = env.run_ldr(&["do", "--flat", "9"]);
+        result.assert_failure();
+        assert!(result
+            .stdout
+            .contains("Invalid flat number: 9. Valid range: 1-2"));
+    }
+}
+
+#[cfg(test)]
+mod clear_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "First task"]);
+        env.run_ldr(&["add", "Second task"]);
+        env.run_ldr(&["add", "Sub item", "--under", "1"]);
+        env.run_ldr(&["add", "Work task", "--list", "Work", "--create"]);
+    }
+
+    #[test]
+    fn test_clear_requires_yes_when_not_interactive() {
+        let env = TestEnv::new();
+        setup(&env);
+        let before = env.read_todos();
+
+        let result = env.run_ldr(&["clear"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("Aborted; nothing was cleared"));
+        assert_eq!(env.read_todos(), before);
+    }
+
+    #[test]
+    fn test_clear_archives_all_tasks() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["clear", "-y"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Archived 2 item(s)"));
+
+        let result = env.run_ldr(&["ls"]);
+        assert!(result.stdout.contains("No notes yet."));
+        let archive = env.read_archive();
+        assert!(archive.contains("First task"));
+        assert!(archive.contains("Second task"));
+        assert!(archive.contains("Sub item"));
+        // Other lists are left alone
+        assert!(env.read_todos().contains("Work task"));
+
+        let result = env.run_ldr(&["clear", "-y"]);
+        result.assert_success();
+        assert!(result.stdout.contains("No notes to clear."));
+    }
+
+    #[test]
+    fn test_clear_no_archive_and_named_list() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["clear", "-y", "--no-archive", "--list", "work"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Removed 1 item(s)"));
+        assert!(!env.read_todos().contains("Work task"));
+        assert!(env.read_todos().contains("First task"));
+        assert!(!env.archive_path().exists());
+    }
+}
+
+#[cfg(test)]
+mod weekly_tests {
+    use super::*;
+
+    fn days_ago(days: i64) -> String {
+        (chrono::Local::now().date_naive() - chrono::Duration::days(days))
+            .format("%Y-%m-%d")
+            .to_string()
+    }
+
+    #[test]
+    fn test_weekly_groups_archive_by_week() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            format!(
+                "# Archive\n\n## {}\n- Shipped release\n- Wrote notes\n\n## {}\n- Fixed bug\n\n## {}\n- Ancient task\n",
+                days_ago(0),
+                days_ago(7),
+                days_ago(40)
+            ),
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["weekly", "--weeks", "2"]);
+        result.assert_success();
+        let stdout = strip_ansi(&result.stdout);
+        let headers: Vec<&str> = stdout
+            .lines()
+            .filter(|line| line.starts_with("Week "))
+            .collect();
+        assert_eq!(headers.len(), 2);
+        assert!(headers[0].ends_with(": 2 items"));
+        assert!(headers[1].ends_with(": 1 item"));
+        assert!(stdout.contains("  - Shipped release"));
+        assert!(stdout.contains("  - Fixed bug"));
+        assert!(!stdout.contains("Ancient task"));
+
+        // The default covers four weeks; the alias works too
+        let result = env.run_ldr(&["review-summary"]);
+        result.assert_success();
+        assert_eq!(
+            strip_ansi(&result.stdout)
+                .lines()
+                .filter(|line| line.starts_with("Week "))
+                .count(),
+            4
+        );
+        assert!(!result.stdout.contains("Ancient task"));
+    }
+}
+
+#[cfg(test)]
+mod damaged_archive_tests {
+    use super::*;
+
+    #[test]
+    fn test_do_succeeds_with_malformed_archive() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Finish report"]);
+        let damaged = "# Archive\n\n## 2025-05-01\n- Old item\n<<<<<<< HEAD\n";
+        fs::write(env.archive_path(), damaged).unwrap();
+
+        let result = env.run_ldr(&["do", "1"]);
+        result.assert_success();
+        assert!(result
+            .stderr
+            .contains("Skipped unrecognized archive line 5: <<<<<<< HEAD"));
+
+        let archive = env.read_archive();
+        assert!(archive.contains("Finish report"));
+        assert!(archive.contains("- Old item"));
+        assert!(!archive.contains("<<<<<<<"));
+
+        // The damaged original is kept next to the archive
+        let mut backup = env.archive_path().into_os_string();
+        backup.push(".corrupt");
+        assert_eq!(fs::read_to_string(backup).unwrap(), damaged);
+    }
+
+    #[test]
+    fn test_reading_malformed_archive_warns() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.archive_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\ngarbage\n## 2025-05-01\n- Done\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["today", "--date", "2025-05-01"]);
+        result.assert_success();
+        assert!(result.stdout.contains("- Done"));
+        assert!(result
+            .stderr
+            .contains("Warning: Skipped unrecognized archive line 3"));
+    }
+}
+
+#[cfg(test)]
+mod overflow_summary_tests {
+    use super::*;
+
+    fn setup(env: &TestEnv) {
+        env.run_ldr(&["add", "Pack bags"]);
+        env.run_ldr(&["add", "Plan trip"]);
+        for subtask in ["Book flights", "Book hotel", "Rent car"] {
+            env.run_ldr(&["add", subtask, "--under", "1"]);
+        }
+        env.run_ldr(&["add", "Trip insurance"]);
+    }
+
+    #[test]
+    fn test_hidden_tasks_and_subtasks_are_counted_apart() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        let result = env.run_ldr(&["ls", "-n", "2"]);
+        result.assert_success();
+        assert!(strip_ansi(&result.stdout).ends_with("... and 1 more task and 3 subtasks\n"));
+
+        let result = env.run_ldr(&["ls", "-n", "1", "--tasks-only"]);
+        assert!(strip_ansi(&result.stdout).ends_with("... and 2 more tasks\n"));
+    }
+
+    #[test]
+    fn test_hidden_subtasks_of_a_shown_task() {
+        let env = TestEnv::new();
+        setup(&env);
+
+        // Only subtasks of the shown "Plan trip" are cut off by the filtered list
+        let result = env.run_ldr(&["ls", "-n", "3", "trip"]);
+        result.assert_success();
+        let stdout = strip_ansi(&result.stdout);
+        assert!(stdout.contains("2. Plan trip"));
+        assert!(stdout.ends_with("... and 2 more subtasks\n"));
+
+        let result = env.run_ldr(&["ls", "-n", "2", "--subtasks-only"]);
+        assert!(strip_ansi(&result.stdout).ends_with("... and 1 more subtask\n"));
+    }
+
+    #[test]
+    fn test_long_file_listing_matches_full_parse() {
+        let env = TestEnv::new();
+        let mut content = String::from("# TODOs\n\n");
+        for num in 1..=500 {
+            content.push_str(&format!(
+                "- Task {}\n  - Step {}a\n    - Detail\n",
+                num, num
+            ));
+        }
+        content.push_str("* Odd bullet\n\n## Work\n\n- Elsewhere\n");
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(env.todos_path(), content).unwrap();
+
+        let result = env.run_ldr_with_env(&["ls", "-n", "4"], &[("LDR_NESTED_SUBTASKS", "1")]);
+        result.assert_success();
+        let stdout = strip_ansi(&result.stdout);
+        assert!(stdout.contains("2. Task 2"));
+        assert!(!stdout.contains("Task 3"));
+        assert!(stdout.ends_with("... and 499 more tasks and 998 subtasks\n"));
+        assert!(result.stderr.contains("Mixed list bullets (line 1503)"));
+
+        // Listing from the bottom reads everything and counts the same
+        let result = env.run_ldr_with_env(
+            &["ls", "-n", "4", "--reverse"],
+            &[("LDR_NESTED_SUBTASKS", "1")],
+        );
+        assert!(strip_ansi(&result.stdout).ends_with("... and 499 more tasks and 998 subtasks\n"));
+    }
+}
+
+#[cfg(test)]
+mod recurring_tests {
+    use super::*;
+
+    #[test]
+    fn test_done_recurring_task_comes_back_due_tomorrow() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Other task"]);
+        env.run_ldr(&["add", "Water plants", "--recur", "daily"])
+            .assert_success();
+        assert!(env.read_todos().contains("recur:daily"));
+
+        let result = env.run_ldr(&["do", "1"]);
+        result.assert_success();
+        let tomorrow = (chrono::Local::now().date_naive() + chrono::Days::new(1))
+            .format("%Y-%m-%d")
+            .to_string();
+        assert!(result
+            .stdout
+            .contains(&format!("↻ Water plants is back as 1, due {}", tomorrow)));
+        assert!(env.read_archive().contains("Water plants"));
+
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tWater plants\n2\tOther task\n");
+        let todos = env.read_todos();
+        assert!(todos.contains(&format!("due:{}", tomorrow)));
+        assert!(todos.contains("recur:daily"));
+    }
+
+    #[test]
+    fn test_renewed_task_gets_new_id_and_unchecked_subtasks() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Weekly review <!-- recur:weekly id:ab12 -->\n  - [x] Inbox\n  - [ ] Calendar\n  - Notes\n",
+        )
+        .unwrap();
+
+        env.run_ldr(&["do", "1"]).assert_success();
+        let todos = env.read_todos();
+        assert!(todos.contains("  - [ ] Inbox\n  - [ ] Calendar\n  - Notes\n"));
+        assert!(!todos.contains("[x]"));
+        assert!(todos.contains(" id:"));
+        assert!(!todos.contains("id:ab12"));
+        // The archived original keeps its ID
+        assert!(env.read_archive().contains("id:ab12"));
+    }
+
+    #[test]
+    fn test_rm_and_clear_do_not_bring_recurring_tasks_back() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Water plants", "--recur", "weekly"]);
+        env.run_ldr(&["rm", "-y", "1"]).assert_success();
+        assert!(!env.read_todos().contains("Water plants"));
+
+        env.run_ldr(&["add", "Water plants", "--recur", "weekly"]);
+        env.run_ldr(&["clear", "-y"]).assert_success();
+        assert!(!env.read_todos().contains("Water plants"));
+
+        let result = env.run_ldr(&["add", "Sub", "--under", "1", "--recur", "daily"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod recently_done_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_done_lists_latest_archived_items() {
+        let env = TestEnv::new();
+        for text in ["Write report", "Pay rent", "Call mom", "Book flights"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+        env.run_ldr(&["do", "/pay"]).assert_success();
+        env.run_ldr(&["do", "/call"]).assert_success();
+        env.run_ldr(&["do", "/write"]).assert_success();
+
+        let result = env.run_ldr(&["ls", "--done", "2"]);
+        result.assert_success();
+        let output = strip_ansi(&result.stdout);
+        let open = output.find("Book flights").unwrap();
+        let divider = output.find("Recently done").unwrap();
+        let newest = output.find("✓ Write report").unwrap();
+        let older = output.find("✓ Call mom").unwrap();
+        assert!(open < divider && divider < newest && newest < older);
+        assert!(!output.contains("Pay rent"));
+
+        // Nothing archived yet still shows the open list on its own
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Only task"]).assert_success();
+        let result = env.run_ldr(&["ls", "--done", "3"]);
+        assert!(!result.stdout.contains("Recently done"));
+    }
+}
+
+#[cfg(test)]
+mod porcelain_tests {
+    use super::*;
+
+    /// Replaces the random task ID in a porcelain line with `????`
+    fn mask_id(line: &str) -> String {
+        match line.find(" id=") {
+            Some(start) => format!("{}????{}", &line[..start + 4], &line[start + 8..]),
+            None => line.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_porcelain_confirmations() {
+        let env = TestEnv::new();
+
+        let result = env.run_ldr(&["--porcelain", "add", "Write report"]);
+        result.assert_success();
+        assert_eq!(
+            mask_id(&result.stdout),
+            "added ref=1 id=???? text=\"Write report\"\n"
+        );
+        let result = env.run_ldr(&["--porcelain", "add", "Say \"hi\""]);
+        assert_eq!(
+            mask_id(&result.stdout),
+            "added ref=1 id=???? text=\"Say \\\"hi\\\"\"\n"
+        );
+        let result = env.run_ldr(&["--porcelain", "add", "Outline", "--under", "2"]);
+        assert_eq!(result.stdout, "added ref=2a text=\"Outline\"\n");
+        let result = env.run_ldr(&["--porcelain", "add", "Ship it", "--list", "Work"]);
+        assert_eq!(
+            mask_id(&result.stdout),
+            "added ref=1 id=???? list=\"Work\" text=\"Ship it\"\n"
+        );
+        let result = env.run_ldr(&["--porcelain", "add", "write report"]);
+        assert_eq!(result.stdout, "duplicate ref=2\n");
+
+        env.run_ldr(&["add", "Call mom"]).assert_success();
+        env.run_ldr(&["add", "Pay rent"]).assert_success();
+        let result = env.run_ldr(&["--porcelain", "up", "4", "2"]);
+        assert_eq!(result.stdout, "prioritized refs=4,2\n");
+        let result = env.run_ldr(&["--porcelain", "bump", "3"]);
+        assert_eq!(result.stdout, "bumped refs=3\n");
+
+        let result = env.run_ldr(&["--porcelain", "set-due", "1", "2030-01-02"]);
+        assert_eq!(result.stdout, "due_set ref=1 date=2030-01-02\n");
+        let result = env.run_ldr(&["--porcelain", "clear-due", "1"]);
+        assert_eq!(result.stdout, "due_cleared ref=1\n");
+
+        let result = env.run_ldr(&["--porcelain", "do", "1", "2", "--dry-run"]);
+        assert_eq!(result.stdout, "archived count=2 dry_run=true\n");
+        let result = env.run_ldr(&["--porcelain", "do", "1", "2", "3"]);
+        assert_eq!(result.stdout, "archived count=3\n");
+        let result = env.run_ldr(&["--porcelain", "rm", "-y", "1"]);
+        assert_eq!(result.stdout, "removed count=1\n");
+
+        let result = env.run_ldr(&["--porcelain", "undo"]);
+        assert_eq!(result.stdout, "undone changed=true\n");
+        let result = env.run_ldr(&["--porcelain", "lists", "create", "Home office"]);
+        assert_eq!(result.stdout, "list_created name=\"Home office\"\n");
+        let result = env.run_ldr(&["--porcelain", "lists", "rm", "Work", "--force"]);
+        assert_eq!(result.stdout, "list_removed name=\"Work\" tasks=1\n");
+    }
+
+    #[test]
+    fn test_porcelain_leaves_reading_commands_alone() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Write report"]).assert_success();
+
+        let result = env.run_ldr(&["--porcelain", "ls", "--plain"]);
+        assert_eq!(result.stdout, "1\tWrite report\n");
+        env.run_ldr(&["--porcelain", "--quiet", "add", "x"])
+            .assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod tree_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_tree_draws_connectors_with_refs() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Plan trip\n  - Flights\n  - Hotel\n    - Late checkout\n  - Car\n    - Automatic\n- Pack\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr_with_env(&["ls", "--tree", "-a"], &[("LDR_NESTED_SUBTASKS", "1")]);
+        result.assert_success();
+        assert_eq!(
+            strip_ansi(&result.stdout),
+            "  1. Plan trip\n     ├─ 1a. Flights\n     ├─ 1b. Hotel\n     │  └─ 1b1. Late checkout\n     └─ 1c. Car\n        └─ 1c1. Automatic\n  2. Pack\n"
+        );
+
+        // The last subtask shown gets the corner, even when later ones are filtered out
+        let result = env.run_ldr(&["ls", "--tree", "--match-subtasks-only", "Flights"]);
+        assert_eq!(
+            strip_ansi(&result.stdout),
+            "  1. Plan trip\n     └─ 1a. Flights\n"
+        );
+
+        // Also when -n cuts them off
+        let result = env.run_ldr_with_env(
+            &["ls", "--tree", "-n", "4"],
+            &[("LDR_NESTED_SUBTASKS", "1")],
+        );
+        assert!(strip_ansi(&result.stdout).starts_with(
+            "  1. Plan trip\n     ├─ 1a. Flights\n     └─ 1b. Hotel\n        └─ 1b1. Late checkout\n... and"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod focus_tests {
+    use super::*;
+
+    #[test]
+    fn test_focus_follows_task_and_clears() {
+        let env = TestEnv::new();
+        for text in ["Write report", "Plan trip", "Pay rent"] {
+            env.run_ldr(&["add", text]).assert_success();
+        }
+        env.run_ldr(&["add", "Book flights", "--under", "2"])
+            .assert_success();
+
+        let result = env.run_ldr(&["focus", "2a"]);
+        result.assert_success();
+        assert!(result.stdout.contains("Focused on task 2: Plan trip"));
+
+        // Moving the task keeps it focused under its new number
+        env.run_ldr(&["up", "3"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "3\tPlan trip\n3a\tBook flights\n");
+        let result = env.run_ldr(&["ls", "--plain", "--no-focus"]);
+        assert_eq!(result.stdout.lines().count(), 4);
+        let result = env.run_ldr(&["focus"]);
+        assert_eq!(result.stdout, "Focused on task 3: Plan trip\n");
+
+        env.run_ldr(&["do", "3"]).assert_success();
+        let result = env.run_ldr(&["ls"]);
+        assert!(result
+            .stdout
+            .contains("The focused task is no longer open: Plan trip"));
+
+        env.run_ldr(&["focus", "--clear"]).assert_success();
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout.lines().count(), 2);
+        env.run_ldr(&["ls", "--focused"]).assert_failure();
+        env.run_ldr(&["focus", "9"]).assert_failure();
+    }
+
+    #[test]
+    fn test_focus_task_without_id_starting_with_hash() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(env.todos_path(), "# TODOs\n\n- #hashtag cleanup\n- Other\n").unwrap();
+
+        env.run_ldr(&["focus", "1"]).assert_success();
+        let result = env.run_ldr(&["focus"]);
+        assert_eq!(result.stdout, "Focused on task 1: #hashtag cleanup\n");
+        let result = env.run_ldr(&["ls", "--plain"]);
+        assert_eq!(result.stdout, "1\t#hashtag cleanup\n");
+    }
+}
+
+#[cfg(test)]
+mod task_id_tests {
+    use super::*;
+
+    #[test]
+    fn test_id_reference_survives_reordering() {
+        let env = TestEnv::new();
+        let result = env.run_ldr(&["--porcelain", "add", "Renew passport"]);
+        result.assert_success();
+        let id = result
+            .stdout
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("id="))
+            .expect("porcelain output carries the ID")
+            .to_string();
+        assert!(env.read_todos().contains(&format!("id:{}", id)));
+
+        env.run_ldr(&["add", "Book flights"]).assert_success();
+        env.run_ldr(&["add", "Pack bags"]).assert_success();
+        // Positional ref 3 now names another task; the ID still finds the passport
+        let result = env.run_ldr(&["do", &format!("#{}", id)]);
+        result.assert_success();
+        let todos = env.read_todos();
+        assert!(!todos.contains("Renew passport"));
+        assert!(todos.contains("Book flights"));
+        assert!(todos.contains("Pack bags"));
+
+        let result = env.run_ldr(&["do", "#zzzz"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("no task has ID 'zzzz'"));
+    }
+}
+
+#[cfg(test)]
+mod max_depth_tests {
+    use super::*;
+
+    #[test]
+    fn test_max_depth_zero_badges_subtask_counts() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Plan trip\n  - Flights\n  - Hotel\n  - Car\n- Pack\n- Call mom\n  - Ask about Sunday\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["ls", "--max-depth", "0", "-a"]);
+        result.assert_success();
+        assert_eq!(
+            strip_ansi(&result.stdout),
+            "  1. Plan trip (3)\n  2. Pack\n  3. Call mom (1)\n"
+        );
+        let todo_file = ldr::parse_todo_file(&env.read_todos()).unwrap();
+        for (line, task) in strip_ansi(&result.stdout).lines().zip(&todo_file.tasks) {
+            let badge = format!(" ({})", task.subtasks.len());
+            assert_eq!(
+                line.ends_with(&badge),
+                !task.subtasks.is_empty(),
+                "{}",
+                line
+            );
+        }
+
+        // One level shows the subtasks, badging only what lies below them
+        let result = env.run_ldr(&["ls", "--max-depth", "1", "-a"]);
+        assert!(strip_ansi(&result.stdout).contains("     a. Flights\n"));
+        assert!(!strip_ansi(&result.stdout).contains("(3)"));
+    }
+
+    #[test]
+    fn test_max_depth_one_counts_children() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Plan trip\n  - Hotel\n    - Late checkout\n    - Pool\n  - Car\n",
+        )
+        .unwrap();
+
+        let result =
+            env.run_ldr_with_env(&["ls", "--max-depth", "1"], &[("LDR_NESTED_SUBTASKS", "1")]);
+        result.assert_success();
+        assert_eq!(
+            strip_ansi(&result.stdout),
+            "  1. Plan trip\n     a. Hotel (2)\n     b. Car\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod lock_tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_adds_keep_every_task() {
+        let env = TestEnv::new();
+        let texts = [
+            "Buy milk",
+            "Call mom",
+            "Water plants",
+            "Pay rent",
+            "Book flights",
+            "Fix bike",
+            "Read novel",
+            "Walk dog",
+        ];
+
+        std::thread::scope(|scope| {
+            let env = &env;
+            let runs: Vec<_> = texts
+                .iter()
+                .map(|text| scope.spawn(move || env.run_ldr(&["add", text])))
+                .collect();
+            for run in runs {
+                run.join().unwrap().assert_success();
+            }
+        });
+
+        let todos = env.read_todos();
+        for text in texts {
+            assert!(todos.contains(text), "lost {:?} in:\n{}", text, todos);
+        }
+        assert!(!env.data_dir.join("ldr/ldr.lock").exists());
+    }
+}
+
+#[cfg(test)]
+mod hyperlink_tests {
+    use super::*;
+
+    #[test]
+    fn test_piped_listing_has_no_hyperlink_escapes() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Read https://example.com/post"])
+            .assert_success();
+
+        // Colors can be forced into a pipe, but links are only for terminals
+        let result = env.run_ldr(&["--color=always", "ls"]);
+        result.assert_success();
+        assert!(result.stdout.contains("https://example.com/post"));
+        assert!(!result.stdout.contains("\x1b]8;;"));
+    }
+}
+
+#[cfg(test)]
+mod doctor_tests {
+    use super::*;
+
+    #[test]
+    fn test_doctor_reports_and_fixes_damage() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Healthy task"]).assert_success();
+        let result = env.run_ldr(&["doctor"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ No problems found"));
+
+        fs::write(env.todos_path(), "# TODOs\n\n- Call\u{7} Bob\n* Starred\n").unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\n## 2025-05-01\n- First\n\n## 2025-05-01\n- Second\n",
+        )
+        .unwrap();
+
+        // Reporting alone changes nothing and fails, so scripts notice
+        let result = env.run_ldr(&["doctor"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("line 3: control character U+0007"));
+        assert!(result.stdout.contains("line 4: mixed bullets"));
+        assert!(result
+            .stdout
+            .contains("line 6: duplicate date section 2025-05-01 (first at line 3)"));
+        assert!(env.read_todos().contains('\u{7}'));
+        assert!(result
+            .stdout
+            .contains("3 problem(s) can be repaired with `ldr doctor --fix`"));
+        assert!(!result.stdout.contains("by hand"));
+
+        let result = env.run_ldr(&["doctor", "--fix"]);
+        result.assert_success();
+        assert_eq!(env.read_todos(), "# TODOs\n\n- Call Bob\n- Starred\n");
+        assert_eq!(
+            env.read_archive(),
+            "# Archive\n\n## 2025-05-01\n- First\n- Second\n"
+        );
+        let backup = fs::read_to_string(env.data_dir.join("ldr/todos.md.bak")).unwrap();
+        assert!(backup.contains('\u{7}'));
+
+        env.run_ldr(&["doctor"]).assert_success();
+    }
+
+    #[test]
+    fn test_doctor_leaves_long_items_to_the_user() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        let content = format!("# TODOs\n\n- {}\n", "word ".repeat(50).trim());
+        fs::write(env.todos_path(), &content).unwrap();
+
+        let result = env.run_ldr(&["doctor", "--fix"]);
+        result.assert_failure();
+        assert!(result
+            .stdout
+            .contains("line 3: item is 249 characters long"));
+        assert!(result.stdout.contains("1 problem(s) left to fix by hand"));
+        assert_eq!(env.read_todos(), content);
+    }
+
+    #[test]
+    fn test_doctor_fix_keeps_unrecognized_archive_lines() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        let content = "# Archive\n\n## 2025-05-01\n- First\nSomething hand-written\n\n## 2025-05-01\n- Second\n";
+        fs::write(env.archive_path(), content).unwrap();
+
+        let result = env.run_ldr(&["doctor", "--fix"]);
+        result.assert_failure();
+        assert!(result.stdout.contains("Something hand-written"));
+        assert!(result
+            .stdout
+            .contains("(--fix repairs this once the unrecognized lines are fixed)"));
+        assert!(result.stdout.contains("Not rewritten"));
+        assert!(result.stdout.contains("2 problem(s) left to fix by hand"));
+        assert_eq!(env.read_archive(), content);
+    }
+
+    #[test]
+    fn test_doctor_fix_keeps_archived_subtasks_nested() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.archive_path(),
+            "# Archive\n\n## 2025-05-01\n- First\n  - note: shipped\n\n## 2025-05-01\n- Second\n  - [x] Tag\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["doctor", "--fix"]);
+        result.assert_success();
+        assert!(result.stdout.contains("duplicate date section"));
+        assert_eq!(
+            env.read_archive(),
+            "# Archive\n\n## 2025-05-01\n- First\n  - note: shipped\n- Second\n  - [x] Tag\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod add_position_tests {
+    use super::*;
+
+    #[test]
+    fn test_add_after_and_before_a_task() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- First\n- Second\n  - Detail\n- Third\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["add", "Right after second", "--after", "2"]);
+        result.assert_success();
+        assert!(result
+            .stdout
+            .contains("✓ Added as task 3: Right after second"));
+        let result = env.run_ldr(&["add", "Before everything", "--before", "first"]);
+        assert!(result
+            .stdout
+            .contains("✓ Added as task 1: Before everything"));
+        let result = env.run_ldr(&["ls", "--plain", "--tasks-only", "-a"]);
+        assert_eq!(
+            result.stdout,
+            "1\tBefore everything\n2\tFirst\n3\tSecond\n4\tRight after second\n5\tThird\n"
+        );
+
+        let before = env.read_todos();
+        let result = env.run_ldr(&["add", "Nope", "--after", "3a"]);
+        result.assert_failure();
+        assert!(result.stderr.contains("--after takes a task number"));
+        let result = env.run_ldr(&["add", "Nope", "--before", "9"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Invalid task number: 9. Valid range: 1-5"));
+        assert_eq!(env.read_todos(), before);
+    }
+}
+
+#[cfg(test)]
+mod pin_tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_tasks_list_first_and_keep_their_numbers() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        let tasks: String = (1..=8).map(|n| format!("- Task {}\n", n)).collect();
+        fs::write(env.todos_path(), format!("# TODOs\n\n{}", tasks)).unwrap();
+
+        let result = env.run_ldr(&["pin", "7"]);
+        result.assert_success();
+        assert!(result.stdout.contains("✓ Pinned task 7: Task 7"));
+        assert!(env.read_todos().contains("- Task 7 <!-- pinned:true -->\n"));
+
+        // Only the top is read for a short listing, but the pinned task is further down
+        let result = env.run_ldr(&["ls", "--plain", "-n", "2"]);
+        assert_eq!(result.stdout, "7\tTask 7\n1\tTask 1\n");
+        let result = env.run_ldr(&["ls", "--plain", "-n", "2", "--reverse"]);
+        assert_eq!(result.stdout, "7\tTask 7\n8\tTask 8\n");
+        let result = env.run_ldr(&["ls", "-n", "1"]);
+        assert!(strip_ansi(&result.stdout).contains("  7. 📌 Task 7"));
+
+        let result = env.run_ldr(&["pin", "7"]);
+        assert!(result.stdout.contains("Task 7 is already pinned."));
+        env.run_ldr(&["unpin", "7"]).assert_success();
+        assert!(!env.read_todos().contains("pinned"));
+        let result = env.run_ldr(&["ls", "--plain", "-n", "1"]);
+        assert_eq!(result.stdout, "1\tTask 1\n");
+    }
+
+    #[test]
+    fn test_pin_rejects_subtasks() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Task"]).assert_success();
+        env.run_ldr(&["add", "Step", "--under", "1"])
+            .assert_success();
+
+        let result = env.run_ldr(&["pin", "1a"]);
+        result.assert_failure();
+        assert!(result
+            .stderr
+            .contains("Only top-level tasks can be pinned, not subtask '1a'"));
+    }
+}
+
+#[cfg(test)]
+mod last_review_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_new_shows_tasks_added_since_the_last_review() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Old <!-- created:2020-01-01T09:00 -->\n- Untimed\n",
+        )
+        .unwrap();
+
+        // Before any review, every task with a creation time is new
+        let result = env.run_ldr(&["ls", "--new", "--plain"]);
+        assert_eq!(result.stdout, "1\tOld\n");
+
+        env.run_ldr_with_env(&["edit"], &[("EDITOR", "true")])
+            .assert_success();
+        assert!(env.todos_path().with_file_name("last_review.txt").exists());
+        let result = env.run_ldr(&["ls", "--new"]);
+        assert!(result.stdout.contains("Nothing new since the last review."));
+
+        env.run_ldr(&["add", "Fresh"]).assert_success();
+        let result = env.run_ldr(&["ls", "--since-last-review", "--plain"]);
+        assert_eq!(result.stdout, "1\tFresh\n");
+    }
+}
+
+#[cfg(test)]
+mod oneline_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_oneline_format() {
+        let env = TestEnv::new();
+        for task in [
+            "Ship release",
+            "Call Bob",
+            "Buy milk",
+            "Old one",
+            "Older one",
+        ] {
+            env.run_ldr(&["add", task]).assert_success();
+        }
+        env.run_ldr(&["add", "Step", "--under", "1"])
+            .assert_success();
+
+        let result = env.run_ldr(&["ls", "--oneline", "-n", "3"]);
+        assert_eq!(
+            result.stdout,
+            "3 tasks: Older one | Old one | Buy milk (+2 more)\n"
+        );
+        let result = env.run_ldr(&["ls", "--oneline", "-a", "--separator", ", ", "milk"]);
+        assert_eq!(result.stdout, "1 task: Buy milk\n");
+
+        // Tasks that don't fit are counted as more; without room for even the counts,
+        // only the number of tasks is left
+        let result = env.run_ldr(&["ls", "--oneline", "--width", "40"]);
+        assert_eq!(result.stdout, "2 tasks: Older one | Old one (+3 more)\n");
+        let result = env.run_ldr(&["ls", "--oneline", "--width", "12"]);
+        assert_eq!(result.stdout, "5 tasks\n");
+
+        let result = env.run_ldr(&["ls", "--oneline", "nothing"]);
+        assert_eq!(result.stdout, "0 tasks\n");
+    }
+
+    #[test]
+    fn test_ls_oneline_narrow_width_keeps_counts() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Call Bob"]).assert_success();
+        env.run_ldr(&["add", "short https://example.com/a/very/long/path"])
+            .assert_success();
+
+        let result = env.run_ldr(&["ls", "--oneline", "--width", "30"]);
+        assert_eq!(result.stdout, "1 task: short https… (+1 more)\n");
+        assert_eq!(strip_ansi(&result.stdout).trim_end().chars().count(), 30);
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_shows_checked_subtask_progress() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Ship release\n  - [x] Tag\n  - [ ] Publish\n- Done project\n  - [x] Only step\n- Plain\n  - Step\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["ls", "-a"]);
+        let stdout = strip_ansi(&result.stdout);
+        assert!(stdout.contains("  1. Ship release [1/2]\n"));
+        assert!(stdout.contains("     a. [x] Tag\n"));
+        assert!(stdout.contains("     b. [ ] Publish\n"));
+        assert!(stdout.contains("  2. Done project [1/1]\n"));
+        assert!(stdout.contains("  3. Plain\n"));
+
+        // Checkboxes survive commands that rewrite the file
+        env.run_ldr(&["add", "Step two", "--under", "1"])
+            .assert_success();
+        assert!(env
+            .read_todos()
+            .contains("- Ship release\n  - [x] Tag\n  - [ ] Publish\n  - Step two\n"));
+    }
+
+    #[test]
+    fn test_checkboxes_in_tree_flat_and_json() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Ship release\n  - [x] Tag\n  - [ ] Publish\n  - Notes\n",
+        )
+        .unwrap();
+
+        let stdout = strip_ansi(&env.run_ldr(&["ls", "--tree"]).stdout);
+        assert!(stdout.contains("├─ 1a. [x] Tag\n"));
+        assert!(stdout.contains("├─ 1b. [ ] Publish\n"));
+        assert!(stdout.contains("└─ 1c. Notes\n"));
+
+        let stdout = strip_ansi(&env.run_ldr(&["ls", "--flat"]).stdout);
+        assert!(stdout.contains("    2. [x] Tag\n"));
+
+        let stdout = strip_ansi(&env.run_ldr(&["ls", "--subtasks-only"]).stdout);
+        assert!(stdout.contains("   1b. [ ] Publish\n"));
+
+        let result = env.run_ldr(&["ls", "--json"]);
+        result.assert_success();
+        let json: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        let subtasks = &json[0]["subtasks"];
+        assert_eq!(subtasks[0]["completed"], true);
+        assert_eq!(subtasks[1]["completed"], false);
+        assert!(subtasks[2].get("completed").is_none());
+    }
+
+    #[test]
+    fn test_checkbox_like_subtask_text_stays_text() {
+        let env = TestEnv::new();
+        env.run_ldr(&["add", "Task"]).assert_success();
+        env.run_ldr(&["add", "[ ] literal sub", "--under", "1"])
+            .assert_success();
+        assert!(env.read_todos().contains("  - \\[ ] literal sub\n"));
+
+        // Another rewrite keeps it unchecked text, without a progress count
+        env.run_ldr(&["add", "Other"]).assert_success();
+        let result = env.run_ldr(&["ls", "-a"]);
+        let stdout = strip_ansi(&result.stdout);
+        assert!(stdout.contains("  2. Task\n"));
+        assert!(stdout.contains("     a. [ ] literal sub\n"));
+        assert!(env.read_todos().contains("  - \\[ ] literal sub\n"));
+    }
+}
+
+#[cfg(test)]
+mod random_tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_random_with_seed_is_deterministic() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        let tasks: String = (1..=10).map(|n| format!("- Task {}\n", n)).collect();
+        fs::write(env.todos_path(), format!("# TODOs\n\n{}  - Step\n", tasks)).unwrap();
+
+        let result = env.run_ldr(&["ls", "--random", "3", "--seed", "42", "--plain"]);
+        result.assert_success();
+        assert_eq!(
+            result.stdout,
+            "5\tTask 5\n6\tTask 6\n10\tTask 10\n10a\tStep\n"
+        );
+        let again = env.run_ldr(&["ls", "--random", "3", "--seed", "42", "--plain"]);
+        assert_eq!(again.stdout, result.stdout);
+
+        // Asking for more than there are shows every task
+        let result = env.run_ldr(&["ls", "--random", "20", "--plain", "--tasks-only"]);
+        assert_eq!(result.stdout.lines().count(), 10);
+    }
+}
+
+#[cfg(test)]
+mod move_list_tests {
+    use super::*;
+
+    #[test]
+    fn test_move_list_moves_task_between_sections() {
+        let env = TestEnv::new();
+        fs::create_dir_all(env.todos_path().parent().unwrap()).unwrap();
+        fs::write(
+            env.todos_path(),
+            "# TODOs\n\n- Call Bob\n- Buy milk\n  - Oat\n- Write report\n\n## Shopping\n\n- Bread\n",
+        )
+        .unwrap();
+
+        let result = env.run_ldr(&["move-list", "2", "--to", "shopping"]);
+        result.assert_success();
+        let output = strip_ansi(&result.stdout);
+        assert!(output.contains("Moved task 2 from Default to Shopping: Buy milk"));
+
+        let content = env.read_todos();
+        assert_eq!(
+            content,
+            "# TODOs\n\n- Call Bob\n- Write report\n\n## Shopping\n\n- Buy milk\n  - Oat\n- Bread\n"
+        );
+
+        // The target list is created when needed, and --list picks the source
+        let result = env.run_ldr(&["move-list", "2", "--list", "shopping", "--to", "Work"]);
+        result.assert_success();
+        assert!(strip_ansi(&result.stdout).contains("to Work (new list): Bread"));
+        let content = env.read_todos();
+        let work = content.find("## Work").unwrap();
+        assert!(content[work..].contains("- Bread"));
+        assert!(!content[..work].contains("Bread"));
+
+        // Subtasks can't be moved on their own
+        let result = env.run_ldr(&["move-list", "1a", "--list", "shopping", "--to", "Work"]);
+        result.assert_failure();
+    }
+}
+
+#[cfg(test)]
+mod completion_log_tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_log_is_opt_in_and_append_only() {
+        let env = TestEnv::new();
+        let log_path = env.todos_path().with_file_name("log.jsonl");
+        env.run_ldr(&["add", "First"]);
+        env.run_ldr(&["do", "1"]).assert_success();
+        assert!(!log_path.exists());
+
+        env.write_config("completion_log = true\n");
+        env.run_ldr(&["add", "Second"]);
+        env.run_ldr(&["add", "Third"]);
+        env.run_ldr(&["add", "Groceries", "--list", "Shopping"]);
+        env.run_ldr(&["do", "1"]).assert_success();
+        let first_run = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(first_run.lines().count(), 1);
+
+        env.run_ldr(&["do", "1"]).assert_success();
+        env.run_ldr(&["do", "1", "--list", "Shopping"])
+            .assert_success();
+        let content = fs::read_to_string(&log_path).unwrap();
+        assert!(content.starts_with(&first_run));
+
+        let entries: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let fields: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry["text"].as_str().unwrap(),
+                    entry["list"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("Third", "Default"),
+                ("Second", "Default"),
+                ("Groceries", "Shopping"),
+            ]
+        );
+        assert!(entries[0]["timestamp"].as_str().unwrap().contains('T'));
+
+        // The Markdown archive is still written as well
+        let archive = fs::read_to_string(env.archive_path()).unwrap();
+        assert!(archive.contains("- Third"));
+        assert!(archive.contains("- Groceries"));
+    }
+}
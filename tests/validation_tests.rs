@@ -66,28 +66,37 @@ fn test_invalid_task_references() {
     run_ldr(&dir, &["add", "Task 1"]);
     run_ldr(&dir, &["add", "Task 2"]);
 
-    // Test zero task number - should print error but return success
+    // Test zero task number - should print error and fail
     let output = run_ldr(&dir, &["up", "0"]);
-    // Check that error message was printed even if exit code is 0
+    assert!(!output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(
-        stdout.contains("Invalid task reference")
-            || stdout.contains("Task number must be at least 1")
-    );
+    assert!(stdout.contains("Task number must be at least 1"));
 
     // Test too large task number
     let output = run_ldr(&dir, &["up", "999"]);
+    assert!(!output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("Invalid task number"));
 
     // Test invalid formats
     let output = run_ldr(&dir, &["up", "1A"]); // uppercase letter
+    assert!(!output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("Invalid") || stdout.contains("uppercase"));
+    assert!(stdout.contains("Invalid character in task reference: A"));
 
     let output = run_ldr(&dir, &["up", "abc"]); // no number
+    assert!(!output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("Invalid") || stdout.contains("must start with a number"));
+    assert!(stdout.contains("Task reference must start with a number: abc"));
+
+    // Archiving and removing fail the same way, leaving the file untouched
+    let output = run_ldr(&dir, &["do", "99"]);
+    assert!(!output.status.success());
+    let output = run_ldr(&dir, &["rm", "1z"]);
+    assert!(!output.status.success());
+    let todos = std::fs::read_to_string(dir.path().join("ldr").join("todos.md")).unwrap();
+    assert!(todos.contains("Task 1"));
+    assert!(todos.contains("Task 2"));
 }
 
 #[test]